@@ -0,0 +1,241 @@
+use anyhow::Result;
+
+use remoteplay_inviter::{config, console, i18n::t};
+
+use crate::{
+    cli::{AutostartAction, Cli},
+    service::background_args,
+};
+
+/// Run an `autostart` subcommand
+pub fn autostart(cli: &Cli, action: &AutostartAction) -> Result<()> {
+    match action {
+        AutostartAction::Enable => {
+            let exe_path = config::get_exe_path()?;
+            let mut command = vec![exe_path.to_string_lossy().into_owned()];
+            command.extend(background_args(cli)?);
+            let location = imp::enable(&command)?;
+            console::println!("✓ {}", t!("autostart-enabled", location = location));
+        }
+        AutostartAction::Disable => {
+            let location = imp::disable()?;
+            console::println!("✓ {}", t!("autostart-disabled", location = location));
+        }
+    }
+    Ok(())
+}
+
+/// Registration in the `Run` key of the registry
+#[cfg(windows)]
+mod imp {
+    use anyhow::{Context as _, Result};
+    use std::process::Command;
+
+    /// Registry key of the programs started at login
+    const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+    /// Name of the registry value
+    const VALUE_NAME: &str = "RemotePlayInviter";
+
+    /// Quote an argument of the command line
+    fn quote(arg: &str) -> String {
+        if arg.contains([' ', '\t', '"']) || arg.is_empty() {
+            format!("\"{}\"", arg.replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    }
+
+    /// Run `reg.exe`
+    fn reg(args: &[&str]) -> Result<()> {
+        let output = Command::new("reg")
+            .args(args)
+            .output()
+            .context("Unable to run reg.exe")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "reg.exe failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /**
+     * Registers the command in the Run key
+     * @return Where it was registered
+     */
+    pub fn enable(command: &[String]) -> Result<String> {
+        let command_line = command
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        reg(&[
+            "add",
+            RUN_KEY,
+            "/v",
+            VALUE_NAME,
+            "/t",
+            "REG_SZ",
+            "/d",
+            &command_line,
+            "/f",
+        ])?;
+        Ok(format!(r"{RUN_KEY}\{VALUE_NAME}"))
+    }
+
+    /**
+     * Removes the command from the Run key
+     * @return Where it was registered
+     */
+    pub fn disable() -> Result<String> {
+        reg(&["delete", RUN_KEY, "/v", VALUE_NAME, "/f"])?;
+        Ok(format!(r"{RUN_KEY}\{VALUE_NAME}"))
+    }
+}
+
+/// Registration as a LaunchAgent
+#[cfg(target_os = "macos")]
+mod imp {
+    use anyhow::{Context as _, Result};
+    use std::{fs, path::PathBuf};
+
+    use remoteplay_inviter::platform;
+
+    /// Label of the LaunchAgent
+    const LABEL: &str = "com.kamesuta.remoteplay-inviter";
+
+    /// Get the path of the LaunchAgent property list
+    fn plist_path() -> Result<PathBuf> {
+        Ok(platform::home_dir()?
+            .join("Library/LaunchAgents")
+            .join(format!("{LABEL}.plist")))
+    }
+
+    /// Escape text for XML
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    /**
+     * Writes the LaunchAgent property list
+     * @return Where it was registered
+     */
+    pub fn enable(command: &[String]) -> Result<String> {
+        let path = plist_path()?;
+        let arguments = command
+            .iter()
+            .map(|arg| format!("        <string>{}</string>", escape(arg)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#
+        );
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Unable to create directory: {:?}", dir))?;
+        }
+        fs::write(&path, plist)
+            .with_context(|| format!("Unable to write LaunchAgent: {:?}", &path))?;
+        Ok(path.display().to_string())
+    }
+
+    /**
+     * Removes the LaunchAgent property list
+     * @return Where it was registered
+     */
+    pub fn disable() -> Result<String> {
+        let path = plist_path()?;
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Unable to remove LaunchAgent: {:?}", &path))?;
+        }
+        Ok(path.display().to_string())
+    }
+}
+
+/// Registration as an XDG autostart entry
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use anyhow::{Context as _, Result};
+    use std::{fs, path::PathBuf};
+
+    use remoteplay_inviter::platform;
+
+    /// Get the path of the autostart entry
+    fn desktop_entry_path() -> Result<PathBuf> {
+        Ok(platform::xdg_config_home()?
+            .join("autostart")
+            .join("remoteplay-inviter.desktop"))
+    }
+
+    /// Quote an argument of the `Exec` key (`%` starts a field code)
+    fn quote(arg: &str) -> String {
+        let escaped = arg
+            .replace('\\', "\\\\\\\\")
+            .replace('"', "\\\\\"")
+            .replace('`', "\\\\`")
+            .replace('$', "\\\\$")
+            .replace('%', "%%");
+        format!("\"{escaped}\"")
+    }
+
+    /**
+     * Writes the autostart entry
+     * @return Where it was registered
+     */
+    pub fn enable(command: &[String]) -> Result<String> {
+        let path = desktop_entry_path()?;
+        let exec = command
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let entry = indoc::formatdoc! {"
+            [Desktop Entry]
+            Type=Application
+            Name=Remote Play Inviter
+            Comment=Creates Steam Remote Play Together invite links on request
+            Exec={exec}
+            Terminal=false
+            X-GNOME-Autostart-enabled=true
+        "};
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Unable to create directory: {:?}", dir))?;
+        }
+        fs::write(&path, entry)
+            .with_context(|| format!("Unable to write autostart entry: {:?}", &path))?;
+        Ok(path.display().to_string())
+    }
+
+    /**
+     * Removes the autostart entry
+     * @return Where it was registered
+     */
+    pub fn disable() -> Result<String> {
+        let path = desktop_entry_path()?;
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Unable to remove autostart entry: {:?}", &path))?;
+        }
+        Ok(path.display().to_string())
+    }
+}
@@ -0,0 +1,364 @@
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use remoteplay_inviter::{
+    console::{OutputFormat, Verbosity},
+    logging::LogFormat,
+    platform,
+    schedule::Schedule,
+};
+use std::{
+    env,
+    io::{stdin, stdout, IsTerminal as _},
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+/// Exit codes of the client, shown at the end of `--help`
+const EXIT_CODES: &str = "\
+Exit codes:
+  0    The user quit, or the server ended the session
+  1    Unexpected error (e.g. another instance is running, the update failed)
+  2    Invalid command line arguments
+  3    Gave up reconnecting to the server (--max-retries)
+  4    The Steam client is not running or cannot be reached
+  5    Invalid configuration files or connection settings
+  6    The server refused the client (unknown UUID or token, outdated version)
+  7    The connection ended with an error the client cannot recover from
+  130  Interrupted (Ctrl+C pressed again while shutting down)";
+
+/// Command line arguments
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    disable_version_flag = true,
+    after_long_help = EXIT_CODES
+)]
+pub struct Cli {
+    /// Display the version of the program
+    #[arg(short = 'v', long, action = ArgAction::Version)]
+    pub version: Option<bool>,
+
+    /// Endpoint URL to connect to (overrides the endpoint config file)
+    #[arg(long, global = true, value_name = "URL")]
+    pub endpoint: Option<String>,
+
+    /// Path to the UUID config file (overrides REMOTEPLAY_INVITER_CONFIG)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Profile to use (a separate UUID, endpoint and settings, see `profile list`)
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "config")]
+    pub profile: Option<String>,
+
+    /// Keep waiting for the Steam client to start instead of exiting
+    #[arg(long, global = true)]
+    pub wait_for_steam: bool,
+
+    /// Interval in seconds between attempts to connect to the Steam client
+    #[arg(long, global = true, value_name = "SECONDS", default_value_t = 5)]
+    pub steam_retry_interval: u64,
+
+    /// Give up after this many consecutive reconnection attempts (overrides network.max_retries)
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub max_retries: Option<u32>,
+
+    /// Connect to the IPv4 addresses of the server first (for broken IPv6 paths)
+    #[arg(long, global = true, conflicts_with = "prefer_ipv6")]
+    pub prefer_ipv4: bool,
+
+    /// Connect to the IPv6 addresses of the server first
+    #[arg(long, global = true)]
+    pub prefer_ipv6: bool,
+
+    /// Maximum number of guests in the session (overrides max_guests in the config file)
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub max_guests: Option<usize>,
+
+    /// Minutes after which the session ends and the invites are revoked, with a warning 5 minutes before
+    /// (overrides session_timeout_minutes in the config file)
+    #[arg(long, global = true, value_name = "MINUTES")]
+    pub session_timeout: Option<u64>,
+
+    /// Address to expose Prometheus metrics on (e.g. `127.0.0.1:9898`)
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Loopback address to serve the web dashboard on (e.g. `127.0.0.1:9899`)
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub dashboard_addr: Option<SocketAddr>,
+
+    /// Accept JSON-RPC requests on a local socket (named pipe on Windows) to control the client
+    #[arg(long, global = true)]
+    pub control: bool,
+
+    /// Stop the instance already running with the same config file and replace it
+    #[arg(long, global = true)]
+    pub takeover: bool,
+
+    /// When the server reports the client is outdated, download the new release,
+    /// check its signature, replace this binary and restart
+    #[arg(long, global = true)]
+    pub self_update: bool,
+
+    /// Notify systemd of readiness and feed its watchdog (for `Type=notify` units)
+    #[arg(long, global = true)]
+    pub systemd: bool,
+
+    /// Run unattended: no banner, no prompt before exiting, no redrawn status line
+    /// (implied when stdin is not a terminal)
+    #[arg(long, global = true)]
+    pub no_interactive: bool,
+
+    /// Steam Deck / Big Picture friendly console: larger, simpler output and single-button prompts
+    /// (implied in the Steam Deck Gaming Mode)
+    #[arg(long, global = true)]
+    pub deck: bool,
+
+    /// Open a window with the status, a button creating invite links and the guest list
+    #[cfg(feature = "gui")]
+    #[arg(long, global = true)]
+    pub gui: bool,
+
+    /// Full-screen interface with connection, guest, invite link and log panes
+    /// (ignored when the console is not attended or in the deck mode)
+    #[arg(long, global = true)]
+    pub tui: bool,
+
+    /// Print plain ASCII instead of the status glyphs and box-drawing characters
+    /// (implied when the terminal cannot display them)
+    #[arg(long, global = true)]
+    pub ascii: bool,
+
+    /// Print without colors (implied when NO_COLOR is set or stdout is not a terminal)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Print only the errors (for services)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also print the messages exchanged with the server: their command (-V),
+    /// or their content with the secrets masked (-VV)
+    #[arg(short = 'V', long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Start the console lines with the time and the level (or `console_timestamps` in the UUID config file)
+    #[arg(long, global = true)]
+    pub timestamps: bool,
+
+    /// Language of the console messages (e.g. `en`, `ja`; defaults to the system language)
+    #[arg(long, global = true, value_name = "LANG")]
+    pub lang: Option<String>,
+
+    /// Format of the console output (`json` prints one event per line for wrappers)
+    #[arg(long, global = true, value_enum, default_value_t = OutputArg::Text)]
+    pub output: OutputArg,
+
+    /// Format of the log file (the filter is controlled by RUST_LOG)
+    #[arg(long, global = true, value_enum, default_value_t = LogFormatArg::Text)]
+    pub log_format: LogFormatArg,
+
+    /// Directory to write the log files to (defaults to `logs` next to the config file)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Subcommand to run (defaults to `run`)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Whether someone is watching the console
+    pub fn interactive(&self) -> bool {
+        !self.no_interactive && stdin().is_terminal()
+    }
+
+    /// Whether the full-screen interface can be shown (someone is watching a terminal)
+    pub fn tui(&self) -> bool {
+        self.tui && self.interactive() && stdout().is_terminal()
+    }
+
+    /// Whether the console is shown in the deck mode
+    pub fn deck(&self) -> bool {
+        self.deck || platform::gaming_mode()
+    }
+
+    /// Whether the console prints plain ASCII
+    pub fn ascii(&self) -> bool {
+        self.ascii || !platform::unicode_console()
+    }
+
+    /// How much the console prints
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    /// Whether the console lines are colored (<https://no-color.org/>)
+    pub fn color(&self) -> bool {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        !self.no_color && !no_color && stdout().is_terminal()
+    }
+}
+
+/// Subcommands
+#[derive(Subcommand)]
+pub enum Command {
+    /// Connect to the server and handle invite requests (default)
+    Run,
+    /// Display the configuration files and their contents
+    Config,
+    /// Display the status of the Steam client and the endpoint
+    Status,
+    /// Check whether everything required to connect is available
+    Doctor {
+        /// Save the results, the environment, the config files (secrets masked) and the recent logs
+        /// to a zip file to attach to a bug report (defaults to a timestamped file in the current directory)
+        #[arg(long, value_name = "FILE")]
+        export: Option<Option<PathBuf>>,
+    },
+    /// List past play sessions from the history file
+    History {
+        /// Number of most recent sessions to display
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Manage the background service (Windows service or systemd user unit)
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Start the client at login
+    Autostart {
+        #[command(subcommand)]
+        action: AutostartAction,
+    },
+    /// Manage the profiles (to link one machine to several servers or accounts)
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Revoke the UUID on the server and generate a new one (unlinks the client from Discord)
+    Unlink,
+    /// Revoke the UUID and link the client to Discord again with a new one
+    Relink,
+    /// Create a single invite link and exit
+    Invite {
+        /// App ID of the game to invite to (defaults to the running game)
+        #[arg(long, value_name = "APPID")]
+        game: Option<u32>,
+        /// Print the link as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Restore the version replaced by the last self-update
+    Rollback,
+    /// Show or change whether anonymous usage counters are sent to the server
+    Telemetry {
+        #[command(subcommand)]
+        action: TelemetryAction,
+    },
+    /// Stay connected and post an invite link through the server at a set time (e.g. for game nights)
+    Schedule {
+        /// When to post the invite: a day and a time for every week (`fri 20:00`) or a time for every day (`20:00`)
+        #[arg(value_name = "WHEN")]
+        when: Schedule,
+        /// App ID of the game to invite to (defaults to the running game)
+        #[arg(long, value_name = "APPID")]
+        game: Option<u32>,
+    },
+}
+
+/// Service actions
+#[derive(Subcommand)]
+pub enum ServiceAction {
+    /// Install the client as a Windows service starting with the machine (requires administrator)
+    Install,
+    /// Stop and remove the Windows service (requires administrator)
+    Uninstall,
+    /// Run as the Windows service (started by the service manager)
+    Run,
+    /// Write a systemd user unit starting the client with the user session
+    GenerateUnit {
+        /// Path of the unit file (defaults to `~/.config/systemd/user/remoteplay-inviter.service`)
+        #[arg(long, value_name = "FILE")]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Telemetry actions
+#[derive(Subcommand)]
+pub enum TelemetryAction {
+    /// Show whether telemetry is enabled and preview the report sent next
+    Status,
+    /// Send anonymous usage counters (version, OS, sessions, reconnects) once a day
+    Enable,
+    /// Stop sending usage counters and discard the ones not sent yet
+    Disable,
+}
+
+/// Autostart actions
+#[derive(Subcommand)]
+pub enum AutostartAction {
+    /// Start the client unattended at login with the current config file and endpoint
+    Enable,
+    /// Stop starting the client at login
+    Disable,
+}
+
+/// Profile actions
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    /// List the profiles
+    List,
+    /// Create a profile with a new UUID (and the endpoint given with `--endpoint`)
+    Create {
+        /// Name of the profile
+        name: String,
+    },
+    /// Delete a profile with its UUID and settings
+    Delete {
+        /// Name of the profile
+        name: String,
+    },
+}
+
+/// Console output format
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputArg {
+    /// Human-readable text
+    Text,
+    /// One JSON event per line
+    Json,
+}
+
+impl From<OutputArg> for OutputFormat {
+    fn from(value: OutputArg) -> Self {
+        match value {
+            OutputArg::Text => OutputFormat::Text,
+            OutputArg::Json => OutputFormat::Json,
+        }
+    }
+}
+
+/// Log file format
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormatArg {
+    /// Human-readable text
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl From<LogFormatArg> for LogFormat {
+    fn from(value: LogFormatArg) -> Self {
+        match value {
+            LogFormatArg::Text => LogFormat::Text,
+            LogFormatArg::Json => LogFormat::Json,
+        }
+    }
+}
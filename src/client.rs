@@ -0,0 +1,987 @@
+use anyhow::{Context as _, Result};
+use futures::SinkExt;
+use futures_util::stream::StreamExt;
+use std::{collections::VecDeque, path::PathBuf, sync::Arc};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc::Receiver, Notify},
+    time::{self, error::Elapsed, timeout, Duration, Instant},
+};
+use tokio_tungstenite::{
+    client_async_with_config,
+    tungstenite::{
+        client::IntoClientRequest as _,
+        http::{
+            header::{AUTHORIZATION, SEC_WEBSOCKET_EXTENSIONS},
+            uri::Builder,
+            HeaderValue, Uri,
+        },
+        protocol::{frame::coding::CloseCode, CloseFrame, Message},
+        Error as WsError,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    console::{self, ConsoleCmd},
+    control::ControlRequest,
+    deflate::{self, DeflateStream},
+    dns::DnsOptions,
+    events::{self, Event},
+    handlers::Handler,
+    http::Network,
+    i18n::t,
+    metrics,
+    models::{Capability, ClientCmd, ClientMessage, SigningKey, WireFormat},
+    outbox::{self, Outbox},
+    proxy::Proxy,
+    retry::{RetryPolicy, RetrySec},
+    update::Release,
+    ws_error_handler::handle_ws_error,
+    VERSION,
+};
+
+/// WebSocket connection to the server
+type WsStream = WebSocketStream<DeflateStream<MaybeTlsStream<TcpStream>>>;
+
+/// How the client loop ended
+pub enum ClientExit {
+    /// The server requested to exit
+    Break,
+    /// The server refused the connection (e.g. the client is outdated)
+    Refused,
+    /// The user requested to quit, or the client was shut down
+    Quit,
+    /// The maximum number of reconnection attempts was reached
+    RetriesExhausted,
+    /// The server refused the outdated client and offered a release to install (with `self_update`)
+    Outdated(Release),
+}
+
+/// Options for the connection to the server
+pub struct ClientOptions {
+    /// Proxy to connect through
+    pub proxy: Option<Proxy>,
+    /// Time to wait for the connection to be established
+    pub connect_timeout: Duration,
+    /// Time without any message from the server before the connection is considered lost
+    pub read_timeout: Duration,
+    /// Reconnection policy
+    pub retry: RetryPolicy,
+    /// Interval between heartbeat pings sent to the server (None: disabled)
+    pub heartbeat_interval: Option<Duration>,
+    /// Number of unanswered heartbeat pings before the connection is considered lost
+    pub max_missed_pongs: u32,
+    /// Token sent in the `Authorization: Bearer` header of the handshake (None: the token is in the URL)
+    pub bearer_token: Option<String>,
+    /// TLS configuration for wss:// (None: the built-in root certificates)
+    pub tls: Option<Arc<rustls::ClientConfig>>,
+    /// Consecutive connection failures before switching to the next endpoint
+    pub failover_after: u32,
+    /// How the host is resolved (not used through a proxy, which resolves it itself)
+    pub dns: DnsOptions,
+    /// Notified when the machine resumes from sleep or the network changes (None: not watched)
+    pub network_change: Option<Arc<Notify>>,
+    /// Offer permessage-deflate compression to the server
+    pub compression: bool,
+    /// Offer MessagePack instead of JSON to the server
+    pub msgpack: bool,
+    /// Maximum number of messages kept while the connection is down
+    pub outbox_size: usize,
+    /// File the unsent messages are saved to, to send them after a restart (None: memory only)
+    pub outbox_spill: Option<PathBuf>,
+    /// Install the release offered by the server when the client is outdated
+    pub self_update: bool,
+}
+
+impl ClientOptions {
+    /// How the connections to the server are made (with the certificate pins of the server)
+    fn network(&self) -> Network {
+        Network {
+            proxy: self.proxy.clone(),
+            dns: self.dns.clone(),
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            retry: RetryPolicy::default(),
+            heartbeat_interval: Some(Duration::from_secs(15)),
+            max_missed_pongs: 2,
+            bearer_token: None,
+            tls: None,
+            failover_after: 3,
+            dns: DnsOptions::default(),
+            network_change: None,
+            compression: false,
+            msgpack: false,
+            outbox_size: outbox::DEFAULT_CAPACITY,
+            outbox_spill: None,
+            self_update: false,
+        }
+    }
+}
+
+/// Commands driving the client besides the server messages
+pub struct Commands {
+    /// Commands entered on the console
+    pub console: Receiver<ConsoleCmd>,
+    /// Requests from the control API
+    pub control: Receiver<ControlRequest>,
+    /// Invites due on the schedule (the game to invite to, None: the running game)
+    pub schedule: Receiver<Option<u32>>,
+}
+
+/// A command from the console, the control API or the schedule
+enum Command {
+    Console(ConsoleCmd),
+    Control(ControlRequest),
+    Scheduled(Option<u32>),
+}
+
+impl Commands {
+    /// Receives the next command from any source
+    async fn recv(&mut self) -> Option<Command> {
+        tokio::select! {
+            Some(cmd) = self.console.recv() => Some(Command::Console(cmd)),
+            Some(req) = self.control.recv() => Some(Command::Control(req)),
+            Some(game) = self.schedule.recv() => Some(Command::Scheduled(game)),
+            else => None,
+        }
+    }
+}
+
+/**
+ * Processes a command from the console, the control API or the schedule
+ * @return Whether to exit (true: exit)
+ */
+async fn handle_command(handler: &mut Handler, command: Command) -> Result<bool> {
+    match command {
+        Command::Console(cmd) => handler.handle_console_command(cmd).await,
+        Command::Control(req) => Ok(handler.handle_control_request(req).await),
+        Command::Scheduled(game) => handler.post_invite(game).await.map(|()| false),
+    }
+}
+
+/// A server to connect to
+#[derive(Clone)]
+pub struct Endpoint {
+    /// Endpoint URL as configured (displayed and remembered)
+    pub name: String,
+    /// WebSocket URL to connect to
+    pub url: String,
+    /// Configured endpoint URL the server redirected the client from (None: `name` is configured)
+    pub redirected_from: Option<String>,
+}
+
+impl Endpoint {
+    /// Remember the endpoint as the last one that worked
+    fn remember(&self) -> Result<()> {
+        match &self.redirected_from {
+            Some(from) => config::write_redirected_endpoint(&self.name, from),
+            None => config::write_last_endpoint(&self.name),
+        }
+    }
+}
+
+/// State of a connection attempt
+struct Attempt {
+    /// Whether the connection was lost before
+    reconnect: bool,
+    /// Set once the WebSocket connection is established
+    connected: bool,
+    /// Endpoint to remember as the last one that worked once connected
+    remember: Option<Endpoint>,
+}
+
+/// Result of a single connection
+enum ResultConfig {
+    Success,
+    NetworkChanged,
+    Break,
+    Refused,
+    Quit,
+    /// The client is outdated, install the release
+    Outdated(Release),
+    /// The server went down for maintenance, reconnect after the delay
+    Maintenance(Duration),
+    /// The server asked to reconnect to another URL
+    Redirect(String),
+}
+
+/// Percent-encodes a value of the URL query (everything but the unreserved characters)
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Builds the WebSocket URL for the endpoint (without the token if it is sent in a header)
+pub fn build_url(endpoint_url: &str, token: Option<&str>) -> Result<String> {
+    // Session ID
+    let session_id: u32 = rand::random();
+
+    // Create the URL
+    let uri: Uri = endpoint_url.parse().context("Failed to parse URL")?;
+    let token = token
+        .map(|token| format!("&token={}", encode_query(token)))
+        .unwrap_or_default();
+    let uri = Builder::from(uri)
+        .path_and_query(format!("/ws?v={VERSION}{token}&session={session_id}"))
+        .build()
+        .context("Failed to build URL")?;
+    Ok(uri.to_string())
+}
+
+/// Scheme and authority of a URL, to compare the origins of the redirects with
+fn origin(uri: &Uri) -> Option<(String, String)> {
+    Some((
+        uri.scheme_str()?.to_ascii_lowercase(),
+        uri.authority()?.as_str().to_ascii_lowercase(),
+    ))
+}
+
+/**
+ * Builds the URL to connect to the server the client was redirected to (with the query of `url`)
+ * @return An error if the target is neither on the origin of `url` nor on one of the `configured` endpoints,
+ *         so that the token in the query is only sent to the trusted servers
+ */
+fn redirect_url(url: &str, target: &str, configured: &[String]) -> Result<String> {
+    let url: Uri = url.parse().context("Failed to parse URL")?;
+    let target: Uri = target
+        .parse()
+        .with_context(|| format!("Invalid redirect URL: {target}"))?;
+    let target_origin =
+        origin(&target).with_context(|| format!("Invalid redirect URL: {target}"))?;
+    let trusted = origin(&url).as_ref() == Some(&target_origin)
+        || configured.iter().any(|endpoint| {
+            endpoint
+                .parse::<Uri>()
+                .ok()
+                .and_then(|endpoint| origin(&endpoint))
+                .as_ref()
+                == Some(&target_origin)
+        });
+    if !trusted {
+        anyhow::bail!("The server redirected to an unknown host: {target}");
+    }
+    let path_and_query = url.path_and_query().map_or("/", |p| p.as_str());
+    let uri = Builder::from(target)
+        .path_and_query(path_and_query)
+        .build()
+        .context("Failed to build URL")?;
+    Ok(uri.to_string())
+}
+
+/// Connects to the server and processes messages, reconnecting whenever the connection is lost
+///
+/// After `options.failover_after` consecutive failures, the next endpoint is tried.
+/// Cancelling `shutdown` closes the connection gracefully and returns [`ClientExit::Quit`].
+#[tracing::instrument(name = "client", skip_all)]
+pub async fn run_client(
+    endpoints: &[Endpoint],
+    options: &ClientOptions,
+    handler: &mut Handler,
+    commands: &mut Commands,
+    shutdown: &CancellationToken,
+) -> Result<ClientExit> {
+    // The servers the client is redirected to replace the endpoints they were redirected from
+    let mut endpoints = endpoints.to_vec();
+    // The servers can only redirect to themselves or to the configured endpoints
+    let configured: Vec<String> = endpoints
+        .iter()
+        .map(|endpoint| {
+            endpoint
+                .redirected_from
+                .as_ref()
+                .unwrap_or(&endpoint.name)
+                .clone()
+        })
+        .collect();
+    let mut attempt = Attempt {
+        reconnect: false,
+        connected: false,
+        remember: None,
+    };
+    // Retry seconds
+    let mut retry_sec = RetrySec::with_policy(options.retry.clone());
+    // Active endpoint and its consecutive failures
+    let mut current = 0;
+    let mut failures = 0;
+    let mut last_working = None;
+    // Delay announced by the server before a maintenance, and whether it may still be going on
+    let mut maintenance = None;
+    let mut in_maintenance = false;
+
+    // Messages that could not be sent are kept until the next connection
+    let outbox = Outbox::new(options.outbox_size, options.outbox_spill.clone());
+    if !outbox.is_empty() {
+        console::println!("↪ {}", t!("outbox-restored", messages = outbox.len()));
+    }
+    handler.set_outbox(outbox);
+
+    loop {
+        let endpoint = endpoints
+            .get(current)
+            .context("No endpoint to connect to")?;
+        attempt.connected = false;
+        attempt.remember =
+            (endpoints.len() > 1 && last_working != Some(current)).then(|| endpoint.clone());
+        // Redirects and network changes back off from the delay before the connection,
+        // so that a server redirecting in a loop isn't reconnected to immediately
+        let retry_before = retry_sec.clone();
+        let result = connect(
+            &endpoint.url,
+            options,
+            &mut attempt,
+            handler,
+            commands,
+            shutdown,
+            &mut retry_sec,
+        )
+        .await;
+        match result {
+            Ok(ResultConfig::Break) => return Ok(ClientExit::Break),
+            Ok(ResultConfig::Refused) => return Ok(ClientExit::Refused),
+            Ok(ResultConfig::Outdated(release)) => return Ok(ClientExit::Outdated(release)),
+            Ok(ResultConfig::Quit) => return Ok(ClientExit::Quit),
+            Ok(ResultConfig::Success) => (),
+            Ok(ResultConfig::NetworkChanged) => retry_sec = retry_before,
+            Ok(ResultConfig::Maintenance(delay)) => maintenance = Some(delay),
+            Ok(ResultConfig::Redirect(target)) => {
+                let endpoint = &mut endpoints[current];
+                match redirect_url(&endpoint.url, &target, &configured) {
+                    Ok(url) => {
+                        let from = match endpoint.redirected_from.take() {
+                            Some(from) => from,
+                            None => std::mem::take(&mut endpoint.name),
+                        };
+                        *endpoint = Endpoint {
+                            name: target,
+                            url,
+                            redirected_from: Some(from),
+                        };
+                        if let Err(err) = endpoint.remember() {
+                            tracing::warn!("{err:#}");
+                        }
+                    }
+                    Err(err) => console::eprintln!("☓ {:#}", err),
+                }
+                retry_sec = retry_before;
+            }
+            // The server is expected to be unreachable until the maintenance is over
+            Err(err) if in_maintenance && !attempt.connected => {
+                tracing::debug!("The server is still under maintenance: {err:#}");
+            }
+            Err(err) => console::eprintln!("☓ {:#}", err),
+        }
+
+        // Move on to the next endpoint if this one keeps failing
+        if attempt.connected {
+            failures = 0;
+            last_working = Some(current);
+            in_maintenance = maintenance.is_some();
+        } else {
+            failures += 1;
+            if endpoints.len() > 1 && failures >= options.failover_after {
+                current = (current + 1) % endpoints.len();
+                failures = 0;
+                console::println!(
+                    "↪ {}",
+                    t!("endpoint-switching", name = endpoints[current].name)
+                );
+            }
+        }
+
+        // Reconnect to the server if the connection is lost (at the time given for a maintenance)
+        let scheduled = maintenance.take();
+        let delay = match scheduled {
+            Some(delay) => {
+                retry_sec.reset();
+                Some(delay)
+            }
+            None => retry_sec.next(),
+        };
+        let Some(delay) = delay else {
+            console::eprintln!(
+                "☓ {}",
+                t!("reconnect-gave-up", attempts = retry_sec.attempts())
+            );
+            return Ok(ClientExit::RetriesExhausted);
+        };
+        tracing::debug!(
+            delay_ms = delay.as_millis() as u64,
+            attempt = retry_sec.attempts(),
+            "Scheduling reconnection"
+        );
+        // The time of a scheduled reconnection was displayed with the maintenance notice
+        match (scheduled, in_maintenance) {
+            (Some(_), _) => (),
+            (None, true) => {
+                console::println!(
+                    "↪ {}",
+                    t!(
+                        "maintenance-retrying",
+                        seconds = format!("{:.1}", delay.as_secs_f64())
+                    )
+                );
+                console::set_waiting(delay);
+            }
+            (None, false) => events::emit(Event::Reconnecting {
+                delay_ms: delay.as_millis() as u64,
+            }),
+        }
+        let sleep = time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                // The server may be reachable again
+                _ = network_changed(&options.network_change) => break,
+                _ = shutdown.cancelled() => return Ok(ClientExit::Quit),
+                Some(cmd) = commands.recv() => {
+                    // Process the command while waiting
+                    match handle_command(handler, cmd).await {
+                        Ok(true) => return Ok(ClientExit::Quit),
+                        Ok(false) => (),
+                        Err(err) => console::eprintln!("☓ {}", err),
+                    }
+                }
+            }
+        }
+        attempt.reconnect = true;
+        metrics::RECONNECT_ATTEMPTS.inc();
+    }
+}
+
+/// Connects to the server once and processes messages until the connection is lost
+#[tracing::instrument(name = "connection", skip_all, fields(reconnect = attempt.reconnect))]
+async fn connect(
+    url: &str,
+    options: &ClientOptions,
+    attempt: &mut Attempt,
+    handler: &mut Handler,
+    commands: &mut Commands,
+    shutdown: &CancellationToken,
+    retry_sec: &mut RetrySec,
+) -> Result<ResultConfig> {
+    let reconnect = attempt.reconnect;
+
+    // Display the reconnection message (a spinner on the live status line)
+    if reconnect && !console::has_live_status() {
+        console::println!("↪ {}", t!("reconnecting"));
+    }
+    console::set_connecting();
+
+    // Resume the previous session if the server issued a token
+    let mut url = match handler.session_token() {
+        Some(token) if reconnect => format!("{url}&resume={}", encode_query(token)),
+        _ => url.to_string(),
+    };
+    // Offer MessagePack, the server switches to it by sending binary messages
+    if options.msgpack {
+        url.push_str("&format=msgpack");
+    }
+    handler.reset_connection();
+
+    // Create a WebSocket client
+    tracing::debug!(reconnect, "Connecting to the server");
+    let connect_result = tokio::select! {
+        result = timeout(options.connect_timeout, open_websocket(&url, options)) => result,
+        _ = shutdown.cancelled() => return Ok(ResultConfig::Quit),
+    }
+    .context("Connection timed out to the server")??;
+    let ws_stream = match connect_result {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            // If OK is returned, the error was displayed; exit without retrying
+            return Ok(match handle_ws_error(err, options.self_update)? {
+                Some(release) => ResultConfig::Outdated(release),
+                None => ResultConfig::Refused,
+            });
+        }
+    };
+
+    // Stream and sink for communicating with the server
+    let (mut write, mut read) = ws_stream.split();
+    attempt.connected = true;
+    if let Some(endpoint) = attempt.remember.take() {
+        if let Err(err) = endpoint.remember() {
+            tracing::warn!("{err:#}");
+        }
+    }
+
+    if reconnect {
+        handler.record_reconnect().await;
+    }
+    events::emit(Event::Connected {
+        reconnected: reconnect,
+    });
+
+    // Announce the supported features, the server replies with its own
+    let mut capabilities = vec![Capability::Resume, Capability::Pairing, Capability::Events];
+    if options.msgpack {
+        capabilities.push(Capability::Msgpack);
+    }
+    if metrics::COMPRESSION_ACTIVE.get() == 1 {
+        capabilities.push(Capability::Compression);
+    }
+    let hello = ClientMessage {
+        id: Uuid::new_v4().to_string(),
+        cmd: handler.hello(capabilities),
+        key: None,
+    };
+    write
+        .send(handler.encode(&hello)?)
+        .await
+        .context("Failed to send message to the server")?;
+    metrics::MESSAGES_SENT.inc();
+
+    // Send the messages that could not be sent before the connection was lost
+    handler.flush_outbox(&mut write).await?;
+
+    // Heartbeat pings to detect a stalled server
+    let mut heartbeat = Heartbeat::new(options);
+
+    // Loop to process messages received from the server and console commands
+    loop {
+        let message = tokio::select! {
+            message = timeout(options.read_timeout, read.next()) => message,
+            _ = heartbeat.tick() => {
+                heartbeat.ping(&mut write).await?;
+                continue;
+            }
+            _ = shutdown.cancelled() => {
+                close_connection(&mut write, &mut read).await?;
+                return Ok(ResultConfig::Quit);
+            }
+            _ = network_changed(&options.network_change) => {
+                // The connection is most likely dead, don't wait for the read timeout
+                console::println!("↪ {}", t!("reestablishing"));
+                return Ok(ResultConfig::NetworkChanged);
+            }
+            Some(cmd) = handler.recv_event() => {
+                if !handler.server_accepts(&cmd) {
+                    tracing::debug!(?cmd, "Event not supported by the server");
+                    continue;
+                }
+
+                // Notify the server of the event
+                tracing::debug!(?cmd, "Sending event to the server");
+                let res = ClientMessage {
+                    id: Uuid::new_v4().to_string(),
+                    cmd,
+                    key: None,
+                };
+                handler.send(res, &mut write).await?;
+                continue;
+            }
+            Some(cmd) = commands.recv() => {
+                // Process the command
+                match handle_command(handler, cmd).await {
+                    Ok(true) => {
+                        close_connection(&mut write, &mut read).await?;
+                        return Ok(ResultConfig::Quit);
+                    }
+                    Ok(false) => (),
+                    Err(err) => console::eprintln!("☓ {}", err),
+                }
+                // Send the replies to the requests answered on the console
+                handler.flush_outbox(&mut write).await?;
+                continue;
+            }
+        };
+
+        // Process each message
+        let message = match process_frame(message, &mut write, &mut heartbeat).await? {
+            Frame::Closed => break,
+            Frame::Alive => {
+                if let Some(latency) = heartbeat.latency.take() {
+                    handler.set_latency(latency);
+                }
+                // Reset the retry seconds
+                retry_sec.reset();
+                continue;
+            }
+            Frame::Ignored => continue,
+            Frame::Message(message) => message,
+        };
+
+        // Messages received while another one is handled wait for their turn
+        let mut backlog = VecDeque::from([message]);
+        while let Some(message) = backlog.pop_front() {
+            // Parse the JSON or MessagePack data
+            let wire_format = match message {
+                Message::Binary(_) => WireFormat::MessagePack,
+                _ => WireFormat::Json,
+            };
+            let Some(msg) = handler.decode(&message.into_data(), wire_format)? else {
+                continue;
+            };
+            // Keep answering pings while the message is handled (Steam calls can be slow)
+            let (exit, closed) = {
+                let handling = handler.handle_server_message(msg);
+                tokio::pin!(handling);
+                let mut closed = false;
+                let exit = loop {
+                    let message = tokio::select! {
+                        exit = &mut handling => break exit?,
+                        message = timeout(options.read_timeout, read.next()), if !closed => message,
+                        _ = heartbeat.tick(), if !closed => {
+                            heartbeat.ping(&mut write).await?;
+                            continue;
+                        }
+                        _ = shutdown.cancelled() => {
+                            close_connection(&mut write, &mut read).await?;
+                            return Ok(ResultConfig::Quit);
+                        }
+                    };
+                    match process_frame(message, &mut write, &mut heartbeat).await? {
+                        // Finish handling the message, its reply is sent after reconnecting
+                        Frame::Closed => closed = true,
+                        Frame::Alive => retry_sec.reset(),
+                        Frame::Ignored => (),
+                        Frame::Message(message) => backlog.push_back(message),
+                    }
+                };
+                (exit, closed)
+            };
+            if let Some(latency) = heartbeat.latency.take() {
+                handler.set_latency(latency);
+            }
+
+            if exit {
+                // If the exit flag is set, break the loop and exit
+                handler.flush_outbox(&mut write).await.ok();
+                return Ok(ResultConfig::Break);
+            }
+            if let Some(delay) = handler.take_maintenance() {
+                // Leave before the server drops the connection
+                handler.flush_outbox(&mut write).await.ok();
+                write.close().await.ok();
+                return Ok(ResultConfig::Maintenance(delay));
+            }
+            if let Some(url) = handler.take_redirect() {
+                handler.flush_outbox(&mut write).await.ok();
+                write.close().await.ok();
+                return Ok(ResultConfig::Redirect(url));
+            }
+            if closed {
+                return Ok(ResultConfig::Success);
+            }
+            // Send the reply
+            handler.flush_outbox(&mut write).await?;
+
+            // Reset the retry seconds
+            retry_sec.reset();
+        }
+    }
+
+    Ok(ResultConfig::Success)
+}
+
+/// Connects to the server only to tell it that the token in `url` is revoked
+pub async fn send_revocation(
+    url: &str,
+    options: &ClientOptions,
+    signing_key: Option<&SigningKey>,
+) -> Result<()> {
+    let ws_stream = match timeout(options.connect_timeout, open_websocket(url, options))
+        .await
+        .context("Connection timed out to the server")??
+    {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            handle_ws_error(err, false)?;
+            anyhow::bail!("The server refused the connection");
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    let message = ClientMessage {
+        id: Uuid::new_v4().to_string(),
+        cmd: ClientCmd::Unlink,
+        key: None,
+    };
+    let mut message = serde_json::to_string(&message)
+        .context("Failed to serialize JSON message for the server")?;
+    if let Some(key) = signing_key {
+        message = serde_json::to_string(&key.sign(message))
+            .context("Failed to serialize JSON message for the server")?;
+    }
+    write
+        .send(Message::Text(message))
+        .await
+        .context("Failed to send message to the server")?;
+    metrics::MESSAGES_SENT.inc();
+
+    close_connection(&mut write, &mut read).await
+}
+
+/// Heartbeat pings sent to detect a stalled server
+struct Heartbeat {
+    interval: Option<time::Interval>,
+    /// Sequence number and send time of the unanswered ping
+    pending_ping: Option<(u64, Instant)>,
+    ping_seq: u64,
+    missed_pongs: u32,
+    max_missed_pongs: u32,
+    /// Latest round-trip time, not yet passed to the handler
+    latency: Option<Duration>,
+}
+
+impl Heartbeat {
+    fn new(options: &ClientOptions) -> Self {
+        Self {
+            interval: options
+                .heartbeat_interval
+                .map(|period| time::interval_at(Instant::now() + period, period)),
+            pending_ping: None,
+            ping_seq: 0,
+            missed_pongs: 0,
+            max_missed_pongs: options.max_missed_pongs,
+            latency: None,
+        }
+    }
+
+    /// Waits for the next heartbeat tick (never completes if the heartbeat is disabled)
+    async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Sends a Ping message, failing if too many of them were not answered
+    async fn ping(
+        &mut self,
+        write: &mut (impl SinkExt<Message, Error = WsError> + Unpin),
+    ) -> Result<()> {
+        // The previous ping was not answered
+        if self.pending_ping.is_some() {
+            self.missed_pongs += 1;
+            if self.missed_pongs >= self.max_missed_pongs {
+                anyhow::bail!("Connection timed out (no response to heartbeat)");
+            }
+        }
+
+        self.ping_seq += 1;
+        write
+            .send(Message::Ping(self.ping_seq.to_be_bytes().to_vec()))
+            .await
+            .context("Failed to send ping message to the server")?;
+        self.pending_ping = Some((self.ping_seq, Instant::now()));
+        Ok(())
+    }
+
+    /// Measures the round-trip time of the heartbeat
+    fn pong(&mut self, pong: &[u8]) {
+        if let Some((seq, sent_at)) = self.pending_ping {
+            if pong == seq.to_be_bytes() {
+                let latency = sent_at.elapsed();
+                tracing::debug!(latency_ms = latency.as_millis() as u64, "Heartbeat");
+                self.latency = Some(latency);
+                self.pending_ping = None;
+                self.missed_pongs = 0;
+            }
+        }
+    }
+}
+
+/// A frame received from the server
+enum Frame {
+    /// A ping or a pong (the server is alive)
+    Alive,
+    /// A message to handle
+    Message(Message),
+    /// The connection was closed
+    Closed,
+    /// Nothing to do
+    Ignored,
+}
+
+/// Answers pings and records pongs from the server
+async fn process_frame(
+    message: Result<Option<Result<Message, WsError>>, Elapsed>,
+    write: &mut (impl SinkExt<Message, Error = WsError> + Unpin),
+    heartbeat: &mut Heartbeat,
+) -> Result<Frame> {
+    let Some(message) = message.context("Connection timed out")? else {
+        return Ok(Frame::Closed);
+    };
+    match message.context("Failed to receive message from the server")? {
+        Message::Close(_) => Ok(Frame::Closed),
+        Message::Ping(ping) => {
+            // Send a Pong message
+            write
+                .send(Message::Pong(ping))
+                .await
+                .context("Failed to send pong message to the server")?;
+            Ok(Frame::Alive)
+        }
+        Message::Pong(pong) => {
+            heartbeat.pong(&pong);
+            Ok(Frame::Alive)
+        }
+        message @ (Message::Text(_) | Message::Binary(_)) => Ok(Frame::Message(message)),
+        _ => Ok(Frame::Ignored),
+    }
+}
+
+/// Waits for a network change (forever if not watched)
+async fn network_changed(network_change: &Option<Arc<Notify>>) {
+    match network_change {
+        Some(notify) => notify.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+/**
+ * Opens the TCP connection (through the proxy if configured) and performs the WebSocket handshake
+ * @return The handshake result, or an error if the TCP connection could not be established
+ */
+async fn open_websocket(url: &str, options: &ClientOptions) -> Result<Result<WsStream, WsError>> {
+    // Get the host and port to connect to
+    let uri: Uri = url.parse().context("Failed to parse URL")?;
+    let host = uri
+        .host()
+        .context("Missing host in URL")?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("wss") => 443,
+        _ => 80,
+    });
+
+    // Connect to the server
+    let network = options.network();
+    let stream = network.connect(host, port).await?;
+    stream
+        .set_nodelay(true)
+        .context("Failed to configure the connection")?;
+
+    // Keep the token out of the URL (and the logs of proxies and servers) if possible
+    let mut request = url
+        .into_client_request()
+        .context("Failed to build the handshake request")?;
+    if let Some(token) = &options.bearer_token {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .context("Invalid characters in the token")?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+    if options.compression {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(deflate::EXTENSION_OFFER),
+        );
+    }
+
+    // Perform the TLS handshake for wss://
+    let stream = if uri.scheme_str() == Some("wss") {
+        let stream = network
+            .tls_connect(host, stream)
+            .await
+            .context("TLS handshake with the server failed")?;
+        MaybeTlsStream::Rustls(stream)
+    } else {
+        MaybeTlsStream::Plain(stream)
+    };
+
+    // Perform the WebSocket handshake (the library doesn't compress, the stream does)
+    let stream = DeflateStream::new(stream, options.compression);
+    Ok(client_async_with_config(request, stream, None)
+        .await
+        .map(|(ws_stream, _)| ws_stream))
+}
+
+/// Flushes pending messages, sends a Close frame and waits briefly for the server to acknowledge it
+async fn close_connection(
+    write: &mut (impl SinkExt<Message, Error = WsError> + Unpin),
+    read: &mut (impl StreamExt<Item = Result<Message, WsError>> + Unpin),
+) -> Result<()> {
+    console::println!("↪ {}", t!("disconnecting"));
+    write
+        .send(Message::Close(Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "Client shutdown".into(),
+        })))
+        .await
+        .context("Failed to send close message to the server")?;
+
+    // Wait for the server to acknowledge the close
+    let _ = timeout(Duration::from_secs(3), async {
+        while let Some(Ok(message)) = read.next().await {
+            if let Message::Close(_) = message {
+                break;
+            }
+        }
+    })
+    .await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "wss://a.example.com/ws?v=1&token=secret&session=1";
+
+    fn configured() -> Vec<String> {
+        vec!["wss://b.example.com".to_string()]
+    }
+
+    #[test]
+    fn encodes_the_tokens_in_the_url() {
+        assert_eq!(encode_query("abc-123_~."), "abc-123_~.");
+        assert_eq!(encode_query("a&b=c d+é"), "a%26b%3Dc%20d%2B%C3%A9");
+
+        let url = build_url("wss://a.example.com", Some("x&session=0#")).unwrap();
+        assert!(url.starts_with("wss://a.example.com/ws?v="));
+        assert!(url.contains("&token=x%26session%3D0%23&session="));
+    }
+
+    #[test]
+    fn redirects_to_the_same_origin() {
+        let url = redirect_url(URL, "wss://a.example.com", &configured()).unwrap();
+        assert_eq!(url, URL);
+    }
+
+    #[test]
+    fn redirects_to_the_configured_endpoints() {
+        let url = redirect_url(URL, "wss://B.example.com", &configured()).unwrap();
+        assert_eq!(url, "wss://B.example.com/ws?v=1&token=secret&session=1");
+    }
+
+    #[test]
+    fn refuses_to_redirect_to_other_hosts() {
+        for target in [
+            "wss://evil.example.com",
+            "ws://a.example.com",
+            "wss://a.example.com:8443",
+            "/ws",
+        ] {
+            assert!(
+                redirect_url(URL, target, &configured()).is_err(),
+                "{target}"
+            );
+        }
+    }
+}
@@ -0,0 +1,855 @@
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use std::{
+    collections::HashMap,
+    env, fs,
+    future::Future,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+use steam_stuff::GameUID;
+use tokio::time::{self, Duration};
+use tokio_rustls::{rustls::pki_types::ServerName, TlsConnector};
+use tokio_tungstenite::{
+    client_async_with_config,
+    tungstenite::{
+        client::IntoClientRequest as _,
+        http::{header::DATE, Uri},
+        Error as WsError,
+    },
+    MaybeTlsStream,
+};
+use uuid::Uuid;
+
+use remoteplay_inviter::{
+    client::{build_url, send_revocation, ClientOptions},
+    config, console, control,
+    diagnostics::{self, Bundle},
+    dns,
+    handlers::Handler,
+    history::{self, HistoryEvent},
+    i18n::t,
+    instance, library, logging,
+    models::{ConnectionErrorMessage, ConnectionErrorType, SigningKey, SIGNATURE_MAX_SKEW},
+    platform,
+    proxy::Proxy,
+    steam::{self, Steam},
+    store, telemetry, tls, update, VERSION,
+};
+
+use crate::{
+    apply_limits, authenticate,
+    cli::{Cli, TelemetryAction},
+    client_options, endpoint_url, endpoint_urls, new_config, shutdown_on_ctrl_c,
+};
+
+/// Maximum time to wait for the Steam client to create an invite link
+const INVITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum time for each step of the connection to an endpoint in the doctor checks
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Environment variables included in the diagnostics bundle (the URLs are masked)
+const ENVIRONMENT_VARIABLES: [&str; 8] = [
+    "STEAM_DIR",
+    config::CONFIG_PATH_ENV,
+    "RUST_LOG",
+    "HTTPS_PROXY",
+    "ALL_PROXY",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SESSION_TYPE",
+    "APPIMAGE",
+];
+
+/// Mask a secret so that only the first few characters are visible
+fn mask(secret: &str) -> String {
+    let visible: String = secret.chars().take(8).collect();
+    format!("{visible}…")
+}
+
+/// Display the configuration files and their contents
+pub fn config() -> Result<()> {
+    // Endpoint configuration
+    let endpoint_path = config::endpoint_config_path()?;
+    console::println!(
+        "□ {}",
+        t!("config-endpoint-file", path = endpoint_path.display())
+    );
+    match config::read_endpoint_config()? {
+        Some(endpoint) => {
+            let url = endpoint.url.unwrap_or_else(|| t!("config-default"));
+            let seconds = |seconds: u64| t!("config-seconds", seconds = seconds);
+            console::println!("  url             : {url}");
+            console::println!("  bearer_auth     : {}", endpoint.bearer_auth);
+            let network = &endpoint.network;
+            console::println!("  connect_timeout : {}", seconds(network.connect_timeout));
+            console::println!("  read_timeout    : {}", seconds(network.read_timeout));
+            console::println!("  backoff         : x{}", network.backoff_multiplier);
+            console::println!("  max_backoff     : {}", seconds(network.max_backoff));
+            match network.max_retries {
+                Some(max_retries) => console::println!("  max_retries     : {max_retries}"),
+                None => console::println!("  max_retries     : {}", t!("config-unlimited")),
+            }
+            console::println!("  jitter          : {}", network.jitter);
+            console::println!("  compression     : {}", network.compression);
+            console::println!("  msgpack         : {}", network.msgpack);
+            let outbox = if network.outbox_spill {
+                t!("config-outbox-spilled", messages = network.outbox_size)
+            } else {
+                t!("config-outbox", messages = network.outbox_size)
+            };
+            console::println!("  outbox          : {outbox}");
+        }
+        None => console::println!("  {}", t!("config-endpoint-not-found")),
+    }
+
+    // UUID configuration
+    let config_path = config::config_path()?;
+    console::println!("□ {}", t!("config-uuid-file", path = config_path.display()));
+    match config::read_config()? {
+        Some(config) => {
+            console::println!("  uuid            : {}", mask(&config.uuid));
+            if config.keychain {
+                console::println!("  {}", t!("config-keychain"));
+            }
+        }
+        None => console::println!("  {}", t!("config-uuid-not-found")),
+    }
+
+    Ok(())
+}
+
+/// Format a number of bytes
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Format the bytes before and after compression with the ratio
+fn format_ratio(uncompressed: u64, compressed: u64) -> String {
+    let ratio = if uncompressed > 0 {
+        compressed as f64 * 100.0 / uncompressed as f64
+    } else {
+        100.0
+    };
+    format!(
+        "{} -> {} ({ratio:.0}%)",
+        format_bytes(uncompressed),
+        format_bytes(compressed)
+    )
+}
+
+/// Display the status of the Steam client, the endpoint and the running client
+pub async fn status(cli: &Cli) -> Result<()> {
+    // Endpoint URLs
+    for endpoint_url in endpoint_urls(cli)? {
+        console::println!("□ {}", t!("status-endpoint-url", url = endpoint_url));
+    }
+
+    // Steam client
+    match steam::connect() {
+        Ok(steam) => {
+            console::println!("✓ {}", t!("status-steam-running"));
+
+            let game_id = steam.get_running_game_id();
+            if game_id.is_valid_app() {
+                let app_id = game_id.app_id;
+                let game_uid: GameUID = game_id.into();
+                let supported = steam.can_remote_play_together(game_uid);
+                console::println!(
+                    "✓ {}",
+                    t!(
+                        "status-running-game",
+                        game = store::game_label(app_id),
+                        supported = supported
+                    )
+                );
+            } else {
+                console::println!("□ {}", t!("status-no-game"));
+            }
+        }
+        Err(_) => console::println!("☓ {}", t!("status-steam-not-running")),
+    }
+
+    // Running client (only reachable with --control)
+    match time::timeout(Duration::from_secs(3), control::call("get_status")).await {
+        Ok(Ok(status)) => {
+            console::println!("✓ {}", t!("status-client-reachable"));
+            let compression = &status["compression"];
+            let count = |key: &str| compression[key].as_u64().unwrap_or_default();
+            if compression["active"].as_bool() == Some(true) {
+                console::println!(
+                    "✓ {}",
+                    t!(
+                        "status-compression",
+                        sent = format_ratio(count("sent_bytes"), count("sent_compressed_bytes")),
+                        received = format_ratio(
+                            count("received_bytes"),
+                            count("received_compressed_bytes")
+                        )
+                    )
+                );
+            } else {
+                console::println!("□ {}", t!("status-compression-unused"));
+            }
+        }
+        _ => console::println!("□ {}", t!("status-client-unreachable")),
+    }
+
+    Ok(())
+}
+
+/// Create a single invite link (for the running game if `game` is None) and print it
+pub async fn invite(cli: &Cli, game: Option<u32>, json: bool) -> Result<()> {
+    let steam = steam::connect().with_context(|| t!("steam-connect-failed"))?;
+    let mut handler = Handler::new(Steam::spawn(steam));
+    apply_limits(cli, &mut handler).await;
+    handler.setup_steam_callbacks().await;
+    handler.watch_steam();
+
+    let (guest_id, url) = time::timeout(INVITE_TIMEOUT, handler.invite(game))
+        .await
+        .context("Timed out waiting for the invite link from the Steam client")??;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "guest_id": guest_id, "url": url })
+        );
+    } else {
+        console::println!(
+            "-> {}",
+            t!("invite-created", guest_id = guest_id, url = url)
+        );
+        console::print_qr(&url)?;
+    }
+
+    Ok(())
+}
+
+/// Revoke the UUID on the server and replace it with a new one
+pub async fn unlink(cli: &Cli) -> Result<()> {
+    // Don't change the UUID under a running client
+    let _instance = instance::acquire(cli.takeover)?;
+
+    let mut config = config::read_config()?
+        .context("The UUID config file does not exist, so the client has never been linked")?;
+
+    console::println!("↪ {}", t!("unlink-notifying"));
+    let result = async {
+        let (mut options, _) = client_options(cli)?;
+        let url = authenticate(&endpoint_url(cli)?, &config.uuid, &mut options)?;
+        let signing_key = config
+            .signing_secret
+            .as_deref()
+            .map(SigningKey::from_base64)
+            .transpose()?;
+        send_revocation(&url, &options, signing_key.as_ref()).await
+    }
+    .await;
+    match result {
+        Ok(()) => console::println!("✓ {}", t!("unlink-notified")),
+        Err(err) => {
+            console::eprintln!(
+                "☓ {}",
+                t!("unlink-notify-failed", error = format!("{err:#}"))
+            );
+            console::eprintln!("  {}", t!("unlink-still-linked"));
+        }
+    }
+
+    config.uuid = Uuid::new_v4().to_string();
+    config::write_config(&config)?;
+    console::println!("✓ {}", t!("unlink-done"));
+    Ok(())
+}
+
+/// Revoke the UUID, then connect with a new one and guide the user through linking it again
+pub async fn relink(cli: &Cli) -> Result<()> {
+    unlink(cli).await?;
+
+    let steps: Vec<String> = t!("relink-steps")
+        .lines()
+        .map(|line| format!("  {line}"))
+        .collect();
+    console::printdoc! {"
+        □ {title}
+        {steps}
+
+    ", title = t!("relink-title"), steps = steps.join("\n")};
+    let result = crate::run(cli, shutdown_on_ctrl_c()).await;
+    logging::shutdown();
+    result
+}
+
+/// A guest's stay in a play session
+struct PlaySession {
+    joined: DateTime<Local>,
+    left: Option<DateTime<Local>>,
+    name: String,
+    steam_id: u64,
+    /// Game of the invite the guest joined with
+    game: Option<u32>,
+}
+
+/// List past play sessions from the history file
+pub fn history(limit: usize) -> Result<()> {
+    let entries = history::read()?;
+
+    // Pair the join and leave events of each guest
+    let mut sessions: Vec<PlaySession> = Vec::new();
+    let mut open: HashMap<u64, usize> = HashMap::new();
+    let mut invites = 0;
+    let mut invite_games: HashMap<u64, u32> = HashMap::new();
+    for entry in &entries {
+        match &entry.event {
+            HistoryEvent::InviteCreated { guest_id, game, .. } => {
+                invites += 1;
+                invite_games.insert(*guest_id, *game);
+            }
+            HistoryEvent::GuestJoined {
+                guest_id,
+                name,
+                steam_id,
+            } => {
+                open.insert(*guest_id, sessions.len());
+                sessions.push(PlaySession {
+                    joined: entry.time,
+                    left: None,
+                    name: name.clone(),
+                    steam_id: *steam_id,
+                    game: invite_games.get(guest_id).copied(),
+                });
+            }
+            HistoryEvent::GuestLeft { guest_id, .. } => {
+                if let Some(index) = open.remove(guest_id) {
+                    sessions[index].left = Some(entry.time);
+                }
+            }
+            HistoryEvent::SessionEnded => {
+                for (_, index) in open.drain() {
+                    sessions[index].left = Some(entry.time);
+                }
+            }
+            HistoryEvent::Unknown => (),
+        }
+    }
+
+    // Show the nicknames given since
+    let nicknames = config::read_config()
+        .ok()
+        .flatten()
+        .map(|config| config.nicknames)
+        .unwrap_or_default();
+    for session in &mut sessions {
+        if let Some(nickname) = nicknames.get(&session.steam_id.to_string()) {
+            session.name = nickname.clone();
+        }
+    }
+
+    console::println!(
+        "□ {}",
+        t!("history-file", path = history::history_path()?.display())
+    );
+    console::println!(
+        "□ {}",
+        t!(
+            "history-counts",
+            invites = invites,
+            sessions = sessions.len()
+        )
+    );
+    if sessions.is_empty() {
+        console::println!("★ {}", t!("history-empty"));
+        return Ok(());
+    }
+
+    // Display the most recent sessions
+    for session in sessions.iter().skip(sessions.len().saturating_sub(limit)) {
+        let (left, duration) = match session.left {
+            Some(left) => (
+                left.format("%H:%M").to_string(),
+                t!(
+                    "history-minutes",
+                    minutes = (left - session.joined).num_minutes()
+                ),
+            ),
+            None => ("...".to_string(), t!("history-playing")),
+        };
+        let game = session
+            .game
+            .map(|game| format!(", game_id={}", store::game_label(game)))
+            .unwrap_or_default();
+        console::println!(
+            "  {} - {left:<5} ({duration:>8}) {} (steam_id={}{game})",
+            session.joined.format("%Y-%m-%d %H:%M"),
+            session.name,
+            session.steam_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Restore the version replaced by the last self-update
+pub fn rollback() -> Result<()> {
+    let path = update::rollback()?;
+    console::println!("✓ {}", t!("rollback-done", path = path.display()));
+    console::println!("★ {}", t!("rollback-restart"));
+    Ok(())
+}
+
+/// Show, enable or disable the anonymous usage telemetry
+pub fn telemetry(cli: &Cli, action: &TelemetryAction) -> Result<()> {
+    let mut config = config::read_or_generate_config(new_config)?;
+    match action {
+        TelemetryAction::Status => {
+            if config.telemetry {
+                console::println!("✓ {}", t!("telemetry-is-enabled"));
+            } else {
+                console::println!("□ {}", t!("telemetry-is-disabled"));
+            }
+            let url = telemetry::report_url(&endpoint_url(cli)?)?;
+            let report = serde_json::to_string_pretty(&telemetry::preview())?;
+            console::println!("□ {}", t!("telemetry-report", url = url));
+            console::println!("{report}");
+        }
+        TelemetryAction::Enable => {
+            config.telemetry = true;
+            config::write_config(&config)?;
+            console::println!("✓ {}", t!("telemetry-enabled"));
+        }
+        TelemetryAction::Disable => {
+            config.telemetry = false;
+            config::write_config(&config)?;
+            telemetry::clear()?;
+            console::println!("✓ {}", t!("telemetry-disabled"));
+        }
+    }
+    Ok(())
+}
+
+/// Results of the doctor checks, displayed and kept for the diagnostics bundle
+#[derive(Default)]
+struct Checks {
+    report: Vec<String>,
+    failed: usize,
+}
+
+impl Checks {
+    /// Records a passed check
+    fn pass(&mut self, line: String) -> Result<()> {
+        console::println!("✓ {line}");
+        self.report.push(format!("✓ {line}"));
+        Ok(())
+    }
+
+    /// Records a failed check
+    fn fail(&mut self, line: String) -> Result<()> {
+        console::eprintln!("☓ {line}");
+        self.report.push(format!("☓ {line}"));
+        self.failed += 1;
+        Ok(())
+    }
+
+    /// Records the details of the last check
+    fn note(&mut self, line: &str) -> Result<()> {
+        console::eprintln!("  {line}");
+        self.report.push(format!("  {line}"));
+        Ok(())
+    }
+}
+
+/// Runs a step of the connection in the doctor checks, giving up after `STEP_TIMEOUT`
+async fn step<T>(future: impl Future<Output = Result<T>>) -> Result<T> {
+    time::timeout(STEP_TIMEOUT, future)
+        .await
+        .context("Timed out")?
+}
+
+/**
+ * Checks each step of the connection to an endpoint (DNS, TCP, TLS, WebSocket), stopping at the first failure
+ * @return The time of the server (Date header of the handshake response) if it answered
+ */
+async fn check_endpoint(
+    checks: &mut Checks,
+    url: &str,
+    options: &ClientOptions,
+) -> Result<Option<DateTime<FixedOffset>>> {
+    let Ok(uri) = url.parse::<Uri>() else {
+        return Ok(None);
+    };
+    let host = uri
+        .host()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let secure = uri.scheme_str() == Some("wss");
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+    // DNS resolution (the proxy resolves the host itself)
+    match &options.proxy {
+        Some(proxy) => checks.pass(t!("doctor-dns-proxy", host = host, proxy = proxy))?,
+        None if host.parse::<IpAddr>().is_ok() => checks.pass(t!("doctor-dns-ip", host = host))?,
+        None => match step(dns::resolve(host, port, &options.dns)).await {
+            Ok(addrs) => {
+                let ips: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+                checks.pass(t!("doctor-dns-ok", host = host, addresses = ips.join(", ")))?;
+            }
+            Err(err) => {
+                checks.fail(t!(
+                    "doctor-dns-failed",
+                    host = host,
+                    error = format!("{err:#}")
+                ))?;
+                checks.note(&t!("doctor-dns-hint"))?;
+                return Ok(None);
+            }
+        },
+    }
+
+    // TCP connection
+    let started = time::Instant::now();
+    let result = match &options.proxy {
+        Some(proxy) => step(proxy.connect(host, port)).await,
+        None => step(dns::connect(host, port, &options.dns)).await,
+    };
+    let stream = match result {
+        Ok(stream) => {
+            let address = format!("{host}:{port}");
+            let ms = started.elapsed().as_millis();
+            checks.pass(match &options.proxy {
+                Some(proxy) => t!(
+                    "doctor-tcp-ok-proxy",
+                    address = address,
+                    proxy = proxy,
+                    ms = ms
+                ),
+                None => t!("doctor-tcp-ok", address = address, ms = ms),
+            })?;
+            stream
+        }
+        Err(err) => {
+            checks.fail(t!(
+                "doctor-tcp-failed",
+                address = format!("{host}:{port}"),
+                error = format!("{err:#}")
+            ))?;
+            match &options.proxy {
+                Some(_) => checks.note(&t!("doctor-tcp-proxy-hint"))?,
+                None => checks.note(&t!("doctor-tcp-hint"))?,
+            }
+            return Ok(None);
+        }
+    };
+
+    // TLS handshake for wss://
+    let stream = if secure {
+        let started = time::Instant::now();
+        let result: Result<_> = step(async {
+            let config = match &options.tls {
+                Some(config) => config.clone(),
+                None => tls::builtin_config()?,
+            };
+            let server_name =
+                ServerName::try_from(host.to_string()).context("Invalid host in URL")?;
+            Ok(TlsConnector::from(config)
+                .connect(server_name, stream)
+                .await?)
+        })
+        .await;
+        match result {
+            Ok(stream) => {
+                checks.pass(t!(
+                    "doctor-tls-ok",
+                    host = host,
+                    ms = started.elapsed().as_millis()
+                ))?;
+                MaybeTlsStream::Rustls(stream)
+            }
+            Err(err) => {
+                checks.fail(t!(
+                    "doctor-tls-failed",
+                    host = host,
+                    error = format!("{err:#}")
+                ))?;
+                checks.note(&t!("doctor-tls-hint"))?;
+                return Ok(None);
+            }
+        }
+    } else {
+        MaybeTlsStream::Plain(stream)
+    };
+
+    // WebSocket handshake (without the UUID, so that a running client isn't disconnected)
+    let request = build_url(url, None)?
+        .into_client_request()
+        .context("Failed to build the handshake request")?;
+    let response =
+        match step(async { Ok(client_async_with_config(request, stream, None).await) }).await {
+            Ok(Ok((mut ws_stream, response))) => {
+                let _ = ws_stream.close(None).await;
+                checks.pass(t!("doctor-ws-ok", url = url))?;
+                response
+            }
+            // The server answered but refused the connection
+            Ok(Err(WsError::Http(response))) if response.status().is_client_error() => {
+                let error = response
+                    .headers()
+                    .get("X-Error")
+                    .and_then(|header| header.to_str().ok())
+                    .and_then(|text| serde_json::from_str::<ConnectionErrorMessage>(text).ok());
+                match error {
+                    Some(ConnectionErrorMessage {
+                        error:
+                            ConnectionErrorType::Outdated {
+                                required, download, ..
+                            },
+                        ..
+                    }) => {
+                        checks.fail(t!(
+                            "doctor-ws-outdated",
+                            required = required,
+                            version = VERSION
+                        ))?;
+                        checks.note(&t!("doctor-ws-outdated-hint", download = download))?;
+                    }
+                    Some(ConnectionErrorMessage { message, .. }) => checks.pass(t!(
+                        "doctor-ws-answered",
+                        url = url,
+                        status = message.unwrap_or_else(|| response.status().to_string())
+                    ))?,
+                    None => checks.pass(t!(
+                        "doctor-ws-answered",
+                        url = url,
+                        status = response.status()
+                    ))?,
+                }
+                response.map(|_| None)
+            }
+            Ok(Err(WsError::Http(response))) => {
+                checks.fail(t!(
+                    "doctor-ws-refused",
+                    url = url,
+                    status = response.status()
+                ))?;
+                checks.note(&t!("doctor-ws-down-hint"))?;
+                return Ok(None);
+            }
+            Ok(Err(err)) => {
+                checks.fail(t!("doctor-ws-failed", url = url, error = err))?;
+                checks.note(&t!("doctor-ws-failed-hint"))?;
+                return Ok(None);
+            }
+            Err(err) => {
+                checks.fail(t!(
+                    "doctor-ws-failed",
+                    url = url,
+                    error = format!("{err:#}")
+                ))?;
+                checks.note(&t!("doctor-ws-down-hint"))?;
+                return Ok(None);
+            }
+        };
+
+    Ok(response
+        .headers()
+        .get(DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok()))
+}
+
+/// Compares the local clock with the time of the server
+fn check_clock(checks: &mut Checks, server_time: Option<DateTime<FixedOffset>>) -> Result<()> {
+    let Some(server_time) = server_time else {
+        checks.pass(t!("doctor-clock-unchecked"))?;
+        return Ok(());
+    };
+    let skew = Utc::now().signed_duration_since(server_time).num_seconds();
+    // The Date header has a precision of a second, and the handshake takes some time
+    if skew.unsigned_abs() <= SIGNATURE_MAX_SKEW {
+        checks.pass(t!("doctor-clock-ok", skew = format!("{skew:+}")))?;
+    } else {
+        let key = if skew > 0 {
+            "doctor-clock-ahead"
+        } else {
+            "doctor-clock-behind"
+        };
+        checks.fail(t!(key, seconds = skew.unsigned_abs()))?;
+        checks.note(&t!("doctor-clock-hint"))?;
+    }
+    Ok(())
+}
+
+/// Checks the proxy settings (endpoint config file > HTTPS_PROXY / ALL_PROXY)
+fn check_proxy(checks: &mut Checks) -> Result<()> {
+    let configured = config::read_endpoint_config()
+        .ok()
+        .flatten()
+        .and_then(|e| e.proxy);
+    let (result, source) = match &configured {
+        Some(proxy) => (proxy.parse::<Proxy>().map(Some), t!("doctor-source-config")),
+        None => (Proxy::from_env(), t!("doctor-source-env")),
+    };
+    match result {
+        Ok(Some(proxy)) => checks.pass(t!("doctor-proxy", proxy = proxy, source = source))?,
+        Ok(None) => {
+            checks.pass(t!("doctor-proxy-none"))?;
+            if ["HTTP_PROXY", "http_proxy"]
+                .iter()
+                .any(|name| env::var_os(name).is_some_and(|value| !value.is_empty()))
+            {
+                checks.note(&t!("doctor-proxy-http-hint"))?;
+            }
+        }
+        Err(err) => {
+            checks.fail(t!(
+                "doctor-proxy-invalid",
+                source = source,
+                error = format!("{err:#}")
+            ))?;
+            checks.note(&t!("doctor-proxy-hint"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe the environment the client runs in (for the diagnostics bundle)
+fn environment(cli: &Cli) -> String {
+    let steam_package = match library::sandboxed_steam() {
+        Some(package) => format!("{package:?}"),
+        None => "native (or not installed)".to_string(),
+    };
+    let path = |path: Result<PathBuf>| match path {
+        Ok(path) => path.display().to_string(),
+        Err(err) => format!("unknown ({err:#})"),
+    };
+    let mut lines = vec![
+        format!("version         : {VERSION}"),
+        format!("platform        : {}", update::platform()),
+        format!("time            : {}", Local::now().to_rfc3339()),
+        format!("executable      : {}", path(config::get_exe_path())),
+        format!("config          : {}", path(config::config_path())),
+        format!("endpoint config : {}", path(config::endpoint_config_path())),
+        format!("steam package   : {steam_package}"),
+        format!("gaming mode     : {}", platform::gaming_mode()),
+        format!("interactive     : {}", cli.interactive()),
+    ];
+    for name in ENVIRONMENT_VARIABLES {
+        if let Ok(value) = env::var(name) {
+            lines.push(format!("{name} = {}", diagnostics::mask_url(&value)));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Gather the doctor results, the environment, the sanitized config files and the recent logs into a zip file
+fn export_diagnostics(cli: &Cli, checks: &Checks, path: &Path) -> Result<()> {
+    let mut bundle = Bundle::default();
+    // The UUID would link the client to Discord
+    if let Some(config) = config::read_config().ok().flatten() {
+        bundle.redact(&config.uuid);
+    }
+    bundle.add("doctor.txt", checks.report.join("\n") + "\n");
+    bundle.add("environment.txt", environment(cli));
+    for (name, config_path) in [
+        ("config.toml", config::config_path()),
+        ("endpoint.toml", config::endpoint_config_path()),
+    ] {
+        if let Some(content) = config_path.ok().and_then(|p| fs::read_to_string(p).ok()) {
+            bundle.add(name, diagnostics::sanitize_config(&content));
+        }
+    }
+    let log_dir = match &cli.log_dir {
+        Some(dir) => dir.clone(),
+        None => logging::default_log_dir()?,
+    };
+    for log in logging::log_files(&log_dir) {
+        let name = log.file_name().unwrap_or_default().to_string_lossy();
+        bundle.add_file(&format!("logs/{name}"), &log)?;
+    }
+    bundle.write(path)
+}
+
+/// Check each step required to connect, with hints to fix the failures, and export the results with `export`
+pub async fn doctor(cli: &Cli, export: Option<&Option<PathBuf>>) -> Result<()> {
+    let mut checks = Checks::default();
+
+    // Check the Steam client (IPC)
+    match steam::connect() {
+        Ok(_) => checks.pass(t!("doctor-steam-ok"))?,
+        Err(_) => {
+            checks.fail(t!("doctor-steam-failed"))?;
+            match library::sandboxed_steam() {
+                Some(package) => checks.note(&package.guidance())?,
+                None => checks.note(&t!("doctor-steam-hint"))?,
+            }
+        }
+    }
+
+    // Check the configuration files
+    let result: Result<()> = (|| {
+        config::read_endpoint_config()?;
+        config::read_config()?;
+        Ok(())
+    })();
+    match result {
+        Ok(()) => checks.pass(t!("doctor-config-ok"))?,
+        Err(err) => {
+            checks.fail(t!("doctor-config-failed", error = format!("{err:#}")))?;
+            checks.note(&t!("doctor-config-hint"))?;
+        }
+    }
+
+    // Check the endpoint URL
+    let result: Result<Uri> = (|| endpoint_url(cli)?.parse().context("Failed to parse URL"))();
+    match result {
+        Ok(uri) => checks.pass(t!("doctor-url-ok", url = uri))?,
+        Err(err) => {
+            checks.fail(t!("doctor-url-invalid", error = format!("{err:#}")))?;
+            checks.note(&t!("doctor-url-hint"))?;
+        }
+    }
+
+    // Check each step of the connection to the endpoints
+    match client_options(cli) {
+        Ok((options, _)) => {
+            let mut server_time = None;
+            for url in endpoint_urls(cli).unwrap_or_default() {
+                let time = check_endpoint(&mut checks, &url, &options).await?;
+                server_time = server_time.or(time);
+            }
+            check_clock(&mut checks, server_time)?;
+        }
+        Err(err) => {
+            checks.fail(t!("doctor-settings-invalid", error = format!("{err:#}")))?;
+            checks.note(&t!("doctor-settings-hint"))?;
+        }
+    }
+
+    // Check the proxy settings
+    check_proxy(&mut checks)?;
+
+    if checks.failed == 0 {
+        console::println!("✓ {}", t!("doctor-passed"));
+    } else {
+        console::eprintln!("☓ {}", t!("doctor-failed", count = checks.failed));
+    }
+
+    if let Some(path) = export {
+        let path = path.clone().unwrap_or_else(|| {
+            PathBuf::from(format!(
+                "remoteplay-inviter-diagnostics-{}.zip",
+                Local::now().format("%Y%m%d-%H%M%S")
+            ))
+        });
+        export_diagnostics(cli, &checks, &path)?;
+        console::println!("✓ {}", t!("doctor-exported", path = path.display()));
+        console::println!("★ {}", t!("doctor-attach"));
+    }
+
+    Ok(())
+}
@@ -1,22 +1,340 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     env, fs,
     path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
 };
 
+/// Environment variable to override the UUID config file path
+pub const CONFIG_PATH_ENV: &str = "REMOTEPLAY_INVITER_CONFIG";
+
+/// UUID config file path set from the command line
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Profile selected from the command line
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// UUID read from the OS credential store (read once, as it may ask the user to unlock it)
+static KEYCHAIN_UUID: Mutex<Option<String>> = Mutex::new(None);
+
+/// Service name of the UUID in the OS credential store
+const KEYCHAIN_SERVICE: &str = "remoteplay-inviter";
+
+/// File name of the UUID config file in a profile directory
+pub const PROFILE_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// File name of the endpoint config file in a profile directory
+pub const PROFILE_ENDPOINT_FILE_NAME: &str = "endpoint.toml";
+
 /// Endpoint configuration
 #[derive(Serialize, Deserialize)]
 pub struct EndpointConfig {
-    /// Endpoint URL to connect to
-    pub url: String,
+    /// Endpoint URL to connect to (None: the default endpoint URL)
+    pub url: Option<String>,
+    /// Endpoint URLs to fail over to, in order (after `url`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// Proxy to connect through (`http://host:port` or `socks5://host:port`)
+    /// Overrides the HTTPS_PROXY / ALL_PROXY environment variables
+    pub proxy: Option<String>,
+    /// Send the UUID in an `Authorization: Bearer` header instead of the URL (requires server support)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bearer_auth: bool,
+    /// Timeouts and retry policy (`[network]` section)
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Certificates trusted for the connection to the server (`[tls]` section)
+    #[serde(default)]
+    pub tls: TlsConfig,
 }
 
-/// UUID configuration
+/// TLS configuration
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM file of additional root certificates (e.g. the private CA of a self-hosted server)
+    pub ca_file: Option<PathBuf>,
+    /// Trust the root certificates built into the client
+    pub builtin_roots: bool,
+    /// Public keys the server certificate chain must contain (`sha256/<base64>` of the SubjectPublicKeyInfo)
+    /// Replaces the pins built into the client for the default endpoint; `[]` disables pinning.
+    pub pins: Option<Vec<String>>,
+    /// PEM file of the client certificate chain, for servers requiring mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// PEM file of the private key of the client certificate
+    pub client_key: Option<PathBuf>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            ca_file: None,
+            builtin_roots: true,
+            pins: None,
+            client_cert: None,
+            client_key: None,
+        }
+    }
+}
+
+/// Network configuration
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Seconds to wait for the connection to the server to be established
+    pub connect_timeout: u64,
+    /// Seconds without any message from the server before the connection is considered lost
+    pub read_timeout: u64,
+    /// Factor the reconnection delay is multiplied by after each attempt
+    pub backoff_multiplier: f64,
+    /// Maximum seconds to wait between reconnection attempts
+    pub max_backoff: u64,
+    /// Maximum number of consecutive reconnection attempts (None: retry forever)
+    pub max_retries: Option<u32>,
+    /// Random variation applied to the reconnection delay (0.0 - 1.0, 0.5 = ±50%)
+    pub jitter: f64,
+    /// Consecutive connection failures before switching to the next endpoint URL
+    pub failover_after: u32,
+    /// Reconnect immediately when the machine resumes from sleep or the network changes
+    pub detect_network_changes: bool,
+    /// Compress the messages (permessage-deflate) if the server supports it, to save bandwidth
+    pub compression: bool,
+    /// Exchange MessagePack instead of JSON if the server supports it
+    pub msgpack: bool,
+    /// Maximum number of messages to the server kept while the connection is down
+    pub outbox_size: usize,
+    /// Save the unsent messages to a file, to send them after a restart
+    pub outbox_spill: bool,
+    /// DNS server to resolve the endpoint host with: `1.1.1.1`, `1.1.1.1:53` or a DNS-over-HTTPS URL
+    /// such as `https://1.1.1.1/dns-query` (None: the system resolver)
+    pub resolver: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            read_timeout: 60,
+            backoff_multiplier: 2.0,
+            max_backoff: 120,
+            max_retries: None,
+            jitter: 0.2,
+            failover_after: 3,
+            detect_network_changes: true,
+            compression: false,
+            msgpack: false,
+            outbox_size: 64,
+            outbox_spill: false,
+            resolver: None,
+        }
+    }
+}
+
+/// UUID configuration
+#[derive(Default, Serialize, Deserialize)]
 pub struct Config {
-    /// UUID
+    /// UUID (not written to the file when kept in the OS credential store)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub uuid: String,
+    /// Keep the UUID in the OS credential store (Windows Credential Manager, macOS Keychain, Secret Service)
+    /// instead of this file. The UUID is moved on the next start.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keychain: bool,
+    /// Maximum number of guests in the session (None: unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_guests: Option<usize>,
+    /// Default maximum number of times an invite link can be used (None: unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_max_uses: Option<u32>,
+    /// Default seconds until an unused invite link expires (None: never)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_ttl: Option<u64>,
+    /// Secret shared with the server to sign the messages (base64, at least 16 bytes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
+    /// Send a summary of each play session to the server
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub report_session_stats: bool,
+    /// Start the console lines with the time and the level (like `--timestamps`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub console_timestamps: bool,
+    /// Show desktop notifications when a guest joins or leaves and when an invite link is created
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notifications: bool,
+    /// Copy the invite links requested by the server to the clipboard
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub copy_invite_to_clipboard: bool,
+    /// Open new invite links in the browser
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_open_invite: bool,
+    /// Start the requested game through Steam when it isn't running, before creating the invite
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_launch_game: bool,
+    /// Seconds to wait for a game started by `auto_launch_game` (None: 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_timeout: Option<u64>,
+    /// Milliseconds between polls of the Steam client callbacks (None: 200)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_interval_ms: Option<u64>,
+    /// Milliseconds between polls while no invite or guest is active, to save battery (None: always `callback_interval_ms`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_callback_interval_ms: Option<u64>,
+    /// Minutes after which the guests are kicked while no game is running for them (None: never)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_kick_minutes: Option<u64>,
+    /// Minutes after which the session ends and the invites are revoked (None: no limit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_timeout_minutes: Option<u64>,
+    /// Local nicknames of the recurring guests shown instead of their IDs (Steam ID -> nickname)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub nicknames: BTreeMap<String, String>,
+    /// Discord user IDs or Steam IDs whose invite requests are approved; the requests of the others
+    /// are asked on the console (empty: everyone is approved)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_users: Vec<String>,
+    /// Discord user IDs or Steam IDs whose invite requests are declined
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_users: Vec<String>,
+    /// App IDs of the only games that can be invited to (empty: any game)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_games: Vec<u32>,
+    /// App IDs of the games that can't be invited to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_games: Vec<u32>,
+    /// Maximum number of invite requests per minute from each Discord user (None: unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_rate_limit: Option<u32>,
+    /// Discord application ID to show the hosted session in the Discord profile with (None: no Rich Presence)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord_client_id: Option<String>,
+    /// Server commands to ignore (e.g. `message` to hide the announcements)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_commands: Vec<String>,
+    /// Commands to run on application events (`[hooks]` section)
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    pub hooks: HooksConfig,
+    /// Settings of the games overriding the ones above (`[games.<app_id>]` sections)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub games: BTreeMap<String, GameConfig>,
+    /// HTTP endpoints to post the session events to (`[[webhooks]]` sections)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Plugins started with the client (`[[plugins]]` sections, with the `plugins` feature)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginConfig>,
+    /// Sentry DSN to upload the crash reports to (None: they are only saved locally)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentry_dsn: Option<String>,
+    /// Post anonymous usage counters to the server once a day (see `telemetry status`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub telemetry: bool,
+}
+
+/// Settings of a game applied when it is invited to (e.g. fewer guests for a 2-player game)
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GameConfig {
+    /// Maximum number of guests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_guests: Option<usize>,
+    /// Approve every invite request (true), or ask on the console for the users not on the allow list (false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_accept: Option<bool>,
+    /// Maximum number of times an invite can be used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_max_uses: Option<u32>,
+    /// Seconds until an unused invite expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_ttl: Option<u64>,
+    /// Commands run instead of the general hooks while the game is invited to
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    pub hooks: HooksConfig,
+}
+
+/// HTTP endpoint the session events are posted to as JSON
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// URL to post to (`http://` or `https://`)
+    pub url: String,
+    /// Events to post: `invite_created`, `guest_joined`, `guest_left`, `reconnecting` and `steam_lost` (empty: all of them)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Payload format
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Maximum number of retries of a failed delivery
+    #[serde(default = "default_webhook_retries")]
+    pub max_retries: u32,
+}
+
+/// Plugin: a command speaking the JSON-RPC of the control API on its stdin and stdout
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Name shown on the console
+    pub name: String,
+    /// Command started through the shell
+    pub command: String,
+}
+
+/// Payload format of a webhook
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The event as printed by `--output json`, with the time it happened
+    #[default]
+    Json,
+    /// A Discord webhook message describing the event
+    Discord,
+}
+
+fn default_webhook_retries() -> u32 {
+    5
+}
+
+/// Commands run through the shell on application events, with the event data in `RPI_*` environment variables
+/// (e.g. `on_guest_join = "obs-cli scene switch Coop"`, the guest name is in `RPI_NAME`)
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Connected or reconnected to the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_connected: Option<String>,
+    /// The connection to the server was lost (`RPI_DELAY_MS`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_reconnecting: Option<String>,
+    /// An invite link was created (`RPI_GUEST_ID`, `RPI_URL`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_invite_created: Option<String>,
+    /// A guest joined the session (`RPI_GUEST_ID`, `RPI_NAME`, `RPI_GUESTS`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_guest_join: Option<String>,
+    /// A guest left the session (`RPI_GUEST_ID`, `RPI_NAME`, `RPI_GUESTS`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_guest_leave: Option<String>,
+    /// The Steam client stopped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_steam_lost: Option<String>,
+    /// The Steam client restarted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_steam_restored: Option<String>,
+}
+
+impl HooksConfig {
+    /// Whether no hook is configured
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.on_connected,
+            &self.on_reconnecting,
+            &self.on_invite_created,
+            &self.on_guest_join,
+            &self.on_guest_leave,
+            &self.on_steam_lost,
+            &self.on_steam_restored,
+        ]
+        .iter()
+        .all(|hook| hook.is_none())
+    }
 }
 
 /// Get the current executable path
@@ -38,10 +356,163 @@ pub fn get_exe_path() -> Result<PathBuf> {
     }
 }
 
+/// Get the endpoint configuration file path
+pub fn endpoint_config_path() -> Result<PathBuf> {
+    if let Some(profile) = PROFILE.get() {
+        return Ok(profile_dir(profile)?.join(PROFILE_ENDPOINT_FILE_NAME));
+    }
+    Ok(get_exe_path()?.with_extension("endpoint.toml"))
+}
+
+/// Select a profile: its UUID, endpoint and settings are read from its own directory
+pub fn set_profile(name: &str) -> Result<()> {
+    validate_profile_name(name)?;
+    let _ = PROFILE.set(name.to_string());
+    Ok(())
+}
+
+/// Get the selected profile
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().map(String::as_str)
+}
+
+/// Check that a profile name can be used as a directory name
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!(
+            "Invalid profile name: {:?} (use letters, digits, '-' and '_')",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Get the directory containing the profiles (`profiles` next to the default UUID config file)
+pub fn profiles_dir() -> Result<PathBuf> {
+    let default_path = match env::var_os(CONFIG_PATH_ENV) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => get_exe_path()?,
+    };
+    let dir = match default_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok(dir.join("profiles"))
+}
+
+/// Get the directory of a profile
+pub fn profile_dir(name: &str) -> Result<PathBuf> {
+    validate_profile_name(name)?;
+    Ok(profiles_dir()?.join(name))
+}
+
+/**
+ * Lists the profiles
+ * @return The profile names in alphabetical order
+ */
+pub fn list_profiles() -> Result<Vec<String>> {
+    let dir = profiles_dir()?;
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut profiles = vec![];
+    for entry in
+        fs::read_dir(&dir).with_context(|| format!("Unable to read directory: {:?}", &dir))?
+    {
+        let entry = entry.with_context(|| format!("Unable to read directory: {:?}", &dir))?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if validate_profile_name(name).is_ok() {
+                profiles.push(name.to_string());
+            }
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+/// Override the UUID configuration file path (e.g. from `--config`)
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// Get the UUID configuration file path
+/// (`set_config_path` > `set_profile` > `REMOTEPLAY_INVITER_CONFIG` > next to the executable)
+pub fn config_path() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Ok(path.clone());
+    }
+    if let Some(profile) = PROFILE.get() {
+        return Ok(profile_dir(profile)?.join(PROFILE_CONFIG_FILE_NAME));
+    }
+    match env::var_os(CONFIG_PATH_ENV) {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Ok(get_exe_path()?.with_extension("config.toml")),
+    }
+}
+
+/// Get the configuration directory (the directory containing the UUID config file)
+pub fn config_dir() -> Result<PathBuf> {
+    let config_path = config_path()?;
+    match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Ok(parent.to_path_buf()),
+        _ => Ok(PathBuf::from(".")),
+    }
+}
+
+/// Get the file remembering the last endpoint URL that worked
+fn last_endpoint_path() -> Result<PathBuf> {
+    Ok(config_path()?.with_extension("last-endpoint"))
+}
+
+/// Get the file the messages not sent to the server are saved to
+pub fn outbox_path() -> Result<PathBuf> {
+    Ok(config_path()?.with_extension("outbox"))
+}
+
+/// Last endpoint URL that worked
+pub struct LastEndpoint {
+    pub url: String,
+    /// Configured endpoint URL the server redirected the client from (None: `url` is configured)
+    pub redirected_from: Option<String>,
+}
+
+/// Read the last endpoint URL that worked
+pub fn read_last_endpoint() -> Option<LastEndpoint> {
+    let content = fs::read_to_string(last_endpoint_path().ok()?).ok()?;
+    // The URL, followed by the one it was redirected from on the next line
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    Some(LastEndpoint {
+        url: lines.next()?.to_string(),
+        redirected_from: lines.next().map(String::from),
+    })
+}
+
+/// Remember the last endpoint URL that worked
+pub fn write_last_endpoint(url: &str) -> Result<()> {
+    let path = last_endpoint_path()?;
+    fs::write(&path, url).with_context(|| format!("Unable to write file: {:?}", &path))
+}
+
+/// Remember the endpoint URL the server redirected the client to from a configured one
+pub fn write_redirected_endpoint(url: &str, from: &str) -> Result<()> {
+    let path = last_endpoint_path()?;
+    fs::write(&path, format!("{url}\n{from}\n"))
+        .with_context(|| format!("Unable to write file: {:?}", &path))
+}
+
 /// Read the endpoint configuration
 pub fn read_endpoint_config() -> Result<Option<EndpointConfig>> {
-    let exe_path = get_exe_path()?;
-    let config_path = exe_path.with_extension("endpoint.toml");
+    let config_path = endpoint_config_path()?;
 
     if config_path.exists() {
         let config_content = fs::read_to_string(&config_path)
@@ -54,22 +525,120 @@ pub fn read_endpoint_config() -> Result<Option<EndpointConfig>> {
     }
 }
 
-/// Read or generate the UUID configuration
-pub fn read_or_generate_config<F: Fn() -> Config>(generate_config: F) -> Result<Config> {
-    let exe_path = get_exe_path()?;
-    let config_path = exe_path.with_extension("config.toml");
+/// Get the entry of the UUID in the OS credential store (one per config file)
+fn keychain_entry() -> Result<keyring::Entry> {
+    let config_path =
+        std::path::absolute(config_path()?).context("Unable to resolve the config file path")?;
+    keyring::Entry::new(KEYCHAIN_SERVICE, &config_path.to_string_lossy())
+        .context("Unable to access the OS credential store")
+}
+
+/// Read the UUID from the OS credential store
+fn read_keychain_uuid() -> Result<String> {
+    let mut cached = KEYCHAIN_UUID.lock().unwrap();
+    if let Some(uuid) = cached.as_ref() {
+        return Ok(uuid.clone());
+    }
+    let uuid = keychain_entry()?
+        .get_password()
+        .context("Unable to read the UUID from the OS credential store")?;
+    *cached = Some(uuid.clone());
+    Ok(uuid)
+}
+
+/// Read the UUID configuration (without generating it)
+/// The UUID is read from the OS credential store when the file doesn't contain it.
+pub fn read_config() -> Result<Option<Config>> {
+    let config_path = config_path()?;
 
     if config_path.exists() {
         let config_content = fs::read_to_string(&config_path)
             .with_context(|| format!("Unable to read UUID config file: {:?}", &config_path))?;
-        let config: Config =
+        let mut config: Config =
             toml::from_str(&config_content).context("Unable to parse UUID config file")?;
+        if config.uuid.is_empty() {
+            config.uuid =
+                read_keychain_uuid().context("The UUID config file does not contain a UUID")?;
+        }
+        Ok(Some(config))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Move the UUID between the file and the OS credential store according to `keychain` (done once at startup)
+pub fn migrate_keychain() -> Result<()> {
+    let config_path = config_path()?;
+    if !config_path.exists() {
+        return Ok(());
+    }
+    let config_content = fs::read_to_string(&config_path)
+        .with_context(|| format!("Unable to read UUID config file: {:?}", &config_path))?;
+    let config: Config =
+        toml::from_str(&config_content).context("Unable to parse UUID config file")?;
+
+    match (config.keychain, config.uuid.is_empty()) {
+        // Move the UUID from the file to the credential store
+        (true, false) => {
+            write_config(&config)?;
+            tracing::info!("Moved the UUID to the OS credential store");
+        }
+        // Move the UUID back from the credential store to the file
+        (false, true) => {
+            let config = Config {
+                uuid: read_keychain_uuid()
+                    .context("The UUID config file does not contain a UUID")?,
+                ..config
+            };
+            write_config(&config)?;
+            if let Err(err) = keychain_entry().and_then(|entry| {
+                entry
+                    .delete_credential()
+                    .context("Unable to remove the UUID from the OS credential store")
+            }) {
+                tracing::warn!("{err:#}");
+            }
+            tracing::info!("Moved the UUID back to the config file");
+        }
+        (true, true) | (false, false) => (),
+    }
+    Ok(())
+}
+
+/// Write the UUID configuration
+/// With `keychain`, the UUID is stored in the OS credential store instead of the file.
+pub fn write_config(config: &Config) -> Result<()> {
+    let config_path = config_path()?;
+
+    let config_content = if config.keychain {
+        keychain_entry()?
+            .set_password(&config.uuid)
+            .context("Unable to store the UUID in the OS credential store")?;
+        *KEYCHAIN_UUID.lock().unwrap() = Some(config.uuid.clone());
+        let mut file_config =
+            toml::Table::try_from(config).context("Unable to serialize config")?;
+        file_config.remove("uuid");
+        toml::to_string(&file_config)
+    } else {
+        toml::to_string(config)
+    }
+    .context("Unable to serialize config")?;
+
+    if let Some(parent) = config_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Unable to create config directory: {:?}", parent))?;
+    }
+    fs::write(&config_path, config_content)
+        .with_context(|| format!("Unable to write config file: {:?}", &config_path))
+}
+
+/// Read or generate the UUID configuration
+pub fn read_or_generate_config<F: Fn() -> Config>(generate_config: F) -> Result<Config> {
+    if let Some(config) = read_config()? {
         Ok(config)
     } else {
         let config = generate_config();
-        let config_content = toml::to_string(&config).context("Unable to serialize config")?;
-        fs::write(&config_path, config_content)
-            .with_context(|| format!("Unable to write config file: {:?}", &config_path))?;
+        write_config(&config)?;
         Ok(config)
     }
 }
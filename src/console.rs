@@ -1,13 +1,435 @@
 use anyhow::{Context as _, Result};
-use crossterm::{cursor, terminal, QueueableCommand};
-use std::io::{stdout, Write};
-use std::sync::{LazyLock, Mutex};
+use crossterm::{
+    cursor,
+    style::{Color, Stylize as _},
+    terminal, QueueableCommand,
+};
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use std::borrow::Cow;
+use std::io::{stdin, stdout, IsTerminal as _, Write};
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    LazyLock, Mutex, OnceLock,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::{
+    broadcast::error::RecvError,
+    mpsc::{channel, Receiver},
+};
+
+use crate::{
+    diagnostics,
+    events::{self, Event},
+    i18n::t,
+};
 
 /// Last line
 static LAST_LINE: LazyLock<Mutex<String>> = LazyLock::new(|| Mutex::new("".to_string()));
 
+/// Output format of the console
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Output format of the console
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON event per line (see `events::Event`)
+    Json,
+}
+
+/// How much the console prints
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// How much the console prints
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the errors
+    Quiet,
+    /// The errors and the status lines
+    #[default]
+    Normal,
+    /// Also the command of each message exchanged with the server
+    Verbose,
+    /// Also the content of each message exchanged with the server (secrets masked)
+    Trace,
+}
+
+/// Sets how much the console prints (must be called before anything is printed)
+pub fn set_verbosity(verbosity: Verbosity) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+/// Gets how much the console prints
+pub fn verbosity() -> Verbosity {
+    VERBOSITY.get().copied().unwrap_or_default()
+}
+
+/// Whether the console is attended (the current line is redrawn in place)
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Stops redrawing the current line in place (for logs collected by systemd or Docker)
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+/// Whether the console is shown in the deck mode (Steam Deck / Big Picture)
+static DECK: AtomicBool = AtomicBool::new(false);
+
+/// Shows larger, simpler output and takes single-button input (read from a couch with a controller)
+pub fn set_deck(deck: bool) {
+    DECK.store(deck, Ordering::Relaxed);
+}
+
+/// Whether the console is shown in the deck mode
+pub fn is_deck() -> bool {
+    is_text() && DECK.load(Ordering::Relaxed)
+}
+
+/// Whether the console is replaced by the full-screen interface (see `tui`)
+static TUI: AtomicBool = AtomicBool::new(false);
+
+/// Stops printing the console lines, which are shown in the log pane of the full-screen interface
+pub fn set_tui(tui: bool) {
+    TUI.store(tui, Ordering::Relaxed);
+}
+
+/// Whether the console is replaced by the full-screen interface
+pub fn is_tui() -> bool {
+    TUI.load(Ordering::Relaxed)
+}
+
+/// Whether the console lines are printed to the terminal (as text, outside the full-screen interface)
+pub fn prints_lines() -> bool {
+    is_text() && !is_tui()
+}
+
+/// Whether the status lines are printed to the terminal (not only the errors)
+pub fn prints_info() -> bool {
+    prints_lines() && verbosity() > Verbosity::Quiet
+}
+
+/// Whether the console prints plain ASCII (for legacy consoles and code pages)
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// ASCII replacements of the status glyphs and box-drawing characters
+const ASCII_REPLACEMENTS: [(char, &str); 14] = [
+    ('✓', "[+]"),
+    ('☓', "[x]"),
+    ('↪', "[>]"),
+    ('□', "[-]"),
+    ('★', "[*]"),
+    ('↑', "[^]"),
+    ('…', "..."),
+    ('═', "="),
+    ('─', "-"),
+    ('│', "|"),
+    ('┌', "+"),
+    ('┐', "+"),
+    ('└', "+"),
+    ('┘', "+"),
+];
+
+/// Prints plain ASCII instead of the status glyphs and box-drawing characters
+pub fn set_ascii(ascii: bool) {
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+/// Whether the console prints plain ASCII
+pub fn is_ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+/// Replaces the glyphs of a line with ASCII in the ASCII mode (the logs keep the original text)
+pub fn plain(text: &str) -> Cow<'_, str> {
+    if !is_ascii() || text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let mut plain = String::with_capacity(text.len());
+    for c in text.chars() {
+        match ASCII_REPLACEMENTS.iter().find(|(glyph, _)| *glyph == c) {
+            Some((_, replacement)) => plain.push_str(replacement),
+            None => plain.push(c),
+        }
+    }
+    Cow::Owned(plain)
+}
+
+/// Whether the console lines are colored
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Colors of the console lines, by their status glyph
+const THEME: [(&str, Color); 5] = [
+    ("✓", Color::Green),    // Success
+    ("☓", Color::Red),      // Error
+    ("↪", Color::Yellow),   // Retrying or waiting
+    ("★", Color::Cyan),     // Highlight
+    ("->", Color::Magenta), // Action taken
+];
+
+/// Colors the console lines according to their status glyph
+pub fn set_color(color: bool) {
+    // The legacy Windows console needs the virtual terminal processing for the escape sequences
+    #[cfg(windows)]
+    let color = color && crossterm::ansi_support::supports_ansi();
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Colors a line with the theme color of its status glyph (after the time it may start with)
+fn color_line(line: &str) -> Cow<'_, str> {
+    let status = line.trim_start();
+    let status = match status
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+    {
+        Some((_, rest)) => rest,
+        None => status,
+    };
+    match THEME.iter().find(|(glyph, _)| status.starts_with(glyph)) {
+        Some((_, color)) => Cow::Owned(line.with(*color).to_string()),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Formats a text for the terminal: colored by the theme, and in ASCII in the ASCII mode
+pub fn styled(text: &str) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return plain(text).into_owned();
+    }
+    let colored: Vec<Cow<str>> = text.split('\n').map(color_line).collect();
+    plain(&colored.join("\n")).into_owned()
+}
+
+/// Whether the console lines start with the time and the level
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+/// Starts the console lines with the time and the level (to tell when things happened)
+pub fn set_timestamps(timestamps: bool) {
+    TIMESTAMPS.store(timestamps, Ordering::Relaxed);
+}
+
+/// Whether the console lines start with the time and the level
+pub fn has_timestamps() -> bool {
+    TIMESTAMPS.load(Ordering::Relaxed)
+}
+
+/// Starts each line of a text with the time and the level (`error`: printed to stderr) when enabled
+pub fn stamped(text: &str, error: bool) -> Cow<'_, str> {
+    if !has_timestamps() {
+        return Cow::Borrowed(text);
+    }
+    let level = if error { "ERROR" } else { "INFO " };
+    let prefix = format!("[{}] {level}", chrono::Local::now().format("%H:%M:%S%.3f"));
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| match line {
+            "" => String::new(),
+            line => format!("{prefix} {line}"),
+        })
+        .collect();
+    Cow::Owned(lines.join("\n"))
+}
+
+/// Whether the current line is redrawn in place
+fn redraws_line() -> bool {
+    prints_info() && INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// State of the connection shown on the live status line
+#[derive(Clone, Copy)]
+enum Status {
+    /// Connecting to the server (with a spinner)
+    Connecting,
+    /// Waiting before reconnecting (with a countdown)
+    Waiting { until: Instant },
+    /// Connected to the server (with the guest count)
+    Connected,
+}
+
+/// State shown on the live status line (None: the last line is shown as is)
+static STATUS: Mutex<Option<Status>> = Mutex::new(None);
+
+/// Guests connected, shown on the live status line
+static GUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Frame of the spinner shown while connecting
+static SPINNER_FRAME: AtomicUsize = AtomicUsize::new(0);
+
+/// Frames of the spinner shown while connecting
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Frames of the spinner in the ASCII mode
+const ASCII_SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+
+/// Interval between the redraws of the spinner and the countdown
+const STATUS_TICK: Duration = Duration::from_millis(100);
+
+/// Whether the connection state is shown on a live status line (an attended terminal, not in the deck mode)
+pub fn has_live_status() -> bool {
+    redraws_line() && !is_deck() && stdout().is_terminal()
+}
+
+/// Sets the state shown on the live status line and redraws it
+fn set_status(status: Option<Status>) {
+    if !has_live_status() {
+        return;
+    }
+    *STATUS.lock().unwrap_or_else(|err| err.into_inner()) = status;
+    let _ = update_line();
+}
+
+/// Shows a spinner on the live status line while connecting to the server
+pub fn set_connecting() {
+    set_status(Some(Status::Connecting));
+}
+
+/// Shows a countdown on the live status line until the next connection attempt
+pub fn set_waiting(delay: Duration) {
+    set_status(Some(Status::Waiting {
+        until: Instant::now() + delay,
+    }));
+}
+
+/// Removes the live status line (when the client stops connecting)
+pub fn end_status() {
+    set_status(None);
+}
+
+/**
+ * Gets the text of the live status line
+ * @return The last line if there is no live status, or the player list while guests are connected
+ */
+fn status_text(last_line: &str) -> String {
+    let status = *STATUS.lock().unwrap_or_else(|err| err.into_inner());
+    match status {
+        None => last_line.to_string(),
+        Some(Status::Connecting) => {
+            let frame = SPINNER_FRAME.load(Ordering::Relaxed);
+            let spinner = if is_ascii() {
+                ASCII_SPINNER[frame % ASCII_SPINNER.len()]
+            } else {
+                SPINNER[frame % SPINNER.len()]
+            };
+            format!("{spinner} {}", t!("status-connecting"))
+        }
+        Some(Status::Waiting { until }) => {
+            let seconds = until
+                .saturating_duration_since(Instant::now())
+                .as_secs_f64();
+            format!(
+                "↪ {}",
+                t!("status-reconnecting-in", seconds = seconds.ceil() as u64)
+            )
+        }
+        Some(Status::Connected) => match GUESTS.load(Ordering::Relaxed) {
+            0 => format!("✓ {}", t!("status-waiting-for-guests")),
+            _ if !last_line.is_empty() => last_line.to_string(),
+            guests => format!("✓ {}", t!("status-guests", guests = guests)),
+        },
+    }
+}
+
+/// Gets the columns a character takes on the terminal (CJK and full-width characters take two)
+fn char_width(c: char) -> usize {
+    match c {
+        '\u{2e80}'..='\u{a4cf}' | '\u{ac00}'..='\u{d7a3}' | '\u{ff00}'..='\u{ff60}' => 2,
+        _ => 1,
+    }
+}
+
+/// Gets the columns a text takes on the terminal
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Cuts a text to a number of columns, ending it with `…` when it does not fit
+pub fn cut_to_width(text: &str, columns: usize) -> Cow<'_, str> {
+    if text_width(text) <= columns {
+        return Cow::Borrowed(text);
+    }
+    if columns == 0 {
+        return Cow::Borrowed("");
+    }
+    let mut width = 0;
+    let mut end = 0;
+    for (index, c) in text.char_indices() {
+        width += char_width(c);
+        // Room for the `…`
+        if width >= columns {
+            break;
+        }
+        end = index + c.len_utf8();
+    }
+    Cow::Owned(format!("{}…", &text[..end]))
+}
+
+/// Cuts a line to the width of the terminal, a wrapped line could not be redrawn in place
+fn fit_width(line: &str) -> Cow<'_, str> {
+    let Ok((columns, _)) = terminal::size() else {
+        return Cow::Borrowed(line);
+    };
+    // Leave the last column free, some terminals wrap when it is written
+    cut_to_width(line, columns.saturating_sub(1) as usize)
+}
+
+/// Starts a task animating the spinner and the countdown of the live status line
+pub fn spawn_status_line() {
+    if !has_live_status() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(STATUS_TICK);
+        loop {
+            tick.tick().await;
+            let status = *STATUS.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(Status::Connecting | Status::Waiting { .. }) = status {
+                SPINNER_FRAME.fetch_add(1, Ordering::Relaxed);
+                let _ = update_line();
+            }
+        }
+    });
+}
+
+/// Sets the output format (must be called before anything is printed)
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+/// Whether the console prints human-readable text
+pub fn is_text() -> bool {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default() == OutputFormat::Text
+}
+
+/// Prints an event as a JSON line (in the JSON output format)
+pub fn print_event(event: &Event) {
+    if is_text() {
+        return;
+    }
+    // Console lines are reported as plain messages or errors
+    let line = match event {
+        Event::Log { text, error: false } => {
+            serde_json::json!({ "event": "log", "message": text.trim() }).to_string()
+        }
+        Event::Log { text, error: true } => {
+            serde_json::json!({ "event": "error", "message": text.trim() }).to_string()
+        }
+        event => match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(_) => return,
+        },
+    };
+    let mut stdout = stdout().lock();
+    let _ = writeln!(stdout, "{line}");
+    let _ = stdout.flush();
+}
+
 /// Clears the current line
 pub fn clear_line() -> Result<()> {
+    if !redraws_line() {
+        return Ok(());
+    }
     stdout()
         .queue(terminal::Clear(terminal::ClearType::CurrentLine))
         .context("Failed to update output (clear line)")?;
@@ -21,21 +443,53 @@ pub fn save_line(args: std::fmt::Arguments<'_>) -> Result<()> {
         .lock()
         .map_err(|_| anyhow::anyhow!("Failed to lock last line"))?;
     *data = std::fmt::format(args);
+    tracing::debug!(target: "console", "{}", data.trim());
     Ok(())
 }
 
+/// Records an output line in the log
+pub fn log_info(text: &str) {
+    tracing::info!(target: "console", "{}", text.trim());
+    let event = Event::Log {
+        text: text.to_string(),
+        error: false,
+    };
+    print_event(&event);
+    events::emit(event);
+}
+
+/// Records an error line in the log
+pub fn log_error(text: &str) {
+    tracing::error!(target: "console", "{}", text.trim());
+    let event = Event::Log {
+        text: text.to_string(),
+        error: true,
+    };
+    print_event(&event);
+    events::emit(event);
+}
+
 /// Updates the current line
 /// <https://stackoverflow.com/a/59890400>
 pub fn update_line() -> Result<()> {
+    if !redraws_line() {
+        return Ok(());
+    }
     let mut stdout = stdout();
     let data = LAST_LINE
         .lock()
         .map_err(|_| anyhow::anyhow!("Failed to lock last line"))?;
+    let line = status_text(&data);
+    let line = if has_live_status() {
+        fit_width(&plain(&line)).into_owned()
+    } else {
+        line
+    };
     stdout
         .queue(terminal::Clear(terminal::ClearType::CurrentLine))
         .context("Failed to update output (clear line)")?;
     stdout
-        .write_all(data.as_bytes())
+        .write_all(styled(&line).as_bytes())
         .context("Failed to update output (write)")?;
     stdout
         .queue(cursor::MoveToColumn(0))
@@ -44,41 +498,453 @@ pub fn update_line() -> Result<()> {
     Ok(())
 }
 
+/// Prints text as a QR code (for scanning invite links with a phone)
+pub fn print_qr(text: &str) -> Result<()> {
+    if !prints_info() || is_deck() {
+        return Ok(());
+    }
+    let code = QrCode::new(text.as_bytes()).context("Failed to encode QR code")?;
+    // Inverted so that the code is readable on dark terminals
+    let image = if is_ascii() {
+        code.render::<&str>()
+            .dark_color("  ")
+            .light_color("##")
+            .quiet_zone(true)
+            .build()
+    } else {
+        code.render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .quiet_zone(true)
+            .build()
+    };
+    clear_line()?;
+    std::println!("{image}");
+    update_line()?;
+    Ok(())
+}
+
+/**
+ * Prints a message exchanged with the server (`arrow`: its direction), with `--verbose`
+ * Only the command is printed once, the content with the secrets masked twice (`-VV`).
+ */
+pub fn trace_message(arrow: &str, message: &serde_json::Value) {
+    if verbosity() < Verbosity::Verbose || !prints_lines() {
+        return;
+    }
+    let line = if verbosity() == Verbosity::Trace {
+        let mut message = message.clone();
+        diagnostics::sanitize_json("", &mut message);
+        format!("{arrow} {message}")
+    } else {
+        let cmd = message
+            .get("cmd")
+            .and_then(|cmd| cmd.as_str())
+            .unwrap_or("?");
+        match message.get("id").and_then(|id| id.as_str()) {
+            Some(id) => format!("{arrow} {cmd} (id={id})"),
+            None => format!("{arrow} {cmd}"),
+        }
+    };
+    // Printed only, the log has its own traces of the messages
+    let _: Result<()> = (|| {
+        clear_line()?;
+        std::println!("{}", stamped(&styled(&line), false));
+        update_line()
+    })();
+}
+
 /// println macro
-macro_rules! println {
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_println {
     ($($arg:tt)*) => {{
+        let text = std::format!($($arg)*);
         $crate::console::clear_line()?;
-        std::println!($($arg)*); // Call the original macro
+        if $crate::console::prints_info() {
+            let line = $crate::console::styled(&text);
+            std::println!("{}", $crate::console::stamped(&line, false)); // Call the original macro
+        }
+        $crate::console::log_info(&text);
         $crate::console::update_line()?;
     }};
 }
-pub(crate) use println;
+pub use __console_println as println;
 
 /// eprintln macro
-macro_rules! eprintln {
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_eprintln {
     ($($arg:tt)*) => {{
+        let text = std::format!($($arg)*);
         $crate::console::clear_line()?;
-        std::eprintln!($($arg)*); // Call the original macro
+        if $crate::console::prints_lines() {
+            let line = $crate::console::styled(&text);
+            std::eprintln!("{}", $crate::console::stamped(&line, true)); // Call the original macro
+        }
+        $crate::console::log_error(&text);
         $crate::console::update_line()?;
     }};
 }
-pub(crate) use eprintln;
+pub use __console_eprintln as eprintln;
 
 /// printdoc macro
-macro_rules! printdoc {
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_printdoc {
     ($($arg:tt)*) => {{
+        let text = indoc::formatdoc!($($arg)*);
         $crate::console::clear_line()?;
-        indoc::printdoc!($($arg)*); // Call the original macro
+        if $crate::console::prints_info() {
+            let lines = $crate::console::styled(&text);
+            std::print!("{}", $crate::console::stamped(&lines, false)); // Call the original macro
+        }
+        $crate::console::log_info(&text);
         $crate::console::update_line()?;
     }};
 }
-pub(crate) use printdoc;
+pub use __console_printdoc as printdoc;
 
 /// print_update macro
-macro_rules! print_update {
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_print_update {
     ($($arg:tt)*) => {{
         $crate::console::save_line(format_args!($($arg)*))?;
         $crate::console::update_line()?;
     }};
 }
-pub(crate) use print_update;
+pub use __console_print_update as print_update;
+
+/// Commands entered on the console while running
+#[derive(Debug)]
+pub enum ConsoleCmd {
+    /// Display the current status
+    Status,
+    /// Create an invite link for the running game
+    Invite,
+    /// Display the connected guests
+    Guests,
+    /// Remove a guest from the session (by guest ID or name)
+    Kick(String),
+    /// Give a guest a nickname remembered for their next sessions (guest ID or name, nickname)
+    Nick(String, String),
+    /// Revoke an unused invite link (the most recent one if no guest ID is given)
+    Revoke(Option<u64>),
+    /// List the installed games supporting Remote Play Together
+    Games,
+    /// Select the game invites target (None: the running game)
+    SelectGame(Option<u32>),
+    /// Open a link in the browser
+    Open(OpenTarget),
+    /// Decline the invite requests (true) or accept them again (false)
+    Pause(bool),
+    /// Approve an invite request waiting on the console (the oldest one if no user is given)
+    Approve(Option<String>),
+    /// Decline an invite request waiting on the console (the oldest one if no user is given)
+    Deny(Option<String>),
+    /// Display the available commands
+    Help,
+    /// Exit the application
+    Quit,
+    /// Run a command registered by a plugin (name, arguments)
+    #[cfg(feature = "plugins")]
+    Plugin(String, String),
+}
+
+/// Links that can be opened with the `open` command
+#[derive(Debug)]
+pub enum OpenTarget {
+    /// The most recent invite link
+    Invite,
+    /// The link in the welcome message of the server (Discord)
+    Discord,
+}
+
+impl FromStr for ConsoleCmd {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let mut args = line.split_whitespace();
+        let cmd = match args.next() {
+            Some("status") => ConsoleCmd::Status,
+            Some("invite") => ConsoleCmd::Invite,
+            Some("guests") => ConsoleCmd::Guests,
+            Some("kick") => {
+                let guest = args.collect::<Vec<_>>().join(" ");
+                if guest.is_empty() {
+                    anyhow::bail!("Usage: kick <guest_id|name>");
+                }
+                ConsoleCmd::Kick(guest)
+            }
+            Some("nick") => {
+                let guest = args.next();
+                let nickname = args.collect::<Vec<_>>().join(" ");
+                match guest {
+                    Some(guest) if !nickname.is_empty() => {
+                        ConsoleCmd::Nick(guest.to_string(), nickname)
+                    }
+                    _ => anyhow::bail!("Usage: nick <guest_id|name> <nickname>"),
+                }
+            }
+            Some("revoke") => match args.next() {
+                Some(guest_id) => {
+                    ConsoleCmd::Revoke(Some(guest_id.parse().context("Usage: revoke [guest_id]")?))
+                }
+                None => ConsoleCmd::Revoke(None),
+            },
+            Some("games") => ConsoleCmd::Games,
+            Some("game") => match args.next() {
+                Some("off") => ConsoleCmd::SelectGame(None),
+                Some(app_id) => ConsoleCmd::SelectGame(Some(
+                    app_id.parse().context("Usage: game <app_id|off>")?,
+                )),
+                None => anyhow::bail!("Usage: game <app_id|off>"),
+            },
+            Some("open") => match args.next() {
+                Some("invite") | None => ConsoleCmd::Open(OpenTarget::Invite),
+                Some("discord") => ConsoleCmd::Open(OpenTarget::Discord),
+                Some(_) => anyhow::bail!("Usage: open [invite|discord]"),
+            },
+            Some("approve") => ConsoleCmd::Approve(
+                Some(args.collect::<Vec<_>>().join(" ")).filter(|s| !s.is_empty()),
+            ),
+            Some("deny") => {
+                ConsoleCmd::Deny(Some(args.collect::<Vec<_>>().join(" ")).filter(|s| !s.is_empty()))
+            }
+            Some("pause") => ConsoleCmd::Pause(true),
+            Some("resume") => ConsoleCmd::Pause(false),
+            Some("help" | "?") => ConsoleCmd::Help,
+            Some("quit" | "exit") => ConsoleCmd::Quit,
+            #[cfg(feature = "plugins")]
+            Some(cmd) if crate::plugins::has_command(cmd) => {
+                ConsoleCmd::Plugin(cmd.to_string(), args.collect::<Vec<_>>().join(" "))
+            }
+            Some(cmd) => anyhow::bail!("Unknown command: {cmd} (type `help` to list commands)"),
+            None => anyhow::bail!("Empty command"),
+        };
+        Ok(cmd)
+    }
+}
+
+/// Prints an event as a console line (in the text output format)
+fn print_event_text(event: &Event) -> Result<()> {
+    // The time is already at the start of the line with the timestamps and in the log pane
+    let time = if has_timestamps() || is_tui() {
+        String::new()
+    } else {
+        format!("[{}] ", chrono::Local::now().format("%H:%M:%S"))
+    };
+    match event {
+        Event::Connected { .. } | Event::GuestJoined { .. } | Event::GuestLeft { .. } => {
+            if let Event::GuestJoined { guests, .. } | Event::GuestLeft { guests, .. } = event {
+                GUESTS.store(*guests, Ordering::Relaxed);
+            }
+            set_status(Some(Status::Connected));
+        }
+        Event::Reconnecting { delay_ms } | Event::Maintenance { delay_ms, .. } => {
+            set_waiting(Duration::from_millis(*delay_ms))
+        }
+        _ => (),
+    }
+    match event {
+        Event::Connected { reconnected: true } => println!("✓ {}", t!("event-reconnected")),
+        Event::Connected { reconnected: false } => println!("✓ {}", t!("event-connected")),
+        Event::Reconnecting { delay_ms } => {
+            let text = t!(
+                "event-reconnecting",
+                seconds = format!("{:.1}", *delay_ms as f64 / 1000.0)
+            );
+            // Counted down on the live status line instead
+            if has_live_status() {
+                tracing::info!(target: "console", "↪ {text}");
+            } else {
+                println!("↪ {text}");
+            }
+        }
+        Event::GuestJoined { name, guests, .. } => println!(
+            "{time}✓ {}",
+            t!("event-guest-joined", name = name, guests = guests)
+        ),
+        Event::GuestLeft { name, .. } => {
+            println!("{time}↪ {}", t!("event-guest-left", name = name))
+        }
+        Event::SteamLost => eprintln!("☓ {}", t!("event-steam-lost")),
+        Event::SteamRestored => println!("✓ {}", t!("event-steam-restored")),
+        Event::SessionEnding { minutes_left } => println!(
+            "{time}★ {}",
+            t!("event-session-ending", minutes = minutes_left)
+        ),
+        Event::Paired {
+            account: Some(account),
+        } => println!("✓ {}", t!("event-paired-as", account = account)),
+        Event::Paired { account: None } => println!("✓ {}", t!("event-paired")),
+        Event::Maintenance { message, delay_ms } => {
+            let back_at = chrono::Local::now() + chrono::Duration::milliseconds(*delay_ms as i64);
+            match message {
+                Some(message) => println!(
+                    "{time}□ {}",
+                    t!("event-maintenance-message", message = message)
+                ),
+                None => println!("{time}□ {}", t!("event-maintenance")),
+            }
+            println!(
+                "↪ {}",
+                t!(
+                    "event-maintenance-back-at",
+                    time = back_at.format("%H:%M:%S")
+                )
+            )
+        }
+        Event::Announcement { title, text, url } => {
+            let rule = "═".repeat(60);
+            println!("\n{rule}");
+            match title {
+                Some(title) => println!("★ {title}"),
+                None => println!("★ {}", t!("event-announcement")),
+            }
+            for line in text.lines() {
+                println!("  {line}");
+            }
+            if let Some(url) = url {
+                println!("  -> {url}");
+            }
+            println!("{rule}\n")
+        }
+        // Printed with more details where they happen, or already printed
+        Event::InviteCreated { .. }
+        | Event::InviteRevoked { .. }
+        | Event::PairingCode { .. }
+        | Event::Log { .. } => (),
+    }
+    Ok(())
+}
+
+/**
+ * Prints an event as a large block readable from a distance (in the deck mode)
+ * @return Ok even for the events that are not shown
+ */
+fn print_event_deck(event: &Event) -> Result<()> {
+    let headline = match event {
+        Event::Connected { .. } => format!("✓ {}", t!("deck-online")),
+        Event::Reconnecting { .. } => format!("↪ {}", t!("deck-reconnecting")),
+        Event::InviteCreated { url, game_name, .. } => match game_name {
+            Some(game) => format!(
+                "-> {}\n\n   {url}",
+                t!("deck-invite-ready-for", game = game)
+            ),
+            None => format!("-> {}\n\n   {url}", t!("deck-invite-ready")),
+        },
+        Event::GuestJoined { name, guests, .. } => {
+            format!(
+                "✓ {}",
+                t!("deck-guest-joined", name = name, guests = guests)
+            )
+        }
+        Event::GuestLeft { name, guests, .. } => {
+            format!("↪ {}", t!("deck-guest-left", name = name, guests = guests))
+        }
+        Event::SteamLost => format!("☓ {}", t!("deck-steam-lost")),
+        Event::SteamRestored => format!("✓ {}", t!("deck-steam-restored")),
+        Event::SessionEnding { minutes_left } => {
+            format!("★ {}", t!("deck-session-ending", minutes = minutes_left))
+        }
+        Event::Announcement { title, text, .. } => match title {
+            Some(title) => format!("★ {title}\n\n   {text}"),
+            None => format!("★ {text}"),
+        },
+        Event::Maintenance { delay_ms, .. } => {
+            let back_at = chrono::Local::now() + chrono::Duration::milliseconds(*delay_ms as i64);
+            format!(
+                "□ {}\n\n   {}",
+                t!("deck-maintenance"),
+                t!("deck-maintenance-back-at", time = back_at.format("%H:%M"))
+            )
+        }
+        Event::PairingCode { code } => format!("★ {}", t!("deck-pairing-code", code = code)),
+        Event::Paired { .. } => format!("✓ {}", t!("event-paired")),
+        Event::InviteRevoked { .. } | Event::Log { .. } => return Ok(()),
+    };
+    let rule = "═".repeat(60);
+    println!("\n{rule}\n\n   {headline}\n\n{rule}\n");
+    Ok(())
+}
+
+/// Starts a task printing the application events (subscribed before returning, so none is missed)
+pub fn spawn_event_printer() {
+    let mut events = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            match event {
+                // Printed with the line
+                Event::Log { .. } => (),
+                event if is_deck() => {
+                    let _ = print_event_deck(&event);
+                }
+                event if is_text() => {
+                    let _ = print_event_text(&event);
+                }
+                event => print_event(&event),
+            }
+        }
+    });
+}
+
+/// Starts a thread reading console commands from stdin
+pub fn spawn_command_reader() -> Receiver<ConsoleCmd> {
+    let (tx, rx) = channel::<ConsoleCmd>(8);
+    std::thread::spawn(move || {
+        // Quit asked in the deck mode, waiting for confirmation
+        let mut quitting = false;
+        for line in stdin().lines() {
+            let Ok(line) = line else { break };
+            let line = line.trim();
+            let cmd = if is_deck() {
+                // A single button (A / Enter) creates an invite or confirms quitting
+                match (std::mem::take(&mut quitting), line) {
+                    (true, "") => Ok(ConsoleCmd::Quit),
+                    (false, "") => Ok(ConsoleCmd::Invite),
+                    (false, "quit" | "exit") => {
+                        quitting = true;
+                        let _: Result<()> = (|| {
+                            println!("□ {}", t!("deck-confirm-quit"));
+                            Ok(())
+                        })();
+                        continue;
+                    }
+                    (true, line) => {
+                        let _: Result<()> = (|| {
+                            println!("↪ {}", t!("deck-still-running"));
+                            Ok(())
+                        })();
+                        line.parse::<ConsoleCmd>()
+                    }
+                    (false, line) => line.parse::<ConsoleCmd>(),
+                }
+            } else if line.is_empty() {
+                continue;
+            } else {
+                line.parse::<ConsoleCmd>()
+            };
+            match cmd {
+                Ok(cmd) => {
+                    if tx.blocking_send(cmd).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    let _: Result<()> = (|| {
+                        eprintln!("☓ {err}");
+                        Ok(())
+                    })();
+                }
+            }
+        }
+    });
+    rx
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncRead, AsyncWrite, AsyncWriteExt as _, BufReader},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{channel, Receiver, Sender},
+        oneshot,
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{self, Event};
+
+/// Method streaming the application events on the connection (answered by the control API itself)
+const SUBSCRIBE_METHOD: &str = "subscribe";
+
+/// Methods of the control API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMethod {
+    /// Get the running game, the number of guests and the latency
+    GetStatus,
+    /// Create an invite link for the running game
+    CreateInvite,
+    /// List the connected guests
+    ListGuests,
+    /// Exit the application
+    Shutdown,
+}
+
+/// A request from the control API, answered through `reply`
+pub struct ControlRequest {
+    pub method: ControlMethod,
+    pub reply: oneshot::Sender<std::result::Result<Value, String>>,
+}
+
+/// JSON-RPC request
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+}
+
+/// JSON-RPC response
+#[derive(Serialize)]
+pub(crate) struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+/// JSON-RPC notification carrying an application event
+#[derive(Serialize)]
+struct RpcNotification<'a> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: &'a Event,
+}
+
+/// JSON-RPC error
+#[derive(Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcResponse {
+    pub(crate) fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub(crate) fn error(id: Value, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(RpcError {
+                code,
+                message: message.into(),
+            }),
+        }
+    }
+}
+
+/// Creates the channel carrying control requests to the client loop
+pub fn channel_pair() -> (Sender<ControlRequest>, Receiver<ControlRequest>) {
+    channel::<ControlRequest>(8)
+}
+
+/// Get the address of the control endpoint
+#[cfg(unix)]
+pub fn endpoint() -> Result<std::path::PathBuf> {
+    Ok(crate::config::config_dir()?.join("remoteplay-inviter.sock"))
+}
+
+/// Get the address of the control endpoint
+#[cfg(windows)]
+pub fn endpoint() -> Result<std::path::PathBuf> {
+    Ok(std::path::PathBuf::from(r"\\.\pipe\remoteplay-inviter"))
+}
+
+/// Connects to the control endpoint of the running client
+#[cfg(unix)]
+async fn connect() -> Result<tokio::net::UnixStream> {
+    let path = endpoint()?;
+    tokio::net::UnixStream::connect(&path)
+        .await
+        .with_context(|| format!("Unable to connect to the control socket: {:?}", &path))
+}
+
+/// Connects to the control endpoint of the running client
+#[cfg(windows)]
+async fn connect() -> Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    let path = endpoint()?;
+    tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(&path)
+        .with_context(|| format!("Unable to connect to the control pipe: {:?}", &path))
+}
+
+/**
+ * Sends a request to the running client (started with `--control`)
+ * @return The result of the request
+ */
+pub async fn call(method: &str) -> Result<Value> {
+    let (read, mut write) = tokio::io::split(connect().await?);
+    let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method });
+    write
+        .write_all(format!("{request}\n").as_bytes())
+        .await
+        .context("Unable to send the control request")?;
+    let line = BufReader::new(read)
+        .lines()
+        .next_line()
+        .await
+        .context("Unable to read the control response")?
+        .context("The client closed the control connection")?;
+    let mut response: Value = serde_json::from_str(&line).context("Invalid control response")?;
+    if let Some(message) = response["error"]["message"].as_str() {
+        anyhow::bail!("The client returned an error: {message}");
+    }
+    Ok(response["result"].take())
+}
+
+/// Serves the control API on a Unix domain socket until `shutdown` is cancelled
+#[cfg(unix)]
+pub async fn serve(tx: Sender<ControlRequest>, shutdown: CancellationToken) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+    use tokio::net::UnixListener;
+
+    let path = endpoint()?;
+    // Remove the socket left over from a previous run
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Unable to remove stale control socket: {:?}", &path))?;
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Unable to listen on the control socket: {:?}", &path))?;
+    // Only the current user may control the client
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).with_context(|| {
+        format!(
+            "Unable to set permissions of the control socket: {:?}",
+            &path
+        )
+    })?;
+
+    loop {
+        tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, tx.clone()));
+                }
+                Err(err) => tracing::warn!("Failed to accept control connection: {err}"),
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+/// Serves the control API on a named pipe until `shutdown` is cancelled
+#[cfg(windows)]
+pub async fn serve(tx: Sender<ControlRequest>, shutdown: CancellationToken) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = endpoint()?;
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)
+        .with_context(|| format!("Unable to create the control pipe: {:?}", &path))?;
+
+    loop {
+        tokio::select! {
+            result = server.connect() => {
+                if let Err(err) = result {
+                    tracing::warn!("Failed to accept control connection: {err}");
+                    continue;
+                }
+                // Create the next instance before handing over the connected one
+                let client = std::mem::replace(
+                    &mut server,
+                    ServerOptions::new()
+                        .create(&path)
+                        .with_context(|| format!("Unable to create the control pipe: {:?}", &path))?,
+                );
+                tokio::spawn(handle_connection(client, tx.clone()));
+            }
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers newline-delimited JSON-RPC requests on a connection, then streams the events once subscribed
+async fn handle_connection(
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    tx: Sender<ControlRequest>,
+) {
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    let mut events = None;
+
+    loop {
+        let text = tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = handle_request(&line, &tx, &mut events).await;
+                serde_json::to_string(&response)
+            }
+            event = next_event(&mut events) => serde_json::to_string(&RpcNotification {
+                jsonrpc: "2.0",
+                method: "event",
+                params: &event,
+            }),
+        };
+        let Ok(mut text) = text else {
+            break;
+        };
+        text.push('\n');
+        if write.write_all(text.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Waits for the next event of a subscription (never returns without one)
+pub(crate) async fn next_event(events: &mut Option<broadcast::Receiver<Event>>) -> Event {
+    let Some(events) = events else {
+        return std::future::pending().await;
+    };
+    loop {
+        match events.recv().await {
+            Ok(event) => return event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
+
+/// Forwards a JSON-RPC request to the client loop and waits for the answer
+pub(crate) async fn handle_request(
+    line: &str,
+    tx: &Sender<ControlRequest>,
+    events: &mut Option<broadcast::Receiver<Event>>,
+) -> RpcResponse {
+    let request: RpcRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return RpcResponse::error(Value::Null, -32700, format!("Parse error: {err}")),
+    };
+    if request.method == SUBSCRIBE_METHOD {
+        events.get_or_insert_with(events::subscribe);
+        return RpcResponse::result(request.id, Value::Bool(true));
+    }
+    let method: ControlMethod = match serde_json::from_value(Value::String(request.method)) {
+        Ok(method) => method,
+        Err(_) => return RpcResponse::error(request.id, -32601, "Method not found"),
+    };
+
+    let (reply, rx) = oneshot::channel();
+    if tx.send(ControlRequest { method, reply }).await.is_err() {
+        return RpcResponse::error(request.id, -32000, "The client is shutting down");
+    }
+    match rx.await {
+        Ok(Ok(result)) => RpcResponse::result(request.id, result),
+        Ok(Err(message)) => RpcResponse::error(request.id, -32000, message),
+        Err(_) => RpcResponse::error(request.id, -32000, "The request was not answered"),
+    }
+}
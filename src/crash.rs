@@ -0,0 +1,286 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::{DateTime, Local, Utc};
+use serde_json::Value;
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::http::Uri;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    events::{self, Event},
+    http::{self, Network},
+    metrics,
+    models::ServerMessage,
+    update, VERSION,
+};
+
+/// Number of handled server messages kept for the crash report
+const RECENT_MESSAGES_KEPT: usize = 10;
+
+/// Suffix of the crash reports already uploaded to Sentry
+const UPLOADED_SUFFIX: &str = ".uploaded.txt";
+
+/// State of the client included in a crash report
+struct CrashContext {
+    /// Whether the client is connected to the server
+    connected: bool,
+    /// Reconnection attempts since the connection was lost
+    reconnect_attempts: u32,
+    /// Whether the Steam client is running
+    steam: bool,
+    /// Recently handled server messages (values redacted), oldest first
+    recent_messages: VecDeque<String>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    connected: false,
+    reconnect_attempts: 0,
+    steam: true,
+    recent_messages: VecDeque::new(),
+});
+
+/// Get the directory the crash reports are written to
+fn crash_dir() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("crashes"))
+}
+
+/// Replaces the values of a message with their type, keeping its structure and command
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| match key.as_str() {
+                    "cmd" => (key.clone(), value.clone()),
+                    _ => (key.clone(), redact(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(redact).collect()),
+        Value::String(_) => Value::String("<string>".to_string()),
+        Value::Number(_) => Value::String("<number>".to_string()),
+        Value::Bool(_) => Value::String("<bool>".to_string()),
+        Value::Null => Value::Null,
+    }
+}
+
+/// Records a server message about to be handled (only its command and structure are kept)
+pub fn record_message(message: &ServerMessage) {
+    let Ok(value) = serde_json::to_value(message) else {
+        return;
+    };
+    let mut context = CONTEXT.lock().unwrap_or_else(|err| err.into_inner());
+    if context.recent_messages.len() >= RECENT_MESSAGES_KEPT {
+        context.recent_messages.pop_front();
+    }
+    context
+        .recent_messages
+        .push_back(redact(&value).to_string());
+}
+
+/// Starts a task following the connection state from the application events (subscribed before returning)
+pub fn spawn_event_recorder() {
+    let mut events = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            let mut context = CONTEXT.lock().unwrap_or_else(|err| err.into_inner());
+            match event {
+                Event::Connected { .. } => {
+                    context.connected = true;
+                    context.reconnect_attempts = 0;
+                }
+                Event::Reconnecting { .. } | Event::Maintenance { .. } => {
+                    context.connected = false;
+                    context.reconnect_attempts += 1;
+                }
+                Event::SteamLost => context.steam = false,
+                Event::SteamRestored => context.steam = true,
+                _ => (),
+            }
+        }
+    });
+}
+
+/// Builds the text of a crash report (the first line sums it up)
+fn report(info: &PanicHookInfo) -> String {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match info.payload().downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    };
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_else(|| "unknown location".to_string());
+
+    let mut text = String::new();
+    let _ = writeln!(text, "Panic at {location}: {message}");
+    let _ = writeln!(text);
+    let _ = writeln!(text, "version  : {VERSION}");
+    let _ = writeln!(text, "platform : {}", update::platform());
+    let _ = writeln!(text, "time     : {}", Local::now().to_rfc3339());
+    let _ = writeln!(
+        text,
+        "thread   : {}",
+        thread::current().name().unwrap_or("unnamed")
+    );
+
+    // The panic may have happened while the context was locked
+    match CONTEXT.try_lock() {
+        Ok(context) => {
+            let _ = writeln!(text);
+            let _ = writeln!(text, "Connection:");
+            let _ = writeln!(text, "  connected          : {}", context.connected);
+            let _ = writeln!(
+                text,
+                "  reconnect attempts : {} (since the connection was lost)",
+                context.reconnect_attempts
+            );
+            let _ = writeln!(
+                text,
+                "  total reconnects   : {}",
+                metrics::RECONNECT_ATTEMPTS.get()
+            );
+            let _ = writeln!(text, "  steam              : {}", context.steam);
+            let _ = writeln!(text);
+            let _ = writeln!(
+                text,
+                "Recent server messages (oldest first, values redacted):"
+            );
+            for message in &context.recent_messages {
+                let _ = writeln!(text, "  {message}");
+            }
+        }
+        Err(_) => {
+            let _ = writeln!(text);
+            let _ = writeln!(text, "Connection: unavailable");
+        }
+    }
+
+    let _ = writeln!(text);
+    let _ = writeln!(text, "Backtrace:");
+    let _ = writeln!(text, "{}", Backtrace::force_capture());
+    text
+}
+
+/**
+ * Writes a crash report to the crash directory
+ * @return The path of the report
+ */
+fn write_report(report: &str) -> Result<PathBuf> {
+    let dir = crash_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Unable to create directory: {:?}", &dir))?;
+    let path = dir.join(format!(
+        "crash-{}.txt",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::write(&path, report).with_context(|| format!("Unable to write file: {:?}", &path))?;
+    Ok(path)
+}
+
+/// Installs a panic hook writing a crash report (after the default message)
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        match write_report(&report(info)) {
+            Ok(path) => eprintln!(
+                "☓ The client crashed. A crash report was saved to {}",
+                path.display()
+            ),
+            Err(err) => {
+                eprintln!("☓ The client crashed, and the crash report could not be saved: {err:#}")
+            }
+        }
+    }));
+}
+
+/**
+ * Builds the URL of the Sentry store endpoint from a DSN (`https://<key>@<host>/<project>`)
+ * @return The URL, with the key in the query
+ */
+fn store_url(dsn: &str) -> Result<String> {
+    let uri: Uri = dsn.parse().context("Invalid Sentry DSN")?;
+    let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+        bail!("Invalid Sentry DSN (expected https://<key>@<host>/<project>)");
+    };
+    let Some((key, host)) = authority.as_str().split_once('@') else {
+        bail!("Missing key in Sentry DSN");
+    };
+    let key = key.split(':').next().unwrap_or_default();
+    let path = uri.path().trim_end_matches('/');
+    let Some((prefix, project)) = path.rsplit_once('/').filter(|(_, p)| !p.is_empty()) else {
+        bail!("Missing project in Sentry DSN");
+    };
+    Ok(format!(
+        "{scheme}://{host}{prefix}/api/{project}/store/?sentry_version=7&sentry_key={key}"
+    ))
+}
+
+/// Uploads the crash reports to Sentry (when opted in with `sentry_dsn`), marking them as uploaded
+pub async fn upload_reports(dsn: String, network: Network) {
+    let result: Result<()> = async {
+        let url = store_url(&dsn)?;
+        let Ok(entries) = fs::read_dir(crash_dir()?) else {
+            return Ok(());
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !name.starts_with("crash-")
+                || !name.ends_with(".txt")
+                || name.ends_with(UPLOADED_SUFFIX)
+            {
+                continue;
+            }
+            let report = fs::read_to_string(&path)
+                .with_context(|| format!("Unable to read file: {:?}", &path))?;
+            let time: DateTime<Utc> = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map(DateTime::from)
+                .unwrap_or_else(|_| Utc::now());
+            let event = serde_json::json!({
+                "event_id": Uuid::new_v4().simple().to_string(),
+                "timestamp": time.to_rfc3339(),
+                "platform": "native",
+                "level": "fatal",
+                "logger": "panic",
+                "release": format!("remoteplay-inviter@{VERSION}"),
+                "tags": { "platform": update::platform() },
+                "message": { "formatted": report.lines().next().unwrap_or_default() },
+                "extra": { "report": report },
+            });
+            let response = http::post_json(&network, &url, &event.to_string()).await?;
+            if !response.is_success() {
+                bail!("Sentry answered with status {}", response.status);
+            }
+            let uploaded = path.with_file_name(name.replace(".txt", UPLOADED_SUFFIX));
+            fs::rename(&path, &uploaded)
+                .with_context(|| format!("Unable to rename {:?}", &path))?;
+            tracing::info!(report = ?uploaded, "Uploaded a crash report");
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to upload the crash reports: {err:#}");
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::{bail, Context as _, Result};
+use futures::SinkExt;
+use futures_util::stream::StreamExt;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::error::RecvError,
+};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        protocol::Message,
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{self, Event};
+
+/// Dashboard page
+const INDEX_HTML: &str = include_str!("dashboard.html");
+
+/// Number of console lines kept for the dashboard
+const LOG_LINES_KEPT: usize = 200;
+
+/// State displayed on the dashboard
+#[derive(Default, Serialize)]
+struct DashboardState {
+    /// Whether the client is connected to the server
+    connected: bool,
+    /// Whether the Steam client is running
+    steam: bool,
+    /// Most recent invite link
+    invite_url: Option<String>,
+    /// Game of the most recent invite link (name, or app ID when not known)
+    invite_game: Option<String>,
+    /// Pairing code waiting to be typed into Discord
+    pairing_code: Option<String>,
+    /// Connected guests (guest ID -> name)
+    guests: BTreeMap<u64, String>,
+    /// Recent console lines
+    logs: VecDeque<String>,
+}
+
+impl DashboardState {
+    /// Applies an event to the state
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Connected { .. } => self.connected = true,
+            Event::Reconnecting { .. } | Event::Maintenance { .. } => self.connected = false,
+            Event::InviteCreated {
+                url,
+                game,
+                game_name,
+                ..
+            } => {
+                self.invite_url = Some(url.clone());
+                self.invite_game = game_name.clone().or(game.map(|game| game.to_string()));
+            }
+            Event::GuestJoined { guest_id, name, .. } => {
+                self.guests.insert(*guest_id, name.clone());
+            }
+            Event::GuestLeft { guest_id, .. } => {
+                self.guests.remove(guest_id);
+            }
+            Event::SteamLost => {
+                self.steam = false;
+                self.guests.clear();
+            }
+            Event::SteamRestored => self.steam = true,
+            Event::InviteRevoked { .. }
+            | Event::SessionEnding { .. }
+            | Event::Announcement { .. } => (),
+            Event::PairingCode { code } => self.pairing_code = Some(code.clone()),
+            Event::Paired { .. } => self.pairing_code = None,
+            Event::Log { text, .. } => {
+                if self.logs.len() >= LOG_LINES_KEPT {
+                    self.logs.pop_front();
+                }
+                self.logs.push_back(text.clone());
+            }
+        }
+    }
+}
+
+/**
+ * Whether a request was made for the dashboard address (`Host`) from the dashboard page (`Origin`),
+ * so that other websites and DNS rebinding can't read the invite links and the pairing codes
+ * @return false if the `Host` is not the dashboard address, or the `Origin` is given and not the dashboard page
+ */
+fn is_allowed(addr: SocketAddr, host: Option<&str>, origin: Option<&str>) -> bool {
+    let hosts = [addr.to_string(), format!("localhost:{}", addr.port())];
+    let Some(host) = host.map(str::trim) else {
+        return false;
+    };
+    if !hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    {
+        return false;
+    }
+    origin.map_or(true, |origin| {
+        hosts.iter().any(|allowed| {
+            origin
+                .trim()
+                .eq_ignore_ascii_case(&format!("http://{allowed}"))
+        })
+    })
+}
+
+/// Gets the value of a header in the head of an HTTP request
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/**
+ * Binds the dashboard address
+ * @return An error if the address is not a loopback address (the dashboard shows the invite links)
+ */
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    if !addr.ip().is_loopback() {
+        bail!("The dashboard can only be served on a loopback address (e.g. 127.0.0.1): {addr}");
+    }
+    TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to listen on the dashboard address: {addr}"))
+}
+
+/// Serves the dashboard page and its WebSocket bridge until `shutdown` is cancelled
+pub async fn serve(listener: TcpListener, shutdown: CancellationToken) {
+    let addr = match listener.local_addr() {
+        Ok(addr) => addr,
+        Err(err) => {
+            tracing::warn!("Failed to get the dashboard address: {err}");
+            return;
+        }
+    };
+    let state = Arc::new(Mutex::new(DashboardState {
+        steam: true,
+        ..Default::default()
+    }));
+
+    // Keep the state up to date
+    let mut events = events::subscribe();
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let Ok(mut state) = state.lock() {
+                            state.apply(&event);
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    });
+
+    loop {
+        let stream = tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    tracing::warn!("Failed to accept dashboard connection: {err}");
+                    continue;
+                }
+            },
+            _ = shutdown.cancelled() => return,
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, addr, state).await {
+                tracing::debug!("Dashboard request failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Answers a request for the page or upgrades it to the WebSocket bridge
+async fn handle_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    state: Arc<Mutex<DashboardState>>,
+) -> Result<()> {
+    // Look at the request head without consuming it (the WebSocket handshake reads it again)
+    let mut buf = [0u8; 4096];
+    let n = stream.peek(&mut buf).await?;
+    let head = String::from_utf8_lossy(&buf[..n]);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+
+    if !is_allowed(addr, header(&head, "Host"), None) {
+        return respond(stream, "403 Forbidden", "text/plain", "Forbidden\n").await;
+    }
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/ws")) => bridge(stream, addr, state).await,
+        (Some("GET"), Some("/")) => {
+            respond(stream, "200 OK", "text/html; charset=utf-8", INDEX_HTML).await
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", "Not Found\n").await,
+    }
+}
+
+/// Sends a plain HTTP response
+async fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    // Consume the request
+    let mut buf = [0u8; 4096];
+    let _ = stream.read(&mut buf).await?;
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Sends the current state, then forwards every event to the browser
+async fn bridge(
+    stream: TcpStream,
+    addr: SocketAddr,
+    state: Arc<Mutex<DashboardState>>,
+) -> Result<()> {
+    // Browsers let any page connect to a local WebSocket, only the dashboard page is answered
+    let check = |request: &Request, response: Response| {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        match header("Origin") {
+            Some(_) if is_allowed(addr, header("Host"), header("Origin")) => Ok(response),
+            _ => {
+                let mut error = ErrorResponse::new(Some("Forbidden".to_string()));
+                *error.status_mut() = StatusCode::FORBIDDEN;
+                Err(error)
+            }
+        }
+    };
+    let ws_stream = accept_hdr_async(stream, check)
+        .await
+        .context("Failed to accept the dashboard WebSocket")?;
+    let (mut write, mut read) = ws_stream.split();
+
+    // Subscribe before taking the snapshot so that no event is missed
+    let mut events = events::subscribe();
+    let snapshot = {
+        let state = state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock dashboard state"))?;
+        serde_json::json!({ "event": "snapshot", "state": &*state }).to_string()
+    };
+    write.send(Message::Text(snapshot)).await?;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let text = serde_json::to_string(&event)?;
+                    write.send(Message::Text(text)).await?;
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            message = read.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => (),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:9899".parse().unwrap()
+    }
+
+    #[test]
+    fn allows_the_dashboard_address() {
+        assert!(is_allowed(addr(), Some("127.0.0.1:9899"), None));
+        assert!(is_allowed(addr(), Some("localhost:9899"), None));
+        assert!(is_allowed(
+            addr(),
+            Some("127.0.0.1:9899"),
+            Some("http://127.0.0.1:9899")
+        ));
+    }
+
+    #[test]
+    fn rejects_other_hosts() {
+        // DNS rebinding
+        assert!(!is_allowed(addr(), Some("evil.example.com:9899"), None));
+        assert!(!is_allowed(addr(), Some("127.0.0.1:80"), None));
+        assert!(!is_allowed(addr(), None, None));
+    }
+
+    #[test]
+    fn rejects_other_origins() {
+        assert!(!is_allowed(
+            addr(),
+            Some("127.0.0.1:9899"),
+            Some("https://evil.example.com")
+        ));
+        assert!(!is_allowed(addr(), Some("127.0.0.1:9899"), Some("null")));
+    }
+
+    #[test]
+    fn reads_the_headers() {
+        let head = "GET / HTTP/1.1\r\nhost: 127.0.0.1:9899\r\nOrigin: http://a\r\n\r\n";
+        assert_eq!(header(head, "Host"), Some("127.0.0.1:9899"));
+        assert_eq!(header(head, "origin"), Some("http://a"));
+        assert_eq!(header(head, "Cookie"), None);
+    }
+
+    #[tokio::test]
+    async fn refuses_other_addresses() {
+        assert!(bind("0.0.0.0:0".parse().unwrap()).await.is_err());
+        assert!(bind("127.0.0.1:0".parse().unwrap()).await.is_ok());
+    }
+}
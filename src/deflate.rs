@@ -0,0 +1,603 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::metrics;
+
+/// Extension offered in the handshake request (`Sec-WebSocket-Extensions`)
+/// Each message is compressed on its own so that neither side keeps a window between messages.
+pub const EXTENSION_OFFER: &str =
+    "permessage-deflate; client_no_context_takeover; server_no_context_takeover";
+
+/// Largest message accepted after decompression
+const MAX_MESSAGE_SIZE: usize = 64 << 20;
+/// Trailer removed from each compressed message (RFC 7692, section 7.2.1)
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Negotiation state
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for the handshake response
+    Handshake,
+    /// The server accepted permessage-deflate
+    Deflate,
+    /// Compression is off or was declined by the server
+    Plain,
+}
+
+/// Header of a WebSocket frame
+struct Header {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+/// Stream between the WebSocket library and the connection, compressing the data frames
+/// when the server accepts permessage-deflate (the WebSocket library doesn't support it)
+pub struct DeflateStream<S> {
+    inner: S,
+    state: State,
+    /// Bytes read from the connection, not yet processed
+    raw_in: Vec<u8>,
+    /// Processed bytes to hand to the WebSocket library
+    plain_in: Vec<u8>,
+    plain_pos: usize,
+    /// Opcode and payload of a compressed message split into fragments
+    fragments: Option<(u8, Vec<u8>)>,
+    inflater: Decompress,
+    /// Bytes written by the WebSocket library, not yet processed
+    raw_out: Vec<u8>,
+    /// Processed bytes to write to the connection
+    wire_out: Vec<u8>,
+    deflater: Compress,
+}
+
+impl<S> DeflateStream<S> {
+    /// Wraps the connection (`enabled`: whether permessage-deflate is offered in the handshake)
+    pub fn new(inner: S, enabled: bool) -> Self {
+        metrics::COMPRESSION_ACTIVE.set(0);
+        Self {
+            inner,
+            state: if enabled {
+                State::Handshake
+            } else {
+                State::Plain
+            },
+            raw_in: Vec::new(),
+            plain_in: Vec::new(),
+            plain_pos: 0,
+            fragments: None,
+            inflater: Decompress::new(false),
+            raw_out: Vec::new(),
+            wire_out: Vec::new(),
+            deflater: Compress::new(Compression::default(), false),
+        }
+    }
+
+    /// Processes the bytes read from the connection
+    fn process_incoming(&mut self) -> io::Result<()> {
+        if self.state == State::Handshake {
+            let Some(end) = self.raw_in.windows(4).position(|w| w == b"\r\n\r\n") else {
+                return Ok(());
+            };
+            let head: Vec<u8> = self.raw_in.drain(..end + 4).collect();
+            self.state = if accepts_deflate(&String::from_utf8_lossy(&head)) {
+                tracing::debug!("permessage-deflate negotiated");
+                metrics::COMPRESSION_ACTIVE.set(1);
+                State::Deflate
+            } else {
+                tracing::debug!("The server declined permessage-deflate");
+                State::Plain
+            };
+            self.plain_in.extend_from_slice(&head);
+        }
+        if self.state == State::Plain {
+            self.plain_in.append(&mut self.raw_in);
+            return Ok(());
+        }
+
+        // Frames from the server
+        while let Some(header) = parse_header(&self.raw_in)? {
+            let frame_len = header.header_len + header.payload_len;
+            if self.raw_in.len() < frame_len {
+                break;
+            }
+            let payload = &self.raw_in[header.header_len..frame_len];
+            match (header.opcode, &mut self.fragments) {
+                // Start of a compressed message
+                (1 | 2, None) if header.rsv1 => {
+                    metrics::COMPRESSED_BYTES_RECEIVED.add(payload.len() as u64);
+                    if header.fin {
+                        let message = inflate(&mut self.inflater, payload)?;
+                        write_frame(&mut self.plain_in, header.opcode, None, &message);
+                    } else {
+                        self.fragments = Some((header.opcode, payload.to_vec()));
+                    }
+                }
+                // Rest of a compressed message
+                (0, Some((opcode, fragments))) => {
+                    metrics::COMPRESSED_BYTES_RECEIVED.add(payload.len() as u64);
+                    fragments.extend_from_slice(payload);
+                    if fragments.len() > MAX_MESSAGE_SIZE {
+                        return Err(invalid_data("Compressed message too large"));
+                    }
+                    if header.fin {
+                        let opcode = *opcode;
+                        let message = inflate(&mut self.inflater, fragments)?;
+                        self.fragments = None;
+                        write_frame(&mut self.plain_in, opcode, None, &message);
+                    }
+                }
+                // Control frames and uncompressed messages
+                (opcode, _) => {
+                    if opcode < 8 {
+                        metrics::COMPRESSED_BYTES_RECEIVED.add(payload.len() as u64);
+                        metrics::UNCOMPRESSED_BYTES_RECEIVED.add(payload.len() as u64);
+                    }
+                    self.plain_in.extend_from_slice(&self.raw_in[..frame_len]);
+                }
+            }
+            self.raw_in.drain(..frame_len);
+        }
+        Ok(())
+    }
+
+    /// Processes the bytes written by the WebSocket library
+    fn process_outgoing(&mut self) -> io::Result<()> {
+        if self.state != State::Deflate {
+            self.wire_out.append(&mut self.raw_out);
+            return Ok(());
+        }
+
+        // Frames from the client (masked)
+        while let Some(header) = parse_header(&self.raw_out)? {
+            let frame_len = header.header_len + header.payload_len;
+            if self.raw_out.len() < frame_len {
+                break;
+            }
+            let frame = &self.raw_out[..frame_len];
+            let compressible = matches!(header.opcode, 1 | 2) && header.fin && !header.rsv1;
+            match header.mask {
+                Some(mask) if compressible => {
+                    let mut payload = frame[header.header_len..].to_vec();
+                    apply_mask(&mut payload, mask);
+                    let compressed = deflate(&mut self.deflater, &payload)?;
+                    metrics::UNCOMPRESSED_BYTES_SENT.add(payload.len() as u64);
+                    // Messages that don't shrink are sent as they are
+                    if compressed.len() < payload.len() {
+                        metrics::COMPRESSED_BYTES_SENT.add(compressed.len() as u64);
+                        let mut masked = compressed;
+                        apply_mask(&mut masked, mask);
+                        write_frame(
+                            &mut self.wire_out,
+                            header.opcode | 0x40,
+                            Some(mask),
+                            &masked,
+                        );
+                    } else {
+                        metrics::COMPRESSED_BYTES_SENT.add(payload.len() as u64);
+                        self.wire_out.extend_from_slice(frame);
+                    }
+                }
+                _ => self.wire_out.extend_from_slice(frame),
+            }
+            self.raw_out.drain(..frame_len);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsyncWrite + Unpin> DeflateStream<S> {
+    /// Writes the processed bytes to the connection
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.wire_out.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.wire_out))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.wire_out.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.plain_pos < this.plain_in.len() {
+                let available = &this.plain_in[this.plain_pos..];
+                let n = available.len().min(buf.remaining());
+                buf.put_slice(&available[..n]);
+                this.plain_pos += n;
+                if this.plain_pos == this.plain_in.len() {
+                    this.plain_in.clear();
+                    this.plain_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // End of the stream
+                return Poll::Ready(Ok(()));
+            }
+            this.raw_in.extend_from_slice(chunk_buf.filled());
+            this.process_incoming()?;
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.raw_out.extend_from_slice(buf);
+        this.process_outgoing()?;
+        // The rest is written on the next write or flush
+        if let Poll::Ready(Err(err)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Whether the handshake response accepts permessage-deflate
+fn accepts_deflate(head: &str) -> bool {
+    head.lines().any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("sec-websocket-extensions")
+                && value.split(',').any(|ext| {
+                    ext.split(';').next().unwrap_or_default().trim() == "permessage-deflate"
+                })
+        })
+    })
+}
+
+/**
+ * Parses the header of a frame
+ * @return None if more bytes are needed
+ */
+fn parse_header(buf: &[u8]) -> io::Result<Option<Header>> {
+    let (Some(&b0), Some(&b1)) = (buf.first(), buf.get(1)) else {
+        return Ok(None);
+    };
+    let (payload_len, mut pos) = match b1 & 0x7f {
+        126 => match buf.get(2..4) {
+            Some(len) => (u16::from_be_bytes([len[0], len[1]]) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match buf.get(2..10) {
+            Some(len) => (u64::from_be_bytes(len.try_into().unwrap_or_default()), 10),
+            None => return Ok(None),
+        },
+        len => (len as u64, 2),
+    };
+    if payload_len > MAX_MESSAGE_SIZE as u64 {
+        return Err(invalid_data("Frame too large"));
+    }
+    let mask = if b1 & 0x80 != 0 {
+        let Some(mask) = buf.get(pos..pos + 4) else {
+            return Ok(None);
+        };
+        pos += 4;
+        Some([mask[0], mask[1], mask[2], mask[3]])
+    } else {
+        None
+    };
+    Ok(Some(Header {
+        fin: b0 & 0x80 != 0,
+        rsv1: b0 & 0x40 != 0,
+        opcode: b0 & 0x0f,
+        mask,
+        header_len: pos,
+        payload_len: payload_len as usize,
+    }))
+}
+
+/// Writes a complete (FIN) frame, `opcode` may include the RSV1 bit
+fn write_frame(out: &mut Vec<u8>, opcode: u8, mask: Option<[u8; 4]>, payload: &[u8]) {
+    out.push(0x80 | opcode);
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len @ 0..=125 => out.push(mask_bit | len as u8),
+        len @ 126..=0xffff => {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if let Some(mask) = mask {
+        out.extend_from_slice(&mask);
+    }
+    out.extend_from_slice(payload);
+}
+
+/// Masks or unmasks a payload
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Compresses a message (without the trailer)
+fn deflate(deflater: &mut Compress, payload: &[u8]) -> io::Result<Vec<u8>> {
+    deflater.reset();
+    let mut output = Vec::with_capacity(payload.len() / 2 + 64);
+    loop {
+        let consumed = deflater.total_in() as usize;
+        deflater
+            .compress_vec(&payload[consumed..], &mut output, FlushCompress::Sync)
+            .map_err(io::Error::other)?;
+        // Done once everything is consumed and flushed with room to spare
+        if deflater.total_in() as usize == payload.len() && output.len() < output.capacity() {
+            break;
+        }
+        output.reserve(output.capacity().max(64));
+    }
+    if output.ends_with(&TRAILER) {
+        output.truncate(output.len() - TRAILER.len());
+    }
+    Ok(output)
+}
+
+/// Decompresses a message
+fn inflate(inflater: &mut Decompress, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let input: Vec<u8> = payload.iter().chain(&TRAILER).copied().collect();
+    let start_in = inflater.total_in();
+    let mut output = Vec::with_capacity(payload.len() * 4 + 64);
+    loop {
+        let consumed = (inflater.total_in() - start_in) as usize;
+        let status = inflater
+            .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+            .map_err(|err| invalid_data(&format!("Invalid compressed message: {err}")))?;
+        if output.len() > MAX_MESSAGE_SIZE {
+            return Err(invalid_data("Compressed message too large"));
+        }
+        let consumed = (inflater.total_in() - start_in) as usize;
+        if consumed == input.len() && output.len() < output.capacity()
+            || status == Status::StreamEnd
+        {
+            break;
+        }
+        if status == Status::BufError && output.len() < output.capacity() {
+            return Err(invalid_data("Truncated compressed message"));
+        }
+        output.reserve(output.capacity());
+    }
+    metrics::UNCOMPRESSED_BYTES_RECEIVED.add(output.len() as u64);
+    Ok(output)
+}
+
+/// Error for data the server should not have sent
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCEPTED: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nSec-WebSocket-Extensions: permessage-deflate; server_no_context_takeover\r\n\r\n";
+    const DECLINED: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n";
+    const MASK: [u8; 4] = [1, 2, 3, 4];
+
+    /// Stream after the handshake response `head`
+    fn stream(head: &[u8]) -> DeflateStream<()> {
+        let mut stream = DeflateStream::new((), true);
+        stream.raw_in.extend_from_slice(head);
+        stream.process_incoming().unwrap();
+        assert_eq!(stream.plain_in, head);
+        stream.plain_in.clear();
+        stream
+    }
+
+    /// Frame with up to 125 bytes of unmasked payload (`b0`: FIN, RSV1 and opcode)
+    fn frame(b0: u8, payload: &[u8]) -> Vec<u8> {
+        [&[b0, payload.len() as u8], payload].concat()
+    }
+
+    fn compress(payload: &[u8]) -> Vec<u8> {
+        deflate(&mut Compress::new(Compression::default(), false), payload).unwrap()
+    }
+
+    /// Parses the frames of `buf` (FIN, RSV1, opcode, unmasked payload)
+    fn frames(mut buf: &[u8]) -> Vec<(bool, bool, u8, Vec<u8>)> {
+        let mut frames = vec![];
+        while let Some(header) = parse_header(buf).unwrap() {
+            let end = header.header_len + header.payload_len;
+            let mut payload = buf[header.header_len..end].to_vec();
+            if let Some(mask) = header.mask {
+                apply_mask(&mut payload, mask);
+            }
+            frames.push((header.fin, header.rsv1, header.opcode, payload));
+            buf = &buf[end..];
+        }
+        assert!(buf.is_empty());
+        frames
+    }
+
+    #[test]
+    fn writes_and_parses_every_length() {
+        for (len, header_len) in [(0, 2), (125, 2), (126, 4), (65535, 4), (65536, 10)] {
+            for mask in [None, Some(MASK)] {
+                let payload = vec![7; len];
+                let mut buf = vec![];
+                write_frame(&mut buf, 0x42, mask, &payload);
+                let header = parse_header(&buf).unwrap().unwrap();
+                let mask_len = if mask.is_some() { 4 } else { 0 };
+                assert_eq!(header.header_len, header_len + mask_len, "{len}");
+                assert_eq!(header.payload_len, len);
+                assert_eq!(header.mask, mask);
+                assert!(header.fin && header.rsv1);
+                assert_eq!(header.opcode, 2);
+                // Incomplete headers need more bytes
+                for end in 0..header.header_len {
+                    assert!(parse_header(&buf[..end]).unwrap().is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_frames_too_large() {
+        let mut buf = vec![0x82, 127];
+        buf.extend_from_slice(&(MAX_MESSAGE_SIZE as u64 + 1).to_be_bytes());
+        assert!(parse_header(&buf).is_err());
+    }
+
+    #[test]
+    fn negotiates_in_the_handshake() {
+        assert!(stream(ACCEPTED).state == State::Deflate);
+        assert!(stream(DECLINED).state == State::Plain);
+
+        // The response can arrive in pieces
+        let mut stream = DeflateStream::new((), true);
+        stream.raw_in.extend_from_slice(&ACCEPTED[..20]);
+        stream.process_incoming().unwrap();
+        assert!(stream.state == State::Handshake && stream.plain_in.is_empty());
+        stream.raw_in.extend_from_slice(&ACCEPTED[20..]);
+        stream.process_incoming().unwrap();
+        assert!(stream.state == State::Deflate);
+        assert_eq!(stream.plain_in, ACCEPTED);
+    }
+
+    #[test]
+    fn passes_through_when_declined() {
+        let mut stream = stream(DECLINED);
+        let data = frame(0xc1, b"not really compressed");
+        stream.raw_in.extend_from_slice(&data);
+        stream.process_incoming().unwrap();
+        assert_eq!(stream.plain_in, data);
+
+        let mut data = vec![];
+        write_frame(&mut data, 1, Some(MASK), &[b'a'; 100]);
+        stream.raw_out.extend_from_slice(&data);
+        stream.process_outgoing().unwrap();
+        assert_eq!(stream.wire_out, data);
+    }
+
+    #[test]
+    fn inflates_a_single_frame() {
+        let mut stream = stream(ACCEPTED);
+        let message = b"{\"cmd\":\"game\",\"cmd\":\"game\",\"cmd\":\"game\"}";
+        let data = frame(0xc1, &compress(message));
+        // Frames can arrive in pieces
+        stream.raw_in.extend_from_slice(&data[..3]);
+        stream.process_incoming().unwrap();
+        assert!(stream.plain_in.is_empty());
+        stream.raw_in.extend_from_slice(&data[3..]);
+        stream.process_incoming().unwrap();
+        assert_eq!(
+            frames(&stream.plain_in),
+            [(true, false, 1, message.to_vec())]
+        );
+    }
+
+    #[test]
+    fn inflates_fragments_around_control_frames() {
+        let mut stream = stream(ACCEPTED);
+        let message = b"hello hello hello hello hello";
+        let compressed = compress(message);
+        let (first, rest) = compressed.split_at(compressed.len() / 2);
+        stream.raw_in.extend_from_slice(&frame(0x41, first));
+        stream.raw_in.extend_from_slice(&frame(0x89, b"ping"));
+        stream.raw_in.extend_from_slice(&frame(0x80, rest));
+        stream.process_incoming().unwrap();
+        assert_eq!(
+            frames(&stream.plain_in),
+            [
+                (true, false, 9, b"ping".to_vec()),
+                (true, false, 1, message.to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_through_uncompressed_fragments() {
+        let mut stream = stream(ACCEPTED);
+        let data = [frame(0x01, b"ab"), frame(0x80, b"cd"), frame(0x82, b"ef")].concat();
+        stream.raw_in.extend_from_slice(&data);
+        stream.process_incoming().unwrap();
+        assert_eq!(stream.plain_in, data);
+    }
+
+    #[test]
+    fn deflates_outgoing_messages() {
+        let mut stream = stream(ACCEPTED);
+        let message = [b'a'; 200];
+        let mut masked = message;
+        apply_mask(&mut masked, MASK);
+        write_frame(&mut stream.raw_out, 1, Some(MASK), &masked);
+        stream.process_outgoing().unwrap();
+        let frames = frames(&stream.wire_out);
+        let [(true, true, 1, compressed)] = &frames[..] else {
+            panic!("Not a compressed frame");
+        };
+        assert!(compressed.len() < message.len());
+        let inflated = inflate(&mut Decompress::new(false), compressed).unwrap();
+        assert_eq!(inflated, message);
+    }
+
+    #[test]
+    fn sends_messages_that_dont_shrink_as_they_are() {
+        let mut stream = stream(ACCEPTED);
+        let mut data = vec![];
+        write_frame(&mut data, 1, Some(MASK), b"a");
+        // Control frames and fragments are never compressed
+        write_frame(&mut data, 9, Some(MASK), &[b'a'; 100]);
+        data.extend_from_slice(&[0x01, 0x80 | 100]);
+        data.extend_from_slice(&MASK);
+        data.extend_from_slice(&[b'a'; 100]);
+        stream.raw_out.extend_from_slice(&data);
+        stream.process_outgoing().unwrap();
+        assert_eq!(stream.wire_out, data);
+    }
+
+    #[test]
+    fn limits_the_inflated_size() {
+        let compressed = compress(&vec![0; MAX_MESSAGE_SIZE]);
+        assert!(inflate(&mut Decompress::new(false), &compressed).is_ok());
+        let compressed = compress(&vec![0; MAX_MESSAGE_SIZE + 1]);
+        assert!(inflate(&mut Decompress::new(false), &compressed).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_compressed_data() {
+        let mut stream = stream(ACCEPTED);
+        stream.raw_in.extend_from_slice(&frame(0xc1, &[0xff; 10]));
+        assert!(stream.process_incoming().is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{Context as _, Result};
+use chrono::{Datelike as _, Local, Timelike as _};
+use flate2::{write::DeflateEncoder, Compression, Crc};
+use std::{
+    fs::{self, File},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::Path,
+};
+
+/// Largest part of a file (its end) put in a bundle, the logs can grow large
+const MAX_FILE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Error when the sizes or offsets of a bundle don't fit in a zip archive (without the Zip64 extensions)
+const TOO_LARGE: &str = "The bundle is too large for a zip archive";
+
+/// Keys whose string values are masked in the configuration files (matched as substrings)
+const SECRET_KEYS: [&str; 5] = ["uuid", "token", "secret", "password", "key"];
+
+/// Files gathered to be attached to a bug report, written as a zip archive
+#[derive(Default)]
+pub struct Bundle {
+    files: Vec<(String, Vec<u8>)>,
+    /// Texts masked in the files added from now on
+    secrets: Vec<String>,
+}
+
+impl Bundle {
+    /// Masks a text in the files added from now on (e.g. the UUID in the logs)
+    pub fn redact(&mut self, secret: &str) {
+        if !secret.is_empty() {
+            self.secrets.push(secret.to_string());
+        }
+    }
+
+    /// Adds a file with the given content
+    pub fn add(&mut self, name: &str, content: impl Into<Vec<u8>>) {
+        let mut content = content.into();
+        if let Ok(text) = std::str::from_utf8(&content) {
+            let mut text = text.to_string();
+            for secret in &self.secrets {
+                text = text.replace(secret, &mask(secret));
+            }
+            content = text.into_bytes();
+        }
+        self.files.push((name.to_string(), content));
+    }
+
+    /// Adds the end of a file from the disk (at most `MAX_FILE_SIZE` bytes)
+    pub fn add_file(&mut self, name: &str, path: &Path) -> Result<()> {
+        let mut file =
+            File::open(path).with_context(|| format!("Unable to open file: {:?}", path))?;
+        let size = file.metadata()?.len();
+        if size > MAX_FILE_SIZE {
+            file.seek(SeekFrom::Start(size - MAX_FILE_SIZE))?;
+        }
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .with_context(|| format!("Unable to read file: {:?}", path))?;
+        self.add(name, content);
+        Ok(())
+    }
+
+    /// Writes the files to a zip archive (deflated)
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let (time, date) = dos_time();
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for (name, content) in &self.files {
+            let mut crc = Crc::new();
+            crc.update(content);
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content)?;
+            let compressed = encoder.finish()?;
+            let offset = u32::try_from(archive.len()).context(TOO_LARGE)?;
+            let compressed_size = u32::try_from(compressed.len()).context(TOO_LARGE)?;
+            let size = u32::try_from(content.len()).context(TOO_LARGE)?;
+            let name_len = u16::try_from(name.len()).context("The file name is too long")?;
+
+            // Fields shared by the local header and the central directory entry
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&20u16.to_le_bytes()); // Version needed to extract
+            fields.extend_from_slice(&0x0800u16.to_le_bytes()); // UTF-8 names
+            fields.extend_from_slice(&8u16.to_le_bytes()); // Deflate
+            fields.extend_from_slice(&time.to_le_bytes());
+            fields.extend_from_slice(&date.to_le_bytes());
+            fields.extend_from_slice(&crc.sum().to_le_bytes());
+            fields.extend_from_slice(&compressed_size.to_le_bytes());
+            fields.extend_from_slice(&size.to_le_bytes());
+            fields.extend_from_slice(&name_len.to_le_bytes());
+            fields.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+
+            archive.extend_from_slice(&0x04034b50u32.to_le_bytes());
+            archive.extend_from_slice(&fields);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(&compressed);
+
+            directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // Version made by
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 6]); // Comment length, disk, internal attributes
+            directory.extend_from_slice(&0u32.to_le_bytes()); // External attributes
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        // End of the central directory
+        let entries =
+            u16::try_from(self.files.len()).context("Too many files for a zip archive")?;
+        let directory_offset = u32::try_from(archive.len()).context(TOO_LARGE)?;
+        let directory_size = u32::try_from(directory.len()).context(TOO_LARGE)?;
+        archive.extend_from_slice(&directory);
+        archive.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        archive.extend_from_slice(&[0; 4]); // Disk numbers
+        archive.extend_from_slice(&entries.to_le_bytes());
+        archive.extend_from_slice(&entries.to_le_bytes());
+        archive.extend_from_slice(&directory_size.to_le_bytes());
+        archive.extend_from_slice(&directory_offset.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+
+        fs::write(path, archive).with_context(|| format!("Unable to write file: {:?}", path))
+    }
+}
+
+/// Gets the current local time in the MS-DOS format of the zip headers
+fn dos_time() -> (u16, u16) {
+    let now = Local::now();
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let year = now.year().clamp(1980, 2107) as u32 - 1980;
+    let date = ((year << 9) | (now.month() << 5) | now.day()) as u16;
+    (time, date)
+}
+
+/// Masks a secret so that only the first few characters are visible (none of the short ones)
+fn mask(secret: &str) -> String {
+    if secret.chars().count() <= 4 {
+        return "…".to_string();
+    }
+    let visible: String = secret.chars().take(4).collect();
+    format!("{visible}…")
+}
+
+/// Keeps only the scheme and the host of a URL (the path and the query of webhooks contain tokens)
+pub fn mask_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    // Credentials of a proxy
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if authority.len() == rest.len() && host.len() == authority.len() {
+        url.to_string()
+    } else {
+        format!("{scheme}://{host}/…")
+    }
+}
+
+/// Masks the secrets in a value of a configuration file
+fn sanitize_value(key: &str, value: &mut toml::Value) {
+    match value {
+        toml::Value::String(text) if SECRET_KEYS.iter().any(|secret| key.contains(secret)) => {
+            *text = mask(text);
+        }
+        toml::Value::String(text) if text.contains("://") => *text = mask_url(text),
+        toml::Value::Array(values) => {
+            for value in values {
+                sanitize_value(key, value);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                sanitize_value(&key.to_lowercase(), value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Masks the secrets in a JSON value (e.g. a message traced on the console)
+pub fn sanitize_json(key: &str, value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text)
+            if SECRET_KEYS.iter().any(|secret| key.contains(secret)) =>
+        {
+            *text = mask(text);
+        }
+        serde_json::Value::String(text) if text.contains("://") => *text = mask_url(text),
+        serde_json::Value::Array(values) => {
+            for value in values {
+                sanitize_json(key, value);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                sanitize_json(&key.to_lowercase(), value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/**
+ * Masks the UUID, the tokens, the secrets and the URL paths in a configuration file
+ * @return The sanitized TOML, or a note if the file cannot be parsed (it is not included as is)
+ */
+pub fn sanitize_config(content: &str) -> String {
+    match content.parse::<toml::Table>() {
+        Ok(table) => {
+            let mut value = toml::Value::Table(table);
+            sanitize_value("", &mut value);
+            toml::to_string_pretty(&value).unwrap_or_default()
+        }
+        Err(err) => format!("# The file is not valid TOML, it was left out: {err}\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+
+    fn u16_at(data: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    /// Reads the files of a zip archive through its central directory, checking the local headers and the CRCs
+    fn read_zip(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = data.len() - 22;
+        assert_eq!(u32_at(data, end), 0x06054b50);
+        let entries = u16_at(data, end + 10);
+        assert_eq!(u16_at(data, end + 8), entries);
+        let directory_size = u32_at(data, end + 12) as usize;
+        let directory_offset = u32_at(data, end + 16) as usize;
+        assert_eq!(directory_offset + directory_size, end);
+
+        let mut files = Vec::new();
+        let mut pos = directory_offset;
+        for _ in 0..entries {
+            assert_eq!(u32_at(data, pos), 0x02014b50);
+            assert_eq!(u16_at(data, pos + 10), 8);
+            let crc = u32_at(data, pos + 16);
+            let compressed_size = u32_at(data, pos + 20) as usize;
+            let size = u32_at(data, pos + 24) as usize;
+            let name_len = u16_at(data, pos + 28) as usize;
+            let offset = u32_at(data, pos + 42) as usize;
+            let name = &data[pos + 46..pos + 46 + name_len];
+
+            // The local header repeats the fields of the directory entry
+            assert_eq!(u32_at(data, offset), 0x04034b50);
+            assert_eq!(data[offset + 4..offset + 30], data[pos + 6..pos + 32]);
+            assert_eq!(&data[offset + 30..offset + 30 + name_len], name);
+            let start = offset + 30 + name_len;
+            let mut content = Vec::new();
+            DeflateDecoder::new(&data[start..start + compressed_size])
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content.len(), size);
+            let mut sum = Crc::new();
+            sum.update(&content);
+            assert_eq!(sum.sum(), crc);
+
+            files.push((String::from_utf8(name.to_vec()).unwrap(), content));
+            pos += 46 + name_len;
+        }
+        assert_eq!(pos, end);
+        files
+    }
+
+    #[test]
+    fn writes_a_readable_zip_archive() {
+        let mut bundle = Bundle::default();
+        bundle.redact("0123456789abcdef");
+        bundle.add("system.txt", "OS: Linux\n");
+        bundle.add(
+            "logs/client.log",
+            "Connected as 0123456789abcdef\n".repeat(100),
+        );
+        bundle.add("empty.txt", "");
+        bundle.add("binary.bin", vec![0, 159, 146, 150]);
+
+        let path =
+            std::env::temp_dir().join(format!("diagnostics-test-{}.zip", std::process::id()));
+        bundle.write(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let files = read_zip(&data);
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            files[0],
+            ("system.txt".to_string(), b"OS: Linux\n".to_vec())
+        );
+        assert_eq!(files[1].0, "logs/client.log");
+        assert_eq!(files[1].1, "Connected as 0123…\n".repeat(100).into_bytes());
+        assert_eq!(files[2], ("empty.txt".to_string(), vec![]));
+        assert_eq!(files[3], ("binary.bin".to_string(), vec![0, 159, 146, 150]));
+    }
+
+    #[test]
+    fn masks_the_short_secrets_fully() {
+        assert_eq!(mask("0123456789"), "0123…");
+        assert_eq!(mask("01234"), "0123…");
+        assert_eq!(mask("0123"), "…");
+        assert_eq!(mask("ab"), "…");
+        assert_eq!(mask(""), "…");
+    }
+
+    #[test]
+    fn masks_the_secrets_in_the_configuration() {
+        let config = sanitize_config(
+            "uuid = \"0123456789\"\npin = \"1234\"\n\n[[webhooks]]\nurl = \"https://example.com/hooks/secret\"\nsecret = \"abc\"\n",
+        );
+        assert!(config.contains("uuid = \"0123…\""));
+        assert!(config.contains("pin = \"1234\""));
+        assert!(config.contains("url = \"https://example.com/…\""));
+        assert!(config.contains("secret = \"…\""));
+    }
+}
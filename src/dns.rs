@@ -0,0 +1,546 @@
+use anyhow::{bail, Context as _, Result};
+use rand::Rng as _;
+use rustls::pki_types::ServerName;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{lookup_host, TcpStream, UdpSocket},
+    time::timeout,
+};
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::http::Uri;
+
+use crate::tls;
+
+/// Seconds to wait for the DNS server to answer
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds to wait for an address before falling back to the next one
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// DNS record type of IPv4 addresses
+const TYPE_A: u16 = 1;
+/// DNS record type of IPv6 addresses
+const TYPE_AAAA: u16 = 28;
+/// Largest HTTP response read from a DNS-over-HTTPS server (a DNS message is at most 64 KiB)
+const MAX_HTTP_RESPONSE_SIZE: u64 = 128 * 1024;
+
+/// IP version to connect with first when the server has both IPv4 and IPv6 addresses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// The order given by the resolver
+    #[default]
+    Any,
+    /// IPv4 addresses first
+    Ipv4,
+    /// IPv6 addresses first
+    Ipv6,
+}
+
+/// DNS server to resolve the server host with
+#[derive(Clone, Debug, Default)]
+pub enum Resolver {
+    /// The resolver of the operating system
+    #[default]
+    System,
+    /// A DNS server queried over UDP
+    Udp(SocketAddr),
+    /// A DNS-over-HTTPS server (RFC 8484)
+    Https(Uri),
+}
+
+impl fmt::Display for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resolver::System => write!(f, "the system resolver"),
+            Resolver::Udp(addr) => write!(f, "{addr}"),
+            Resolver::Https(uri) => write!(f, "{uri}"),
+        }
+    }
+}
+
+impl FromStr for Resolver {
+    type Err = anyhow::Error;
+
+    /// Parses `system`, `1.1.1.1`, `1.1.1.1:53`, `[2606:4700::1111]:53` or `https://1.1.1.1/dns-query`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("system") {
+            return Ok(Resolver::System);
+        }
+        if s.contains("://") {
+            let uri: Uri = s.parse().context("Failed to parse resolver URL")?;
+            if uri.scheme_str() != Some("https") {
+                bail!("The resolver URL must start with https:// (DNS over HTTPS): {s}");
+            }
+            uri.host().context("Missing host in resolver URL")?;
+            return Ok(Resolver::Https(uri));
+        }
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Resolver::Udp(SocketAddr::new(ip, 53)));
+        }
+        s.parse::<SocketAddr>().map(Resolver::Udp).with_context(|| {
+            format!("Invalid resolver (expected an IP address or an https:// URL): {s}")
+        })
+    }
+}
+
+/// How the host of the server is resolved and connected to
+#[derive(Clone, Debug, Default)]
+pub struct DnsOptions {
+    /// DNS server to resolve the host with
+    pub resolver: Resolver,
+    /// IP version to connect with first
+    pub preference: IpPreference,
+}
+
+/**
+ * Resolves the host of the server
+ * @return The addresses to connect to, the preferred IP version first
+ */
+pub async fn resolve(host: &str, port: u16, options: &DnsOptions) -> Result<Vec<SocketAddr>> {
+    let mut addrs: Vec<SocketAddr> = if let Ok(ip) = host.parse::<IpAddr>() {
+        vec![SocketAddr::new(ip, port)]
+    } else {
+        match &options.resolver {
+            Resolver::System => lookup_host((host, port))
+                .await
+                .with_context(|| format!("Failed to resolve {host}"))?
+                .collect(),
+            resolver => {
+                let (v4, v6) = tokio::join!(
+                    query(resolver, host, TYPE_A),
+                    query(resolver, host, TYPE_AAAA)
+                );
+                // Either of them is enough
+                let ips = match (v4, v6) {
+                    (Err(err), Err(_)) => {
+                        return Err(err)
+                            .with_context(|| format!("Failed to resolve {host} with {resolver}"))
+                    }
+                    (v4, v6) => v4
+                        .unwrap_or_default()
+                        .into_iter()
+                        .chain(v6.unwrap_or_default()),
+                };
+                ips.map(|ip| SocketAddr::new(ip, port)).collect()
+            }
+        }
+    };
+
+    // Stable sort keeps the order of the resolver within each IP version
+    match options.preference {
+        IpPreference::Any => (),
+        IpPreference::Ipv4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+        IpPreference::Ipv6 => addrs.sort_by_key(|addr| !addr.is_ipv6()),
+    }
+    if addrs.is_empty() {
+        bail!("No addresses found for {host}");
+    }
+    tracing::debug!(host, ?addrs, "resolved");
+    Ok(addrs)
+}
+
+/// Connects to the first address of the server that answers
+pub async fn connect(host: &str, port: u16, options: &DnsOptions) -> Result<TcpStream> {
+    let addrs = resolve(host, port, options).await?;
+    let mut last_err = std::io::Error::from(std::io::ErrorKind::NotFound);
+    for (i, addr) in addrs.iter().enumerate() {
+        // Don't let a broken path use up the whole connection timeout
+        let result = if i + 1 < addrs.len() {
+            timeout(FALLBACK_TIMEOUT, TcpStream::connect(addr))
+                .await
+                .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+        } else {
+            TcpStream::connect(addr).await
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                tracing::debug!(%addr, "failed to connect: {err}");
+                last_err = err;
+            }
+        }
+    }
+    Err(last_err).context("Failed to connect to the server")
+}
+
+/// Queries the DNS server for the addresses of a type
+async fn query(resolver: &Resolver, host: &str, record_type: u16) -> Result<Vec<IpAddr>> {
+    // The ID is 0 over HTTPS so that the answers can be cached (RFC 8484)
+    let id = match resolver {
+        Resolver::Https(_) => 0,
+        _ => rand::thread_rng().gen(),
+    };
+    let request = build_query(id, host, record_type)?;
+    let response = match resolver {
+        Resolver::System => bail!("The system resolver cannot be queried directly"),
+        Resolver::Udp(server) => timeout(QUERY_TIMEOUT, query_udp(*server, &request))
+            .await
+            .context("The DNS server did not answer in time")??,
+        Resolver::Https(uri) => timeout(QUERY_TIMEOUT, query_https(uri, &request))
+            .await
+            .context("The DNS server did not answer in time")??,
+    };
+    parse_response(&response, id, record_type)
+}
+
+/// Sends a query over UDP
+async fn query_udp(server: SocketAddr, request: &[u8]) -> Result<Vec<u8>> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)
+        .await
+        .context("Failed to open a UDP socket")?;
+    socket
+        .connect(server)
+        .await
+        .with_context(|| format!("Failed to reach the DNS server {server}"))?;
+    socket
+        .send(request)
+        .await
+        .context("Failed to send the DNS query")?;
+    let mut buf = vec![0; 4096];
+    let len = socket
+        .recv(&mut buf)
+        .await
+        .context("Failed to receive the DNS answer")?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Sends a query over HTTPS (POST with `application/dns-message`)
+async fn query_https(uri: &Uri, request: &[u8]) -> Result<Vec<u8>> {
+    let host = uri
+        .host()
+        .context("Missing host in resolver URL")?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(443);
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+    let stream = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("Failed to connect to the DNS server {host}"))?;
+    let server_name = ServerName::try_from(host.to_string()).context("Invalid resolver host")?;
+    let connector = TlsConnector::from(tls::builtin_config()?);
+    let mut stream = connector
+        .connect(server_name, stream)
+        .await
+        .context("TLS handshake with the DNS server failed")?;
+
+    let header = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/dns-message\r\nAccept: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        request.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(request).await?;
+    stream.flush().await?;
+
+    // Read until the server closes the connection (some don't send close_notify)
+    let mut response = Vec::new();
+    if let Err(err) = (&mut stream)
+        .take(MAX_HTTP_RESPONSE_SIZE + 1)
+        .read_to_end(&mut response)
+        .await
+    {
+        if err.kind() != std::io::ErrorKind::UnexpectedEof {
+            return Err(err).context("Failed to read the DNS answer");
+        }
+    }
+    if response.len() as u64 > MAX_HTTP_RESPONSE_SIZE {
+        bail!("The answer of the DNS server is too large");
+    }
+    parse_http_response(&response)
+}
+
+/// Extracts the body of an HTTP/1.1 response
+fn parse_http_response(response: &[u8]) -> Result<Vec<u8>> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("Invalid HTTP response from the DNS server")?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("The DNS server answered: {status}");
+    }
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<usize>().ok();
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+
+    if chunked {
+        return decode_chunked(body);
+    }
+    match content_length {
+        Some(len) => body
+            .get(..len)
+            .map(<[u8]>::to_vec)
+            .context("Truncated HTTP response from the DNS server"),
+        None => Ok(body.to_vec()),
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .context("Invalid chunked response from the DNS server")?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .context("Invalid chunked response from the DNS server")?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = body
+            .get(line_end + 2..line_end + 2 + size)
+            .context("Truncated HTTP response from the DNS server")?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(line_end + 4 + size..).unwrap_or_default();
+    }
+}
+
+/// Builds a recursive query for one record type
+fn build_query(id: u16, host: &str, record_type: u16) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(host.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Flags (recursion desired), 1 question, no answer, authority or additional records
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid host name: {host}");
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    // Class IN
+    query.extend_from_slice(&1u16.to_be_bytes());
+    Ok(query)
+}
+
+/// Parses the addresses of a type in the answer section
+fn parse_response(response: &[u8], id: u16, record_type: u16) -> Result<Vec<IpAddr>> {
+    let invalid = || anyhow::anyhow!("Invalid answer from the DNS server");
+    let read_u16 = |pos: usize| -> Result<u16> {
+        let bytes = response.get(pos..pos + 2).ok_or_else(invalid)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    if read_u16(0)? != id {
+        bail!("The DNS answer does not match the query");
+    }
+    let flags = read_u16(2)?;
+    // The rest of a truncated answer is only sent over TCP
+    if flags & 0x0200 != 0 {
+        bail!("The DNS answer was truncated");
+    }
+    match flags & 0x000f {
+        0 => (),
+        3 => bail!("The host does not exist"),
+        rcode => bail!("The DNS server returned an error (RCODE {rcode})"),
+    }
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(response, pos).ok_or_else(invalid)? + 4;
+    }
+    let mut addrs = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(response, pos).ok_or_else(invalid)?;
+        let rtype = read_u16(pos)?;
+        let len = read_u16(pos + 8)? as usize;
+        let data = response.get(pos + 10..pos + 10 + len).ok_or_else(invalid)?;
+        pos += 10 + len;
+
+        // Answers may also include the CNAME records leading to the addresses
+        if rtype != record_type {
+            continue;
+        }
+        if let Ok(octets) = <[u8; 4]>::try_from(data) {
+            addrs.push(IpAddr::from(octets));
+        } else if let Ok(octets) = <[u8; 16]>::try_from(data) {
+            addrs.push(IpAddr::from(octets));
+        }
+    }
+    Ok(addrs)
+}
+
+/**
+ * Skips a (possibly compressed) domain name
+ * @return The position after the name
+ */
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // Pointer to a name elsewhere in the message
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u16 = 0x1234;
+
+    /// Answer to `A example.com` (the name of the answer points to the question)
+    const ANSWER_A: &[u8] = &[
+        0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // header
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, // name
+        0x00, 0x01, 0x00, 0x01, // A, IN
+        0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, // answer
+        93, 184, 216, 34,
+    ];
+
+    /// Answer to `AAAA example.com`
+    const ANSWER_AAAA: &[u8] = &[
+        0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // header
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, // name
+        0x00, 0x1c, 0x00, 0x01, // AAAA, IN
+        0xc0, 0x0c, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x10, // answer
+        0x26, 0x06, 0x28, 0x00, 0x02, 0x20, 0x00, 0x01, 0x02, 0x48, 0x18, 0x93, 0x25, 0xc8, 0x19,
+        0x46,
+    ];
+
+    /// Answer to `A www.example.com`: a CNAME to `example.com` (pointing into the question), then its address
+    const ANSWER_CNAME: &[u8] = &[
+        0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, // header
+        0x03, b'w', b'w', b'w', 0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o',
+        b'm', 0x00, // name
+        0x00, 0x01, 0x00, 0x01, // A, IN
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x02, // CNAME
+        0xc0, 0x10, // -> example.com
+        0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, // A
+        93, 184, 216, 34,
+    ];
+
+    /// Sets the flags of an answer
+    fn with_flags(answer: &[u8], flags: u16) -> Vec<u8> {
+        let mut answer = answer.to_vec();
+        answer[2..4].copy_from_slice(&flags.to_be_bytes());
+        answer
+    }
+
+    #[test]
+    fn builds_queries() {
+        let query = build_query(ID, "example.com.", TYPE_A).unwrap();
+        // Recursion desired, one question and no answer
+        let header = [
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(query[..12], header);
+        assert_eq!(query[12..], ANSWER_A[12..29]);
+        assert!(build_query(ID, "example..com", TYPE_A).is_err());
+        assert!(build_query(ID, &format!("{}.com", "a".repeat(64)), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            parse_response(ANSWER_A, ID, TYPE_A).unwrap(),
+            [IpAddr::from([93, 184, 216, 34])]
+        );
+        assert_eq!(
+            parse_response(ANSWER_AAAA, ID, TYPE_AAAA).unwrap(),
+            ["2606:2800:220:1:248:1893:25c8:1946"
+                .parse::<IpAddr>()
+                .unwrap()]
+        );
+        // Records of the other type are skipped
+        assert!(parse_response(ANSWER_A, ID, TYPE_AAAA).unwrap().is_empty());
+    }
+
+    #[test]
+    fn follows_cname_chains() {
+        assert_eq!(
+            parse_response(ANSWER_CNAME, ID, TYPE_A).unwrap(),
+            [IpAddr::from([93, 184, 216, 34])]
+        );
+    }
+
+    #[test]
+    fn rejects_error_answers() {
+        // NXDOMAIN
+        let err = parse_response(&with_flags(ANSWER_A, 0x8183), ID, TYPE_A).unwrap_err();
+        assert_eq!(err.to_string(), "The host does not exist");
+        // SERVFAIL
+        assert!(parse_response(&with_flags(ANSWER_A, 0x8182), ID, TYPE_A).is_err());
+        // Truncated (TC)
+        assert!(parse_response(&with_flags(ANSWER_A, 0x8380), ID, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn rejects_answers_to_other_queries() {
+        assert!(parse_response(ANSWER_A, ID + 1, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        for answer in [ANSWER_A, ANSWER_AAAA, ANSWER_CNAME] {
+            for len in 0..answer.len() {
+                assert!(parse_response(&answer[..len], ID, TYPE_A).is_err(), "{len}");
+            }
+        }
+    }
+
+    #[test]
+    fn skips_names() {
+        assert_eq!(skip_name(ANSWER_A, 12), Some(25));
+        assert_eq!(skip_name(ANSWER_A, 29), Some(31));
+        assert_eq!(skip_name(&ANSWER_A[..20], 12), None);
+    }
+
+    #[test]
+    fn reads_http_bodies() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabcdef";
+        assert_eq!(parse_http_response(response).unwrap(), b"abc");
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nabc";
+        assert!(parse_http_response(response).is_err());
+        let response = b"HTTP/1.1 200 OK\r\n\r\nabc";
+        assert_eq!(parse_http_response(response).unwrap(), b"abc");
+        let response = b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n";
+        assert!(parse_http_response(response).is_err());
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let response =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nabc\r\na\r\n0123456789\r\n0\r\n\r\n";
+        assert_eq!(parse_http_response(response).unwrap(), b"abc0123456789");
+        assert!(decode_chunked(b"5\r\nabc").is_err());
+        assert!(decode_chunked(b"x\r\nabc\r\n0\r\n\r\n").is_err());
+        assert!(decode_chunked(b"3\r\nabc\r\n").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use serde::Serialize;
+use std::sync::LazyLock;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// Application events, published once and followed by the console, notifications, metrics and the control API
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Connected to the server
+    Connected {
+        /// Whether the connection was lost before
+        reconnected: bool,
+    },
+    /// The connection to the server was lost
+    Reconnecting {
+        /// Delay before the next attempt in milliseconds
+        delay_ms: u64,
+    },
+    /// An invite link was created
+    InviteCreated {
+        /// Guest ID
+        guest_id: u64,
+        /// Invite URL
+        url: String,
+        /// Game ID
+        game: Option<u32>,
+        /// Game name (None: not known yet)
+        game_name: Option<String>,
+    },
+    /// An unused invite link was revoked (or expired)
+    InviteRevoked {
+        /// Guest ID
+        guest_id: u64,
+    },
+    /// A guest joined the session
+    GuestJoined {
+        /// Guest ID
+        guest_id: u64,
+        /// Guest name
+        name: String,
+        /// Number of guests in the session
+        guests: usize,
+    },
+    /// A guest left the session
+    GuestLeft {
+        /// Guest ID
+        guest_id: u64,
+        /// Guest name
+        name: String,
+        /// Number of guests in the session
+        guests: usize,
+    },
+    /// The session will end soon because of its time limit
+    SessionEnding {
+        /// Minutes before the session ends
+        minutes_left: u64,
+    },
+    /// The server sent an announcement
+    Announcement {
+        /// Announcement title
+        title: Option<String>,
+        /// Announcement text
+        text: String,
+        /// Link to more details
+        url: Option<String>,
+    },
+    /// The server is going down for a planned maintenance
+    Maintenance {
+        /// Maintenance notice
+        message: Option<String>,
+        /// Delay before reconnecting in milliseconds
+        delay_ms: u64,
+    },
+    /// The server issued a pairing code to link the client to Discord
+    PairingCode {
+        /// Pairing code
+        code: String,
+    },
+    /// The client was linked to Discord
+    Paired {
+        /// Name of the linked Discord account
+        account: Option<String>,
+    },
+    /// The Steam client stopped
+    SteamLost,
+    /// The Steam client restarted
+    SteamRestored,
+    /// A line was printed on the console
+    Log {
+        /// Printed text
+        text: String,
+        /// Whether it is an error message
+        error: bool,
+    },
+}
+
+/// Broadcast channel of the events
+static EVENTS: LazyLock<Sender<Event>> = LazyLock::new(|| broadcast::channel(256).0);
+
+/// Sends an event to all subscribers (dropped if there are none)
+pub fn emit(event: Event) {
+    let _ = EVENTS.send(event);
+}
+
+/// Subscribes to the events emitted from now on
+pub fn subscribe() -> Receiver<Event> {
+    EVENTS.subscribe()
+}
@@ -0,0 +1,465 @@
+use clipboard::{ClipboardContext, ClipboardProvider};
+use std::{thread, time::Duration};
+use tokio::sync::{
+    broadcast::{error::TryRecvError, Receiver},
+    mpsc::Sender,
+    oneshot,
+};
+
+use crate::{
+    control::{ControlMethod, ControlRequest},
+    events::{self, Event},
+    i18n::t,
+};
+
+/// Interval between two polls of the window loop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Title of the window
+const TITLE: &str = "Remote Play Inviter";
+
+/// Actions taken in the window
+enum Action {
+    /// The "Create invite" button was clicked
+    CreateInvite,
+    /// The "Copy" button was clicked
+    CopyLink,
+    /// The window was closed
+    Close,
+}
+
+/// State shown in the window
+struct GuiState {
+    /// Connection to the server and to Steam
+    status: String,
+    /// Most recent invite link
+    invite_url: Option<String>,
+    /// Connected guests (guest ID, name)
+    guests: Vec<(u64, String)>,
+}
+
+impl GuiState {
+    fn new() -> Self {
+        Self {
+            status: format!("↪ {}", t!("status-connecting")),
+            invite_url: None,
+            guests: Vec::new(),
+        }
+    }
+
+    /**
+     * Applies an event to the state
+     * @return Whether the window must be updated
+     */
+    fn apply(&mut self, event: Event) -> bool {
+        match event {
+            Event::Connected { .. } | Event::SteamRestored => {
+                self.status = format!("✓ {}", t!("deck-online"))
+            }
+            Event::Reconnecting { .. } | Event::Maintenance { .. } => {
+                self.status = format!("↪ {}", t!("deck-reconnecting"))
+            }
+            Event::SteamLost => {
+                self.status = format!("☓ {}", t!("deck-steam-lost"));
+                self.guests.clear();
+            }
+            Event::InviteCreated { url, .. } => self.invite_url = Some(url),
+            Event::GuestJoined { guest_id, name, .. } => {
+                self.guests.retain(|(id, _)| *id != guest_id);
+                self.guests.push((guest_id, name));
+            }
+            Event::GuestLeft { guest_id, .. } => self.guests.retain(|(id, _)| *id != guest_id),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Gets the text of the guest list
+    fn guest_text(&self) -> String {
+        if self.guests.is_empty() {
+            return t!("gui-no-guests");
+        }
+        let names: Vec<String> = self
+            .guests
+            .iter()
+            .map(|(_, name)| format!("★ {name}"))
+            .collect();
+        names.join("\n")
+    }
+}
+
+/// Opens the window on its own thread
+///
+/// The buttons are sent to the client loop as control requests, like the tray menu.
+#[cfg(any(target_os = "linux", windows))]
+pub fn spawn(control_tx: Sender<ControlRequest>) {
+    // Subscribe now so that the events emitted while the window opens are not missed
+    let events = events::subscribe();
+    let result = thread::Builder::new().name("gui".into()).spawn(move || {
+        if let Err(err) = run(control_tx, events) {
+            tracing::warn!("The window is not available: {err:#}");
+        }
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to start the window thread: {err}");
+    }
+}
+
+/// Opens the window on its own thread
+///
+/// The window must run on the main thread on this platform, which belongs to the async runtime.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn spawn(_control_tx: Sender<ControlRequest>) {
+    tracing::warn!("The window is not supported on this platform");
+}
+
+/// Creates the window and runs its loop until it is closed
+#[cfg(any(target_os = "linux", windows))]
+fn run(control_tx: Sender<ControlRequest>, mut events: Receiver<Event>) -> anyhow::Result<()> {
+    let window = window::Window::new()?;
+    let mut state = GuiState::new();
+    window.update(&state);
+
+    loop {
+        for action in window.pump() {
+            match action {
+                Action::CreateInvite => request(&control_tx, ControlMethod::CreateInvite),
+                Action::CopyLink => {
+                    if let Some(url) = &state.invite_url {
+                        copy_to_clipboard(url);
+                    }
+                }
+                // Closing the window exits the client
+                Action::Close => {
+                    request(&control_tx, ControlMethod::Shutdown);
+                    return Ok(());
+                }
+            }
+        }
+
+        // Application events
+        let mut changed = false;
+        loop {
+            match events.try_recv() {
+                Ok(event) => changed |= state.apply(event),
+                Err(TryRecvError::Lagged(_)) => (),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => return Ok(()),
+            }
+        }
+        if changed {
+            window.update(&state);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Sends a control request without blocking the window loop
+fn request(control_tx: &Sender<ControlRequest>, method: ControlMethod) {
+    let (reply, rx) = oneshot::channel();
+    if control_tx
+        .blocking_send(ControlRequest { method, reply })
+        .is_err()
+    {
+        return;
+    }
+    // The handler prints the outcome on the console; only log failures here
+    thread::spawn(move || {
+        if let Ok(Err(message)) = rx.blocking_recv() {
+            tracing::warn!("Window request failed: {message}");
+        }
+    });
+}
+
+/// Copies text to the clipboard
+fn copy_to_clipboard(text: &str) {
+    if let Err(err) =
+        ClipboardProvider::new().and_then(|mut ctx: ClipboardContext| ctx.set_contents(text.into()))
+    {
+        tracing::warn!("Failed to copy to clipboard: {err}");
+    }
+}
+
+/// Window drawn with GTK
+#[cfg(target_os = "linux")]
+mod window {
+    use anyhow::{Context as _, Result};
+    use gtk::{glib, prelude::*};
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{Action, GuiState, TITLE};
+    use crate::i18n::t;
+
+    /// Widgets updated from the state
+    pub struct Window {
+        status: gtk::Label,
+        link: gtk::Entry,
+        copy: gtk::Button,
+        guests: gtk::Label,
+        /// Actions taken since the last pump
+        actions: Rc<RefCell<Vec<Action>>>,
+    }
+
+    impl Window {
+        /// Creates the window (GTK is initialized on the calling thread)
+        pub fn new() -> Result<Self> {
+            gtk::init().context("Failed to initialize GTK")?;
+
+            let window = gtk::Window::new(gtk::WindowType::Toplevel);
+            window.set_title(TITLE);
+            window.set_default_size(420, 360);
+
+            let status = gtk::Label::new(None);
+            status.set_xalign(0.0);
+            let create = gtk::Button::with_label(&t!("gui-create-invite"));
+            create.set_size_request(-1, 64);
+            let link = gtk::Entry::new();
+            link.set_editable(false);
+            link.set_placeholder_text(Some(&t!("gui-no-invite")));
+            let copy = gtk::Button::with_label(&t!("gui-copy"));
+            copy.set_sensitive(false);
+            let guests_title = gtk::Label::new(Some(&t!("gui-guests")));
+            guests_title.set_xalign(0.0);
+            let guests = gtk::Label::new(None);
+            guests.set_xalign(0.0);
+            guests.set_yalign(0.0);
+
+            let link_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+            link_row.pack_start(&link, true, true, 0);
+            link_row.pack_start(&copy, false, false, 0);
+            let layout = gtk::Box::new(gtk::Orientation::Vertical, 8);
+            layout.set_border_width(12);
+            layout.pack_start(&status, false, false, 0);
+            layout.pack_start(&create, false, false, 0);
+            layout.pack_start(&link_row, false, false, 0);
+            layout.pack_start(&guests_title, false, false, 0);
+            layout.pack_start(&guests, true, true, 0);
+            window.add(&layout);
+
+            let actions = Rc::new(RefCell::new(Vec::new()));
+            let on_create = actions.clone();
+            create.connect_clicked(move |_| on_create.borrow_mut().push(Action::CreateInvite));
+            let on_copy = actions.clone();
+            copy.connect_clicked(move |_| on_copy.borrow_mut().push(Action::CopyLink));
+            let on_close = actions.clone();
+            window.connect_delete_event(move |_, _| {
+                on_close.borrow_mut().push(Action::Close);
+                glib::Propagation::Proceed
+            });
+            window.show_all();
+
+            Ok(Self {
+                status,
+                link,
+                copy,
+                guests,
+                actions,
+            })
+        }
+
+        /// Shows the state in the widgets
+        pub fn update(&self, state: &GuiState) {
+            self.status.set_text(&state.status);
+            self.link
+                .set_text(state.invite_url.as_deref().unwrap_or_default());
+            self.copy.set_sensitive(state.invite_url.is_some());
+            self.guests.set_text(&state.guest_text());
+        }
+
+        /**
+         * Dispatches the pending GTK events
+         * @return The actions taken meanwhile
+         */
+        pub fn pump(&self) -> Vec<Action> {
+            while gtk::events_pending() {
+                gtk::main_iteration_do(false);
+            }
+            self.actions.take()
+        }
+    }
+}
+
+/// Window drawn with the Win32 controls
+#[cfg(windows)]
+mod window {
+    use anyhow::{bail, Result};
+    use std::{cell::RefCell, iter, ptr};
+    use windows_sys::Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+        Graphics::Gdi::{GetStockObject, DEFAULT_GUI_FONT, WHITE_BRUSH},
+        System::LibraryLoader::GetModuleHandleW,
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, LoadCursorW,
+            PeekMessageW, RegisterClassW, SendMessageW, SetWindowTextW, TranslateMessage,
+            CW_USEDEFAULT, ES_AUTOHSCROLL, ES_READONLY, IDC_ARROW, MSG, PM_REMOVE, WM_CLOSE,
+            WM_COMMAND, WM_SETFONT, WNDCLASSW, WS_BORDER, WS_CAPTION, WS_CHILD, WS_MINIMIZEBOX,
+            WS_OVERLAPPED, WS_SYSMENU, WS_VISIBLE,
+        },
+    };
+
+    use super::{Action, GuiState, TITLE};
+    use crate::i18n::t;
+
+    /// Control ID of the "Create invite" button
+    const ID_CREATE: usize = 1;
+    /// Control ID of the "Copy" button
+    const ID_COPY: usize = 2;
+
+    thread_local! {
+        /// Actions taken since the last pump (the window procedure cannot capture anything)
+        static ACTIONS: RefCell<Vec<Action>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Converts a text to a null-terminated UTF-16 string
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(iter::once(0)).collect()
+    }
+
+    /// Records the clicks and the close button for `pump`
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        let action = match (msg, wparam & 0xffff) {
+            (WM_COMMAND, ID_CREATE) => Action::CreateInvite,
+            (WM_COMMAND, ID_COPY) => Action::CopyLink,
+            (WM_CLOSE, _) => {
+                DestroyWindow(hwnd);
+                Action::Close
+            }
+            _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+        };
+        ACTIONS.with(|actions| actions.borrow_mut().push(action));
+        0
+    }
+
+    /// Controls updated from the state
+    pub struct Window {
+        status: HWND,
+        link: HWND,
+        guests: HWND,
+    }
+
+    impl Window {
+        /// Creates the window (its messages are dispatched on the calling thread)
+        pub fn new() -> Result<Self> {
+            unsafe {
+                let instance = GetModuleHandleW(ptr::null());
+                let class_name = wide("RemotePlayInviterWindow");
+                let class = WNDCLASSW {
+                    style: 0,
+                    lpfnWndProc: Some(window_proc),
+                    cbClsExtra: 0,
+                    cbWndExtra: 0,
+                    hInstance: instance,
+                    hIcon: 0,
+                    hCursor: LoadCursorW(0, IDC_ARROW),
+                    hbrBackground: GetStockObject(WHITE_BRUSH),
+                    lpszMenuName: ptr::null(),
+                    lpszClassName: class_name.as_ptr(),
+                };
+                if RegisterClassW(&class) == 0 {
+                    bail!("Failed to register the window class");
+                }
+                let title = wide(TITLE);
+                let hwnd = CreateWindowExW(
+                    0,
+                    class_name.as_ptr(),
+                    title.as_ptr(),
+                    WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_MINIMIZEBOX | WS_VISIBLE,
+                    CW_USEDEFAULT,
+                    CW_USEDEFAULT,
+                    440,
+                    380,
+                    0,
+                    0,
+                    instance,
+                    ptr::null(),
+                );
+                if hwnd == 0 {
+                    bail!("Failed to create the window");
+                }
+
+                let font = GetStockObject(DEFAULT_GUI_FONT);
+                let control = |class: &str, text: &str, style: u32, rect: [i32; 4], id: usize| {
+                    let class = wide(class);
+                    let text = wide(text);
+                    let [x, y, width, height] = rect;
+                    let control = CreateWindowExW(
+                        0,
+                        class.as_ptr(),
+                        text.as_ptr(),
+                        WS_CHILD | WS_VISIBLE | style,
+                        x,
+                        y,
+                        width,
+                        height,
+                        hwnd,
+                        id as isize,
+                        instance,
+                        ptr::null(),
+                    );
+                    SendMessageW(control, WM_SETFONT, font as WPARAM, 1);
+                    control
+                };
+                let status = control("STATIC", "", 0, [12, 12, 400, 20], 0);
+                control(
+                    "BUTTON",
+                    &t!("gui-create-invite"),
+                    0,
+                    [12, 40, 400, 56],
+                    ID_CREATE,
+                );
+                let link = control(
+                    "EDIT",
+                    "",
+                    WS_BORDER | (ES_READONLY | ES_AUTOHSCROLL) as u32,
+                    [12, 108, 320, 24],
+                    0,
+                );
+                control("BUTTON", &t!("gui-copy"), 0, [340, 108, 72, 24], ID_COPY);
+                control("STATIC", &t!("gui-guests"), 0, [12, 144, 400, 20], 0);
+                let guests = control("STATIC", "", 0, [12, 168, 400, 160], 0);
+
+                Ok(Self {
+                    status,
+                    link,
+                    guests,
+                })
+            }
+        }
+
+        /// Shows the state in the controls
+        pub fn update(&self, state: &GuiState) {
+            let link = match &state.invite_url {
+                Some(url) => url.clone(),
+                None => t!("gui-no-invite"),
+            };
+            // The static controls break the lines on CR LF
+            let guests = state.guest_text().replace('\n', "\r\n");
+            unsafe {
+                SetWindowTextW(self.status, wide(&state.status).as_ptr());
+                SetWindowTextW(self.link, wide(&link).as_ptr());
+                SetWindowTextW(self.guests, wide(&guests).as_ptr());
+            }
+        }
+
+        /**
+         * Dispatches the pending window messages
+         * @return The actions taken meanwhile
+         */
+        pub fn pump(&self) -> Vec<Action> {
+            unsafe {
+                let mut msg: MSG = std::mem::zeroed();
+                while PeekMessageW(&mut msg, 0, 0, 0, PM_REMOVE) != 0 {
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+            ACTIONS.with(|actions| actions.take())
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use clipboard::{ClipboardContext, ClipboardProvider};
+use futures::future::BoxFuture;
+
+use super::{
+    dispatch::{CommandHandler, Outcome},
+    Handler,
+};
+use crate::{
+    console,
+    events::{self, Event},
+    i18n::t,
+    models::{ServerCmd, ServerMessage},
+};
+
+/// Announcements from the server to display
+pub struct Broadcast;
+
+impl CommandHandler for Broadcast {
+    fn handles(&self, cmd: &ServerCmd) -> bool {
+        matches!(
+            cmd,
+            ServerCmd::Message { .. } | ServerCmd::Announcement { .. }
+        )
+    }
+
+    fn handle<'a>(
+        &'a self,
+        handler: &'a mut Handler,
+        msg: ServerMessage,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        Box::pin(async move {
+            let (data, copy) = match msg.cmd {
+                ServerCmd::Message { text, copy } => (text, copy),
+                // Shown by the console and the notifications
+                ServerCmd::Announcement { title, text, url } => {
+                    events::emit(Event::Announcement { title, text, url });
+                    return Ok(Outcome::Done);
+                }
+                cmd => anyhow::bail!("Unexpected command: {cmd:?}"),
+            };
+
+            // Remember the link for the `open discord` command
+            if let Some(url) = data
+                .split_whitespace()
+                .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+            {
+                handler.discord_url = Some(url.to_string());
+            }
+
+            // Indent the message
+            let message = data
+                .lines()
+                .map(|line| format!("  {}", line))
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            // Display the welcome message
+            console::printdoc! {"
+
+                {message}
+
+                "};
+
+            // If there is a copy, copy it
+            if let Some(copy) = copy {
+                // Copy to clipboard
+                if let Err(_err) = ClipboardProvider::new()
+                    .map(|mut ctx: ClipboardContext| ctx.set_contents(copy.clone()))
+                {
+                    console::eprintln!("☓ {}", t!("clipboard-failed", url = copy));
+                }
+            }
+
+            Ok(Outcome::Done)
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use super::{
+    broadcast::Broadcast, error::UnknownCommand, invite::InviteRequests, link_status::LinkStatus,
+    Handler,
+};
+use crate::models::{ClientCmd, ServerCmd, ServerMessage};
+
+/// Result of handling a server message
+pub enum Outcome {
+    /// Reply to the server
+    Reply(ClientCmd),
+    /// Nothing to reply
+    Done,
+    /// Exit the application
+    Exit,
+}
+
+/// Handles one kind of server message
+pub trait CommandHandler: Send + Sync {
+    /// Whether this handler handles the command
+    fn handles(&self, cmd: &ServerCmd) -> bool;
+
+    /// Handles a message whose command `handles` accepted
+    fn handle<'a>(
+        &'a self,
+        handler: &'a mut Handler,
+        msg: ServerMessage,
+    ) -> BoxFuture<'a, Result<Outcome>>;
+}
+
+/// Routes the server messages to the handler registered for their command
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: Vec<Box<dyn CommandHandler>>,
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher with the handlers of the built-in commands
+    pub fn builtin() -> Self {
+        let mut dispatcher = Self::default();
+        dispatcher.register(InviteRequests);
+        dispatcher.register(LinkStatus);
+        dispatcher.register(Broadcast);
+        dispatcher.register(UnknownCommand);
+        dispatcher
+    }
+
+    /// Adds a handler (tried after the ones registered before it)
+    pub fn register(&mut self, handler: impl CommandHandler + 'static) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// Handles a message with the first handler of its command
+    pub async fn dispatch(&self, handler: &mut Handler, msg: ServerMessage) -> Result<Outcome> {
+        match self.handlers.iter().find(|h| h.handles(&msg.cmd)) {
+            Some(command_handler) => command_handler.handle(handler, msg).await,
+            // Commands without a handler are answered like unknown ones
+            None => Ok(UnknownCommand::reply()),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use super::{
+    dispatch::{CommandHandler, Outcome},
+    Handler,
+};
+use crate::models::{ClientCmd, ErrorStatus, ServerCmd, ServerMessage};
+
+/// Commands this client does not support, answered with an error
+pub struct UnknownCommand;
+
+impl UnknownCommand {
+    /// Reply to a command that is not supported
+    pub fn reply() -> Outcome {
+        Outcome::Reply(ClientCmd::Error {
+            code: ErrorStatus::InvalidCmd,
+        })
+    }
+}
+
+impl CommandHandler for UnknownCommand {
+    fn handles(&self, cmd: &ServerCmd) -> bool {
+        matches!(cmd, ServerCmd::Unknown)
+    }
+
+    fn handle<'a>(
+        &'a self,
+        _handler: &'a mut Handler,
+        _msg: ServerMessage,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        Box::pin(async { Ok(Self::reply()) })
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use clipboard::{ClipboardContext, ClipboardProvider};
+use futures::future::BoxFuture;
+use std::time::Duration;
+use steam_stuff::{GameID, GameUID};
+
+use super::{
+    dispatch::{CommandHandler, Outcome},
+    Handler, InviteOptions, UserAccess,
+};
+use crate::{
+    console,
+    history::{self, HistoryEvent},
+    i18n::t,
+    models::{ClientCmd, ErrorStatus, RecentMessages, ServerCmd, ServerMessage, User},
+    store,
+};
+
+/// Requests from Discord users: the running game, invite links and kicking guests
+pub struct InviteRequests;
+
+/// An invite request waiting for the host to approve it (the user is not on the allow list)
+pub struct PendingRequest {
+    /// ID of the request message (answered by the reply)
+    pub id: String,
+    /// Key of the request message among the recently handled ones
+    pub key: Option<String>,
+    pub user: Option<User>,
+    pub game: u32,
+    pub max_uses: Option<u32>,
+    pub ttl: Option<u64>,
+}
+
+impl CommandHandler for InviteRequests {
+    fn handles(&self, cmd: &ServerCmd) -> bool {
+        matches!(
+            cmd,
+            ServerCmd::GameId | ServerCmd::Link { .. } | ServerCmd::Kick { .. }
+        )
+    }
+
+    fn handle<'a>(
+        &'a self,
+        handler: &'a mut Handler,
+        msg: ServerMessage,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        Box::pin(async move {
+            let outcome = match msg.cmd {
+                ServerCmd::GameId | ServerCmd::Link { .. } if handler.is_paused() => {
+                    // Log the output
+                    let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                    console::println!("□ {}", t!("request-declined-paused", claimer = claimer));
+
+                    // Create the response data
+                    Outcome::Reply(ClientCmd::Error {
+                        code: ErrorStatus::HostPaused,
+                    })
+                }
+                ServerCmd::GameId | ServerCmd::Link { .. }
+                    if handler.user_access(msg.user.as_ref(), None) == UserAccess::Blocked =>
+                {
+                    // Log the output
+                    let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                    console::println!("☓ {}", t!("request-declined-blocked", claimer = claimer));
+
+                    // Create the response data
+                    Outcome::Reply(ClientCmd::Error {
+                        code: ErrorStatus::UserDenied,
+                    })
+                }
+                ServerCmd::GameId => match handler.target_game().await {
+                    Ok((app_id, _)) => {
+                        // Look up the game name for the following messages
+                        store::prefetch(app_id);
+
+                        // Log the output
+                        let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                        console::println!(
+                            "-> {}",
+                            t!(
+                                "panel-created",
+                                claimer = claimer,
+                                game = store::game_label(app_id)
+                            )
+                        );
+
+                        // Create the response data
+                        Outcome::Reply(ClientCmd::GameId { game: app_id })
+                    }
+                    // If the game is not running or not supported
+                    // Create the response data
+                    Err(code) => Outcome::Reply(ClientCmd::Error { code }),
+                },
+                ServerCmd::Link { game, .. } if handler.is_full(game).await => {
+                    // Log the output
+                    let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                    console::println!("☓ {}", t!("request-declined-full", claimer = claimer));
+
+                    // Create the response data
+                    Outcome::Reply(ClientCmd::Error {
+                        code: ErrorStatus::SessionFull,
+                    })
+                }
+                ServerCmd::Link { game, .. } if !handler.is_allowed(game) => {
+                    // Log the output
+                    let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                    console::println!(
+                        "☓ {}",
+                        t!("request-declined-game", game = game, claimer = claimer)
+                    );
+
+                    // Create the response data
+                    Outcome::Reply(ClientCmd::Error {
+                        code: ErrorStatus::DisallowedApp,
+                    })
+                }
+                ServerCmd::Link {
+                    game,
+                    max_uses,
+                    ttl,
+                } if handler.user_access(msg.user.as_ref(), Some(game)) == UserAccess::Ask => {
+                    // Log the output
+                    let (claimer, user_id) = msg
+                        .user
+                        .as_ref()
+                        .map_or(("?", "?"), |s| (s.name.as_str(), s.id.as_str()));
+                    console::println!(
+                        "★ {}",
+                        t!(
+                            "request-pending",
+                            claimer = claimer,
+                            user_id = user_id,
+                            game = store::game_label(game)
+                        )
+                    );
+
+                    // Answer once the host decides (see `approve` / `deny`)
+                    handler.pending_requests.push_back(PendingRequest {
+                        key: RecentMessages::key(&msg),
+                        id: msg.id,
+                        user: msg.user,
+                        game,
+                        max_uses,
+                        ttl,
+                    });
+                    Outcome::Done
+                }
+                ServerCmd::Link {
+                    game,
+                    max_uses,
+                    ttl,
+                } => Outcome::Reply(
+                    create_link(handler, msg.user.as_ref(), game, max_uses, ttl).await?,
+                ),
+                ServerCmd::Kick { guest } => match handler.kick_guest(guest).await {
+                    Some(name) => {
+                        // Log the output
+                        let claimer = msg.user.as_ref().map_or_else(|| "?", |s| &s.name);
+                        console::println!(
+                            "-> {}",
+                            t!(
+                                "player-kicked-by",
+                                claimer = claimer,
+                                guest_id = guest,
+                                name = name
+                            )
+                        );
+
+                        // Create the response data
+                        Outcome::Reply(ClientCmd::Kicked { guest })
+                    }
+                    // If the guest is not connected
+                    None => Outcome::Reply(ClientCmd::Error {
+                        code: ErrorStatus::InvalidGuest,
+                    }),
+                },
+                cmd => anyhow::bail!("Unexpected command: {cmd:?}"),
+            };
+            Ok(outcome)
+        })
+    }
+}
+
+/**
+ * Creates the invite link requested by a user, starting the game first if enabled
+ * @return The reply to the request (an error status if the game can't be invited to)
+ */
+pub async fn create_link(
+    handler: &mut Handler,
+    user: Option<&User>,
+    game: u32,
+    max_uses: Option<u32>,
+    ttl: Option<u64>,
+) -> Result<ClientCmd> {
+    // Check that the game supports Remote Play Together
+    if !handler.supports_remote_play(game).await {
+        let claimer = user.map_or_else(|| "?", |s| &s.name);
+        console::eprintln!(
+            "☓ {}",
+            t!(
+                "request-declined-unsupported",
+                game = game,
+                claimer = claimer
+            )
+        );
+        return Ok(ClientCmd::Error {
+            code: ErrorStatus::UnsupportedApp,
+        });
+    }
+
+    // Start the game if needed (when enabled)
+    if !handler.launch_game(game).await? {
+        return Ok(ClientCmd::Error {
+            code: ErrorStatus::InvalidApp,
+        });
+    }
+
+    // Get the game ID
+    let game_uid: GameUID = GameID::new(game, 0, 0).into();
+
+    // Usage limits (requested by the server > game settings > defaults)
+    let defaults = handler.invite_options(game);
+    let options = InviteOptions {
+        max_uses: max_uses.or(defaults.max_uses),
+        ttl: ttl.map(Duration::from_secs).or(defaults.ttl),
+    };
+
+    // Create an invite link
+    let (guest_id, connect_url) = match handler.create_invite(game_uid, options).await {
+        Ok(invite) => invite,
+        Err(err) => {
+            console::eprintln!("☓ {err:#}");
+            return Ok(ClientCmd::Error {
+                code: ErrorStatus::SteamUnavailable,
+            });
+        }
+    };
+
+    // Associate the Discord user with guest_id
+    if let Some(user) = user {
+        handler
+            .guest_data
+            .lock()
+            .await
+            .guest_map
+            .insert(guest_id, user.name.clone());
+    }
+
+    history::record(HistoryEvent::InviteCreated {
+        guest_id,
+        claimer: user.map(|user| user.name.clone()),
+        game,
+    });
+
+    // Log the output
+    let claimer = user.map_or_else(|| "?", |s| &s.name);
+    console::println!(
+        "-> {}",
+        t!(
+            "invite-created-for",
+            claimer = claimer,
+            guest_id = guest_id,
+            game = store::game_label(game),
+            url = connect_url
+        )
+    );
+    console::print_qr(&connect_url)?;
+
+    // Copy to clipboard
+    if handler.copy_invite {
+        match ClipboardProvider::new()
+            .and_then(|mut ctx: ClipboardContext| ctx.set_contents(connect_url.clone()))
+        {
+            Ok(()) => console::println!("✓ {}", t!("invite-copied")),
+            Err(_err) => {
+                console::eprintln!("☓ {}", t!("clipboard-failed", url = connect_url))
+            }
+        }
+    }
+
+    // Create the response data
+    Ok(ClientCmd::Link {
+        url: connect_url,
+        max_uses: options.max_uses,
+        ttl: options.ttl.map(|ttl| ttl.as_secs()),
+    })
+}
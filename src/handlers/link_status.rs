@@ -0,0 +1,187 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+use super::{
+    dispatch::{CommandHandler, Outcome},
+    Handler,
+};
+use crate::{
+    console,
+    events::{self, Event},
+    i18n::t,
+    models::{unix_time, Capability, ServerCmd, ServerMessage, PROTOCOL_VERSION},
+};
+
+/// Difference with the clock of the server above which the user is warned
+/// (signed messages are rejected past `SIGNATURE_MAX_SKEW`, certificates look invalid past hours)
+const CLOCK_SKEW_WARNING: u64 = 60;
+
+/// Delay before reconnecting when the server doesn't say when it will be back
+const DEFAULT_MAINTENANCE_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest delay accepted before reconnecting after a maintenance
+const MAX_MAINTENANCE_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// State of the link with the server: capabilities, session resumption, pairing with Discord, maintenance, redirects and exit
+pub struct LinkStatus;
+
+impl CommandHandler for LinkStatus {
+    fn handles(&self, cmd: &ServerCmd) -> bool {
+        matches!(
+            cmd,
+            ServerCmd::Exit
+                | ServerCmd::Session { .. }
+                | ServerCmd::Resume { .. }
+                | ServerCmd::Pairing { .. }
+                | ServerCmd::Paired { .. }
+                | ServerCmd::Hello { .. }
+                | ServerCmd::Maintenance { .. }
+                | ServerCmd::Redirect { .. }
+        )
+    }
+
+    fn handle<'a>(
+        &'a self,
+        handler: &'a mut Handler,
+        msg: ServerMessage,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        Box::pin(async move {
+            match msg.cmd {
+                ServerCmd::Exit => {
+                    // Exit the application
+                    Ok(Outcome::Exit)
+                }
+                ServerCmd::Session { token } => {
+                    // Keep the token to resume the session after a reconnect
+                    // (it is sent in the query string, so only URL-safe tokens are accepted)
+                    if token
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.~".contains(c))
+                    {
+                        handler.session_token = Some(token);
+                    }
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Resume { accepted } => {
+                    if accepted {
+                        console::println!("✓ {}", t!("session-resumed"));
+                    } else {
+                        // The server has forgotten the session, start a new one
+                        handler.session_token = None;
+                        handler.recent_messages.clear();
+                        console::println!("↪ {}", t!("session-not-resumed"));
+                    }
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Pairing { code, expires_in } => {
+                    // Display the code prominently so that it can be typed into Discord
+                    let line = format!("   Pairing code:  {code}   ");
+                    let border = "─".repeat(line.chars().count());
+                    let hint = match expires_in {
+                        Some(secs) if secs >= 60 => {
+                            t!("pairing-hint-minutes", minutes = secs / 60)
+                        }
+                        Some(secs) => t!("pairing-hint-seconds", seconds = secs),
+                        None => t!("pairing-hint"),
+                    };
+                    console::printdoc! {"
+
+                      ┌{border}┐
+                      │{line}│
+                      └{border}┘
+                    ★ {hint}
+
+                "};
+                    events::emit(Event::PairingCode { code });
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Paired { account } => {
+                    events::emit(Event::Paired { account });
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Maintenance {
+                    message,
+                    reconnect_after,
+                } => {
+                    // The connection is closed after the reply, and opened again once the delay is over
+                    let delay = reconnect_after
+                        .map_or(DEFAULT_MAINTENANCE_DELAY, Duration::from_secs)
+                        .min(MAX_MAINTENANCE_DELAY);
+                    handler.maintenance = Some(delay);
+                    events::emit(Event::Maintenance {
+                        message,
+                        delay_ms: delay.as_millis() as u64,
+                    });
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Redirect { url } => {
+                    // The connection is closed after the reply, and opened to the new server
+                    if !(url.starts_with("ws://") || url.starts_with("wss://")) {
+                        anyhow::bail!("Invalid redirect URL: {url}");
+                    }
+                    console::println!("↪ {}", t!("server-redirected", url = url));
+                    handler.redirect = Some(url);
+                    Ok(Outcome::Done)
+                }
+                ServerCmd::Hello {
+                    protocol,
+                    capabilities,
+                    time,
+                } => {
+                    tracing::info!(protocol, ?capabilities, "Server capabilities");
+                    if let Some(time) = time {
+                        check_clock(handler, time)?;
+                    }
+                    if protocol > PROTOCOL_VERSION {
+                        console::println!("↪ {}", t!("server-newer-protocol", protocol = protocol));
+                    }
+                    handler.server_capabilities = Some(capabilities);
+
+                    // Fall back to what the server supports
+                    if !handler.server_supports(Capability::Resume) {
+                        handler.session_token = None;
+                    }
+                    if handler.signing_key.is_some()
+                        && !handler.server_supports(Capability::Signing)
+                    {
+                        console::println!("☓ {}", t!("server-unsigned"));
+                    }
+                    Ok(Outcome::Done)
+                }
+                cmd => anyhow::bail!("Unexpected command: {cmd:?}"),
+            }
+        })
+    }
+}
+
+/// Formats a clock difference in the largest fitting unit
+fn format_skew(secs: u64) -> String {
+    match secs {
+        ..=119 => t!("skew-seconds", seconds = secs),
+        120..=7199 => t!("skew-minutes", minutes = secs / 60),
+        _ => t!("skew-hours", hours = secs / 3600),
+    }
+}
+
+/// Warns the user when the local clock differs too much from the time of the server (once until it is fixed)
+fn check_clock(handler: &mut Handler, server_time: u64) -> Result<()> {
+    let now = unix_time();
+    let skew = now.abs_diff(server_time);
+    tracing::debug!(now, server_time, "Clock of the server");
+    if skew <= CLOCK_SKEW_WARNING {
+        handler.clock_skew_warned = false;
+        return Ok(());
+    }
+    if !handler.clock_skew_warned {
+        handler.clock_skew_warned = true;
+        let key = if now > server_time {
+            "clock-ahead"
+        } else {
+            "clock-behind"
+        };
+        tracing::warn!(now, server_time, "The local clock is wrong");
+        console::println!("☓ {}", t!(key, skew = format_skew(skew)));
+    }
+    Ok(())
+}
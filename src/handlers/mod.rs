@@ -0,0 +1,2225 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use futures::SinkExt;
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use steam_stuff::{GameID, GameUID};
+use tokio::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    task::{self, JoinHandle},
+    time::{self, interval},
+};
+use tokio_tungstenite::tungstenite::{protocol::Message, Error as WsError};
+use uuid::Uuid;
+
+mod broadcast;
+pub mod dispatch;
+mod error;
+mod invite;
+mod link_status;
+
+use dispatch::{Dispatcher, Outcome};
+use invite::PendingRequest;
+
+/// Interval between status messages to the server (sent right away when the running game changes)
+const STATUS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time before the end of a time-limited session to warn about it
+const SESSION_END_WARNING: Duration = Duration::from_secs(5 * 60);
+
+/// Time the Steam client has to create a requested invite link
+const INVITE_TIMEOUT: Duration = Duration::from_secs(15);
+
+use crate::{
+    config::{self, GameConfig},
+    console::{self, ConsoleCmd, OpenTarget, Verbosity},
+    control::{ControlMethod, ControlRequest},
+    crash,
+    events::{self, Event},
+    history::{self, HistoryEvent},
+    i18n::t,
+    library, metrics,
+    middleware::{Inbound, Pipeline},
+    models::{
+        Capability, ClientCmd, ClientMessage, ErrorStatus, RecentMessages, ReplayGuard, ServerCmd,
+        ServerMessage, SignedMessage, SigningKey, User, WireFormat, PROTOCOL_VERSION,
+    },
+    msgpack,
+    outbox::Outbox,
+    platform,
+    steam::{self, Callbacks, Steam},
+    store,
+};
+
+/// What happens to the invite requests of a user (allow and block lists)
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UserAccess {
+    /// Approved
+    Allowed,
+    /// Declined
+    Blocked,
+    /// Asked on the console (not on the allow list)
+    Ask,
+}
+
+#[derive(Default)]
+pub struct GuestData {
+    pub guest_map: HashMap<u64, String>,
+    pub user_set: BTreeSet<u64>,
+    pub joined_at: HashMap<u64, DateTime<Local>>,
+    pub steam_ids: HashMap<u64, u64>,
+    /// Local nicknames of the guests (Steam ID -> nickname)
+    pub nicknames: HashMap<u64, String>,
+    /// Maximum number of guests set for the game last invited to (overrides the general one)
+    pub game_max_guests: Option<usize>,
+    /// Last time each guest was seen active (joined, or a game was running to stream)
+    pub last_active: HashMap<u64, Instant>,
+    /// Guest IDs of the invites issued but not used yet
+    pub pending_invites: BTreeSet<u64>,
+    /// Guest IDs of the revoked invites
+    pub revoked_invites: HashSet<u64>,
+    /// Usage limits of the issued invites
+    pub invite_limits: HashMap<u64, InviteLimit>,
+    /// Statistics of the current play session
+    pub stats: SessionStats,
+}
+
+/// Statistics of a play session (from the first guest joining until the last one leaves)
+#[derive(Default)]
+pub struct SessionStats {
+    started_at: Option<DateTime<Local>>,
+    guests: HashSet<u64>,
+    peak_guests: usize,
+    reconnects: u32,
+    /// Whether to send the summary to the server
+    pub report: bool,
+}
+
+/// Summary of a finished play session
+pub struct SessionSummary {
+    /// Time from the first guest joining until the last one left
+    pub duration: chrono::TimeDelta,
+    /// Number of distinct guests
+    pub unique_guests: usize,
+    /// Maximum number of guests connected at the same time
+    pub peak_guests: usize,
+    /// Number of reconnections to the server during the session
+    pub reconnects: u32,
+}
+
+impl SessionStats {
+    /// Records a guest joining
+    pub fn on_join(&mut self, steam_id: u64, guest_count: usize) {
+        self.started_at.get_or_insert_with(Local::now);
+        self.guests.insert(steam_id);
+        self.peak_guests = self.peak_guests.max(guest_count);
+    }
+
+    /// When the first guest joined (None: no session)
+    pub fn started_at(&self) -> Option<DateTime<Local>> {
+        self.started_at
+    }
+
+    /// Records a reconnection to the server
+    pub fn on_reconnect(&mut self) {
+        if self.started_at.is_some() {
+            self.reconnects += 1;
+        }
+    }
+
+    /**
+     * Ends the session and resets the statistics
+     * @return The summary, or None if no guest has joined
+     */
+    pub fn finish(&mut self) -> Option<SessionSummary> {
+        let started_at = self.started_at.take()?;
+        let summary = SessionSummary {
+            duration: Local::now() - started_at,
+            unique_guests: self.guests.len(),
+            peak_guests: self.peak_guests,
+            reconnects: self.reconnects,
+        };
+        self.guests.clear();
+        self.peak_guests = 0;
+        self.reconnects = 0;
+        Some(summary)
+    }
+}
+
+impl SessionSummary {
+    /// Displays the summary and sends it to the server if enabled
+    async fn report(self, report: bool, event_tx: &Sender<ClientCmd>) {
+        let _: Result<()> = (|| {
+            console::println!(
+                "★ {}",
+                t!(
+                    "session-ended",
+                    minutes = self.duration.num_minutes(),
+                    guests = self.unique_guests,
+                    peak = self.peak_guests,
+                    reconnects = self.reconnects
+                )
+            );
+            Ok(())
+        })();
+        if report {
+            let _ = event_tx
+                .send(ClientCmd::SessionStats {
+                    duration: self.duration.num_seconds().max(0) as u64,
+                    unique_guests: self.unique_guests,
+                    peak_guests: self.peak_guests,
+                    reconnects: self.reconnects,
+                })
+                .await;
+        }
+    }
+}
+
+/// Usage limits for new invite links
+#[derive(Clone, Copy, Default)]
+pub struct InviteOptions {
+    /// Maximum number of times the invite can be used (None: unlimited)
+    pub max_uses: Option<u32>,
+    /// Time after which the invite expires (None: never)
+    pub ttl: Option<Duration>,
+}
+
+/// Usage of an issued invite link
+pub struct InviteLimit {
+    /// Number of times the invite has been used
+    pub uses: u32,
+    /// Maximum number of times the invite can be used
+    pub max_uses: Option<u32>,
+    /// Time at which the invite expires
+    pub expires_at: Option<Instant>,
+}
+
+impl InviteLimit {
+    /// Whether the invite has expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| Instant::now() >= at)
+    }
+}
+
+impl GuestData {
+    /// Gets the display name of a guest (nickname > Discord name > guest ID)
+    pub fn name(&self, guest_id: u64) -> String {
+        self.nickname(guest_id)
+            .or_else(|| self.guest_map.get(&guest_id))
+            .cloned()
+            .unwrap_or_else(|| format!("Guest {guest_id}"))
+    }
+
+    /// Gets the nickname given to a connected guest
+    fn nickname(&self, guest_id: u64) -> Option<&String> {
+        self.nicknames.get(self.steam_ids.get(&guest_id)?)
+    }
+
+    /// Finds a connected guest by guest ID or name (case-insensitive)
+    pub fn find(&self, query: &str) -> Option<u64> {
+        if let Ok(guest_id) = query.parse::<u64>() {
+            if self.user_set.contains(&guest_id) {
+                return Some(guest_id);
+            }
+        }
+        self.user_set.iter().copied().find(|id| {
+            self.name(*id).eq_ignore_ascii_case(query)
+                || self
+                    .guest_map
+                    .get(id)
+                    .is_some_and(|name| name.eq_ignore_ascii_case(query))
+        })
+    }
+
+    /**
+     * Checks whether a guest may join with the given invite, and counts the use
+     * @return The reason the invite cannot be used, if any
+     */
+    pub fn use_invite(&mut self, guest_id: u64) -> std::result::Result<(), &'static str> {
+        if self.revoked_invites.contains(&guest_id) {
+            return Err("revoked");
+        }
+        if let Some(limit) = self.invite_limits.get_mut(&guest_id) {
+            if limit.is_expired() {
+                return Err("expired");
+            }
+            if limit.max_uses.is_some_and(|max| limit.uses >= max) {
+                return Err("used up");
+            }
+            limit.uses += 1;
+        }
+        Ok(())
+    }
+
+    /**
+     * Revokes the unused invites that have expired
+     * @return The guest IDs of the expired invites
+     */
+    pub fn expire_invites(&mut self) -> Vec<u64> {
+        let expired: Vec<u64> = self
+            .pending_invites
+            .iter()
+            .copied()
+            .filter(|id| self.invite_limits.get(id).is_some_and(|l| l.is_expired()))
+            .collect();
+        for guest_id in &expired {
+            self.pending_invites.remove(guest_id);
+            self.revoked_invites.insert(*guest_id);
+        }
+        expired
+    }
+
+    /**
+     * Finds the guests idle for at least `timeout`
+     * @return The guest IDs and Steam IDs of the idle guests
+     */
+    pub fn idle_guests(&self, timeout: Duration) -> Vec<(u64, u64)> {
+        self.user_set
+            .iter()
+            .copied()
+            .filter(|id| {
+                self.last_active
+                    .get(id)
+                    .is_some_and(|at| at.elapsed() >= timeout)
+            })
+            .map(|id| (id, self.steam_ids.get(&id).copied().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Formats the connected users with the time they joined, one per line
+    pub fn roster_text(&self) -> String {
+        let now = Local::now();
+        self.user_set
+            .iter()
+            .map(|id| match self.joined_at.get(id) {
+                Some(joined_at) => {
+                    let minutes = (now - *joined_at).num_minutes();
+                    format!(
+                        "  [{}] {} {}",
+                        id,
+                        self.name(*id),
+                        t!(
+                            "roster-joined",
+                            time = joined_at.format("%H:%M:%S"),
+                            minutes = minutes
+                        )
+                    )
+                }
+                None => format!("  [{}] {}", id, self.name(*id)),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Formats the connected users as `[guest_id]name, ...`
+    pub fn users_text(&self) -> String {
+        self.user_set
+            .iter()
+            .map(|id| {
+                format!(
+                    "[{}]{}",
+                    id,
+                    self.nickname(*id)
+                        .or_else(|| self.guest_map.get(id))
+                        .map_or_else(|| "?", |s| s)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+pub struct Handler {
+    steam: Steam,
+    invite_tx: Sender<(u64, String)>,
+    invite_rx: Receiver<(u64, String)>,
+    guest_data: Arc<Mutex<GuestData>>,
+    callbacks_task: Option<JoinHandle<()>>,
+    event_tx: Sender<ClientCmd>,
+    event_rx: Receiver<ClientCmd>,
+    latency: Option<Duration>,
+    session_token: Option<String>,
+    /// Delay before reconnecting, set when the server announces a maintenance
+    maintenance: Option<Duration>,
+    /// Server to connect to instead, set when the server redirects the client
+    redirect: Option<String>,
+    max_guests: Option<usize>,
+    invite_defaults: InviteOptions,
+    copy_invite: bool,
+    auto_open_invite: bool,
+    /// Time to wait for a game started for an invite request (None: don't start games)
+    launch_timeout: Option<Duration>,
+    /// Time after which the session ends (None: no limit)
+    session_timeout: Option<Duration>,
+    /// Time after which idle guests are kicked (None: never)
+    idle_kick: Option<Duration>,
+    /// Only these games can be invited to (empty: any game)
+    allowed_games: HashSet<u32>,
+    /// These games can't be invited to
+    denied_games: HashSet<u32>,
+    /// Settings of the games overriding the general ones
+    game_configs: HashMap<u32, GameConfig>,
+    /// Users whose invite requests are approved (empty: everyone)
+    allowed_users: HashSet<String>,
+    /// Users whose invite requests are declined
+    blocked_users: HashSet<String>,
+    /// Invite requests waiting for the host to approve them
+    pending_requests: VecDeque<PendingRequest>,
+    last_invite: Option<String>,
+    discord_url: Option<String>,
+    signing_key: Option<SigningKey>,
+    replay_guard: ReplayGuard,
+    wire_format: WireFormat,
+    /// Features announced by the server (None: it predates the hello message)
+    server_capabilities: Option<Vec<String>>,
+    /// Whether the user was warned that the local clock is wrong (until it is fixed)
+    clock_skew_warned: bool,
+    outbox: Outbox,
+    recent_messages: RecentMessages,
+    dispatcher: Arc<Dispatcher>,
+    middleware: Pipeline,
+    /// Game selected with the `game` command (None: the running game)
+    selected_game: Option<u32>,
+    /// Whether the invite requests are declined (`pause` command)
+    paused: Arc<AtomicBool>,
+}
+
+impl Handler {
+    pub fn new(steam: Steam) -> Self {
+        let (invite_tx, invite_rx) = channel::<(u64, String)>(32);
+        let (event_tx, event_rx) = channel::<ClientCmd>(32);
+        Self {
+            steam,
+            invite_tx,
+            invite_rx,
+            guest_data: Arc::new(Mutex::new(GuestData {
+                guest_map: HashMap::<u64, String>::new(),
+                user_set: BTreeSet::<u64>::new(),
+                joined_at: HashMap::<u64, DateTime<Local>>::new(),
+                steam_ids: HashMap::<u64, u64>::new(),
+                nicknames: HashMap::<u64, String>::new(),
+                game_max_guests: None,
+                last_active: HashMap::<u64, Instant>::new(),
+                pending_invites: BTreeSet::<u64>::new(),
+                revoked_invites: HashSet::<u64>::new(),
+                invite_limits: HashMap::<u64, InviteLimit>::new(),
+                stats: SessionStats::default(),
+            })),
+            callbacks_task: None,
+            event_tx,
+            event_rx,
+            latency: None,
+            session_token: None,
+            maintenance: None,
+            redirect: None,
+            max_guests: None,
+            invite_defaults: InviteOptions::default(),
+            copy_invite: false,
+            auto_open_invite: false,
+            launch_timeout: None,
+            session_timeout: None,
+            idle_kick: None,
+            allowed_games: HashSet::new(),
+            denied_games: HashSet::new(),
+            game_configs: HashMap::new(),
+            allowed_users: HashSet::new(),
+            blocked_users: HashSet::new(),
+            pending_requests: VecDeque::new(),
+            last_invite: None,
+            discord_url: None,
+            signing_key: None,
+            replay_guard: ReplayGuard::default(),
+            wire_format: WireFormat::Json,
+            server_capabilities: None,
+            clock_skew_warned: false,
+            outbox: Outbox::default(),
+            recent_messages: RecentMessages::default(),
+            dispatcher: Arc::new(Dispatcher::builtin()),
+            middleware: Pipeline::standard(),
+            selected_game: None,
+            paused: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Sets the usage limits applied to invites that don't specify their own
+    pub fn set_invite_defaults(&mut self, options: InviteOptions) {
+        self.invite_defaults = options;
+    }
+
+    /// Signs the messages sent to the server and verifies the signed messages from it
+    pub fn set_signing_key(&mut self, signing_key: Option<SigningKey>) {
+        self.signing_key = signing_key;
+    }
+
+    /// Sets the handlers of the server messages (replacing the built-in ones)
+    pub fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        self.dispatcher = Arc::new(dispatcher);
+    }
+
+    /// Sets the middleware the messages pass through (replacing the standard logging and metrics)
+    pub fn set_middleware(&mut self, middleware: Pipeline) {
+        self.middleware = middleware;
+    }
+
+    /// Sets the queue of the messages to the server (replacing the default in-memory one)
+    pub fn set_outbox(&mut self, outbox: Outbox) {
+        self.outbox = outbox;
+    }
+
+    /**
+     * Queues a message for the server and sends the queue
+     * @return An error if the connection failed (the messages are sent again after reconnecting)
+     */
+    pub async fn send(
+        &mut self,
+        msg: ClientMessage,
+        write: &mut (impl SinkExt<Message, Error = WsError> + Unpin),
+    ) -> Result<()> {
+        self.outbox.push(msg);
+        self.flush_outbox(write).await
+    }
+
+    /// Sends the messages left in the queue, in order
+    pub async fn flush_outbox(
+        &mut self,
+        write: &mut (impl SinkExt<Message, Error = WsError> + Unpin),
+    ) -> Result<()> {
+        let result = async {
+            while let Some(msg) = self.outbox.front() {
+                let Some(msg) = self.middleware.outbound(msg.clone()) else {
+                    self.outbox.pop();
+                    continue;
+                };
+                let res = self.encode(&msg)?;
+                write
+                    .send(res)
+                    .await
+                    .context("Failed to send message to the server")?;
+                metrics::MESSAGES_SENT.inc();
+                self.outbox.pop();
+            }
+            Ok(())
+        }
+        .await;
+        self.outbox.save();
+        result
+    }
+
+    /// Forgets what was agreed on with the server over the previous connection
+    pub fn reset_connection(&mut self) {
+        // The server switches to MessagePack by replying in it
+        self.wire_format = WireFormat::Json;
+        self.server_capabilities = None;
+        // The ids of a new session may repeat those of the previous one
+        if self.session_token.is_none() {
+            self.recent_messages.clear();
+        }
+    }
+
+    /// Creates the hello message announcing the features of this client
+    pub fn hello(&self, mut capabilities: Vec<Capability>) -> ClientCmd {
+        if self.signing_key.is_some() {
+            capabilities.push(Capability::Signing);
+        }
+        ClientCmd::Hello {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities,
+        }
+    }
+
+    /// Whether the server supports a feature (servers without the hello message are assumed to support all)
+    pub fn server_supports(&self, capability: Capability) -> bool {
+        self.server_capabilities
+            .as_ref()
+            .map_or(true, |capabilities| {
+                capabilities.iter().any(|c| c == capability.as_str())
+            })
+    }
+
+    /// Whether the server understands an event (the others are not sent)
+    pub fn server_accepts(&self, cmd: &ClientCmd) -> bool {
+        match cmd {
+            ClientCmd::Revoke { .. }
+            | ClientCmd::SessionFull { .. }
+            | ClientCmd::SessionStats { .. }
+            | ClientCmd::Status { .. }
+            | ClientCmd::SteamStatus { .. } => self.server_supports(Capability::Events),
+            _ => true,
+        }
+    }
+
+    /**
+     * Serializes a message for the server (signed if a signing key is set)
+     * @return A text frame for JSON, a binary frame for MessagePack
+     */
+    pub fn encode(&self, msg: &ClientMessage) -> Result<Message> {
+        if console::verbosity() >= Verbosity::Verbose {
+            if let Ok(value) = serde_json::to_value(msg) {
+                console::trace_message("->", &value);
+            }
+        }
+        match self.wire_format {
+            WireFormat::Json => {
+                let json = serde_json::to_string(msg)
+                    .context("Failed to serialize JSON message for the server")?;
+                let text = match &self.signing_key {
+                    Some(key) => serde_json::to_string(&key.sign(json))
+                        .context("Failed to serialize JSON message for the server")?,
+                    None => json,
+                };
+                Ok(Message::Text(text))
+            }
+            // The signature still covers the JSON of the message
+            WireFormat::MessagePack => {
+                let data = match &self.signing_key {
+                    Some(key) => {
+                        let json = serde_json::to_string(msg)
+                            .context("Failed to serialize JSON message for the server")?;
+                        msgpack::to_vec(&key.sign(json))
+                    }
+                    None => msgpack::to_vec(msg),
+                }
+                .context("Failed to serialize MessagePack message for the server")?;
+                Ok(Message::Binary(data))
+            }
+        }
+    }
+
+    /**
+     * Parses a message from the server (verifying its signature if it is signed)
+     * A MessagePack message switches the messages sent to the server to MessagePack.
+     * @return None if the signature is invalid or the message was replayed
+     */
+    pub fn decode(
+        &mut self,
+        data: &[u8],
+        wire_format: WireFormat,
+    ) -> Result<Option<ServerMessage>> {
+        if wire_format != self.wire_format {
+            tracing::debug!(?wire_format, "The server switched the wire format");
+            self.wire_format = wire_format;
+        }
+        let parse = |data: &[u8]| match wire_format {
+            WireFormat::Json => serde_json::from_slice::<Value>(data)
+                .context("Failed to deserialize JSON message from the server"),
+            WireFormat::MessagePack => msgpack::from_slice::<Value>(data)
+                .context("Failed to deserialize MessagePack message from the server"),
+        };
+
+        let signed = match (&self.signing_key, wire_format) {
+            (None, _) => None,
+            (Some(_), WireFormat::Json) => serde_json::from_slice::<SignedMessage>(data).ok(),
+            (Some(_), WireFormat::MessagePack) => msgpack::from_slice::<SignedMessage>(data).ok(),
+        };
+        let value = match (&self.signing_key, &signed) {
+            (Some(key), Some(signed)) => match key.verify(signed, &mut self.replay_guard) {
+                Ok(json) => serde_json::from_str::<Value>(json)
+                    .context("Failed to deserialize JSON message from the server"),
+                Err(err) => {
+                    console::eprintln!("☓ {}", t!("message-ignored", error = format!("{err:#}")));
+                    return Ok(None);
+                }
+            },
+            // Once a key is set, only the server can send messages
+            (Some(_), None) => {
+                let error = "The message is not signed";
+                console::eprintln!("☓ {}", t!("message-ignored", error = error));
+                return Ok(None);
+            }
+            (None, _) => parse(data),
+        };
+        let value = match value {
+            Ok(value) => value,
+            Err(err) => {
+                // Keep the connection, the next messages may be fine
+                tracing::warn!("{err:#}");
+                return Ok(None);
+            }
+        };
+        console::trace_message("<-", &value);
+        Ok(Some(server_message(value)))
+    }
+
+    /// Copies the invite links requested by the server to the clipboard
+    pub fn set_copy_invite(&mut self, copy_invite: bool) {
+        self.copy_invite = copy_invite;
+    }
+
+    /// Opens every new invite link in the browser
+    pub fn set_auto_open_invite(&mut self, auto_open_invite: bool) {
+        self.auto_open_invite = auto_open_invite;
+    }
+
+    /// Starts the requested game through Steam when it isn't running, waiting up to `timeout` for it (None: don't)
+    pub fn set_auto_launch(&mut self, timeout: Option<Duration>) {
+        self.launch_timeout = timeout;
+    }
+
+    /// Restricts the games that can be invited to (empty `allowed`: any game not in `denied`)
+    pub fn set_game_lists(&mut self, allowed: HashSet<u32>, denied: HashSet<u32>) {
+        self.allowed_games = allowed;
+        self.denied_games = denied;
+    }
+
+    /// Approves the invite requests of `allowed` users (asking for the others) and declines the `blocked` ones
+    pub fn set_user_lists(&mut self, allowed: HashSet<String>, blocked: HashSet<String>) {
+        self.allowed_users = allowed;
+        self.blocked_users = blocked;
+    }
+
+    /// Applies settings of games (by app ID) when they are invited to, instead of the general ones
+    pub fn set_game_configs(&mut self, game_configs: HashMap<u32, GameConfig>) {
+        self.game_configs = game_configs;
+    }
+
+    /**
+     * Gets the usage limits of the invites to a game
+     * @return The game's own limits, else the defaults
+     */
+    fn invite_options(&self, app_id: u32) -> InviteOptions {
+        let game = self.game_configs.get(&app_id);
+        InviteOptions {
+            max_uses: game
+                .and_then(|game| game.invite_max_uses)
+                .or(self.invite_defaults.max_uses),
+            ttl: game
+                .and_then(|game| game.invite_ttl)
+                .map(Duration::from_secs)
+                .or(self.invite_defaults.ttl),
+        }
+    }
+
+    /// Gets the maximum number of guests when inviting to a game (its own limit, else the general one)
+    fn max_guests_for(&self, app_id: u32) -> Option<usize> {
+        self.game_configs
+            .get(&app_id)
+            .and_then(|game| game.max_guests)
+            .or(self.max_guests)
+    }
+
+    /// Whether the invite requests of a user for a game are approved, declined or asked on the console
+    fn user_access(&self, user: Option<&User>, app_id: Option<u32>) -> UserAccess {
+        let listed = |list: &HashSet<String>| {
+            user.is_some_and(|user| {
+                list.contains(&user.id)
+                    || user.steam_id.as_ref().is_some_and(|id| list.contains(id))
+            })
+        };
+        let auto_accept = app_id
+            .and_then(|app_id| self.game_configs.get(&app_id))
+            .and_then(|game| game.auto_accept);
+        if listed(&self.blocked_users) {
+            UserAccess::Blocked
+        } else if listed(&self.allowed_users) {
+            UserAccess::Allowed
+        } else {
+            match auto_accept {
+                Some(true) => UserAccess::Allowed,
+                Some(false) => UserAccess::Ask,
+                None if self.allowed_users.is_empty() => UserAccess::Allowed,
+                None => UserAccess::Ask,
+            }
+        }
+    }
+
+    /// Ends the sessions lasting `timeout`, warning 5 minutes before (None: no limit; must be set before watching Steam)
+    pub fn set_session_timeout(&mut self, timeout: Option<Duration>) {
+        self.session_timeout = timeout;
+    }
+
+    /// Kicks the guests idle for `timeout` (None: never; must be set before watching Steam)
+    pub fn set_idle_kick(&mut self, timeout: Option<Duration>) {
+        self.idle_kick = timeout;
+    }
+
+    /// Limits the number of guests in the session (must be set before setting up the callbacks)
+    pub fn set_max_guests(&mut self, max_guests: Option<usize>) {
+        self.max_guests = max_guests;
+    }
+
+    /// Shows the guests with a nickname (Steam ID -> nickname) by it
+    pub async fn set_nicknames(&mut self, nicknames: HashMap<u64, String>) {
+        self.guest_data.lock().await.nicknames = nicknames;
+    }
+
+    /// Sends the play session statistics to the server when a session ends
+    pub async fn set_report_stats(&mut self, report: bool) {
+        self.guest_data.lock().await.stats.report = report;
+    }
+
+    /// Records a reconnection to the server in the session statistics
+    pub async fn record_reconnect(&self) {
+        self.guest_data.lock().await.stats.on_reconnect();
+    }
+
+    /// Records the latest round-trip time to the server
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = Some(latency);
+    }
+
+    /// Gets the token to resume the current session after a reconnect
+    pub fn session_token(&self) -> Option<&str> {
+        self.session_token.as_deref()
+    }
+
+    /**
+     * Takes the delay announced by the server before going down for maintenance
+     * @return None unless a maintenance was announced since the last call
+     */
+    pub fn take_maintenance(&mut self) -> Option<Duration> {
+        self.maintenance.take()
+    }
+
+    /**
+     * Takes the URL the server redirected the client to
+     * @return None unless a redirect was received since the last call
+     */
+    pub fn take_redirect(&mut self) -> Option<String> {
+        self.redirect.take()
+    }
+
+    /// Receives the next event to notify the server of
+    pub async fn recv_event(&mut self) -> Option<ClientCmd> {
+        self.event_rx.recv().await
+    }
+
+    /**
+     * Handles server messages (the replies are queued, see `flush_outbox`)
+     * @return Whether to exit (true: exit)
+     */
+    #[tracing::instrument(
+        name = "handle_server_message",
+        skip_all,
+        fields(id = %msg.id, user = msg.user.as_ref().map(|u| u.id.as_str()))
+    )]
+    pub async fn handle_server_message(&mut self, msg: ServerMessage) -> Result<bool> {
+        crash::record_message(&msg);
+        let msg = match self.middleware.inbound(msg) {
+            Inbound::Handle(msg) => msg,
+            Inbound::Reply(reply) => {
+                self.outbox.push(reply);
+                return Ok(false);
+            }
+            Inbound::Drop => return Ok(false),
+        };
+
+        // Handle a message retransmitted after a resume only once, answering it with the same reply
+        let recent_key = RecentMessages::key(&msg);
+        if let Some(key) = &recent_key {
+            if let Some(reply) = self.recent_messages.get(key).map(|reply| reply.cloned()) {
+                tracing::info!(key, "Ignored a message that was already handled");
+                if let Some(reply) = reply {
+                    self.outbox.push(reply);
+                }
+                return Ok(false);
+            }
+            self.recent_messages.insert(key.clone(), None);
+        }
+
+        // Route the message to the handler of its command
+        let id = msg.id.clone();
+        let dispatcher = self.dispatcher.clone();
+        let cmd = match dispatcher.dispatch(self, msg).await? {
+            Outcome::Reply(cmd) => cmd,
+            Outcome::Done => return Ok(false),
+            Outcome::Exit => return Ok(true),
+        };
+        self.queue_reply(id, recent_key, cmd);
+
+        Ok(false)
+    }
+
+    /// Queues the reply to a server message (`recent_key`: its key among the recently handled ones)
+    fn queue_reply(&mut self, id: String, recent_key: Option<String>, cmd: ClientCmd) {
+        let mut res = ClientMessage { id, cmd, key: None };
+
+        // Remember the reply, with the same idempotency key if it has to be sent again
+        res.key = Some(Uuid::new_v4().to_string());
+        if let Some(key) = recent_key {
+            self.recent_messages.insert(key, Some(res.clone()));
+        }
+
+        // Queue the response data (kept until it is sent, even across a reconnect)
+        self.outbox.push(res);
+    }
+
+    /// Approves or declines an invite request waiting on the console (by user name or ID, else the oldest one)
+    async fn answer_request(&mut self, query: Option<String>, approve: bool) -> Result<()> {
+        let index = match &query {
+            Some(query) => self.pending_requests.iter().position(|request| {
+                request
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| user.id == *query || user.name.eq_ignore_ascii_case(query))
+            }),
+            None => (!self.pending_requests.is_empty()).then_some(0),
+        };
+        let Some(request) = index.and_then(|index| self.pending_requests.remove(index)) else {
+            console::eprintln!("☓ {}", t!("request-none-waiting"));
+            return Ok(());
+        };
+
+        let claimer = request.user.as_ref().map_or("?", |user| &user.name);
+        let cmd = if approve {
+            console::println!("✓ {}", t!("request-approved", claimer = claimer));
+            let user = request.user.clone();
+            match invite::create_link(
+                self,
+                user.as_ref(),
+                request.game,
+                request.max_uses,
+                request.ttl,
+            )
+            .await
+            {
+                Ok(cmd) => cmd,
+                Err(err) => {
+                    console::eprintln!("☓ {err:#}");
+                    ClientCmd::Error {
+                        code: ErrorStatus::InvalidApp,
+                    }
+                }
+            }
+        } else {
+            console::println!("↪ {}", t!("request-declined", claimer = claimer));
+            ClientCmd::Error {
+                code: ErrorStatus::UserDenied,
+            }
+        };
+        self.queue_reply(request.id, request.key, cmd);
+        Ok(())
+    }
+
+    /**
+     * Handles console commands
+     * @return Whether to exit (true: exit)
+     */
+    pub async fn handle_console_command(&mut self, cmd: ConsoleCmd) -> Result<bool> {
+        match cmd {
+            ConsoleCmd::Status => {
+                match self.target_game().await {
+                    Ok((app_id, _)) if self.selected_game.is_some() => {
+                        console::println!(
+                            "✓ {}",
+                            t!("console-target-game", game = store::game_label(app_id))
+                        )
+                    }
+                    Ok((app_id, _)) => console::println!(
+                        "✓ {}",
+                        t!("console-running-game", game = store::game_label(app_id))
+                    ),
+                    Err(ErrorStatus::UnsupportedApp) => {
+                        console::println!("☓ {}", t!("console-game-unsupported"))
+                    }
+                    Err(_) => console::println!("□ {}", t!("console-no-game")),
+                }
+                let guest_data = self.guest_data.lock().await;
+                let guest_count = guest_data.user_set.len();
+                let max_guests = guest_data.game_max_guests.or(self.max_guests);
+                drop(guest_data);
+                let players = match max_guests {
+                    Some(max) => format!("{guest_count}/{max}"),
+                    None => guest_count.to_string(),
+                };
+                console::println!("★ {}", t!("console-players", players = players));
+                match self.latency {
+                    Some(latency) => {
+                        console::println!("✓ {}", t!("console-latency", ms = latency.as_millis()))
+                    }
+                    None => console::println!("□ {}", t!("console-latency-unknown")),
+                }
+                if self.is_paused() {
+                    console::println!("□ {}", t!("console-invites-paused"));
+                }
+            }
+            ConsoleCmd::Invite => {
+                // Create an invite link
+                let (guest_id, connect_url) = self.invite(None).await?;
+
+                // Log the output
+                console::println!(
+                    "-> {}",
+                    t!("invite-created", guest_id = guest_id, url = connect_url)
+                );
+                console::print_qr(&connect_url)?;
+
+                // Copy to clipboard
+                if let Err(_err) = ClipboardProvider::new()
+                    .map(|mut ctx: ClipboardContext| ctx.set_contents(connect_url.clone()))
+                {
+                    console::eprintln!("☓ {}", t!("clipboard-failed", url = connect_url));
+                }
+            }
+            ConsoleCmd::Guests => {
+                let guest_data = self.guest_data.lock().await;
+                if guest_data.user_set.is_empty() {
+                    console::println!("★ {}", t!("players-none"));
+                } else {
+                    let roster = guest_data.roster_text();
+                    console::println!(
+                        "★ {}",
+                        t!("players-list", count = guest_data.user_set.len())
+                    );
+                    console::println!("{roster}");
+                }
+            }
+            ConsoleCmd::Kick(query) => {
+                let guest_id = self.guest_data.lock().await.find(&query);
+                match guest_id {
+                    Some(guest_id) => {
+                        if let Some(name) = self.kick_guest(guest_id).await {
+                            console::println!(
+                                "-> {}",
+                                t!("player-kicked", guest_id = guest_id, name = name)
+                            );
+                        }
+                    }
+                    None => console::eprintln!("☓ {}", t!("player-not-found", query = query)),
+                }
+            }
+            ConsoleCmd::Nick(query, nickname) => {
+                let mut guest_data = self.guest_data.lock().await;
+                let guest = guest_data
+                    .find(&query)
+                    .and_then(|guest_id| Some((guest_id, *guest_data.steam_ids.get(&guest_id)?)));
+                match guest {
+                    Some((guest_id, steam_id)) => {
+                        let name = guest_data.name(guest_id);
+                        guest_data.nicknames.insert(steam_id, nickname.clone());
+                        drop(guest_data);
+
+                        // Remember the nickname for the next sessions
+                        let mut config = config::read_config()?
+                            .context("The UUID config file does not exist")?;
+                        config
+                            .nicknames
+                            .insert(steam_id.to_string(), nickname.clone());
+                        config::write_config(&config)?;
+                        console::println!(
+                            "✓ {}",
+                            t!(
+                                "player-nicknamed",
+                                name = name,
+                                nickname = nickname,
+                                steam_id = steam_id
+                            )
+                        );
+                    }
+                    None => console::eprintln!("☓ {}", t!("player-not-found", query = query)),
+                }
+            }
+            ConsoleCmd::Revoke(guest_id) => match self.revoke_invite(guest_id).await {
+                Some(guest_id) => {
+                    events::emit(Event::InviteRevoked { guest_id });
+                    console::println!("-> {}", t!("invite-revoked", guest_id = guest_id));
+
+                    // Notify the server so that the link is no longer shown
+                    let _ = self
+                        .event_tx
+                        .send(ClientCmd::Revoke { guest: guest_id })
+                        .await;
+                }
+                None => console::eprintln!("☓ {}", t!("invite-none-to-revoke")),
+            },
+            ConsoleCmd::Games => {
+                let games = library::installed_games()?;
+                let app_ids = games.iter().map(|game| game.app_id).collect();
+                let supported = self.steam.remote_play_apps(app_ids).await?;
+                let games: Vec<_> = games
+                    .into_iter()
+                    .filter(|game| supported.contains(&game.app_id))
+                    .collect();
+                if games.is_empty() {
+                    console::println!("□ {}", t!("games-none"));
+                } else {
+                    console::println!("★ {}", t!("games-list", count = games.len()));
+                    for game in &games {
+                        let selected = if self.selected_game == Some(game.app_id) {
+                            format!(" {}", t!("games-selected"))
+                        } else {
+                            String::new()
+                        };
+                        console::println!("  {:>8}  {}{selected}", game.app_id, game.name);
+                    }
+                    console::println!("↪ {}", t!("games-hint"));
+                }
+            }
+            ConsoleCmd::SelectGame(Some(app_id)) => {
+                if self.steam.remote_play_apps(vec![app_id]).await?.is_empty() {
+                    console::eprintln!("☓ {}", t!("game-unavailable", app_id = app_id));
+                } else {
+                    self.selected_game = Some(app_id);
+                    store::prefetch(app_id);
+                    console::println!("✓ {}", t!("game-selected", app_id = app_id));
+                }
+            }
+            ConsoleCmd::SelectGame(None) => {
+                self.selected_game = None;
+                console::println!("✓ {}", t!("game-running-selected"));
+            }
+            ConsoleCmd::Open(target) => {
+                let url = match target {
+                    OpenTarget::Invite => self
+                        .last_invite
+                        .clone()
+                        .context("No invite link has been created yet")?,
+                    OpenTarget::Discord => self
+                        .discord_url
+                        .clone()
+                        .context("The server has not sent a Discord link")?,
+                };
+                platform::open_url(&url)?;
+                console::println!("-> {}", t!("link-opened", url = url));
+            }
+            ConsoleCmd::Help => {
+                console::println!("{}", t!("help-commands"));
+                if console::is_deck() {
+                    console::println!("    {}", t!("help-deck-invite"));
+                }
+                #[cfg(feature = "plugins")]
+                for (name, help) in crate::plugins::commands() {
+                    console::println!("    {name:<9} {help}");
+                }
+            }
+            ConsoleCmd::Approve(query) => self.answer_request(query, true).await?,
+            ConsoleCmd::Deny(query) => self.answer_request(query, false).await?,
+            ConsoleCmd::Pause(paused) => {
+                if self.is_paused() == paused {
+                    let key = if paused {
+                        "requests-already-paused"
+                    } else {
+                        "requests-already-accepted"
+                    };
+                    console::println!("□ {}", t!(key));
+                } else {
+                    self.set_paused(paused).await;
+                    if paused {
+                        console::println!("□ {}", t!("requests-paused"));
+                    } else {
+                        console::println!("✓ {}", t!("requests-resumed"));
+                    }
+                }
+            }
+            ConsoleCmd::Quit => {
+                // Exit the application
+                return Ok(true);
+            }
+            #[cfg(feature = "plugins")]
+            ConsoleCmd::Plugin(name, args) => {
+                if let Err(err) = crate::plugins::run_command(&name, &args) {
+                    console::eprintln!("☓ {err:#}");
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /**
+     * Handles requests from the control API
+     * @return Whether to exit (true: exit)
+     */
+    pub async fn handle_control_request(&mut self, req: ControlRequest) -> bool {
+        let (result, exit) = match req.method {
+            ControlMethod::GetStatus => {
+                let (game, supported) = match self.target_game().await {
+                    Ok((app_id, _)) => (Some(app_id), true),
+                    Err(_) => {
+                        let game = self.steam.running_game().await.ok();
+                        (
+                            game.and_then(|(game_uid, _)| steam::app_id(game_uid)),
+                            false,
+                        )
+                    }
+                };
+                let guest_data = self.guest_data.lock().await;
+                let guests = guest_data.user_set.len();
+                let max_guests = guest_data.game_max_guests.or(self.max_guests);
+                drop(guest_data);
+                let status = json!({
+                    "game": game,
+                    "remote_play_together": supported,
+                    "guests": guests,
+                    "max_guests": max_guests,
+                    "latency_ms": self.latency.map(|latency| latency.as_millis() as u64),
+                    "compression": {
+                        "active": metrics::COMPRESSION_ACTIVE.get() == 1,
+                        "sent_bytes": metrics::UNCOMPRESSED_BYTES_SENT.get(),
+                        "sent_compressed_bytes": metrics::COMPRESSED_BYTES_SENT.get(),
+                        "received_bytes": metrics::UNCOMPRESSED_BYTES_RECEIVED.get(),
+                        "received_compressed_bytes": metrics::COMPRESSED_BYTES_RECEIVED.get(),
+                    },
+                });
+                (Ok(status), false)
+            }
+            ControlMethod::CreateInvite => {
+                let result = self
+                    .invite(None)
+                    .await
+                    .map(|(guest_id, url)| json!({ "guest_id": guest_id, "url": url }))
+                    .map_err(|err| err.to_string());
+                (result, false)
+            }
+            ControlMethod::ListGuests => {
+                let guest_data = self.guest_data.lock().await;
+                let guests: Vec<Value> = guest_data
+                    .user_set
+                    .iter()
+                    .map(|id| {
+                        json!({
+                            "guest_id": id,
+                            "name": guest_data.name(*id),
+                            "steam_id": guest_data.steam_ids.get(id),
+                            "joined_at": guest_data.joined_at.get(id).map(|at| at.to_rfc3339()),
+                        })
+                    })
+                    .collect();
+                (Ok(Value::Array(guests)), false)
+            }
+            ControlMethod::Shutdown => (Ok(Value::Null), true),
+        };
+        let _ = req.reply.send(result);
+        exit
+    }
+
+    /**
+     * Creates an invite link with the default usage limits
+     * for the given game, or the running game if `app_id` is None
+     * @return The guest ID and the invite URL
+     */
+    pub async fn invite(&mut self, app_id: Option<u32>) -> Result<(u64, String)> {
+        let (app_id, game_uid) = match app_id {
+            Some(app_id) => (app_id, GameID::new(app_id, 0, 0).into()),
+            None => match self.target_game().await {
+                Ok(game) => game,
+                Err(ErrorStatus::UnsupportedApp) => {
+                    anyhow::bail!("The running game does not support Remote Play Together")
+                }
+                Err(_) => anyhow::bail!("No game is running"),
+            },
+        };
+        if !self.is_allowed(app_id) {
+            anyhow::bail!("Invites to this game are not allowed: game_id={app_id}");
+        }
+        if !self.supports_remote_play(app_id).await {
+            anyhow::bail!("The game does not support Remote Play Together: game_id={app_id}");
+        }
+        if self.is_full(app_id).await {
+            anyhow::bail!("The session is full");
+        }
+
+        let (guest_id, connect_url) = self
+            .create_invite(game_uid, self.invite_options(app_id))
+            .await?;
+        history::record(HistoryEvent::InviteCreated {
+            guest_id,
+            claimer: None,
+            game: app_id,
+        });
+        Ok((guest_id, connect_url))
+    }
+
+    /// Whether the invite requests are declined
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Declines or accepts the invite requests again, telling the server right away
+    pub async fn set_paused(&mut self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        let game = self
+            .steam
+            .running_game()
+            .await
+            .ok()
+            .and_then(|(game_uid, _)| steam::app_id(game_uid));
+        let guests = self.guest_data.lock().await.user_set.len();
+        let _ = self
+            .event_tx
+            .send(ClientCmd::Status {
+                game,
+                guests,
+                paused,
+            })
+            .await;
+    }
+
+    /// Creates an invite link on schedule and posts it through the server (as if requested from Discord)
+    pub async fn post_invite(&mut self, app_id: Option<u32>) -> Result<()> {
+        if self.is_paused() {
+            anyhow::bail!("Invite requests are paused, skipped the scheduled invite");
+        }
+        if let Some(app_id) = app_id {
+            if !self.launch_game(app_id).await? {
+                anyhow::bail!("The scheduled game did not start: game_id={app_id}");
+            }
+        }
+        let (guest_id, connect_url) = self
+            .invite(app_id)
+            .await
+            .context("Unable to create the scheduled invite")?;
+        let options = match app_id.or(self.target_game().await.ok().map(|(app_id, _)| app_id)) {
+            Some(app_id) => self.invite_options(app_id),
+            None => self.invite_defaults,
+        };
+        console::println!(
+            "-> {}",
+            t!("invite-scheduled", guest_id = guest_id, url = connect_url)
+        );
+        console::print_qr(&connect_url)?;
+
+        let _ = self
+            .event_tx
+            .send(ClientCmd::Link {
+                url: connect_url,
+                max_uses: options.max_uses,
+                ttl: options.ttl.map(|ttl| ttl.as_secs()),
+            })
+            .await;
+        Ok(())
+    }
+
+    /**
+     * Gets the game invites target (the one selected with the `game` command, else the running game)
+     * @return The app ID and game UID, or the error status if it cannot be invited to
+     */
+    async fn target_game(&self) -> std::result::Result<(u32, GameUID), ErrorStatus> {
+        if let Some(app_id) = self.selected_game {
+            return Ok((app_id, GameID::new(app_id, 0, 0).into()));
+        }
+
+        let (game_uid, supported) = self
+            .steam
+            .running_game()
+            .await
+            .map_err(|_| ErrorStatus::InvalidApp)?;
+
+        let Some(app_id) = steam::app_id(game_uid) else {
+            // If the game is not running
+            return Err(ErrorStatus::InvalidApp);
+        };
+
+        if !supported {
+            // If the game is not supported for Remote Play Together
+            return Err(ErrorStatus::UnsupportedApp);
+        }
+
+        Ok((app_id, game_uid))
+    }
+
+    /**
+     * Starts a game through Steam (`steam://rungameid/`) if it isn't running and auto-launch is enabled
+     * @return Whether an invite can be created for the game (false: it did not start in time)
+     */
+    async fn launch_game(&self, app_id: u32) -> Result<bool> {
+        let Some(launch_timeout) = self.launch_timeout else {
+            return Ok(true);
+        };
+        let is_running = || async {
+            self.steam
+                .running_game()
+                .await
+                .ok()
+                .and_then(|(game_uid, _)| steam::app_id(game_uid))
+                == Some(app_id)
+        };
+        if is_running().await {
+            return Ok(true);
+        }
+
+        if let Err(err) = platform::open_url(&format!("steam://rungameid/{app_id}")) {
+            console::eprintln!("☓ {}", t!("game-launch-failed", error = format!("{err:#}")));
+            return Ok(false);
+        }
+        console::println!(
+            "↪ {}",
+            t!(
+                "game-launching",
+                app_id = app_id,
+                seconds = launch_timeout.as_secs()
+            )
+        );
+        let started = time::timeout(launch_timeout, async {
+            while !is_running().await {
+                time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .is_ok();
+        if started {
+            console::println!("✓ {}", t!("game-started", game = store::game_label(app_id)));
+        } else {
+            console::eprintln!(
+                "☓ {}",
+                t!(
+                    "game-start-timed-out",
+                    app_id = app_id,
+                    seconds = launch_timeout.as_secs()
+                )
+            );
+        }
+        Ok(started)
+    }
+
+    /**
+     * Whether a game supports Remote Play Together, according to the Steam client if it is running, else the Steam store
+     * @return true when it cannot be known
+     */
+    async fn supports_remote_play(&self, app_id: u32) -> bool {
+        if let Ok((game_uid, supported)) = self.steam.running_game().await {
+            if steam::app_id(game_uid) == Some(app_id) {
+                return supported;
+            }
+        }
+        store::supports_remote_play(app_id).await.unwrap_or(true)
+    }
+
+    /// Whether invites to a game are allowed by the allow/deny lists
+    fn is_allowed(&self, app_id: u32) -> bool {
+        (self.allowed_games.is_empty() || self.allowed_games.contains(&app_id))
+            && !self.denied_games.contains(&app_id)
+    }
+
+    /// Whether the session has reached the maximum number of guests for a game
+    async fn is_full(&self, app_id: u32) -> bool {
+        let guest_count = self.guest_data.lock().await.user_set.len();
+        self.max_guests_for(app_id)
+            .is_some_and(|max| guest_count >= max)
+    }
+
+    /**
+     * Removes a guest from the Remote Play session
+     * @return The name of the guest, or None if the guest is not connected
+     */
+    async fn kick_guest(&self, guest_id: u64) -> Option<String> {
+        let guest_data = self.guest_data.lock().await;
+        if !guest_data.user_set.contains(&guest_id) {
+            return None;
+        }
+        let steam_id = guest_data.steam_ids.get(&guest_id).copied().unwrap_or(0);
+        let name = guest_data.name(guest_id);
+        drop(guest_data);
+
+        // The player is removed from the roster when the session stopped callback is called
+        self.steam.cancel_invite(steam_id, guest_id).await;
+        Some(name)
+    }
+
+    /**
+     * Creates an invite link
+     * @return The guest ID and the invite URL
+     */
+    async fn create_invite(
+        &mut self,
+        game_uid: GameUID,
+        options: InviteOptions,
+    ) -> Result<(u64, String)> {
+        metrics::INVITE_REQUESTS.inc();
+        // Revoke the links delivered after their request timed out
+        while let Ok((guest_id, _)) = self.invite_rx.try_recv() {
+            tracing::info!(guest_id, "Revoking an invite link created too late");
+            self.steam.cancel_invite(0, guest_id).await;
+        }
+        self.steam.send_invite(0, game_uid).await?;
+        let Ok(received) = time::timeout(INVITE_TIMEOUT, self.invite_rx.recv()).await else {
+            metrics::STEAM_CALLBACK_ERRORS.inc();
+            anyhow::bail!("The Steam client did not create the invite link in time");
+        };
+        let Some((guest_id, connect_url)) = received else {
+            metrics::STEAM_CALLBACK_ERRORS.inc();
+            anyhow::bail!("Failed to receive the invite link from the Steam client");
+        };
+        let game = steam::app_id(game_uid);
+        let mut guest_data = self.guest_data.lock().await;
+        guest_data.pending_invites.insert(guest_id);
+        // The guests joining from now on count against the limit of this game
+        guest_data.game_max_guests = game.and_then(|app_id| {
+            self.game_configs
+                .get(&app_id)
+                .and_then(|game| game.max_guests)
+        });
+        guest_data.invite_limits.insert(
+            guest_id,
+            InviteLimit {
+                uses: 0,
+                max_uses: options.max_uses,
+                expires_at: options.ttl.map(|ttl| Instant::now() + ttl),
+            },
+        );
+        drop(guest_data);
+        events::emit(Event::InviteCreated {
+            guest_id,
+            url: connect_url.clone(),
+            game,
+            game_name: game.and_then(store::game_name),
+        });
+
+        self.last_invite = Some(connect_url.clone());
+        if self.auto_open_invite {
+            if let Err(err) = platform::open_url(&connect_url) {
+                console::eprintln!("☓ {err:#}");
+            }
+        }
+        Ok((guest_id, connect_url))
+    }
+
+    /**
+     * Revokes an unused invite link (the most recent one if `guest_id` is None)
+     * @return The guest ID of the revoked invite, or None if there is no such invite
+     */
+    async fn revoke_invite(&self, guest_id: Option<u64>) -> Option<u64> {
+        let mut guest_data = self.guest_data.lock().await;
+        let guest_id = match guest_id {
+            Some(guest_id) => guest_id,
+            None => *guest_data.pending_invites.last()?,
+        };
+        if !guest_data.pending_invites.remove(&guest_id) {
+            return None;
+        }
+        guest_data.revoked_invites.insert(guest_id);
+        drop(guest_data);
+
+        self.steam.cancel_invite(0, guest_id).await;
+        Some(guest_id)
+    }
+
+    // Set up SteamStuff callbacks
+    pub async fn setup_steam_callbacks(&self) {
+        let callbacks = self.steam_callbacks();
+        self.steam.set_callbacks(Some(callbacks)).await;
+    }
+
+    // Create the SteamStuff callbacks
+    fn steam_callbacks(&self) -> Callbacks {
+        let max_guests = self.max_guests;
+        let guest_data_clone = self.guest_data.clone();
+        let steam_clone = self.steam.clone();
+        let event_tx_clone = self.event_tx.clone();
+        let on_started = Arc::new(move |invitee: u64, guest_id: u64| {
+            let guest_data = guest_data_clone.clone();
+            let steam = steam_clone.clone();
+            let event_tx = event_tx_clone.clone();
+            tokio::spawn(async move {
+                let mut guest_data = guest_data.lock().await;
+                guest_data.pending_invites.remove(&guest_id);
+
+                // Remove the guest if the invite is revoked, expired or used up
+                if let Err(reason) = guest_data.use_invite(guest_id) {
+                    drop(guest_data);
+                    steam.cancel_invite(invitee, guest_id).await;
+                    tracing::info!(
+                        guest_id,
+                        steam_id = invitee,
+                        reason,
+                        "Invalid invite used, player removed"
+                    );
+                    let _: Result<()> = (|| {
+                        let message = match reason {
+                            "revoked" => t!("player-removed-revoked", guest_id = guest_id),
+                            "expired" => t!("player-removed-expired", guest_id = guest_id),
+                            _ => t!("player-removed-used-up", guest_id = guest_id),
+                        };
+                        console::eprintln!("☓ {message}");
+                        Ok(())
+                    })();
+                    return;
+                }
+
+                // Remove the guest if the session is already full
+                let max_guests = guest_data.game_max_guests.or(max_guests);
+                if let Some(max) = max_guests.filter(|max| guest_data.user_set.len() >= *max) {
+                    let user_name = guest_data.name(guest_id);
+                    drop(guest_data);
+                    steam.cancel_invite(invitee, guest_id).await;
+                    tracing::info!(guest_id, steam_id = invitee, name = %user_name, "Session full, player removed");
+                    let _: Result<()> = (|| {
+                        console::eprintln!(
+                            "☓ {}",
+                            t!("player-removed-full", max = max, name = user_name)
+                        );
+                        Ok(())
+                    })();
+                    let _ = event_tx
+                        .send(ClientCmd::SessionFull { max_guests: max })
+                        .await;
+                    return;
+                }
+
+                let now = Local::now();
+                guest_data.user_set.insert(guest_id);
+                guest_data.joined_at.insert(guest_id, now);
+                guest_data.steam_ids.insert(guest_id, invitee);
+                guest_data.last_active.insert(guest_id, Instant::now());
+                let user_name = guest_data.name(guest_id);
+                let guest_count = guest_data.user_set.len();
+                guest_data.stats.on_join(invitee, guest_count);
+                tracing::info!(guest_id, steam_id = invitee, name = %user_name, "Player joined");
+                events::emit(Event::GuestJoined {
+                    guest_id,
+                    name: user_name.clone(),
+                    guests: guest_count,
+                });
+                history::record(HistoryEvent::GuestJoined {
+                    guest_id,
+                    name: user_name.clone(),
+                    steam_id: invitee,
+                });
+                let _: Result<()> = (|| {
+                    // Display the user list
+                    let users_text = guest_data.users_text();
+                    console::print_update!(
+                        "★ {}",
+                        t!(
+                            "players-update",
+                            count = guest_data.user_set.len(),
+                            players = users_text
+                        )
+                    );
+
+                    Ok(())
+                })();
+            });
+        });
+        let guest_data_clone = self.guest_data.clone();
+        let event_tx_clone = self.event_tx.clone();
+        let on_stopped = Arc::new(move |invitee: u64, guest_id: u64| {
+            let guest_data = guest_data_clone.clone();
+            let event_tx = event_tx_clone.clone();
+            tokio::spawn(async move {
+                let mut guest_data = guest_data.lock().await;
+                if !guest_data.user_set.remove(&guest_id) {
+                    // The guest was removed before joining (e.g. the session was full)
+                    return;
+                }
+                let user_name = guest_data.name(guest_id);
+                guest_data.joined_at.remove(&guest_id);
+                guest_data.steam_ids.remove(&guest_id);
+                guest_data.last_active.remove(&guest_id);
+                tracing::info!(guest_id, steam_id = invitee, name = %user_name, "Player left");
+                events::emit(Event::GuestLeft {
+                    guest_id,
+                    name: user_name.clone(),
+                    guests: guest_data.user_set.len(),
+                });
+                history::record(HistoryEvent::GuestLeft {
+                    guest_id,
+                    name: user_name.clone(),
+                    steam_id: invitee,
+                });
+                let _: Result<()> = (|| {
+                    // Display the user list
+                    let users_text = guest_data.users_text();
+                    console::print_update!(
+                        "★ {}",
+                        t!(
+                            "players-update",
+                            count = guest_data.user_set.len(),
+                            players = users_text
+                        )
+                    );
+
+                    Ok(())
+                })();
+
+                // The session ends when the last guest leaves
+                if guest_data.user_set.is_empty() {
+                    let report = guest_data.stats.report;
+                    if let Some(summary) = guest_data.stats.finish() {
+                        drop(guest_data);
+                        summary.report(report, &event_tx).await;
+                    }
+                }
+            });
+        });
+        let invite_tx = self.invite_tx.clone();
+        let on_invited = Arc::new(move |_invitee: u64, guest_id: u64, connect_url: &str| {
+            // Send the invite link
+            let invite_tx = invite_tx.clone();
+            let connect_url = String::from(connect_url);
+            tokio::spawn(async move {
+                invite_tx.send((guest_id, connect_url)).await.unwrap();
+            });
+        });
+
+        Callbacks {
+            on_invited,
+            on_started,
+            on_stopped,
+        }
+    }
+
+    // Start a task to expire invites and to follow the connection to the Steam client
+    // (the Steam worker calls the callbacks and reconnects when it restarts)
+    pub fn watch_steam(&mut self) {
+        let steam = self.steam.clone();
+        let guest_data = self.guest_data.clone();
+        let event_tx = self.event_tx.clone();
+        let idle_kick = self.idle_kick;
+        let session_timeout = self.session_timeout;
+        let paused = self.paused.clone();
+        self.callbacks_task = Some(task::spawn(async move {
+            // Start of the session already warned about its end
+            let mut warned_session: Option<DateTime<Local>> = None;
+            let mut health_interval = interval(Duration::from_secs(5));
+            let mut connection = steam.connection();
+            let mut connected = true;
+            let mut running_game = None;
+            let mut last_status: Option<Instant> = None;
+            loop {
+                tokio::select! {
+                    _ = health_interval.tick(), if connected => {
+                        // Cancel the unused invites that have expired
+                        let expired = guest_data.lock().await.expire_invites();
+                        for guest_id in expired {
+                            steam.cancel_invite(0, guest_id).await;
+                            events::emit(Event::InviteRevoked { guest_id });
+                            let _: Result<()> = (|| {
+                                console::println!(
+                                    "↪ {}",
+                                    t!("invite-expired", guest_id = guest_id)
+                                );
+                                Ok(())
+                            })();
+                        }
+
+                        // Follow the running game, ending the session when it exits (or kicking the idle guests)
+                        let game = steam
+                            .running_game()
+                            .await
+                            .ok()
+                            .and_then(|(game_uid, _)| steam::app_id(game_uid));
+                        let changed = game != running_game;
+                        follow_game(&steam, &guest_data, &event_tx, idle_kick, changed && running_game.is_some(), game.is_some()).await;
+                        running_game = game;
+                        if let Some(app_id) = game.filter(|_| changed) {
+                            store::prefetch(app_id);
+                        }
+
+                        // End the session when its time is up
+                        if let Some(timeout) = session_timeout {
+                            let started_at = guest_data.lock().await.stats.started_at();
+                            let elapsed = started_at
+                                .map(|at| (Local::now() - at).to_std().unwrap_or_default());
+                            match elapsed {
+                                Some(elapsed) if elapsed >= timeout => {
+                                    let reason = t!(
+                                        "session-time-limit",
+                                        minutes = timeout.as_secs() / 60
+                                    );
+                                    end_game_session(&steam, &guest_data, &event_tx, &reason).await;
+                                }
+                                Some(elapsed)
+                                    if timeout - elapsed <= SESSION_END_WARNING
+                                        && warned_session != started_at =>
+                                {
+                                    warned_session = started_at;
+                                    events::emit(Event::SessionEnding {
+                                        minutes_left: (timeout - elapsed).as_secs().div_ceil(60),
+                                    });
+                                }
+                                _ => (),
+                            }
+                        }
+
+                        // Tell the server what is hosted
+                        if changed || last_status.map_or(true, |last| last.elapsed() >= STATUS_INTERVAL) {
+                            last_status = Some(Instant::now());
+                            let guests = guest_data.lock().await.user_set.len();
+                            let paused = paused.load(Ordering::Relaxed);
+                            let _ = event_tx.send(ClientCmd::Status { game, guests, paused }).await;
+                        }
+                    }
+                    changed = connection.changed() => {
+                        if changed.is_err() {
+                            // The Steam worker has stopped
+                            break;
+                        }
+                        let now_connected = *connection.borrow_and_update();
+                        if connected && !now_connected {
+                            // The Steam client has stopped
+                            connected = false;
+                            let mut guest_data = guest_data.lock().await;
+                            guest_data.user_set.clear();
+                            metrics::STEAM_CALLBACK_ERRORS.inc();
+                            guest_data.joined_at.clear();
+                            guest_data.steam_ids.clear();
+                            guest_data.last_active.clear();
+                            guest_data.pending_invites.clear();
+                            let report = guest_data.stats.report;
+                            let summary = guest_data.stats.finish();
+                            drop(guest_data);
+                            history::record(HistoryEvent::SessionEnded);
+                            if let Some(summary) = summary {
+                                summary.report(report, &event_tx).await;
+                            }
+                            let _ = event_tx.send(ClientCmd::SteamStatus { connected: false }).await;
+                            events::emit(Event::SteamLost);
+                        } else if !connected && now_connected {
+                            // The Steam client has restarted (the worker registered the callbacks again)
+                            connected = true;
+                            let _ = event_tx.send(ClientCmd::SteamStatus { connected: true }).await;
+                            events::emit(Event::SteamRestored);
+                        }
+                    }
+                }
+            }
+        }));
+    }
+
+    // Stop the Steam worker, which unregisters the callbacks
+    pub async fn shutdown(&mut self) {
+        if let Some(task) = self.callbacks_task.take() {
+            task.abort();
+        }
+        self.steam.stop().await;
+        history::record(HistoryEvent::SessionEnded);
+
+        // The connection to the server is already closed, so only display the summary
+        let summary = self.guest_data.lock().await.stats.finish();
+        if let Some(summary) = summary {
+            summary.report(false, &self.event_tx).await;
+        }
+    }
+}
+
+/**
+ * Ends the session when the hosted game exits (`game_exited`),
+ * or with `idle_kick`, kicks the guests left without a game for that long
+ * (they are kept meanwhile, in case the game starts again)
+ */
+async fn follow_game(
+    steam: &Steam,
+    guest_data: &Arc<Mutex<GuestData>>,
+    event_tx: &Sender<ClientCmd>,
+    idle_kick: Option<Duration>,
+    game_exited: bool,
+    game_running: bool,
+) {
+    match idle_kick {
+        Some(timeout) => kick_idle_guests(steam, guest_data, event_tx, game_running, timeout).await,
+        None if game_exited => {
+            end_game_session(steam, guest_data, event_tx, &t!("session-game-exited")).await
+        }
+        None => (),
+    }
+}
+
+/**
+ * Kicks the guests idle for `timeout`, telling the server
+ * (Remote Play doesn't report the guests' input: they are active while a game is running to stream)
+ */
+async fn kick_idle_guests(
+    steam: &Steam,
+    guest_data: &Arc<Mutex<GuestData>>,
+    event_tx: &Sender<ClientCmd>,
+    game_running: bool,
+    timeout: Duration,
+) {
+    let mut guest_data = guest_data.lock().await;
+    if game_running {
+        let now = Instant::now();
+        for last_active in guest_data.last_active.values_mut() {
+            *last_active = now;
+        }
+        return;
+    }
+    let idle = guest_data.idle_guests(timeout);
+    let names: Vec<String> = idle
+        .iter()
+        .map(|&(guest_id, _)| guest_data.name(guest_id))
+        .collect();
+    for &(guest_id, _) in &idle {
+        // Kicked once (they are removed when the session stopped callback is called)
+        guest_data.last_active.remove(&guest_id);
+    }
+    drop(guest_data);
+
+    for ((guest_id, steam_id), name) in idle.into_iter().zip(names) {
+        steam.cancel_invite(steam_id, guest_id).await;
+        let _: Result<()> = (|| {
+            console::println!(
+                "-> {}",
+                t!(
+                    "player-kicked-idle",
+                    guest_id = guest_id,
+                    name = name,
+                    minutes = timeout.as_secs() / 60
+                )
+            );
+            Ok(())
+        })();
+        let _ = event_tx.send(ClientCmd::Kicked { guest: guest_id }).await;
+    }
+}
+
+/// Ends the play session (when the hosted game exits or its time is up), disconnecting the guests and revoking the unused invites
+async fn end_game_session(
+    steam: &Steam,
+    guest_data: &Mutex<GuestData>,
+    event_tx: &Sender<ClientCmd>,
+    reason: &str,
+) {
+    let mut guest_data = guest_data.lock().await;
+    let pending_invites = std::mem::take(&mut guest_data.pending_invites);
+    let guests = std::mem::take(&mut guest_data.user_set);
+    if pending_invites.is_empty() && guests.is_empty() {
+        return;
+    }
+    let _: Result<()> = (|| {
+        console::println!("↪ {}", t!("session-ending", reason = reason));
+        Ok(())
+    })();
+
+    // The guests are forgotten now, so the callbacks of their departure are ignored
+    let mut remaining = guests.len();
+    let mut disconnected = Vec::with_capacity(guests.len());
+    for guest_id in guests {
+        remaining -= 1;
+        let name = guest_data.name(guest_id);
+        guest_data.joined_at.remove(&guest_id);
+        guest_data.last_active.remove(&guest_id);
+        let steam_id = guest_data.steam_ids.remove(&guest_id).unwrap_or_default();
+        disconnected.push((steam_id, guest_id));
+        events::emit(Event::GuestLeft {
+            guest_id,
+            name: name.clone(),
+            guests: remaining,
+        });
+        history::record(HistoryEvent::GuestLeft {
+            guest_id,
+            name,
+            steam_id,
+        });
+    }
+    let report = guest_data.stats.report;
+    let summary = guest_data.stats.finish();
+    drop(guest_data);
+
+    for (steam_id, guest_id) in disconnected {
+        steam.cancel_invite(steam_id, guest_id).await;
+    }
+    for guest_id in pending_invites {
+        steam.cancel_invite(0, guest_id).await;
+    }
+    history::record(HistoryEvent::SessionEnded);
+    if let Some(summary) = summary {
+        summary.report(report, event_tx).await;
+    }
+}
+
+/**
+ * Converts a message from the server, keeping the connection on unknown or malformed commands
+ * @return The message with `ServerCmd::Unknown` if its command could not be parsed
+ */
+fn server_message(value: Value) -> ServerMessage {
+    let cmd = value
+        .get("cmd")
+        .and_then(Value::as_str)
+        .unwrap_or("?")
+        .to_string();
+    let message = match serde_json::from_value::<ServerMessage>(value.clone()) {
+        Ok(message) => message,
+        Err(err) => {
+            tracing::warn!(cmd, "Failed to parse a message from the server: {err}");
+            ServerMessage {
+                id: value
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                user: None,
+                seq: None,
+                cmd: ServerCmd::Unknown,
+            }
+        }
+    };
+    if matches!(message.cmd, ServerCmd::Unknown) {
+        tracing::warn!(cmd, "Ignored a command this client does not support");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUEST_ID: u64 = 7;
+    const STEAM_ID: u64 = 76561190000000001;
+
+    /// Guest data with a guest connected, last active `idle` ago
+    fn connected_guest(idle: Duration) -> Arc<Mutex<GuestData>> {
+        let mut guest_data = GuestData::default();
+        guest_data.user_set.insert(GUEST_ID);
+        guest_data.steam_ids.insert(GUEST_ID, STEAM_ID);
+        guest_data.joined_at.insert(GUEST_ID, Local::now());
+        guest_data
+            .last_active
+            .insert(GUEST_ID, Instant::now() - idle);
+        guest_data.stats.on_join(STEAM_ID, 1);
+        Arc::new(Mutex::new(guest_data))
+    }
+
+    #[tokio::test]
+    async fn kicks_the_guests_idle_after_the_game_exits() {
+        let (steam, cancelled) = Steam::recording();
+        let (event_tx, mut event_rx) = channel(8);
+        let guest_data = connected_guest(Duration::from_secs(120));
+
+        let idle_kick = Some(Duration::from_secs(60));
+        follow_game(&steam, &guest_data, &event_tx, idle_kick, true, false).await;
+
+        assert_eq!(*cancelled.lock().unwrap(), [(STEAM_ID, GUEST_ID)]);
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(ClientCmd::Kicked { guest: GUEST_ID })
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_guests_during_the_idle_grace_period() {
+        let (steam, cancelled) = Steam::recording();
+        let (event_tx, _event_rx) = channel(8);
+        let guest_data = connected_guest(Duration::from_secs(10));
+
+        let idle_kick = Some(Duration::from_secs(60));
+        follow_game(&steam, &guest_data, &event_tx, idle_kick, true, false).await;
+
+        assert!(cancelled.lock().unwrap().is_empty());
+        assert!(guest_data.lock().await.user_set.contains(&GUEST_ID));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_guests_active_while_a_game_runs() {
+        let (steam, cancelled) = Steam::recording();
+        let (event_tx, _event_rx) = channel(8);
+        let guest_data = connected_guest(Duration::from_secs(120));
+
+        let idle_kick = Some(Duration::from_secs(60));
+        follow_game(&steam, &guest_data, &event_tx, idle_kick, false, true).await;
+        // The game exits right after: the grace period starts now
+        follow_game(&steam, &guest_data, &event_tx, idle_kick, true, false).await;
+
+        assert!(cancelled.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ends_the_session_when_the_game_exits_without_idle_kick() {
+        let (steam, _cancelled) = Steam::recording();
+        let (event_tx, _event_rx) = channel(8);
+        let guest_data = connected_guest(Duration::ZERO);
+
+        follow_game(&steam, &guest_data, &event_tx, None, true, false).await;
+
+        assert!(guest_data.lock().await.user_set.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disconnects_the_guests_when_the_session_ends() {
+        let (steam, cancelled) = Steam::recording();
+        let (event_tx, _event_rx) = channel(8);
+        let guest_data = connected_guest(Duration::ZERO);
+        guest_data.lock().await.pending_invites.insert(8);
+
+        end_game_session(&steam, &guest_data, &event_tx, "time limit").await;
+
+        assert_eq!(*cancelled.lock().unwrap(), [(STEAM_ID, GUEST_ID), (0, 8)]);
+        let guest_data = guest_data.lock().await;
+        assert!(guest_data.user_set.is_empty());
+        assert!(guest_data.pending_invites.is_empty());
+        // The guests no longer count against the limit
+        assert_eq!(guest_data.stats.started_at(), None);
+    }
+
+    /// An invite request message from a Discord user
+    fn request(cmd: ServerCmd) -> ServerMessage {
+        ServerMessage {
+            id: "1".to_string(),
+            user: Some(User {
+                id: "100".to_string(),
+                name: "alice".to_string(),
+                steam_id: None,
+            }),
+            seq: None,
+            cmd,
+        }
+    }
+
+    fn link(game: u32) -> ServerCmd {
+        ServerCmd::Link {
+            game,
+            max_uses: None,
+            ttl: None,
+        }
+    }
+
+    /// Dispatches a message through the built-in handlers
+    async fn dispatch(handler: &mut Handler, cmd: ServerCmd) -> Outcome {
+        Dispatcher::builtin()
+            .dispatch(handler, request(cmd))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn declines_the_requests_while_paused() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.paused.store(true, Ordering::Relaxed);
+
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::GameId).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::HostPaused
+            })
+        ));
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::HostPaused
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn declines_the_requests_of_blocked_users() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.set_user_lists(HashSet::new(), HashSet::from(["100".to_string()]));
+
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::GameId).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::UserDenied
+            })
+        ));
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::UserDenied
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn answers_the_running_game() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+
+        // No game is running
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::GameId).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::InvalidApp
+            })
+        ));
+
+        handler.selected_game = Some(440);
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::GameId).await,
+            Outcome::Reply(ClientCmd::GameId { game: 440 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn declines_the_links_when_the_session_is_full() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.guest_data = connected_guest(Duration::ZERO);
+        handler.set_max_guests(Some(1));
+
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::SessionFull
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn declines_the_links_to_disallowed_games() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.set_game_lists(HashSet::from([570]), HashSet::new());
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::DisallowedApp
+            })
+        ));
+
+        handler.set_game_lists(HashSet::new(), HashSet::from([440]));
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::DisallowedApp
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn holds_the_links_of_unlisted_users_for_approval() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.set_user_lists(HashSet::from(["200".to_string()]), HashSet::new());
+
+        assert!(matches!(
+            dispatch(&mut handler, link(440)).await,
+            Outcome::Done
+        ));
+        assert_eq!(handler.pending_requests.len(), 1);
+        let pending = &handler.pending_requests[0];
+        assert_eq!(pending.id, "1");
+        assert_eq!(pending.game, 440);
+        assert_eq!(
+            pending.user.as_ref().map(|user| user.id.as_str()),
+            Some("100")
+        );
+    }
+
+    #[tokio::test]
+    async fn kicks_the_connected_guests_only() {
+        let (steam, cancelled) = Steam::recording();
+        let mut handler = Handler::new(steam);
+        handler.guest_data = connected_guest(Duration::ZERO);
+
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::Kick { guest: 8 }).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::InvalidGuest
+            })
+        ));
+        assert!(cancelled.lock().unwrap().is_empty());
+
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::Kick { guest: GUEST_ID }).await,
+            Outcome::Reply(ClientCmd::Kicked { guest: GUEST_ID })
+        ));
+        assert_eq!(*cancelled.lock().unwrap(), [(STEAM_ID, GUEST_ID)]);
+    }
+
+    #[tokio::test]
+    async fn answers_the_unknown_commands_with_an_error() {
+        let (steam, _) = Steam::recording();
+        let mut handler = Handler::new(steam);
+
+        assert!(matches!(
+            dispatch(&mut handler, ServerCmd::Unknown).await,
+            Outcome::Reply(ClientCmd::Error {
+                code: ErrorStatus::InvalidCmd
+            })
+        ));
+    }
+}
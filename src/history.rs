@@ -0,0 +1,109 @@
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+};
+
+use crate::config;
+
+/// History file name
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// A line of the history file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Time of the event
+    pub time: DateTime<Local>,
+    /// Event
+    #[serde(flatten)]
+    pub event: HistoryEvent,
+}
+
+/// Events recorded in the history file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    /// An invite link was created
+    InviteCreated {
+        /// Guest ID
+        guest_id: u64,
+        /// Discord user who requested the invite
+        claimer: Option<String>,
+        /// Game ID
+        game: u32,
+    },
+    /// A guest joined the session
+    GuestJoined {
+        /// Guest ID
+        guest_id: u64,
+        /// Guest name
+        name: String,
+        /// Steam ID of the guest
+        steam_id: u64,
+    },
+    /// A guest left the session
+    GuestLeft {
+        /// Guest ID
+        guest_id: u64,
+        /// Guest name
+        name: String,
+        /// Steam ID of the guest
+        steam_id: u64,
+    },
+    /// The client stopped (all guests are disconnected)
+    SessionEnded,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Get the history file path
+pub fn history_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join(HISTORY_FILE_NAME))
+}
+
+/// Append an event to the history file
+pub fn append(event: HistoryEvent) -> Result<()> {
+    let path = history_path()?;
+    let entry = HistoryEntry {
+        time: Local::now(),
+        event,
+    };
+    let mut line = serde_json::to_string(&entry).context("Unable to serialize history entry")?;
+    line.push('\n');
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Unable to create history directory: {:?}", parent))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Unable to write history file: {:?}", &path))
+}
+
+/// Append an event to the history file, logging a warning if it fails
+pub fn record(event: HistoryEvent) {
+    if let Err(err) = append(event) {
+        tracing::warn!("{err:#}");
+    }
+}
+
+/// Read all entries of the history file (lines that cannot be parsed are skipped)
+pub fn read() -> Result<Vec<HistoryEntry>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read history file: {:?}", &path))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
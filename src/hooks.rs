@@ -0,0 +1,144 @@
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::{
+    process::Command,
+    sync::broadcast::error::RecvError,
+    time::{timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::HooksConfig,
+    events::{self, Event},
+};
+
+/// Time a hook command may run before it is killed
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs the hook commands of the application events until `shutdown` is cancelled
+/// (`game_hooks`: the hooks of the games by app ID, replacing the general ones while they are invited to)
+pub async fn run(
+    hooks: HooksConfig,
+    game_hooks: HashMap<u32, HooksConfig>,
+    shutdown: CancellationToken,
+) {
+    let mut events = events::subscribe();
+    // Game of the last invite
+    let mut game = None;
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = shutdown.cancelled() => return,
+        };
+
+        if let Event::InviteCreated {
+            game: Some(app_id), ..
+        } = &event
+        {
+            game = Some(*app_id);
+        }
+        let command = game
+            .and_then(|app_id| game_hooks.get(&app_id))
+            .and_then(|hooks| hook(hooks, &event))
+            .or_else(|| hook(&hooks, &event));
+        let Some(command) = command else {
+            continue;
+        };
+        let command = command.to_string();
+        // Don't hold up the following events while the command runs
+        tokio::spawn(async move {
+            if let Err(err) = run_hook(&command, &event).await {
+                tracing::warn!("{err:#}");
+            }
+        });
+    }
+}
+
+/// Gets the command configured for an event
+fn hook<'a>(hooks: &'a HooksConfig, event: &Event) -> Option<&'a str> {
+    let hook = match event {
+        Event::Connected { .. } => &hooks.on_connected,
+        Event::Reconnecting { .. } => &hooks.on_reconnecting,
+        Event::InviteCreated { .. } => &hooks.on_invite_created,
+        Event::GuestJoined { .. } => &hooks.on_guest_join,
+        Event::GuestLeft { .. } => &hooks.on_guest_leave,
+        Event::SteamLost => &hooks.on_steam_lost,
+        Event::SteamRestored => &hooks.on_steam_restored,
+        Event::SessionEnding { .. }
+        | Event::InviteRevoked { .. }
+        | Event::Announcement { .. }
+        | Event::Maintenance { .. }
+        | Event::PairingCode { .. }
+        | Event::Paired { .. }
+        | Event::Log { .. } => &None,
+    };
+    hook.as_deref()
+}
+
+/**
+ * Gets the event data as environment variables
+ * @return `RPI_EVENT` (the event name) and `RPI_<FIELD>` for each field of the event
+ */
+fn env_vars(event: &Event) -> Vec<(String, String)> {
+    let Ok(Value::Object(fields)) = serde_json::to_value(event) else {
+        return vec![];
+    };
+    fields
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value,
+                Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            (format!("RPI_{}", key.to_uppercase()), value)
+        })
+        .collect()
+}
+
+/// Runs a hook command through the shell and checks its exit status
+async fn run_hook(command: &str, event: &Event) -> Result<()> {
+    let mut shell = shell(command);
+    shell.envs(env_vars(event)).kill_on_drop(true);
+    let output = timeout(HOOK_TIMEOUT, shell.output())
+        .await
+        .with_context(|| format!("The hook timed out: {command}"))?
+        .with_context(|| format!("Failed to run the hook: {command}"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "The hook failed ({}): {command}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    tracing::debug!(command, "Ran a hook");
+    Ok(())
+}
+
+/// Creates the shell process running a command
+#[cfg(not(windows))]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Creates the shell process running a command
+#[cfg(windows)]
+pub(crate) fn shell(command: &str) -> Command {
+    /// Don't flash a console window
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let mut shell = Command::new("cmd");
+    shell
+        .arg("/C")
+        .raw_arg(command)
+        .creation_flags(CREATE_NO_WINDOW);
+    shell
+}
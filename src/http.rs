@@ -0,0 +1,286 @@
+use anyhow::{bail, Context as _, Result};
+use rustls::{pki_types::ServerName, ClientConfig};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::TcpStream,
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use tokio_tungstenite::tungstenite::http::Uri;
+
+use crate::{
+    dns::{self, DnsOptions},
+    proxy::Proxy,
+    tls, VERSION,
+};
+
+/// Maximum size of a response (headers and body)
+const MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Maximum size of a downloaded file (headers and body)
+const MAX_DOWNLOAD_SIZE: usize = 256 * 1024 * 1024;
+
+/// Maximum number of redirects followed by a download
+const MAX_REDIRECTS: usize = 5;
+
+/// How the connections reach the servers (proxy, DNS resolver and trusted certificates of the network)
+#[derive(Clone, Default)]
+pub struct Network {
+    /// Proxy to connect through
+    pub proxy: Option<Proxy>,
+    /// How the hosts are resolved (not used through a proxy, which resolves them itself)
+    pub dns: DnsOptions,
+    /// TLS configuration (None: the built-in root certificates)
+    pub tls: Option<Arc<ClientConfig>>,
+}
+
+impl Network {
+    /// Opens a TCP connection to a host, through the proxy if there is one
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        match &self.proxy {
+            Some(proxy) => proxy.connect(host, port).await,
+            None => dns::connect(host, port, &self.dns).await,
+        }
+    }
+
+    /// Performs the TLS handshake with a host over a connection
+    pub async fn tls_connect(&self, host: &str, stream: TcpStream) -> Result<TlsStream<TcpStream>> {
+        let config = match &self.tls {
+            Some(config) => config.clone(),
+            None => tls::builtin_config()?,
+        };
+        let server_name = ServerName::try_from(host.to_string())
+            .with_context(|| format!("Invalid host: {host}"))?;
+        TlsConnector::from(config)
+            .connect(server_name, stream)
+            .await
+            .with_context(|| format!("TLS handshake with {host} failed"))
+    }
+}
+
+/// Response to an HTTP request
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+    /// Target of a redirect (`Location` header)
+    pub location: Option<String>,
+}
+
+impl Response {
+    /// Whether the status is 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Sends a GET request
+pub async fn get(network: &Network, url: &str) -> Result<Response> {
+    request(network, "GET", url, None, MAX_RESPONSE_SIZE).await
+}
+
+/// Sends a POST request with a JSON body
+pub async fn post_json(network: &Network, url: &str, body: &str) -> Result<Response> {
+    request(network, "POST", url, Some(body), MAX_RESPONSE_SIZE).await
+}
+
+/// Downloads a file, following the redirects (release assets are served from another host)
+pub async fn download(network: &Network, url: &str) -> Result<Vec<u8>> {
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let response = request(network, "GET", &url, None, MAX_DOWNLOAD_SIZE).await?;
+        match response.location {
+            Some(location) if (300..400).contains(&response.status) => {
+                url = resolve(&url, &location)?;
+            }
+            _ if response.is_success() => return Ok(response.body),
+            _ => bail!(
+                "The download of {url} failed with status {}",
+                response.status
+            ),
+        }
+    }
+    bail!("Too many redirects while downloading {url}")
+}
+
+/**
+ * Resolves the target of a redirect against the URL of the request
+ * @return An error if it leaves TLS (`https://` to `http://`), which would let the download be tampered with
+ */
+fn resolve(url: &str, location: &str) -> Result<String> {
+    if location.starts_with("http://") && url.starts_with("https://") {
+        bail!("Refusing to follow the redirect of {url} to {location} without TLS");
+    }
+    if location.starts_with("https://") || location.starts_with("http://") {
+        return Ok(location.to_string());
+    }
+    let uri: Uri = url.parse().with_context(|| format!("Invalid URL: {url}"))?;
+    let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+        bail!("Invalid URL: {url}");
+    };
+    if !location.starts_with('/') {
+        bail!("Unsupported redirect: {location}");
+    }
+    Ok(format!("{scheme}://{authority}{location}"))
+}
+
+/// Sends a request over HTTP/1.1 (plain or TLS) and reads the whole response
+async fn request(
+    network: &Network,
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+    max_size: usize,
+) -> Result<Response> {
+    let uri: Uri = url.parse().with_context(|| format!("Invalid URL: {url}"))?;
+    let host = uri
+        .host()
+        .with_context(|| format!("Missing host in URL: {url}"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => bail!("Unsupported URL (expected http:// or https://): {url}"),
+    };
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: remoteplay-inviter/{VERSION}\r\nAccept-Encoding: identity\r\nConnection: close\r\n"
+    );
+    match body {
+        Some(body) => request.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )),
+        None => request.push_str("\r\n"),
+    }
+
+    let stream = network
+        .connect(host, port)
+        .await
+        .with_context(|| format!("Failed to connect to {host}"))?;
+    if !https {
+        return send(stream, &request, max_size).await;
+    }
+    let stream = network.tls_connect(host, stream).await?;
+    send(stream, &request, max_size).await
+}
+
+/// Sends a request and reads the response until the server closes the connection
+async fn send(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    request: &str,
+    max_size: usize,
+) -> Result<Response> {
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        let n = match stream.read(&mut chunk).await {
+            Ok(n) => n,
+            // Some servers close TLS connections without a close_notify
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+            Err(err) => return Err(err).context("Failed to read the HTTP response"),
+        };
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..n]);
+        if response.len() > max_size {
+            bail!("HTTP response too large");
+        }
+    }
+    parse_response(&response)
+}
+
+/// Parses the status line, the headers and the body of a response
+fn parse_response(response: &[u8]) -> Result<Response> {
+    let (head, body) = match response.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => (&response[..end], &response[end + 4..]),
+        None => (response, &[][..]),
+    };
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("Invalid HTTP response: {status_line}"))?;
+    let mut chunked = false;
+    let mut location = None;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+            chunked = true;
+        } else if name.eq_ignore_ascii_case("location") {
+            location = Some(value.to_string());
+        }
+    }
+    let body = if chunked {
+        dechunk(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(Response {
+        status,
+        body,
+        location,
+    })
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .context("Truncated chunked HTTP body")?;
+        let size = String::from_utf8_lossy(&data[..line_end]);
+        // Ignore the chunk extensions
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .with_context(|| format!("Invalid chunk size in HTTP body: {size}"))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size {
+            bail!("Truncated chunked HTTP body");
+        }
+        body.extend_from_slice(&data[..size]);
+        data = data.get(size + 2..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_redirects() {
+        let url = "https://github.com/releases/download/v1/app";
+        assert_eq!(
+            resolve(url, "https://objects.example.com/app").unwrap(),
+            "https://objects.example.com/app"
+        );
+        assert_eq!(
+            resolve(url, "/other/app").unwrap(),
+            "https://github.com/other/app"
+        );
+        assert_eq!(
+            resolve("http://localhost:8080/app", "http://localhost:8081/app").unwrap(),
+            "http://localhost:8081/app"
+        );
+        assert!(resolve(url, "other/app").is_err());
+    }
+
+    #[test]
+    fn refuses_to_leave_tls() {
+        let url = "https://github.com/releases/download/v1/app";
+        assert!(resolve(url, "http://objects.example.com/app").is_err());
+    }
+}
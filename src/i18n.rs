@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::{LazyLock, OnceLock},
+};
+
+/// Catalogs shipped with the client (language code, Fluent source), English first as the fallback
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("ja", include_str!("../locales/ja.ftl")),
+];
+
+/// Environment variables giving the language on Unix, in order of precedence
+const LANGUAGE_VARIABLES: [&str; 4] = ["LC_ALL", "LC_MESSAGES", "LANG", "LANGUAGE"];
+
+/// Messages of the English catalog, used for the keys missing from the selected one
+static FALLBACK: LazyLock<HashMap<String, String>> = LazyLock::new(|| parse(CATALOGS[0].1));
+
+/// Messages of the selected catalog
+static MESSAGES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Joins the first line of a message with its continuation lines, removing their common indentation
+fn join_lines(first: &str, continuation: &[&str]) -> String {
+    let indent = continuation
+        .iter()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or_default();
+    let first = (!first.is_empty()).then_some(first);
+    first
+        .into_iter()
+        .chain(continuation.iter().map(|line| line[indent..].trim_end()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/**
+ * Parses a catalog written in the subset of the Fluent syntax used by the client
+ * (`key = value`, indented continuation lines, `#` comments and `{ $variable }` placeables)
+ * @return The messages by key
+ */
+fn parse(source: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let mut current: Option<(&str, &str, Vec<&str>)> = None;
+    for line in source.lines() {
+        // Continuation of a multiline message
+        if line.starts_with([' ', '\t']) && !line.trim().is_empty() {
+            if let Some((_, _, continuation)) = &mut current {
+                continuation.push(line);
+            }
+            continue;
+        }
+        if let Some((key, first, continuation)) = current.take() {
+            messages.insert(key.to_string(), join_lines(first, &continuation));
+        }
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            current = Some((key.trim(), value.trim(), Vec::new()));
+        }
+    }
+    if let Some((key, first, continuation)) = current {
+        messages.insert(key.to_string(), join_lines(first, &continuation));
+    }
+    messages
+}
+
+/// Gets the language of a locale name (`ja_JP.UTF-8` -> `ja`)
+fn language_of(locale: &str) -> String {
+    locale
+        .split(['_', '-', '.', '@', ':'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Detects the language of the user from the environment (or the Windows settings)
+fn detect() -> Option<String> {
+    let from_env = LANGUAGE_VARIABLES
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX");
+    if let Some(locale) = from_env {
+        return Some(language_of(&locale));
+    }
+
+    #[cfg(windows)]
+    {
+        let output = std::process::Command::new("reg")
+            .args([
+                "query",
+                r"HKCU\Control Panel\International",
+                "/v",
+                "LocaleName",
+            ])
+            .output()
+            .ok()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let locale = output.split_whitespace().last()?;
+        return Some(language_of(locale));
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
+/**
+ * Selects the language of the messages (`--lang` flag > environment), falling back to English
+ * @return The language in use
+ */
+pub fn set_language(lang: Option<&str>) -> &'static str {
+    let wanted = lang.map(language_of).or_else(detect).unwrap_or_default();
+    let (code, source) = CATALOGS
+        .iter()
+        .find(|(code, _)| *code == wanted)
+        .unwrap_or(&CATALOGS[0]);
+    let _ = MESSAGES.set(parse(source));
+    code
+}
+
+/// Lists the languages shipped with the client
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(code, _)| *code)
+}
+
+/**
+ * Gets a message in the selected language, replacing the `{ $name }` placeables with `args`
+ * @return The English message if it is missing from the catalog, or the key if it is missing from both
+ */
+pub fn message(key: &str, args: &[(&str, String)]) -> String {
+    let text = MESSAGES
+        .get()
+        .and_then(|messages| messages.get(key))
+        .or_else(|| FALLBACK.get(key));
+    let Some(text) = text else {
+        return key.to_string();
+    };
+    let mut text = text.clone();
+    for (name, value) in args {
+        text = text.replace(&format!("{{ ${name} }}"), value);
+    }
+    text
+}
+
+/// Gets a translated message: `t!("key")` or `t!("key", name = value, ...)`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __i18n_t {
+    ($key:expr) => {
+        $crate::i18n::message($key, &[])
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::message($key, &[$((stringify!($name), $value.to_string())),+])
+    };
+}
+pub use __i18n_t as t;
@@ -0,0 +1,137 @@
+use anyhow::{Context as _, Result};
+use fs4::fs_std::FileExt as _;
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{config, console, i18n::t};
+
+/// How long to wait for the other instance to exit on takeover
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lock held while the client is running with a config file
+/// The lock is released when dropped (or when the process exits).
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Get the lock file path (next to the UUID config file)
+fn lock_path() -> Result<PathBuf> {
+    Ok(config::config_path()?.with_extension("lock"))
+}
+
+/// Get the file recording the PID of the instance holding the lock
+/// (a locked file cannot be read by other processes on Windows)
+fn pid_path() -> Result<PathBuf> {
+    Ok(config::config_path()?.with_extension("pid"))
+}
+
+/// Read the PID of the instance holding the lock
+fn read_pid() -> Option<u32> {
+    fs::read_to_string(pid_path().ok()?)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/**
+ * Tries to lock the file
+ * @return false if another process holds the lock
+ */
+fn try_lock(file: &File) -> Result<bool> {
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(true),
+        Err(err)
+            if err.kind() == io::ErrorKind::WouldBlock
+                || err.raw_os_error() == fs4::lock_contended_error().raw_os_error() =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err).context("Unable to lock the instance lock file"),
+    }
+}
+
+/// Prevents two instances from using the same config file (and UUID) at the same time
+/// With `takeover`, the instance already running is stopped and replaced.
+pub fn acquire(takeover: bool) -> Result<InstanceLock> {
+    let path = lock_path()?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create directory: {:?}", dir))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Unable to open lock file: {:?}", &path))?;
+
+    if !try_lock(&file)? {
+        let pid = read_pid();
+        let holder = match pid {
+            Some(pid) => format!("PID {pid}"),
+            None => "unknown PID".to_string(),
+        };
+        if !takeover {
+            anyhow::bail!(
+                "Another instance is already running with this config file ({holder}). Run with --takeover to replace it"
+            );
+        }
+        let pid = pid.with_context(|| {
+            format!(
+                "Unable to take over: the PID of the other instance is not recorded in {:?}",
+                pid_path().unwrap_or_default()
+            )
+        })?;
+
+        console::println!("↪ {}", t!("instance-stopping", holder = holder));
+        terminate(pid)?;
+        let start = Instant::now();
+        while !try_lock(&file)? {
+            if start.elapsed() > TAKEOVER_TIMEOUT {
+                anyhow::bail!("The other instance ({holder}) did not exit in time");
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        console::println!("✓ {}", t!("instance-took-over"));
+    }
+
+    let pid_path = pid_path()?;
+    fs::write(&pid_path, std::process::id().to_string())
+        .with_context(|| format!("Unable to write PID file: {:?}", &pid_path))?;
+    Ok(InstanceLock { _file: file })
+}
+
+/// Asks the process to exit like Ctrl+C would, so that it can leave the server cleanly
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<()> {
+    run_command(Command::new("kill").args(["-INT", &pid.to_string()]))
+}
+
+/// Terminates the process (console programs cannot be sent Ctrl+C from another console)
+#[cfg(windows)]
+fn terminate(pid: u32) -> Result<()> {
+    run_command(Command::new("taskkill").args(["/PID", &pid.to_string(), "/F"]))
+}
+
+/// Runs a command and checks its exit status
+fn run_command(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Unable to run {program}"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Unable to stop the other instance with {program}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Remote Play Inviter client
+//!
+//! Connects the local Steam client to the Remote Play Inviter server and
+//! creates Remote Play Together invite links on request.
+
+pub mod client;
+pub mod config;
+pub mod console;
+pub mod control;
+pub mod crash;
+pub mod dashboard;
+pub mod deflate;
+pub mod diagnostics;
+pub mod dns;
+pub mod events;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod handlers;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod i18n;
+pub mod instance;
+pub mod library;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod msgpack;
+pub mod netwatch;
+pub mod notifications;
+pub mod outbox;
+pub mod platform;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod presence;
+pub mod proxy;
+pub mod retry;
+pub mod schedule;
+pub mod steam;
+pub mod store;
+#[cfg(unix)]
+pub mod systemd;
+pub mod telemetry;
+pub mod tls;
+#[cfg(feature = "tray")]
+pub mod tray;
+pub mod tui;
+pub mod update;
+pub mod webhooks;
+pub mod ws_error_handler;
+
+// Version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
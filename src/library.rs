@@ -0,0 +1,171 @@
+use anyhow::{Context as _, Result};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::i18n::t;
+
+/// Environment variable to override the Steam installation directory
+pub const STEAM_DIR_ENV: &str = "STEAM_DIR";
+
+/// A game installed in one of the Steam libraries
+#[derive(Clone, Debug)]
+pub struct InstalledGame {
+    pub app_id: u32,
+    pub name: String,
+}
+
+/// Get the Steam installation directory
+fn steam_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os(STEAM_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    #[cfg(windows)]
+    let candidates = vec![PathBuf::from(
+        env::var_os("ProgramFiles(x86)").unwrap_or_else(|| r"C:\Program Files (x86)".into()),
+    )
+    .join("Steam")];
+    #[cfg(target_os = "macos")]
+    let candidates = vec![crate::platform::home_dir()?.join("Library/Application Support/Steam")];
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let candidates = {
+        let home = crate::platform::home_dir()?;
+        vec![
+            home.join(".steam/steam"),
+            home.join(".local/share/Steam"),
+            home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+            home.join("snap/steam/common/.local/share/Steam"),
+        ]
+    };
+
+    candidates
+        .into_iter()
+        .find(|dir| dir.join("steamapps").is_dir())
+        .with_context(|| format!("Unable to find the Steam installation (set {STEAM_DIR_ENV})"))
+}
+
+/// Steam packaged in a sandbox on Linux (the client keeps its `~/.steam` in a separate home directory)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SteamPackage {
+    /// `com.valvesoftware.Steam` from Flathub
+    Flatpak,
+    /// `steam` from the Snap Store
+    Snap,
+}
+
+impl SteamPackage {
+    /// Gets the home directory the sandboxed Steam client runs with
+    pub fn home(self) -> Result<PathBuf> {
+        let home = crate::platform::home_dir()?;
+        Ok(match self {
+            SteamPackage::Flatpak => home.join(".var/app/com.valvesoftware.Steam"),
+            SteamPackage::Snap => home.join("snap/steam/common"),
+        })
+    }
+
+    /// What to do when the client still cannot connect to the sandboxed Steam client
+    pub fn guidance(self) -> String {
+        match self {
+            SteamPackage::Flatpak => t!("steam-flatpak-guidance"),
+            SteamPackage::Snap => t!("steam-snap-guidance"),
+        }
+    }
+}
+
+/**
+ * Detects a Steam client installed with Flatpak or Snap
+ * @return None when the native package is installed (or no Steam at all)
+ */
+pub fn sandboxed_steam() -> Option<SteamPackage> {
+    if !cfg!(all(unix, not(target_os = "macos"))) {
+        return None;
+    }
+    let home = crate::platform::home_dir().ok()?;
+    if home.join(".steam/steam").exists() || home.join(".steam/sdk64").exists() {
+        return None;
+    }
+    [SteamPackage::Flatpak, SteamPackage::Snap]
+        .into_iter()
+        .find(|package| {
+            package
+                .home()
+                .is_ok_and(|home| home.join(".steam").is_dir())
+        })
+}
+
+/**
+ * Lists the games installed in the Steam libraries, sorted by name
+ * @return The games, including the ones that don't support Remote Play Together
+ */
+pub fn installed_games() -> Result<Vec<InstalledGame>> {
+    let steam_dir = steam_dir()?;
+
+    // Libraries on other drives are listed in libraryfolders.vdf
+    let mut libraries = vec![steam_dir.clone()];
+    if let Ok(folders) = fs::read_to_string(steam_dir.join("steamapps/libraryfolders.vdf")) {
+        libraries.extend(
+            vdf_values(&folders)
+                .filter(|(key, _)| key == "path")
+                .map(|(_, path)| PathBuf::from(path)),
+        );
+    }
+
+    let mut games = BTreeMap::new();
+    for library in libraries {
+        let Ok(entries) = fs::read_dir(library.join("steamapps")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_manifest = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("appmanifest_") && name.ends_with(".acf"));
+            if !is_manifest {
+                continue;
+            }
+            if let Some(game) = read_manifest(&path) {
+                games.insert(game.app_id, game);
+            }
+        }
+    }
+
+    let mut games: Vec<InstalledGame> = games.into_values().collect();
+    games.sort_by_key(|game| game.name.to_lowercase());
+    Ok(games)
+}
+
+/// Reads the app ID and the name of a game from its manifest (`appmanifest_<app_id>.acf`)
+fn read_manifest(path: &Path) -> Option<InstalledGame> {
+    let manifest = fs::read_to_string(path).ok()?;
+    let mut app_id = None;
+    let mut name = None;
+    for (key, value) in vdf_values(&manifest) {
+        match key.as_str() {
+            "appid" if app_id.is_none() => app_id = value.parse().ok(),
+            "name" if name.is_none() => name = Some(value),
+            _ => (),
+        }
+    }
+    Some(InstalledGame {
+        app_id: app_id?,
+        name: name?,
+    })
+}
+
+/// Gets the `"key" "value"` pairs of a Valve KeyValues (VDF) text, ignoring the nesting
+fn vdf_values(text: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    text.lines().filter_map(|line| {
+        let mut parts = line.trim().split('"');
+        // `"key"\t\t"value"` splits into ["", key, "\t\t", value, ""]
+        let (Some(""), Some(key), Some(_), Some(value)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+        Some((key.to_lowercase(), value.replace(r"\\", r"\")))
+    })
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context as _, Result};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _, EnvFilter};
+
+use crate::config;
+
+/// Log file name
+const LOG_FILE_NAME: &str = "remoteplay-inviter.log";
+
+/// Number of old log files to keep
+const LOG_FILES_KEPT: usize = 5;
+
+/// Log output format
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Logging options
+#[derive(Default)]
+pub struct LogOptions {
+    /// Output format of the log file
+    pub format: LogFormat,
+    /// Directory to write the log files to (defaults to `logs` under the config directory)
+    pub dir: Option<PathBuf>,
+}
+
+/// Get the default log directory
+pub fn default_log_dir() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("logs"))
+}
+
+/// Rotates the log files (`x.log` -> `x.log.1` -> ... -> `x.log.N`, dropping the oldest)
+fn rotate(dir: &Path) -> Result<()> {
+    let path = |n: usize| match n {
+        0 => dir.join(LOG_FILE_NAME),
+        n => dir.join(format!("{LOG_FILE_NAME}.{n}")),
+    };
+
+    for n in (0..LOG_FILES_KEPT).rev() {
+        let from = path(n);
+        if from.exists() {
+            fs::rename(&from, path(n + 1))
+                .with_context(|| format!("Unable to rotate log file: {:?}", &from))?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the log files in a directory, from the current one to the oldest
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    (0..=LOG_FILES_KEPT)
+        .map(|n| match n {
+            0 => dir.join(LOG_FILE_NAME),
+            n => dir.join(format!("{LOG_FILE_NAME}.{n}")),
+        })
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/**
+ * Initializes the tracing subscriber writing to the log file
+ * The previous log files are rotated on every start.
+ * The `RUST_LOG` environment variable controls the filter (default: `info`)
+ * @return The path of the log file
+ */
+pub fn init(options: &LogOptions) -> Result<PathBuf> {
+    // Log directory
+    let dir = match &options.dir {
+        Some(dir) => dir.clone(),
+        None => default_log_dir()?,
+    };
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Unable to create log directory: {:?}", &dir))?;
+
+    // Rotate and create the log file
+    rotate(&dir)?;
+    let path = dir.join(LOG_FILE_NAME);
+    let file =
+        File::create(&path).with_context(|| format!("Unable to create log file: {:?}", &path))?;
+
+    // Filter from RUST_LOG
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let layer = fmt::layer().with_writer(Mutex::new(file)).with_ansi(false);
+    let registry = tracing_subscriber::registry().with(filter);
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otel_layer()?);
+    match options.format {
+        LogFormat::Text => registry.with(layer).try_init(),
+        LogFormat::Json => registry.with(layer.json()).try_init(),
+    }
+    .context("Unable to initialize logging")?;
+
+    Ok(path)
+}
+
+/**
+ * Creates the layer exporting spans via OTLP
+ * The exporter is configured by the standard `OTEL_EXPORTER_OTLP_*` environment variables.
+ * @return None if `OTEL_EXPORTER_OTLP_ENDPOINT` is not set
+ */
+#[cfg(feature = "otel")]
+fn otel_layer<S>(
+) -> Result<Option<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::{trace::TracerProvider as _, KeyValue};
+    use opentelemetry_sdk::{runtime, trace, Resource};
+
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none() {
+        return Ok(None);
+    }
+
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+        .with_trace_config(trace::Config::default().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "remoteplay-inviter"),
+            KeyValue::new("service.version", crate::VERSION),
+        ])))
+        .install_batch(runtime::Tokio)
+        .context("Unable to initialize the OTLP exporter")?;
+    let tracer = provider.tracer("remoteplay-inviter");
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Flushes the spans that have not been exported yet
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -1,243 +1,847 @@
 use anyhow::{Context as _, Result};
+use clap::Parser as _;
 use dotenvy_macro::dotenv;
-use futures::SinkExt;
-use futures_util::stream::StreamExt;
-use std::sync::Arc;
-use steam_stuff::SteamStuff;
-use tokio::{
-    sync::Mutex,
-    time::{self, timeout, Duration},
-};
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{
-        http::{uri::Builder, Uri},
-        protocol::Message,
-    },
+use remoteplay_inviter::{
+    client::{build_url, run_client, ClientExit, ClientOptions, Commands, Endpoint},
+    config::{self, read_or_generate_config, Config, GameConfig, HooksConfig},
+    console, control, crash, dashboard,
+    dns::{DnsOptions, IpPreference, Resolver},
+    handlers::{Handler, InviteOptions},
+    hooks,
+    http::Network,
+    i18n::{self, t},
+    instance, library,
+    logging::{self, LogOptions},
+    metrics,
+    middleware::{Filter, Pipeline, RateLimit},
+    models::SigningKey,
+    netwatch, notifications, platform, presence,
+    proxy::Proxy,
+    retry::RetryPolicy,
+    schedule,
+    steam::{self, Polling, Steam},
+    store, telemetry, tls, tui, update, webhooks, VERSION,
 };
+use std::collections::HashMap;
+use steam_stuff::SteamStuff;
+use tokio::time::{self, Duration};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-mod config;
-mod console;
-mod handlers;
-mod models;
-mod retry;
-mod ws_error_handler;
+mod autostart;
+mod cli;
+mod commands;
+mod profile;
+mod service;
 
-use config::{read_or_generate_config, Config};
-use handlers::Handler;
-use models::*;
-use retry::RetrySec;
-use ws_error_handler::handle_ws_error;
-
-// Version
-pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+use cli::{Cli, Command};
 
 // Endpoint URL
 const DEFAULT_URL: &str = dotenv!("ENDPOINT_URL");
 
+// Public keys of the default endpoint (comma-separated `sha256/<base64>`, empty: no pinning)
+const DEFAULT_PINS: &str = dotenv!("ENDPOINT_PINS");
+
+// Exit codes for wrappers and service managers (listed in `--help`, keep them in sync with `cli::EXIT_CODES`)
+
+// Exit code when the user quits or the server ends the session
+const EXIT_OK: i32 = 0;
+
+// Exit code for unexpected errors (another instance is running, the update failed)
+const EXIT_FATAL: i32 = 1;
+
+// Exit code when the maximum number of reconnection attempts is reached
+const EXIT_RETRIES_EXHAUSTED: i32 = 3;
+
+// Exit code when the Steam client is not running or cannot be reached
+const EXIT_STEAM_UNAVAILABLE: i32 = 4;
+
+// Exit code when the configuration files or the connection settings are invalid
+const EXIT_CONFIG: i32 = 5;
+
+// Exit code when the server refuses the client (unknown UUID or token, outdated version)
+const EXIT_REFUSED: i32 = 6;
+
+// Exit code when the connection ends with an error the client cannot recover from
+const EXIT_PROTOCOL: i32 = 7;
+
+// Time the deck mode shows the last messages before exiting
+const DECK_EXIT_DELAY: Duration = Duration::from_secs(10);
+
+// Seconds to wait for a game started for an invite request when `launch_timeout` is not set
+const DEFAULT_LAUNCH_TIMEOUT: u64 = 60;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Event loop
-    'main: {
+    // Parse the command line arguments
+    let cli = Cli::parse();
+
+    // Language of the messages
+    let language = i18n::set_language(cli.lang.as_deref());
+
+    // Keep stdout parseable when printing JSON
+    console::set_output_format(cli.output.into());
+    console::set_verbosity(cli.verbosity());
+    console::set_interactive(cli.interactive());
+    console::set_deck(cli.deck());
+    console::set_ascii(cli.ascii());
+    console::set_color(cli.color());
+    let json_output = matches!(cli.command, Some(Command::Invite { json: true, .. }));
+    if console::is_deck() && !json_output {
+        console::println!(
+            "★ Remote Play Inviter {VERSION}  ({})",
+            t!("deck-banner-hint")
+        );
+    } else if console::is_text() && !json_output && cli.interactive() {
+        print_banner()?;
+    }
+    if platform::gaming_mode() {
+        console::println!("□ {}", t!("gaming-mode"));
+    }
+    if let Some(lang) = cli
+        .lang
+        .as_deref()
+        .filter(|lang| !lang.to_lowercase().starts_with(language))
+    {
+        let available: Vec<&str> = i18n::languages().collect();
+        console::eprintln!(
+            "☓ {}",
+            t!(
+                "unknown-language",
+                lang = lang,
+                available = available.join(", ")
+            )
+        );
+    }
+
+    // Override the config file path
+    if let Some(path) = &cli.config {
+        config::set_config_path(path.clone());
+    }
+    if let Some(profile) = &cli.profile {
+        config::set_profile(profile)?;
+    }
+
+    // Move the UUID to or from the OS credential store
+    if let Err(err) = config::migrate_keychain() {
+        console::eprintln!("☓ {err:#}");
+    }
+
+    // Time and level on the console lines
+    let timestamps = cli.timestamps
+        || config::read_config()
+            .ok()
+            .flatten()
+            .is_some_and(|c| c.console_timestamps);
+    console::set_timestamps(timestamps);
+
+    // Dispatch the subcommand
+    match cli.command.as_ref().unwrap_or(&Command::Run) {
+        Command::Run | Command::Schedule { .. } => {
+            let result = run(&cli, shutdown_on_ctrl_c()).await;
+            logging::shutdown();
+            result
+        }
+        Command::Config => commands::config(),
+        Command::Status => commands::status(&cli).await,
+        Command::Doctor { export } => commands::doctor(&cli, export.as_ref()).await,
+        Command::History { limit } => commands::history(*limit),
+        Command::Service { action } => service::service(&cli, action),
+        Command::Autostart { action } => autostart::autostart(&cli, action),
+        Command::Profile { action } => profile::profile(&cli, action),
+        Command::Unlink => commands::unlink(&cli).await,
+        Command::Relink => commands::relink(&cli).await,
+        Command::Invite { game, json } => commands::invite(&cli, *game, *json).await,
+        Command::Rollback => commands::rollback(),
+        Command::Telemetry { action } => commands::telemetry(&cli, action),
+    }
+}
+
+/// Display the banner
+fn print_banner() -> Result<()> {
+    let tagline = t!("banner-tagline");
+    if console::is_ascii() {
         console::printdoc! {"
             ------------------------------------------------------------------------------
-                        ╦═╗┌─┐┌┬┐┌─┐┌┬┐┌─┐┌─┐┬  ┌─┐┬ ┬  ╦┌┐┌┬  ┬┬┌┬┐┌─┐┬─┐
-                        ╠╦╝├┤ ││││ │ │ ├┤ ├─┘│  ├─┤└┬┘  ║│││└┐┌┘│ │ ├┤ ├┬┘
-                        ╩╚═└─┘┴ ┴└─┘ ┴ └─┘┴  ┴─┘┴ ┴ ┴   ╩┘└┘ └┘ ┴ ┴ └─┘┴└─
+                                         Remote Play Inviter
                            Version: {VERSION}                   by Kamesuta
-                                                            
-                Invite your friends via Discord and play Steam games together for free! 
+
+                {tagline}
             ------------------------------------------------------------------------------
-        
+
         "};
+        return Ok(());
+    }
+    console::printdoc! {"
+        ------------------------------------------------------------------------------
+                    ╦═╗┌─┐┌┬┐┌─┐┌┬┐┌─┐┌─┐┬  ┌─┐┬ ┬  ╦┌┐┌┬  ┬┬┌┬┐┌─┐┬─┐
+                    ╠╦╝├┤ ││││ │ │ ├┤ ├─┘│  ├─┤└┬┘  ║│││└┐┌┘│ │ ├┤ ├┬┘
+                    ╩╚═└─┘┴ ┴└─┘ ┴ └─┘┴  ┴─┘┴ ┴ ┴   ╩┘└┘ └┘ ┴ ┴ └─┘┴└─
+                       Version: {VERSION}                   by Kamesuta
+                                                        
+            {tagline}
+        ------------------------------------------------------------------------------
+    
+    "};
+    Ok(())
+}
 
-        // Version command
-        if std::env::args().any(|arg| arg == "--version" || arg == "-v") {
-            console::println!("✓ Version: {}", VERSION);
-            return Ok(());
+/// Generate a configuration with a new UUID
+fn new_config() -> Config {
+    Config {
+        uuid: Uuid::new_v4().to_string(),
+        ..Default::default()
+    }
+}
+
+/// Resolve the endpoint URLs to connect to, in order of failover
+/// (`--endpoint` flag > endpoint config file > default URL)
+fn endpoint_urls(cli: &Cli) -> Result<Vec<String>> {
+    if let Some(endpoint) = &cli.endpoint {
+        return Ok(vec![endpoint.clone()]);
+    }
+
+    // Read the endpoint configuration file (`url` first, then `urls`)
+    let mut urls: Vec<String> = config::read_endpoint_config()?
+        .map(|e| e.url.into_iter().chain(e.urls).collect())
+        .unwrap_or_default();
+    urls.dedup();
+    if urls.is_empty() {
+        urls.push(DEFAULT_URL.to_string());
+    }
+    Ok(urls)
+}
+
+/// Resolve the (first) endpoint URL to connect to
+fn endpoint_url(cli: &Cli) -> Result<String> {
+    Ok(endpoint_urls(cli)?.swap_remove(0))
+}
+
+/// Get the per-game settings by app ID (`[games.<app_id>]` sections)
+fn game_configs(config: &Config) -> HashMap<u32, GameConfig> {
+    config
+        .games
+        .iter()
+        .filter_map(|(app_id, game)| match app_id.parse() {
+            Ok(app_id) => Some((app_id, game.clone())),
+            Err(_) => {
+                tracing::warn!("Ignored the settings of [games.{app_id}]: not an app ID");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Apply the guest and invite limits (`--max-guests` flag > config file) and the session settings
+async fn apply_limits(cli: &Cli, handler: &mut Handler) {
+    let config = config::read_config().ok().flatten();
+    let config = config.as_ref();
+
+    handler.set_max_guests(cli.max_guests.or(config.and_then(|c| c.max_guests)));
+    handler.set_invite_defaults(InviteOptions {
+        max_uses: config.and_then(|c| c.invite_max_uses),
+        ttl: config.and_then(|c| c.invite_ttl).map(Duration::from_secs),
+    });
+    match config
+        .and_then(|c| c.signing_secret.as_deref())
+        .map(SigningKey::from_base64)
+        .transpose()
+    {
+        Ok(signing_key) => handler.set_signing_key(signing_key),
+        Err(err) => {
+            let _: Result<()> = (|| {
+                console::eprintln!("☓ {err:#}");
+                Ok(())
+            })();
         }
+    }
+    handler.set_copy_invite(config.is_some_and(|c| c.copy_invite_to_clipboard));
+    // Opening the browser would take the focus from the game in the Steam Deck Gaming Mode
+    handler.set_auto_open_invite(
+        config.is_some_and(|c| c.auto_open_invite) && !platform::gaming_mode(),
+    );
+    handler.set_auto_launch(
+        config
+            .filter(|c| c.auto_launch_game)
+            .map(|c| Duration::from_secs(c.launch_timeout.unwrap_or(DEFAULT_LAUNCH_TIMEOUT))),
+    );
+    handler.set_idle_kick(
+        config
+            .and_then(|c| c.idle_kick_minutes)
+            .map(|minutes| Duration::from_secs(minutes.max(1) * 60)),
+    );
+    handler.set_session_timeout(
+        cli.session_timeout
+            .or(config.and_then(|c| c.session_timeout_minutes))
+            .map(|minutes| Duration::from_secs(minutes.max(1) * 60)),
+    );
+    if let Some(config) = config {
+        handler.set_game_lists(
+            config.allowed_games.iter().copied().collect(),
+            config.denied_games.iter().copied().collect(),
+        );
+        handler
+            .set_nicknames(
+                config
+                    .nicknames
+                    .iter()
+                    .filter_map(|(steam_id, name)| Some((steam_id.parse().ok()?, name.clone())))
+                    .collect(),
+            )
+            .await;
+        handler.set_game_configs(game_configs(config));
+        handler.set_user_lists(
+            config.allowed_users.iter().cloned().collect(),
+            config.blocked_users.iter().cloned().collect(),
+        );
+    }
+    handler
+        .set_report_stats(config.is_some_and(|c| c.report_session_stats))
+        .await;
+
+    // Compose the layers the messages pass through
+    let mut middleware = Pipeline::standard();
+    if let Some(commands) = config
+        .map(|c| &c.ignored_commands)
+        .filter(|c| !c.is_empty())
+    {
+        middleware = middleware.with(Filter::new(commands.iter().cloned()));
+    }
+    if let Some(max_requests) = config.and_then(|c| c.invite_rate_limit) {
+        middleware = middleware.with(RateLimit::new(max_requests, Duration::from_secs(60)));
+    }
+    handler.set_middleware(middleware);
+}
+
+/**
+ * Build the connection options from the command line, the endpoint config file and environment variables
+ * @return The options of the connection to the server, and the network of the other HTTP requests
+ *         (the same proxy, resolver and certificates, without the pins of the server)
+ */
+fn client_options(cli: &Cli) -> Result<(ClientOptions, Network)> {
+    let endpoint_config = config::read_endpoint_config()?;
 
-        // Help command
-        if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
-            let program = std::env::current_exe()
-                .ok()
-                .and_then(|f| f.file_name().map(|f| f.to_string_lossy().into_owned()))
-                .unwrap_or_else(|| "remoteplay-inviter".to_owned());
-            console::printdoc! {"
-                Usage: {program} [options]
+    // Proxy (endpoint config file > HTTPS_PROXY / ALL_PROXY)
+    let proxy = match endpoint_config.as_ref().and_then(|e| e.proxy.as_ref()) {
+        Some(proxy) => Some(proxy.parse::<Proxy>()?),
+        None => Proxy::from_env()?,
+    };
+    if let Some(proxy) = &proxy {
+        console::println!("✓ {}", t!("using-proxy", proxy = proxy));
+    }
 
-                Options:
-                    -v, --version    Display the version of the program
-                    -h, --help       Display this help message
-            "};
-            return Ok(());
+    let (network, tls) = endpoint_config
+        .map(|e| (e.network, e.tls))
+        .unwrap_or_default();
+
+    // Trusted certificates
+    if let Some(ca_file) = &tls.ca_file {
+        console::println!("✓ {}", t!("trusting-ca", path = ca_file.display()));
+    }
+    if let Some(client_cert) = &tls.client_cert {
+        console::println!(
+            "✓ {}",
+            t!("using-client-certificate", path = client_cert.display())
+        );
+    }
+    // Pins (config file > built-in pins of the default endpoint)
+    let pins = match &tls.pins {
+        Some(pins) => pins.clone(),
+        None if endpoint_urls(cli)? == [DEFAULT_URL] => DEFAULT_PINS
+            .split(',')
+            .map(str::trim)
+            .filter(|pin| !pin.is_empty())
+            .map(String::from)
+            .collect(),
+        None => vec![],
+    };
+    let http_tls = tls::client_config(&tls, &[])?;
+    let tls = tls::client_config(&tls, &pins)?;
+
+    // DNS resolution (bypassed by the proxy)
+    let resolver = match &network.resolver {
+        Some(resolver) => resolver.parse::<Resolver>()?,
+        None => Resolver::System,
+    };
+    let preference = if cli.prefer_ipv4 {
+        IpPreference::Ipv4
+    } else if cli.prefer_ipv6 {
+        IpPreference::Ipv6
+    } else {
+        IpPreference::Any
+    };
+    if !matches!(resolver, Resolver::System) {
+        console::println!("✓ {}", t!("using-resolver", resolver = resolver));
+    }
+    if preference != IpPreference::Any {
+        let version = if preference == IpPreference::Ipv4 {
+            "IPv4"
+        } else {
+            "IPv6"
+        };
+        console::println!("✓ {}", t!("preferring-ip-version", version = version));
+    }
+
+    // Timeouts and retry policy
+    if !(0.0..=1.0).contains(&network.jitter) {
+        anyhow::bail!("network.jitter must be between 0.0 and 1.0");
+    }
+    if network.backoff_multiplier < 1.0 {
+        anyhow::bail!("network.backoff_multiplier must be at least 1.0");
+    }
+
+    let dns = DnsOptions {
+        resolver,
+        preference,
+    };
+    let http_network = Network {
+        proxy: proxy.clone(),
+        dns: dns.clone(),
+        tls: http_tls,
+    };
+    let options = ClientOptions {
+        proxy,
+        connect_timeout: Duration::from_secs(network.connect_timeout.max(1)),
+        read_timeout: Duration::from_secs(network.read_timeout.max(1)),
+        retry: RetryPolicy {
+            multiplier: network.backoff_multiplier,
+            max_backoff: network.max_backoff.max(1),
+            max_retries: cli.max_retries.or(network.max_retries),
+            jitter: network.jitter,
+        },
+        tls,
+        failover_after: network.failover_after.max(1),
+        dns,
+        network_change: network.detect_network_changes.then(netwatch::watch),
+        compression: network.compression,
+        msgpack: network.msgpack,
+        outbox_size: network.outbox_size,
+        outbox_spill: network.outbox_spill.then(config::outbox_path).transpose()?,
+        self_update: cli.self_update,
+        ..Default::default()
+    };
+    Ok((options, http_network))
+}
+
+/**
+ * Puts the token in the `Authorization: Bearer` header if `bearer_auth` is set in the endpoint config,
+ * otherwise in the query string (for servers that don't support the header)
+ * @return The URL to connect to
+ */
+fn authenticate(endpoint_url: &str, token: &str, options: &mut ClientOptions) -> Result<String> {
+    let bearer_auth = config::read_endpoint_config()?.is_some_and(|e| e.bearer_auth);
+    if bearer_auth {
+        options.bearer_token = Some(token.to_string());
+        build_url(endpoint_url, None)
+    } else {
+        build_url(endpoint_url, Some(token))
+    }
+}
+
+/// Read the intervals the Steam callbacks are polled at from the config file
+fn steam_polling() -> Polling {
+    let config = config::read_config().ok().flatten();
+    let active = config
+        .as_ref()
+        .and_then(|c| c.callback_interval_ms)
+        .map_or(steam::CALLBACK_INTERVAL, |ms| {
+            Duration::from_millis(ms.max(10))
+        });
+    let idle = config
+        .as_ref()
+        .and_then(|c| c.idle_callback_interval_ms)
+        .map_or(active, |ms| Duration::from_millis(ms).max(active));
+    Polling { active, idle }
+}
+
+/**
+ * Connect to the Steam client, waiting for it to start if `--wait-for-steam` is given
+ * @return None if cancelled while waiting
+ */
+async fn init_steam(cli: &Cli, shutdown: &CancellationToken) -> Result<Option<SteamStuff>> {
+    let mut waiting = false;
+    loop {
+        match steam::connect() {
+            Ok(steam) => {
+                if waiting {
+                    console::println!("✓ {}", t!("steam-connected"));
+                }
+                return Ok(Some(steam));
+            }
+            Err(err) if !cli.wait_for_steam => {
+                return Err(err).with_context(|| t!("steam-connect-failed"));
+            }
+            Err(_) => (),
         }
 
+        if !waiting {
+            console::println!(
+                "↪ {}",
+                t!("steam-waiting", seconds = cli.steam_retry_interval)
+            );
+            if let Some(package) = library::sandboxed_steam() {
+                console::println!("  {}", package.guidance());
+            }
+            waiting = true;
+        }
+
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(cli.steam_retry_interval.max(1))) => (),
+            _ = shutdown.cancelled() => return Ok(None),
+        }
+    }
+}
+
+/// Creates a token cancelled when Ctrl+C is pressed
+fn shutdown_on_ctrl_c() -> CancellationToken {
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            let _ = tokio::signal::ctrl_c().await;
+            shutdown.cancel();
+
+            // Force exit if Ctrl+C is pressed again while shutting down
+            let _ = tokio::signal::ctrl_c().await;
+            std::process::exit(130);
+        }
+    });
+    shutdown
+}
+
+/// Connect to the server and handle requests until exit or until `shutdown` is cancelled
+async fn run(cli: &Cli, shutdown: CancellationToken) -> Result<()> {
+    // Show the application events on the console and count them in the metrics
+    console::spawn_event_printer();
+    console::spawn_status_line();
+    metrics::spawn_event_recorder();
+
+    // Save a crash report if the client panics
+    crash::install();
+    crash::spawn_event_recorder();
+
+    // Initialize logging
+    if let Err(err) = logging::init(&LogOptions {
+        format: cli.log_format.into(),
+        dir: cli.log_dir.clone(),
+    }) {
+        console::eprintln!("☓ {err:#}");
+    }
+
+    // Don't connect twice with the same UUID
+    let instance = match instance::acquire(cli.takeover) {
+        Ok(lock) => lock,
+        Err(err) => {
+            console::eprintln!("☓ {err:#}");
+            return finish(cli, EXIT_FATAL).await;
+        }
+    };
+    // The binary replaced by an update could not be removed while it was running
+    update::cleanup();
+
+    // Expose the metrics endpoint
+    if let Some(addr) = cli.metrics_addr {
+        match metrics::bind(addr).await {
+            Ok(listener) => {
+                console::println!(
+                    "✓ {}",
+                    t!("serving-metrics", url = format!("http://{addr}/metrics"))
+                );
+                tokio::spawn(metrics::serve(listener, shutdown.clone()));
+            }
+            Err(err) => console::eprintln!("☓ {err:#}"),
+        }
+    }
+
+    // Serve the dashboard
+    if let Some(addr) = cli.dashboard_addr {
+        match dashboard::bind(addr).await {
+            Ok(listener) => {
+                console::println!(
+                    "✓ {}",
+                    t!("serving-dashboard", url = format!("http://{addr}/"))
+                );
+                tokio::spawn(dashboard::serve(listener, shutdown.clone()));
+            }
+            Err(err) => console::eprintln!("☓ {err:#}"),
+        }
+    }
+
+    // Report the state to systemd
+    if cli.systemd {
+        #[cfg(unix)]
+        tokio::spawn(remoteplay_inviter::systemd::run(shutdown.clone()));
+        #[cfg(not(unix))]
+        console::eprintln!("☓ {}", t!("systemd-linux-only"));
+    }
+
+    // Connection options (the other HTTP requests go through the same proxy and resolver)
+    let (mut options, network) = match client_options(cli) {
+        Ok(options) => options,
+        Err(err) => {
+            console::eprintln!("☓ {:#}", err);
+            return finish(cli, EXIT_CONFIG).await;
+        }
+    };
+    store::set_network(network.clone());
+
+    // Upload the crash reports of the previous runs (opt-in)
+    if let Some(dsn) = config::read_config()
+        .ok()
+        .flatten()
+        .and_then(|c| c.sentry_dsn)
+    {
+        tokio::spawn(crash::upload_reports(dsn, network.clone()));
+    }
+
+    // Post the anonymous usage counters (opt-in)
+    if config::read_config()
+        .ok()
+        .flatten()
+        .is_some_and(|c| c.telemetry)
+    {
+        if let Ok(url) = endpoint_url(cli) {
+            tokio::spawn(telemetry::run(url, network.clone(), shutdown.clone()));
+        }
+    }
+
+    // Show desktop notifications
+    if config::read_config()
+        .ok()
+        .flatten()
+        .is_some_and(|c| c.notifications)
+    {
+        tokio::spawn(notifications::run(shutdown.clone()));
+    }
+
+    // Follow the events with the hook commands, the webhooks and Discord Rich Presence
+    if let Some(config) = config::read_config().ok().flatten() {
+        let game_hooks: HashMap<u32, HooksConfig> = game_configs(&config)
+            .into_iter()
+            .filter(|(_, game)| !game.hooks.is_empty())
+            .map(|(app_id, game)| (app_id, game.hooks))
+            .collect();
+        if !config.hooks.is_empty() || !game_hooks.is_empty() {
+            tokio::spawn(hooks::run(config.hooks, game_hooks, shutdown.clone()));
+        }
+        if !config.webhooks.is_empty() {
+            tokio::spawn(webhooks::run(
+                config.webhooks,
+                network.clone(),
+                shutdown.clone(),
+            ));
+        }
+
+        // Show the hosted session in the Discord profile
+        if let Some(client_id) = config.discord_client_id {
+            let max_guests = cli.max_guests.or(config.max_guests);
+            tokio::spawn(presence::run(client_id, max_guests, shutdown.clone()));
+        }
+    }
+
+    // Event loop (evaluates to the exit code)
+    let exit_code = 'main: {
         // Initialize SteamStuff
-        let steam = match SteamStuff::new()
-            .context("Failed to connect to Steam Client. Please make sure Steam is running.")
-        {
-            Ok(steam) => Arc::new(Mutex::new(steam)),
+        let steam = match init_steam(cli, &shutdown).await {
+            Ok(Some(steam)) => Steam::spawn_with_polling(steam, steam_polling()),
+            // Cancelled while waiting for Steam
+            Ok(None) => return Ok(()),
             Err(err) => {
                 console::eprintln!("☓ {}", err);
-                break 'main;
+                if let Some(package) = library::sandboxed_steam() {
+                    console::eprintln!("  {}", package.guidance());
+                }
+                break 'main EXIT_STEAM_UNAVAILABLE;
             }
         };
 
         // Create a Handler
-        let mut handler = Handler::new(steam.clone());
+        let mut handler = Handler::new(steam);
+        apply_limits(cli, &mut handler).await;
 
         // Set up Steam callbacks
         handler.setup_steam_callbacks().await;
-        // Start a task to periodically call Steam callbacks
-        handler.run_steam_callbacks();
+        // Start a task to expire invites and follow the Steam client
+        handler.watch_steam();
+
+        // Start reading console commands (the keys of the full-screen interface)
+        let console_rx = if cli.tui() && console::is_text() && !console::is_deck() {
+            tui::spawn().unwrap_or_else(|err| {
+                let _: Result<()> = (|| {
+                    console::eprintln!("☓ {err:#}");
+                    Ok(())
+                })();
+                console::spawn_command_reader()
+            })
+        } else {
+            console::spawn_command_reader()
+        };
+        let (control_tx, control_rx) = control::channel_pair();
+        let mut commands = Commands {
+            console: console_rx,
+            control: control_rx,
+            schedule: match &cli.command {
+                Some(Command::Schedule { when, game }) => {
+                    schedule::spawn(*when, *game, shutdown.clone())
+                }
+                _ => tokio::sync::mpsc::channel(1).1,
+            },
+        };
+
+        // Show the tray icon
+        #[cfg(feature = "tray")]
+        remoteplay_inviter::tray::spawn(control_tx.clone());
+
+        // Open the window
+        #[cfg(feature = "gui")]
+        if cli.gui {
+            remoteplay_inviter::gui::spawn(control_tx.clone());
+        }
 
-        // Reconnection flag
-        let mut reconnect = false;
-        // Retry seconds
-        let mut retry_sec = RetrySec::new();
+        // Start the plugins
+        #[cfg(feature = "plugins")]
+        if let Some(config) = config::read_config().ok().flatten() {
+            remoteplay_inviter::plugins::spawn(
+                config.plugins,
+                control_tx.clone(),
+                shutdown.clone(),
+            );
+        }
 
-        // URL to connect to
-        let result: Result<String> = (|| {
-            // Read the endpoint configuration file
-            let endpoint_config = config::read_endpoint_config()?;
+        // Start the control API
+        if cli.control {
+            match control::endpoint() {
+                Ok(endpoint) => {
+                    console::println!("✓ {}", t!("control-listening", path = endpoint.display()));
+                    let shutdown = shutdown.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = control::serve(control_tx, shutdown).await {
+                            let _: Result<()> = (|| {
+                                console::eprintln!("☓ {err:#}");
+                                Ok(())
+                            })();
+                        }
+                    });
+                }
+                Err(err) => console::eprintln!("☓ {err:#}"),
+            }
+        }
 
+        // URLs to connect to
+        let result: Result<Vec<Endpoint>> = (|| {
             // Read or generate the configuration file (if it doesn't exist)
-            let config = read_or_generate_config(|| Config {
-                uuid: Uuid::new_v4().to_string(),
-            })?;
-
-            // Session ID
-            let session_id: u32 = rand::random();
-
-            // Endpoint URL
-            let endpoint_url = match endpoint_config {
-                Some(e) => {
-                    console::println!("✓ Using custom endpoint URL: {}", e.url);
-                    e.url
+            let config = read_or_generate_config(new_config)?;
+
+            // Endpoint URLs (starting from the last one that worked)
+            let mut endpoint_urls = endpoint_urls(cli)?;
+            let mut redirect = None;
+            if let Some(last) = config::read_last_endpoint() {
+                if let Some(index) = endpoint_urls.iter().position(|url| *url == last.url) {
+                    endpoint_urls.rotate_left(index);
+                } else if let Some(from) = last.redirected_from {
+                    if let Some(index) = endpoint_urls.iter().position(|url| *url == from) {
+                        // Keep following the redirect, the configured endpoint is still the failover
+                        endpoint_urls.rotate_left(index);
+                        endpoint_urls.insert(0, last.url.clone());
+                        redirect = Some((last.url, from));
+                    }
+                }
+            }
+            if endpoint_urls.len() > 1 {
+                console::println!("✓ {}", t!("using-endpoint-urls"));
+                for endpoint_url in &endpoint_urls {
+                    console::println!("  - {}", endpoint_url);
                 }
-                None => DEFAULT_URL.to_string(),
-            };
-
-            // Create the URL
-            let uri: Uri = endpoint_url.parse().context("Failed to parse URL")?;
-            let uri = Builder::from(uri)
-                .path_and_query(format!(
-                    "/ws?v={VERSION}&token={0}&session={session_id}",
-                    config.uuid
-                ))
-                .build()
-                .context("Failed to build URL")?;
-            Ok(uri.to_string())
+            } else if endpoint_urls[0] != DEFAULT_URL {
+                console::println!("✓ {}", t!("using-endpoint-url", url = endpoint_urls[0]));
+            }
+
+            // Create the URLs
+            endpoint_urls
+                .into_iter()
+                .map(|name| {
+                    let url = authenticate(&name, &config.uuid, &mut options)?;
+                    let redirected_from = redirect
+                        .as_ref()
+                        .filter(|(to, _)| *to == name)
+                        .map(|(_, from)| from.clone());
+                    Ok(Endpoint {
+                        name,
+                        url,
+                        redirected_from,
+                    })
+                })
+                .collect()
         })();
 
-        let url = match result {
-            Ok(url) => url,
+        let endpoints = match result {
+            Ok(endpoints) => endpoints,
             Err(err) => {
                 console::eprintln!("☓ {}", err);
-                break 'main;
+                break 'main EXIT_CONFIG;
             }
         };
 
-        enum ResultConfig {
-            Success,
-            Break,
-        }
-
-        loop {
-            let result: Result<ResultConfig> = {
-                let retry_sec = Mutex::new(&mut retry_sec);
-                let handler = Mutex::new(&mut handler);
-                #[allow(clippy::redundant_closure_call)]
-                (|| async {
-                    // Display the reconnection message
-                    if reconnect {
-                        console::println!("↪ Reconnecting to the server...");
-                    }
-
-                    // Create a WebSocket client
-                    let connect_result = timeout(Duration::from_secs(10), connect_async(&url))
-                        .await
-                        .context("Connection timed out to the server")?;
-                    let ws_stream = match connect_result {
-                        Ok((ws_stream, _)) => ws_stream,
-                        Err(err) => {
-                            handle_ws_error(err)?;
-                            // If OK is returned, break the loop and exit
-                            return Ok(ResultConfig::Break);
-                        }
-                    };
+        // Connect to the server and process messages
+        let exit = run_client(&endpoints, &options, &mut handler, &mut commands, &shutdown).await;
+        console::end_status();
+        tui::stop();
 
-                    // Stream and sink for communicating with the server
-                    let (mut write, mut read) = ws_stream.split();
+        // Tear down the Steam callbacks
+        handler.shutdown().await;
 
-                    // Display the reconnection message
-                    if reconnect {
-                        console::println!("✓ Reconnected!");
-                    } else {
-                        console::println!("✓ Connected to the server!");
+        match exit {
+            Ok(ClientExit::Break) => EXIT_OK,
+            Ok(ClientExit::Refused) => EXIT_REFUSED,
+            Ok(ClientExit::Quit) => return Ok(()),
+            Ok(ClientExit::RetriesExhausted) => EXIT_RETRIES_EXHAUSTED,
+            Ok(ClientExit::Outdated(release)) => {
+                console::println!("↪ {}", t!("updating", version = release.version));
+                match update::install(&release, &network).await {
+                    Ok(binary) => {
+                        console::println!("✓ {}", t!("updated", version = release.version));
+                        drop(instance);
+                        logging::shutdown();
+                        let err = update::restart(&binary);
+                        console::eprintln!("☓ {err:#}");
+                        EXIT_FATAL
                     }
-
-                    // Loop to process messages received from the server
-                    while let Some(message) = timeout(Duration::from_secs(60), read.next())
-                        .await
-                        .context("Connection timed out")?
-                    {
-                        // Process each message
-                        match message.context("Failed to receive message from the server")? {
-                            Message::Close(_) => break,
-                            Message::Ping(ping) => {
-                                // Send a Pong message
-                                write
-                                    .send(Message::Pong(ping))
-                                    .await
-                                    .context("Failed to send pong message to the server")?;
-
-                                // Reset the retry seconds
-                                retry_sec.lock().await.reset();
-                            }
-                            Message::Text(text) => {
-                                // Parse the JSON data
-                                let msg: ServerMessage = serde_json::from_str(&text).context(
-                                    "Failed to deserialize JSON message from the server",
-                                )?;
-
-                                // Process the message
-                                if handler.lock().await.handle_server_message(msg, &mut write).await? {
-                                    // If the exit flag is set, break the loop and exit
-                                    return Ok(ResultConfig::Break);
-                                }
-
-                                // Reset the retry seconds
-                                retry_sec.lock().await.reset();
-                            }
-                            _ => (),
-                        }
+                    Err(err) => {
+                        console::eprintln!("☓ {}", t!("update-failed", error = format!("{err:#}")));
+                        let _ = platform::open_url(&release.page);
+                        EXIT_REFUSED
                     }
-
-                    Ok(ResultConfig::Success)
-                })()
-                .await
-            };
-            if let Ok(ResultConfig::Break) = result {
-                break 'main;
+                }
             }
-            if let Err(err) = result {
-                console::eprintln!("☓ {}", err);
+            Err(err) => {
+                console::eprintln!("☓ {err:#}");
+                EXIT_PROTOCOL
             }
-
-            // Reconnect to the server if the connection is lost
-            let sec = retry_sec.next();
-            console::println!("↪ Connection lost. Reconnecting in {sec} seconds...");
-            time::sleep(Duration::from_secs(sec)).await;
-            reconnect = true;
         }
-    }
+    };
 
-    // Wait for input before exiting
-    console::println!("□ Press Ctrl+C to exit...");
-    let _ = tokio::signal::ctrl_c().await;
+    finish(cli, exit_code).await
+}
+
+/// Wait for input before exiting if someone is watching, then exit with `exit_code` if it is an error
+async fn finish(cli: &Cli, exit_code: i32) -> Result<()> {
+    // Wait for input before exiting (so that the message can be read, unless only the errors are printed)
+    let attended = cli.interactive() && console::prints_info();
+    if attended && console::is_deck() {
+        // No keyboard needed to close the window
+        console::println!(
+            "□ {}",
+            t!("exiting-in", seconds = DECK_EXIT_DELAY.as_secs())
+        );
+        let _ = time::timeout(DECK_EXIT_DELAY, tokio::signal::ctrl_c()).await;
+    } else if attended {
+        console::println!("□ {}", t!("press-ctrl-c"));
+        let _ = tokio::signal::ctrl_c().await;
+    }
 
+    if exit_code != EXIT_OK {
+        logging::shutdown();
+        std::process::exit(exit_code);
+    }
     Ok(())
 }
@@ -0,0 +1,232 @@
+use anyhow::{Context as _, Result};
+use std::{
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::error::RecvError,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{self, Event};
+
+/// A counter or gauge
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    value: AtomicU64,
+}
+
+impl Metric {
+    const fn counter(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "counter",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    const fn gauge(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "gauge",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    /// Increments the value by one
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Increases the value (for counters)
+    pub fn add(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Sets the value (for gauges)
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    /// Gets the current value
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Number of attempts to reconnect to the server
+pub static RECONNECT_ATTEMPTS: Metric = Metric::counter(
+    "remoteplay_inviter_reconnect_attempts_total",
+    "Number of attempts to reconnect to the server",
+);
+/// Number of messages received from the server
+pub static MESSAGES_RECEIVED: Metric = Metric::counter(
+    "remoteplay_inviter_messages_received_total",
+    "Number of messages received from the server",
+);
+/// Number of messages sent to the server
+pub static MESSAGES_SENT: Metric = Metric::counter(
+    "remoteplay_inviter_messages_sent_total",
+    "Number of messages sent to the server",
+);
+/// Number of guests currently connected
+pub static ACTIVE_GUESTS: Metric = Metric::gauge(
+    "remoteplay_inviter_active_guests",
+    "Number of guests currently connected",
+);
+/// Number of invite links created
+pub static INVITES_CREATED: Metric = Metric::counter(
+    "remoteplay_inviter_invites_created_total",
+    "Number of invite links created",
+);
+/// Number of guests who joined a session
+pub static GUESTS_JOINED: Metric = Metric::counter(
+    "remoteplay_inviter_guests_joined_total",
+    "Number of guests who joined a session",
+);
+/// Number of invite links requested
+pub static INVITE_REQUESTS: Metric = Metric::counter(
+    "remoteplay_inviter_invite_requests_total",
+    "Number of invite links requested",
+);
+/// Number of errors from the Steam client
+pub static STEAM_CALLBACK_ERRORS: Metric = Metric::counter(
+    "remoteplay_inviter_steam_callback_errors_total",
+    "Number of errors while waiting for Steam client callbacks",
+);
+/// Whether permessage-deflate is used on the current connection
+pub static COMPRESSION_ACTIVE: Metric = Metric::gauge(
+    "remoteplay_inviter_compression_active",
+    "Whether the connection to the server is compressed (permessage-deflate)",
+);
+/// Payload bytes of the messages sent, before compression
+pub static UNCOMPRESSED_BYTES_SENT: Metric = Metric::counter(
+    "remoteplay_inviter_uncompressed_bytes_sent_total",
+    "Payload bytes of the messages sent to the server, before compression",
+);
+/// Payload bytes of the messages sent, after compression
+pub static COMPRESSED_BYTES_SENT: Metric = Metric::counter(
+    "remoteplay_inviter_compressed_bytes_sent_total",
+    "Payload bytes of the messages sent to the server, after compression",
+);
+/// Payload bytes of the messages received, after decompression
+pub static UNCOMPRESSED_BYTES_RECEIVED: Metric = Metric::counter(
+    "remoteplay_inviter_uncompressed_bytes_received_total",
+    "Payload bytes of the messages received from the server, after decompression",
+);
+/// Payload bytes of the messages received, before decompression
+pub static COMPRESSED_BYTES_RECEIVED: Metric = Metric::counter(
+    "remoteplay_inviter_compressed_bytes_received_total",
+    "Payload bytes of the messages received from the server, before decompression",
+);
+
+/// All metrics, in the order they are exposed
+static METRICS: [&Metric; 13] = [
+    &RECONNECT_ATTEMPTS,
+    &MESSAGES_RECEIVED,
+    &MESSAGES_SENT,
+    &ACTIVE_GUESTS,
+    &GUESTS_JOINED,
+    &INVITES_CREATED,
+    &INVITE_REQUESTS,
+    &STEAM_CALLBACK_ERRORS,
+    &COMPRESSION_ACTIVE,
+    &UNCOMPRESSED_BYTES_SENT,
+    &COMPRESSED_BYTES_SENT,
+    &UNCOMPRESSED_BYTES_RECEIVED,
+    &COMPRESSED_BYTES_RECEIVED,
+];
+
+/// Formats the metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut text = String::new();
+    for metric in METRICS {
+        let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(text, "# TYPE {} {}", metric.name, metric.kind);
+        let _ = writeln!(text, "{} {}", metric.name, metric.get());
+    }
+    text
+}
+
+/// Starts a task updating the metrics from the application events (subscribed before returning)
+pub fn spawn_event_recorder() {
+    let mut events = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(Event::InviteCreated { .. }) => INVITES_CREATED.inc(),
+                Ok(Event::GuestJoined { guests, .. }) => {
+                    GUESTS_JOINED.inc();
+                    ACTIVE_GUESTS.set(guests as u64);
+                }
+                Ok(Event::GuestLeft { guests, .. }) => ACTIVE_GUESTS.set(guests as u64),
+                Ok(Event::SteamLost) => ACTIVE_GUESTS.set(0),
+                Ok(_) | Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+/// Binds the metrics endpoint
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to listen on the metrics address: {addr}"))
+}
+
+/// Serves `GET /metrics` until `shutdown` is cancelled
+pub async fn serve(listener: TcpListener, shutdown: CancellationToken) {
+    loop {
+        let stream = tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    tracing::warn!("Failed to accept metrics connection: {err}");
+                    continue;
+                }
+            },
+            _ = shutdown.cancelled() => return,
+        };
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream).await {
+                tracing::debug!("Metrics request failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Answers a single HTTP request
+async fn handle_connection(mut stream: TcpStream) -> Result<()> {
+    // Read the request head
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() > 8192 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let request = String::from_utf8_lossy(&buf);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        _ => ("404 Not Found", "Not Found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    metrics,
+    models::{ClientCmd, ClientMessage, ErrorStatus, ServerCmd, ServerMessage},
+};
+
+/// What to do with a message from the server
+pub enum Inbound {
+    /// Handle the message (possibly modified)
+    Handle(ServerMessage),
+    /// Answer the message without handling it
+    Reply(ClientMessage),
+    /// Ignore the message
+    Drop,
+}
+
+/// A layer around the processing of the messages exchanged with the server
+pub trait Middleware: Send + Sync {
+    /// Called for each message from the server before it is handled
+    fn inbound(&self, msg: ServerMessage) -> Inbound {
+        Inbound::Handle(msg)
+    }
+
+    /**
+     * Called for each message to the server before it is sent
+     * @return None to not send it
+     */
+    fn outbound(&self, msg: ClientMessage) -> Option<ClientMessage> {
+        Some(msg)
+    }
+}
+
+/// Middleware applied in order to the inbound messages and in reverse order to the outbound ones
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl Pipeline {
+    /// The layers every client uses: logging and metrics
+    pub fn standard() -> Self {
+        Self::default().with(Logging).with(Metrics)
+    }
+
+    /// Adds a layer (inside the ones added before it)
+    pub fn with(mut self, layer: impl Middleware + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Passes a message from the server through the layers
+    pub fn inbound(&self, msg: ServerMessage) -> Inbound {
+        let mut msg = msg;
+        for layer in &self.layers {
+            match layer.inbound(msg) {
+                Inbound::Handle(next) => msg = next,
+                verdict => return verdict,
+            }
+        }
+        Inbound::Handle(msg)
+    }
+
+    /// Passes a message to the server through the layers
+    pub fn outbound(&self, msg: ClientMessage) -> Option<ClientMessage> {
+        self.layers
+            .iter()
+            .rev()
+            .try_fold(msg, |msg, layer| layer.outbound(msg))
+    }
+}
+
+/// Logs the messages at debug level
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn inbound(&self, msg: ServerMessage) -> Inbound {
+        tracing::debug!(?msg, "Received message from the server");
+        Inbound::Handle(msg)
+    }
+
+    fn outbound(&self, msg: ClientMessage) -> Option<ClientMessage> {
+        tracing::debug!(?msg, "Sending message to the server");
+        Some(msg)
+    }
+}
+
+/// Counts the messages received from the server (the sent ones are counted once actually sent)
+pub struct Metrics;
+
+impl Middleware for Metrics {
+    fn inbound(&self, msg: ServerMessage) -> Inbound {
+        metrics::MESSAGES_RECEIVED.inc();
+        Inbound::Handle(msg)
+    }
+}
+
+/// Limits the invite requests of each Discord user, answering the extra ones with an error
+pub struct RateLimit {
+    max_requests: u32,
+    window: Duration,
+    /// Start of the current window and the number of requests in it, per user
+    requests: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl RateLimit {
+    /// Allows `max_requests` requests per user every `window`
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn inbound(&self, msg: ServerMessage) -> Inbound {
+        if !matches!(msg.cmd, ServerCmd::GameId | ServerCmd::Link { .. }) {
+            return Inbound::Handle(msg);
+        }
+        let user = msg.user.as_ref().map_or("", |user| user.id.as_str());
+        let now = Instant::now();
+        let mut requests = self.requests.lock().unwrap();
+        requests.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        let (_, count) = requests.entry(user.to_string()).or_insert((now, 0));
+        *count += 1;
+        if *count <= self.max_requests {
+            return Inbound::Handle(msg);
+        }
+
+        tracing::info!(user, "Too many invite requests, declined");
+        Inbound::Reply(ClientMessage {
+            id: msg.id,
+            cmd: ClientCmd::Error {
+                code: ErrorStatus::RateLimited,
+            },
+            key: None,
+        })
+    }
+}
+
+/// Ignores the server commands with the given names (e.g. `message`)
+pub struct Filter {
+    commands: HashSet<String>,
+}
+
+impl Filter {
+    pub fn new(commands: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            commands: commands.into_iter().collect(),
+        }
+    }
+}
+
+impl Middleware for Filter {
+    fn inbound(&self, msg: ServerMessage) -> Inbound {
+        let name = serde_json::to_value(&msg.cmd)
+            .ok()
+            .and_then(|cmd| cmd.get("cmd")?.as_str().map(String::from));
+        match name {
+            Some(name) if self.commands.contains(&name) => {
+                tracing::info!(cmd = name, "Ignored a filtered command");
+                Inbound::Drop
+            }
+            _ => Inbound::Handle(msg),
+        }
+    }
+}
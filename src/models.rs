@@ -1,4 +1,20 @@
-use serde::{Deserialize, Serialize};
+use anyhow::{Context as _, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::hmac;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum difference in seconds between the timestamp of a signed message and the local clock
+pub const SIGNATURE_MAX_SKEW: u64 = 300;
+
+/// Number of handled server messages remembered to detect retransmissions
+const RECENT_MESSAGES: usize = 256;
+
+/// Version of the message protocol announced in the hello message
+pub const PROTOCOL_VERSION: u32 = 1;
 
 /// Connection error message
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +36,9 @@ pub enum ConnectionErrorType {
         required: String,
         /// Download URL
         download: String,
+        /// URL of the release binary for each platform (e.g. `windows-x86_64`), to update automatically
+        #[serde(default)]
+        assets: BTreeMap<String, String>,
     },
     #[serde(other)]
     Other,
@@ -31,7 +50,11 @@ pub struct ServerMessage {
     /// Request ID
     pub id: String,
     /// Request user
+    #[serde(default, deserialize_with = "lenient")]
     pub user: Option<User>,
+    /// Sequence number (for servers that number their messages)
+    #[serde(default, deserialize_with = "lenient")]
+    pub seq: Option<u64>,
     /// Request type
     #[serde(flatten)]
     pub cmd: ServerCmd,
@@ -47,8 +70,37 @@ pub enum ServerCmd {
         /// Message text
         text: String,
         /// Text to copy to clipboard
+        #[serde(default, deserialize_with = "lenient")]
         copy: Option<String>,
     },
+    /// Announcement from the server operators (e.g. maintenance windows, new bot features)
+    #[serde(rename = "announcement")]
+    Announcement {
+        /// Announcement title
+        #[serde(default, deserialize_with = "lenient")]
+        title: Option<String>,
+        /// Announcement text
+        text: String,
+        /// Link to more details
+        #[serde(default, deserialize_with = "lenient")]
+        url: Option<String>,
+    },
+    /// The server is going down for a planned maintenance
+    #[serde(rename = "maintenance")]
+    Maintenance {
+        /// Notice to display
+        #[serde(default, deserialize_with = "lenient")]
+        message: Option<String>,
+        /// Seconds after which the server should be back
+        #[serde(default, deserialize_with = "lenient")]
+        reconnect_after: Option<u64>,
+    },
+    /// Reconnect to another server (load balancing or migration to another region)
+    #[serde(rename = "redirect")]
+    Redirect {
+        /// WebSocket URL of the server to connect to
+        url: String,
+    },
     /// Generate a game id
     #[serde(rename = "game")]
     GameId,
@@ -57,26 +109,94 @@ pub enum ServerCmd {
     Link {
         /// Game ID
         game: u32,
+        /// Maximum number of times the invite can be used
+        #[serde(default, deserialize_with = "lenient")]
+        max_uses: Option<u32>,
+        /// Seconds until the invite expires
+        #[serde(default, deserialize_with = "lenient")]
+        ttl: Option<u64>,
+    },
+    /// Kick a guest from the session
+    #[serde(rename = "kick")]
+    Kick {
+        /// Guest ID
+        guest: u64,
     },
     /// Exit request
     #[serde(rename = "exit")]
     Exit,
+    /// Session token to resume the session after a reconnect
+    #[serde(rename = "session")]
+    Session {
+        /// Resumption token
+        token: String,
+    },
+    /// Result of the session resumption
+    #[serde(rename = "resume")]
+    Resume {
+        /// Whether the previous session was resumed
+        accepted: bool,
+    },
+    /// One-time code to type into Discord to link this client
+    #[serde(rename = "pairing")]
+    Pairing {
+        /// Pairing code
+        code: String,
+        /// Seconds until the code expires
+        #[serde(default, deserialize_with = "lenient")]
+        expires_in: Option<u64>,
+    },
+    /// The client was linked to a Discord account with the pairing code
+    #[serde(rename = "paired")]
+    Paired {
+        /// Name of the linked Discord account
+        #[serde(default, deserialize_with = "lenient")]
+        account: Option<String>,
+    },
+    /// Reply to the hello message with the features the server supports
+    #[serde(rename = "hello")]
+    Hello {
+        /// Protocol version of the server
+        protocol: u32,
+        /// Supported features (unknown ones are ignored)
+        #[serde(default)]
+        capabilities: Vec<String>,
+        /// Current time of the server (Unix seconds), to detect a wrong local clock
+        #[serde(default, deserialize_with = "lenient")]
+        time: Option<u64>,
+    },
+    /// A command added to the protocol after this client (or one it could not parse)
     #[serde(other)]
-    Invalid,
+    Unknown,
+}
+
+/// Deserializes an optional field, treating a value of an unexpected type as missing
+fn lenient<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value)
+        .inspect_err(|err| tracing::warn!("Ignored an invalid field from the server: {err}"))
+        .ok())
 }
 
 /// A data structure to represent a response from the daemon
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ClientMessage {
     /// Request ID
     pub id: String,
     /// Request type
     #[serde(flatten)]
     pub cmd: ClientCmd,
+    /// Idempotency key, so that the server can drop a message resent after a reconnect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
 }
 
 /// Request Type
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "cmd")]
 pub enum ClientCmd {
     /// Generate a game id
@@ -90,6 +210,18 @@ pub enum ClientCmd {
     Link {
         /// Invite URL
         url: String,
+        /// Maximum number of times the invite can be used
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_uses: Option<u32>,
+        /// Seconds until the invite expires
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ttl: Option<u64>,
+    },
+    /// Guest kicked response
+    #[serde(rename = "kicked")]
+    Kicked {
+        /// Guest ID
+        guest: u64,
     },
     /// Error response
     #[serde(rename = "error")]
@@ -97,17 +229,274 @@ pub enum ClientCmd {
         /// Error code
         code: ErrorStatus,
     },
+    /// An invite link was revoked
+    #[serde(rename = "revoke")]
+    Revoke {
+        /// Guest ID of the revoked invite
+        guest: u64,
+    },
+    /// The session has reached the maximum number of guests
+    #[serde(rename = "session_full")]
+    SessionFull {
+        /// Maximum number of guests
+        max_guests: usize,
+    },
+    /// Statistics of a finished play session
+    #[serde(rename = "session_stats")]
+    SessionStats {
+        /// Duration of the session in seconds
+        duration: u64,
+        /// Number of distinct guests
+        unique_guests: usize,
+        /// Maximum number of guests connected at the same time
+        peak_guests: usize,
+        /// Number of reconnections to the server during the session
+        reconnects: u32,
+    },
+    /// The token of this client was revoked (the server forgets its link to Discord)
+    #[serde(rename = "unlink")]
+    Unlink,
+    /// Periodic status of the host (sent again when the running game changes)
+    #[serde(rename = "status")]
+    Status {
+        /// App ID of the running game (None: no game is running)
+        game: Option<u32>,
+        /// Number of guests in the session
+        guests: usize,
+        /// Whether the host paused the invite requests
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        paused: bool,
+    },
+    /// Steam client connection status changed
+    #[serde(rename = "steam_status")]
+    SteamStatus {
+        /// Whether the Steam client is connected
+        connected: bool,
+    },
+    /// First message of a connection with the features the client supports
+    #[serde(rename = "hello")]
+    Hello {
+        /// Protocol version of the client
+        protocol: u32,
+        /// Client version
+        version: String,
+        /// Supported features
+        capabilities: Vec<Capability>,
+    },
 }
 
-/// User information
+/// Optional protocol features agreed on with the hello messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Resuming the session after a reconnect (`session`/`resume`)
+    Resume,
+    /// Linking to Discord with a pairing code (`pairing`/`paired`)
+    Pairing,
+    /// Notifications sent without a request (`revoke`, `session_full`, `session_stats`, `status`, `steam_status`)
+    Events,
+    /// Signed messages
+    Signing,
+    /// MessagePack in binary frames
+    Msgpack,
+    /// permessage-deflate compression
+    Compression,
+}
+
+impl Capability {
+    /// Name of the feature in the hello messages
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Resume => "resume",
+            Capability::Pairing => "pairing",
+            Capability::Events => "events",
+            Capability::Signing => "signing",
+            Capability::Msgpack => "msgpack",
+            Capability::Compression => "compression",
+        }
+    }
+}
+
+/// Encoding of the messages exchanged with the server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// MessagePack in binary frames (offered with `format=msgpack` in the URL)
+    MessagePack,
+}
+
+/// A message signed with the per-device secret
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SignedMessage {
+    /// JSON of the message, signed as is
+    pub payload: String,
+    /// Unix time the message was signed at (seconds)
+    pub ts: u64,
+    /// Random value used only once
+    pub nonce: String,
+    /// HMAC-SHA256 of `{ts}.{nonce}.{payload}` (base64)
+    pub sig: String,
+}
+
+/// Key signing the messages exchanged with the server (HMAC-SHA256 of the per-device secret)
+pub struct SigningKey(hmac::Key);
+
+impl SigningKey {
+    /// Creates the key from the secret in base64
+    pub fn from_base64(secret: &str) -> Result<Self> {
+        let secret = BASE64
+            .decode(secret.trim())
+            .context("The signing secret is not valid base64")?;
+        if secret.len() < 16 {
+            anyhow::bail!("The signing secret must be at least 16 bytes long");
+        }
+        Ok(Self(hmac::Key::new(hmac::HMAC_SHA256, &secret)))
+    }
+
+    /// Data covered by the signature
+    fn signed_data(ts: u64, nonce: &str, payload: &str) -> String {
+        format!("{ts}.{nonce}.{payload}")
+    }
+
+    /// Signs the JSON of a message
+    pub fn sign(&self, payload: String) -> SignedMessage {
+        let ts = unix_time();
+        let nonce = BASE64.encode(rand::random::<[u8; 16]>());
+        let tag = hmac::sign(&self.0, Self::signed_data(ts, &nonce, &payload).as_bytes());
+        SignedMessage {
+            payload,
+            ts,
+            nonce,
+            sig: BASE64.encode(tag.as_ref()),
+        }
+    }
+
+    /**
+     * Verifies a message signed by the server
+     * @return The JSON of the message if the signature is valid and the message was not seen before
+     */
+    pub fn verify<'a>(
+        &self,
+        message: &'a SignedMessage,
+        replay_guard: &mut ReplayGuard,
+    ) -> Result<&'a str> {
+        let sig = BASE64
+            .decode(&message.sig)
+            .context("The signature is not valid base64")?;
+        hmac::verify(
+            &self.0,
+            Self::signed_data(message.ts, &message.nonce, &message.payload).as_bytes(),
+            &sig,
+        )
+        .map_err(|_| anyhow::anyhow!("Invalid message signature"))?;
+        replay_guard.check(message.ts, &message.nonce)?;
+        Ok(&message.payload)
+    }
+}
+
+/// Rejects signed messages that are too old or were already received
+#[derive(Default)]
+pub struct ReplayGuard {
+    /// Nonces received within the allowed clock skew (nonce -> timestamp)
+    seen: HashMap<String, u64>,
+}
+
+impl ReplayGuard {
+    /// Checks the timestamp and records the nonce of a message
+    pub fn check(&mut self, ts: u64, nonce: &str) -> Result<()> {
+        let now = unix_time();
+        if now.abs_diff(ts) > SIGNATURE_MAX_SKEW {
+            anyhow::bail!("The signed message is too old (check the system clock)");
+        }
+        // Older nonces are rejected by the timestamp check
+        self.seen
+            .retain(|_, seen_ts| now.abs_diff(*seen_ts) <= SIGNATURE_MAX_SKEW);
+        if self.seen.insert(nonce.to_string(), ts).is_some() {
+            anyhow::bail!("The signed message was replayed");
+        }
+        Ok(())
+    }
+}
+
+/// Server messages handled recently and the replies sent to them, to handle retransmissions only once
+#[derive(Default)]
+pub struct RecentMessages {
+    /// Keys from the least to the most recently seen
+    order: VecDeque<String>,
+    replies: HashMap<String, Option<ClientMessage>>,
+}
+
+impl RecentMessages {
+    /**
+     * Identifies a message: its sequence number if the server numbers them, otherwise its id
+     * @return None if the message can't be identified
+     */
+    pub fn key(msg: &ServerMessage) -> Option<String> {
+        match msg.seq {
+            Some(seq) => Some(format!("#{seq}")),
+            None if msg.id.is_empty() => None,
+            None => Some(msg.id.clone()),
+        }
+    }
+
+    /**
+     * Looks up a message that was already handled
+     * @return The reply sent to it (None: not handled yet, Some(None): handled without reply)
+     */
+    pub fn get(&mut self, key: &str) -> Option<Option<&ClientMessage>> {
+        let index = self.order.iter().position(|k| k == key)?;
+        if let Some(key) = self.order.remove(index) {
+            self.order.push_back(key);
+        }
+        self.replies.get(key).map(Option::as_ref)
+    }
+
+    /// Records a handled message and its reply, forgetting the least recently seen ones
+    pub fn insert(&mut self, key: String, reply: Option<ClientMessage>) {
+        if self.replies.insert(key.clone(), reply).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > RECENT_MESSAGES {
+            if let Some(key) = self.order.pop_front() {
+                self.replies.remove(&key);
+            }
+        }
+    }
+
+    /// Forgets the messages of the previous session
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.replies.clear();
+    }
+}
+
+/// Current Unix time in seconds
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// User information
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
+    /// Discord user ID
     pub id: String,
     pub name: String,
+    /// Steam ID (SteamID64) of the user, when the server knows it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "lenient"
+    )]
+    pub steam_id: Option<String>,
 }
 
 /// Error statuses
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ErrorStatus {
     /// The command is invalid
@@ -116,4 +505,164 @@ pub enum ErrorStatus {
     InvalidApp,
     /// The app does not support remote play
     UnsupportedApp,
+    /// The guest is not connected
+    InvalidGuest,
+    /// The session has reached the maximum number of guests
+    SessionFull,
+    /// The user sent too many requests
+    RateLimited,
+    /// The app is not allowed by the host (allow/deny lists)
+    DisallowedApp,
+    /// The host paused the invite requests
+    HostPaused,
+    /// The host declined the user's requests (block list, or on the console)
+    UserDenied,
+    /// The Steam client is not connected or did not create the invite link
+    SteamUnavailable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key of a 32-byte secret
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_base64(&BASE64.encode([byte; 32])).unwrap()
+    }
+
+    #[test]
+    fn rejects_short_secrets() {
+        assert!(SigningKey::from_base64(&BASE64.encode([0; 8])).is_err());
+        assert!(SigningKey::from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn verifies_signed_messages() {
+        let signed = key(1).sign(r#"{"cmd":"game"}"#.into());
+        let json = key(1).verify(&signed, &mut ReplayGuard::default()).unwrap();
+        assert_eq!(json, r#"{"cmd":"game"}"#);
+    }
+
+    #[test]
+    fn rejects_tampered_messages() {
+        let mut replay_guard = ReplayGuard::default();
+        let mut signed = key(1).sign(r#"{"cmd":"game"}"#.into());
+        signed.payload = r#"{"cmd":"exit"}"#.into();
+        assert!(key(1).verify(&signed, &mut replay_guard).is_err());
+
+        let mut signed = key(1).sign(r#"{"cmd":"game"}"#.into());
+        signed.ts += 1;
+        assert!(key(1).verify(&signed, &mut replay_guard).is_err());
+    }
+
+    #[test]
+    fn rejects_other_keys() {
+        let signed = key(1).sign(r#"{"cmd":"game"}"#.into());
+        assert!(key(2).verify(&signed, &mut ReplayGuard::default()).is_err());
+    }
+
+    #[test]
+    fn rejects_replayed_messages() {
+        let mut replay_guard = ReplayGuard::default();
+        let signed = key(1).sign(r#"{"cmd":"game"}"#.into());
+        assert!(key(1).verify(&signed, &mut replay_guard).is_ok());
+        assert!(key(1).verify(&signed, &mut replay_guard).is_err());
+    }
+
+    #[test]
+    fn replay_guard_allows_the_clock_skew() {
+        let mut replay_guard = ReplayGuard::default();
+        let now = unix_time();
+        assert!(replay_guard
+            .check(now - SIGNATURE_MAX_SKEW + 5, "a")
+            .is_ok());
+        assert!(replay_guard
+            .check(now + SIGNATURE_MAX_SKEW - 5, "b")
+            .is_ok());
+        assert!(replay_guard
+            .check(now - SIGNATURE_MAX_SKEW - 5, "c")
+            .is_err());
+        assert!(replay_guard
+            .check(now + SIGNATURE_MAX_SKEW + 5, "d")
+            .is_err());
+    }
+
+    #[test]
+    fn replay_guard_rejects_duplicate_nonces() {
+        let mut replay_guard = ReplayGuard::default();
+        let now = unix_time();
+        assert!(replay_guard.check(now, "a").is_ok());
+        assert!(replay_guard.check(now, "b").is_ok());
+        assert!(replay_guard.check(now, "a").is_err());
+        // A message rejected for its age doesn't record its nonce
+        assert!(replay_guard
+            .check(now - SIGNATURE_MAX_SKEW - 5, "c")
+            .is_err());
+        assert!(replay_guard.check(now, "c").is_ok());
+    }
+
+    /// Reply identified by its request ID
+    fn reply(id: &str) -> Option<ClientMessage> {
+        Some(ClientMessage {
+            id: id.into(),
+            cmd: ClientCmd::GameId { game: 480 },
+            key: None,
+        })
+    }
+
+    fn message(id: &str, seq: Option<u64>) -> ServerMessage {
+        ServerMessage {
+            id: id.into(),
+            user: None,
+            seq,
+            cmd: ServerCmd::GameId,
+        }
+    }
+
+    #[test]
+    fn identifies_messages_by_seq_or_id() {
+        assert_eq!(
+            RecentMessages::key(&message("a", Some(3))).as_deref(),
+            Some("#3")
+        );
+        assert_eq!(
+            RecentMessages::key(&message("a", None)).as_deref(),
+            Some("a")
+        );
+        assert_eq!(RecentMessages::key(&message("", None)), None);
+    }
+
+    #[test]
+    fn detects_duplicates() {
+        let mut recent = RecentMessages::default();
+        assert!(recent.get("a").is_none());
+        recent.insert("a".into(), reply("a"));
+        recent.insert("b".into(), None);
+
+        let replied = recent.get("a").unwrap().unwrap();
+        assert_eq!(replied.id, "a");
+        assert!(matches!(recent.get("b"), Some(None)));
+        assert!(recent.get("c").is_none());
+
+        recent.clear();
+        assert!(recent.get("a").is_none());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen() {
+        let mut recent = RecentMessages::default();
+        for i in 0..RECENT_MESSAGES {
+            recent.insert(i.to_string(), None);
+        }
+        // Seeing the oldest again keeps it
+        assert!(recent.get("0").is_some());
+        recent.insert("new".into(), None);
+
+        assert!(recent.get("0").is_some());
+        assert!(recent.get("1").is_none());
+        assert!(recent.get("2").is_some());
+        assert!(recent.get("new").is_some());
+        assert_eq!(recent.order.len(), RECENT_MESSAGES);
+        assert_eq!(recent.replies.len(), RECENT_MESSAGES);
+    }
 }
@@ -0,0 +1,385 @@
+use anyhow::{bail, Context as _, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Number, Value};
+
+/// Deepest nesting accepted from the server
+const MAX_DEPTH: usize = 64;
+
+/// Serializes a value as MessagePack (structs become maps keyed by field name)
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let value = serde_json::to_value(value).context("Failed to serialize the message")?;
+    let mut out = Vec::new();
+    write_value(&mut out, &value);
+    Ok(out)
+}
+
+/// Deserializes a value from MessagePack
+pub fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    let mut reader = Reader { data, pos: 0 };
+    let value = reader.read_value(0)?;
+    if reader.pos != data.len() {
+        bail!("Trailing bytes after the MessagePack value");
+    }
+    serde_json::from_value(value).context("Failed to deserialize the message")
+}
+
+/// Writes a value
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(number) => write_number(out, number),
+        Value::String(string) => {
+            let len = string.len();
+            match len {
+                0..=31 => out.push(0xa0 | len as u8),
+                32..=0xff => out.extend_from_slice(&[0xd9, len as u8]),
+                0x100..=0xffff => {
+                    out.push(0xda);
+                    out.extend_from_slice(&(len as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(0xdb);
+                    out.extend_from_slice(&(len as u32).to_be_bytes());
+                }
+            }
+            out.extend_from_slice(string.as_bytes());
+        }
+        Value::Array(array) => {
+            write_len(out, array.len(), 0x90, 0xdc);
+            for item in array {
+                write_value(out, item);
+            }
+        }
+        Value::Object(map) => {
+            write_len(out, map.len(), 0x80, 0xde);
+            for (key, item) in map {
+                write_value(out, &Value::String(key.clone()));
+                write_value(out, item);
+            }
+        }
+    }
+}
+
+/// Writes the length of an array or a map (`fix`: the fixarray/fixmap prefix, `marker16`: the 16-bit marker)
+fn write_len(out: &mut Vec<u8>, len: usize, fix: u8, marker16: u8) {
+    match len {
+        0..=15 => out.push(fix | len as u8),
+        16..=0xffff => {
+            out.push(marker16);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(marker16 + 1);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+/// Writes a number in the smallest format
+fn write_number(out: &mut Vec<u8>, number: &Number) {
+    if let Some(n) = number.as_u64() {
+        match n {
+            0..=0x7f => out.push(n as u8),
+            0x80..=0xff => out.extend_from_slice(&[0xcc, n as u8]),
+            0x100..=0xffff => {
+                out.push(0xcd);
+                out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                out.push(0xce);
+                out.extend_from_slice(&(n as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xcf);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+    } else if let Some(n) = number.as_i64() {
+        // Negative (the positive ones are handled above)
+        if n >= -32 {
+            out.push(n as i8 as u8);
+        } else {
+            out.push(0xd3);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    } else {
+        out.push(0xcb);
+        out.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+    }
+}
+
+/// Reads values from a MessagePack buffer
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    /// Reads `len` bytes
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .context("Truncated MessagePack value")?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .context("Truncated MessagePack value")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Reads a big-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |n, &byte| (n << 8) | byte as u64))
+    }
+
+    /// Reads a UTF-8 string of `len` bytes
+    fn string(&mut self, len: usize) -> Result<Value> {
+        let bytes = self.take(len)?;
+        let string = std::str::from_utf8(bytes).context("Invalid UTF-8 in MessagePack string")?;
+        Ok(Value::String(string.to_string()))
+    }
+
+    /// Reads `len` values into an array
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value> {
+        let mut array = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            array.push(self.read_value(depth + 1)?);
+        }
+        Ok(Value::Array(array))
+    }
+
+    /// Reads `len` key-value pairs into a map (the keys must be strings)
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let Value::String(key) = self.read_value(depth + 1)? else {
+                bail!("Unsupported MessagePack map key (not a string)");
+            };
+            map.insert(key, self.read_value(depth + 1)?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Reads a value
+    fn read_value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("MessagePack value nested too deeply");
+        }
+        let marker = self.take(1)?[0];
+        let value = match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize)?,
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            // bin 8/16/32 (as an array of bytes)
+            0xc4..=0xc6 => {
+                let len = self.uint(1 << (marker - 0xc4))? as usize;
+                Value::from(self.take(len)?.to_vec())
+            }
+            0xca => Value::from(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => Value::from(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => Value::from(self.uint(1 << (marker - 0xcc))?),
+            0xd0 => Value::from(self.uint(1)? as u8 as i8),
+            0xd1 => Value::from(self.uint(2)? as u16 as i16),
+            0xd2 => Value::from(self.uint(4)? as u32 as i32),
+            0xd3 => Value::from(self.uint(8)? as i64),
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                self.string(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(2 << (marker - 0xdc))? as usize;
+                self.array(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.uint(2 << (marker - 0xde))? as usize;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            _ => bail!("Unsupported MessagePack type: 0x{marker:02x}"),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ClientCmd, ClientMessage, ServerCmd, ServerMessage, User};
+    use serde_json::json;
+
+    /// Encodes a value and checks that it decodes back to the same value
+    fn round_trip(value: &Value) -> Vec<u8> {
+        let data = to_vec(value).unwrap();
+        assert_eq!(&from_slice::<Value>(&data).unwrap(), value);
+        data
+    }
+
+    /// Array or map of `len` items, keyed by their index
+    fn container(len: usize, map: bool) -> Value {
+        if map {
+            Value::Object((0..len).map(|i| (i.to_string(), Value::Null)).collect())
+        } else {
+            Value::Array(vec![Value::Null; len])
+        }
+    }
+
+    #[test]
+    fn round_trips_client_messages() {
+        let message = ClientMessage {
+            id: "id".to_string(),
+            cmd: ClientCmd::Link {
+                url: "steam://rpi/1".to_string(),
+                max_uses: Some(3),
+                ttl: None,
+            },
+            key: Some("key".to_string()),
+        };
+        let decoded: ClientMessage = from_slice(&to_vec(&message).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(decoded).unwrap(),
+            serde_json::to_value(message).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_server_messages() {
+        let message = ServerMessage {
+            id: "id".to_string(),
+            user: Some(User {
+                id: "1".to_string(),
+                name: "name".to_string(),
+                steam_id: None,
+            }),
+            seq: Some(u64::MAX),
+            cmd: ServerCmd::Link {
+                game: 480,
+                max_uses: None,
+                ttl: Some(60),
+            },
+        };
+        let decoded: ServerMessage = from_slice(&to_vec(&message).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(decoded).unwrap(),
+            serde_json::to_value(message).unwrap()
+        );
+    }
+
+    #[test]
+    fn encodes_integers_in_the_smallest_width() {
+        for (n, marker) in [
+            (0x7f, 0x7f),
+            (0x80, 0xcc),
+            (0xff, 0xcc),
+            (0x100, 0xcd),
+            (0xffff, 0xcd),
+            (0x1_0000, 0xce),
+            (0xffff_ffff, 0xce),
+            (0x1_0000_0000, 0xcf),
+            (u64::MAX, 0xcf),
+        ] {
+            assert_eq!(round_trip(&json!(n))[0], marker, "{n}");
+        }
+        for (n, marker) in [(-1, 0xff), (-32, 0xe0), (-33, 0xd3), (i64::MIN, 0xd3)] {
+            assert_eq!(round_trip(&json!(n))[0], marker, "{n}");
+        }
+        assert_eq!(round_trip(&json!(1.5))[0], 0xcb);
+    }
+
+    #[test]
+    fn decodes_the_other_integer_widths() {
+        assert_eq!(from_slice::<Value>(&[0xd0, 0x80]).unwrap(), json!(-128));
+        assert_eq!(
+            from_slice::<Value>(&[0xd1, 0x80, 0x00]).unwrap(),
+            json!(-32768)
+        );
+        assert_eq!(
+            from_slice::<Value>(&[0xd2, 0x80, 0, 0, 0]).unwrap(),
+            json!(i32::MIN)
+        );
+        assert_eq!(
+            from_slice::<Value>(&[0xca, 0x3f, 0xc0, 0, 0]).unwrap(),
+            json!(1.5)
+        );
+        assert_eq!(
+            from_slice::<Value>(&[0xc4, 2, 1, 2]).unwrap(),
+            json!([1, 2])
+        );
+    }
+
+    #[test]
+    fn encodes_strings_in_the_smallest_width() {
+        for (len, header) in [
+            (31, &[0xbf][..]),
+            (32, &[0xd9, 32]),
+            (255, &[0xd9, 0xff]),
+            (256, &[0xda, 0x01, 0x00]),
+            (65535, &[0xda, 0xff, 0xff]),
+            (65536, &[0xdb, 0x00, 0x01, 0x00, 0x00]),
+        ] {
+            let data = round_trip(&Value::String("a".repeat(len)));
+            assert_eq!(&data[..header.len()], header, "{len}");
+            assert_eq!(data.len(), header.len() + len);
+        }
+    }
+
+    #[test]
+    fn encodes_arrays_and_maps_in_the_smallest_width() {
+        for (map, fix, marker16) in [(false, 0x90, 0xdc), (true, 0x80, 0xde)] {
+            for (len, header) in [
+                (15, vec![fix | 15]),
+                (16, vec![marker16, 0x00, 16]),
+                (255, vec![marker16, 0x00, 0xff]),
+                (256, vec![marker16, 0x01, 0x00]),
+                (65535, vec![marker16, 0xff, 0xff]),
+                (65536, vec![marker16 + 1, 0x00, 0x01, 0x00, 0x00]),
+            ] {
+                let data = round_trip(&container(len, map));
+                assert_eq!(&data[..header.len()], header, "{len}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_values() {
+        let data = to_vec(&json!({ "cmd": "link", "game": 480, "list": [1, "a"] })).unwrap();
+        for len in 0..data.len() {
+            assert!(from_slice::<Value>(&data[..len]).is_err(), "{len}");
+        }
+        // Lengths beyond the end of the data
+        assert!(from_slice::<Value>(&[0xdb, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(from_slice::<Value>(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(from_slice::<Value>(&[0xc6, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        assert!(from_slice::<Value>(&[0xc0, 0xc0]).is_err());
+    }
+
+    #[test]
+    fn limits_the_nesting() {
+        let nested = |depth| [vec![0x91; depth], vec![0xc0]].concat();
+        assert!(from_slice::<Value>(&nested(MAX_DEPTH)).is_ok());
+        assert!(from_slice::<Value>(&nested(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_non_string_keys() {
+        assert!(from_slice::<Value>(&[0x81, 0x01, 0xc0]).is_err());
+        assert!(from_slice::<Value>(&[0x81, 0xc0, 0xc0]).is_err());
+        // Invalid UTF-8 and unused markers
+        assert!(from_slice::<Value>(&[0xa1, 0xff]).is_err());
+        assert!(from_slice::<Value>(&[0xc1]).is_err());
+    }
+}
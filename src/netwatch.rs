@@ -0,0 +1,89 @@
+use std::{
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::Notify,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{console, i18n::t};
+
+/// Interval between checks of the network and the clock
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// A check this late means the machine was asleep in between
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(15);
+
+/// Public addresses used to find the local address of the default route
+/// (connecting a UDP socket sends nothing)
+const PROBE_V4: &str = "192.0.2.1:9";
+const PROBE_V6: &str = "[2001:db8::1]:9";
+
+/**
+ * Starts watching for the machine resuming from sleep and for network changes (once per process)
+ * @return Notified whenever the connection to the server should be re-established
+ */
+pub fn watch() -> Arc<Notify> {
+    static CHANGED: OnceLock<Arc<Notify>> = OnceLock::new();
+    CHANGED
+        .get_or_init(|| {
+            let changed = Arc::new(Notify::new());
+            tokio::spawn(run(changed.clone()));
+            changed
+        })
+        .clone()
+}
+
+/// Local addresses the machine would use to reach the internet (IPv4, IPv6)
+fn local_addrs() -> (Option<IpAddr>, Option<IpAddr>) {
+    let local_addr = |bind: &str, probe: &str| {
+        let socket = UdpSocket::bind(bind).ok()?;
+        socket.connect(probe.parse::<SocketAddr>().ok()?).ok()?;
+        Some(socket.local_addr().ok()?.ip())
+    };
+    (
+        local_addr("0.0.0.0:0", PROBE_V4),
+        local_addr("[::]:0", PROBE_V6),
+    )
+}
+
+/// Displays a message (failures to write to the console are ignored)
+fn announce(message: &str) {
+    let _: anyhow::Result<()> = (|| {
+        console::println!("{message}");
+        Ok(())
+    })();
+}
+
+/// Polls the local addresses and the clocks
+async fn run(changed: Arc<Notify>) {
+    let mut interval = time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut addrs = local_addrs();
+    let mut last_check = (Instant::now(), SystemTime::now());
+
+    loop {
+        interval.tick().await;
+
+        // The monotonic clock stops while asleep on some platforms and timers are late on others
+        let monotonic = last_check.0.elapsed();
+        let wall = last_check.1.elapsed().unwrap_or_default();
+        last_check = (Instant::now(), SystemTime::now());
+        if monotonic.max(wall) > POLL_INTERVAL + SLEEP_THRESHOLD {
+            tracing::info!(asleep_secs = wall.as_secs(), "Resumed from sleep");
+            announce(&format!("↪ {}", t!("resumed-from-sleep")));
+            addrs = local_addrs();
+            changed.notify_one();
+            continue;
+        }
+
+        let new_addrs = local_addrs();
+        if new_addrs != addrs {
+            tracing::info!(from = ?addrs, to = ?new_addrs, "Network changed");
+            addrs = new_addrs;
+            announce(&format!("↪ {}", t!("network-changed")));
+            changed.notify_one();
+        }
+    }
+}
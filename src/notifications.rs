@@ -0,0 +1,124 @@
+use anyhow::{Context as _, Result};
+use tokio::{process::Command, sync::broadcast::error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{self, Event};
+
+/// Application name shown in the notifications
+const APP_NAME: &str = "Remote Play Inviter";
+
+/// Shows desktop notifications for guests joining or leaving and new invite links until `shutdown` is cancelled
+pub async fn run(shutdown: CancellationToken) {
+    let mut events = events::subscribe();
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = shutdown.cancelled() => return,
+        };
+
+        let (title, body) = match event {
+            Event::GuestJoined { name, .. } => ("Guest joined".to_string(), name),
+            Event::GuestLeft { name, .. } => ("Guest left".to_string(), name),
+            Event::InviteCreated { url, .. } => ("Invite link created".to_string(), url),
+            Event::Announcement { title, text, .. } => {
+                (title.unwrap_or_else(|| "Announcement".to_string()), text)
+            }
+            Event::SessionEnding { minutes_left } => (
+                "Session ending".to_string(),
+                format!("{minutes_left} minutes of play left"),
+            ),
+            _ => continue,
+        };
+        // Don't hold up the following events while the notification is shown
+        tokio::spawn(async move {
+            if let Err(err) = notify(&title, &body).await {
+                tracing::warn!("{err:#}");
+            }
+        });
+    }
+}
+
+/// Shows a notification with `notify-send`
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn notify(title: &str, body: &str) -> Result<()> {
+    run_command(
+        Command::new("notify-send")
+            .args(["--app-name", APP_NAME])
+            .arg(title)
+            .arg(body),
+    )
+    .await
+}
+
+/// Shows a notification in the notification center
+#[cfg(target_os = "macos")]
+async fn notify(title: &str, body: &str) -> Result<()> {
+    // Pass the texts as arguments so that they are not interpreted as AppleScript
+    run_command(
+        Command::new("osascript")
+            .args([
+                "-e",
+                "on run argv",
+                "-e",
+                "display notification (item 3 of argv) with title (item 1 of argv) subtitle (item 2 of argv)",
+                "-e",
+                "end run",
+            ])
+            .arg(APP_NAME)
+            .arg(title)
+            .arg(body),
+    )
+    .await
+}
+
+/// Shows a toast notification through PowerShell
+#[cfg(windows)]
+async fn notify(title: &str, body: &str) -> Result<()> {
+    /// Toast script (the texts are passed as environment variables so that they are not interpreted as PowerShell)
+    const SCRIPT: &str = indoc::indoc! {r#"
+        [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null
+        $template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+        $texts = $template.GetElementsByTagName('text')
+        $texts.Item(0).AppendChild($template.CreateTextNode($env:RPI_NOTIFY_TITLE)) > $null
+        $texts.Item(1).AppendChild($template.CreateTextNode($env:RPI_NOTIFY_BODY)) > $null
+        $toast = [Windows.UI.Notifications.ToastNotification]::new($template)
+        [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe').Show($toast)
+    "#};
+    /// Don't flash a console window
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    run_command(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .env("RPI_NOTIFY_TITLE", format!("{APP_NAME}: {title}"))
+            .env("RPI_NOTIFY_BODY", body)
+            .creation_flags(CREATE_NO_WINDOW),
+    )
+    .await
+}
+
+/// Runs a notification command and checks its exit status
+async fn run_command(command: &mut Command) -> Result<()> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let output = command
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to show a notification with {program}"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to show a notification with {program}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
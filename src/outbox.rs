@@ -0,0 +1,122 @@
+use anyhow::{Context as _, Result};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use crate::models::ClientMessage;
+
+/// Default maximum number of messages waiting to be sent
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Messages to the server waiting to be sent, kept across reconnects
+pub struct Outbox {
+    queue: VecDeque<ClientMessage>,
+    capacity: usize,
+    /// File the unsent messages are saved to (None: kept in memory only)
+    spill: Option<PathBuf>,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, None)
+    }
+}
+
+impl Outbox {
+    /// Creates an outbox, loading the messages left unsent by the previous run from `spill`
+    pub fn new(capacity: usize, spill: Option<PathBuf>) -> Self {
+        let mut outbox = Self {
+            queue: VecDeque::new(),
+            capacity: capacity.max(1),
+            spill,
+        };
+        if let Some(path) = &outbox.spill {
+            match load(path) {
+                Ok(messages) => outbox.queue.extend(messages),
+                Err(err) => tracing::warn!("{err:#}"),
+            }
+            outbox.trim();
+        }
+        outbox
+    }
+
+    /// Number of messages waiting to be sent
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether all the messages were sent
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues a message, giving it an idempotency key so that the server can drop a resent copy
+    pub fn push(&mut self, mut message: ClientMessage) {
+        message
+            .key
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
+        self.queue.push_back(message);
+        self.trim();
+    }
+
+    /// Next message to send
+    pub fn front(&self) -> Option<&ClientMessage> {
+        self.queue.front()
+    }
+
+    /// Removes the message that was sent
+    pub fn pop(&mut self) {
+        self.queue.pop_front();
+    }
+
+    /// Drops the oldest messages beyond the capacity
+    fn trim(&mut self) {
+        while self.queue.len() > self.capacity {
+            if let Some(message) = self.queue.pop_front() {
+                tracing::warn!(
+                    id = message.id,
+                    "Outbox full, dropped a message to the server"
+                );
+            }
+        }
+    }
+
+    /// Saves the unsent messages to the spill file (removed once everything was sent)
+    pub fn save(&self) {
+        let Some(path) = &self.spill else {
+            return;
+        };
+        let result = if self.queue.is_empty() {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
+        } else {
+            let lines: Vec<String> = self
+                .queue
+                .iter()
+                .filter_map(|message| serde_json::to_string(message).ok())
+                .collect();
+            fs::write(path, lines.join("\n") + "\n")
+        };
+        if let Err(err) = result {
+            tracing::warn!("Unable to write the outbox file {:?}: {err}", path);
+        }
+    }
+}
+
+/// Reads the messages saved by a previous run
+fn load(path: &Path) -> Result<Vec<ClientMessage>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Unable to read file: {:?}", path))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context as _, Result};
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// Get the home directory of the user
+pub fn home_dir() -> Result<PathBuf> {
+    #[cfg(windows)]
+    let home = env::var_os("USERPROFILE");
+    #[cfg(not(windows))]
+    let home = env::var_os("HOME");
+    match home {
+        Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+        _ => anyhow::bail!("Unable to find the home directory"),
+    }
+}
+
+/// Get the user configuration directory of the XDG base directory specification (`$XDG_CONFIG_HOME`)
+pub fn xdg_config_home() -> Result<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => Ok(home_dir()?.join(".config")),
+    }
+}
+
+/// Whether the Steam Deck Gaming Mode is running (Steam's Big Picture UI in a gamescope session)
+pub fn gaming_mode() -> bool {
+    let gamescope = env::var_os("XDG_CURRENT_DESKTOP")
+        .is_some_and(|desktop| desktop.eq_ignore_ascii_case("gamescope"));
+    gamescope
+        || env::var_os("SteamGamepadUI").is_some()
+        || env::var_os("GAMESCOPE_WAYLAND_DISPLAY").is_some()
+}
+
+/// Whether the terminal can display the status glyphs and box-drawing characters
+pub fn unicode_console() -> bool {
+    #[cfg(windows)]
+    {
+        // Windows Terminal and VS Code render them, the legacy console only with the UTF-8 code page
+        if env::var_os("WT_SESSION").is_some()
+            || env::var("TERM_PROGRAM").is_ok_and(|program| program == "vscode")
+        {
+            return true;
+        }
+        let Ok(output) = Command::new("cmd").args(["/c", "chcp"]).output() else {
+            return true;
+        };
+        // `Active code page: 65001` (the text is localized)
+        let output = String::from_utf8_lossy(&output.stdout);
+        let code_page = output
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|number| !number.is_empty())
+            .last();
+        code_page.map_or(true, |code_page| code_page == "65001")
+    }
+    #[cfg(not(windows))]
+    {
+        // The character set of the locale (`C` and `POSIX` are ASCII, no locale at all is left alone)
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty());
+        locale.map_or(true, |locale| {
+            let locale = locale.to_ascii_lowercase().replace('-', "");
+            locale.contains("utf8")
+        })
+    }
+}
+
+/// Opens a URL with the handler registered on the system (the browser, Steam or Discord)
+pub fn open_url(url: &str) -> Result<()> {
+    let mut child = opener(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Unable to open URL: {url}"))?;
+    // Reap the launcher without waiting for it
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Command opening a URL
+#[cfg(windows)]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("rundll32");
+    command.args(["url.dll,FileProtocolHandler", url]);
+    command
+}
+
+/// Command opening a URL
+#[cfg(target_os = "macos")]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("open");
+    command.arg(url);
+    command
+}
+
+/// Command opening a URL
+#[cfg(all(unix, not(target_os = "macos")))]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(url);
+    command
+}
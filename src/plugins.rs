@@ -0,0 +1,243 @@
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    process::Stdio,
+    sync::Mutex,
+};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    sync::{
+        broadcast,
+        mpsc::{channel, Sender},
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::PluginConfig,
+    console,
+    control::{self, ControlRequest, RpcResponse},
+    events::Event,
+    hooks,
+    i18n::t,
+};
+
+/// Console commands registered by the plugins (by name)
+static COMMANDS: Mutex<BTreeMap<String, PluginCommand>> = Mutex::new(BTreeMap::new());
+
+/// A console command registered by a plugin
+struct PluginCommand {
+    /// Name of the plugin
+    plugin: String,
+    /// Description shown by `help`
+    help: String,
+    /// Arguments of the command, sent to the plugin
+    tx: Sender<(String, String)>,
+}
+
+/// Parameters of `register_command`
+#[derive(Deserialize)]
+struct RegisterParams {
+    name: String,
+    #[serde(default)]
+    help: String,
+}
+
+/// Parameters of `print`
+#[derive(Deserialize)]
+struct PrintParams {
+    text: String,
+}
+
+/// Starts the plugins, which run until `shutdown` is cancelled
+///
+/// A plugin speaks the JSON-RPC of the control API on its stdin and stdout (one message per line):
+/// it can `subscribe` to the events and call `create_invite`, `list_guests`, ... It can also call
+/// `register_command` (`{"name": "roll", "help": "Roll a die"}`) to add a console command, sent back
+/// as a `command` notification (`{"name": "roll", "args": "1d6"}`), and `print` (`{"text": "..."}`).
+pub fn spawn(
+    plugins: Vec<PluginConfig>,
+    control_tx: Sender<ControlRequest>,
+    shutdown: CancellationToken,
+) {
+    for plugin in plugins {
+        let control_tx = control_tx.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let name = plugin.name.clone();
+            if let Err(err) = run(plugin, control_tx, shutdown).await {
+                let _: Result<()> = (|| {
+                    console::eprintln!(
+                        "☓ {}",
+                        t!("plugin-failed", name = name, error = format!("{err:#}"))
+                    );
+                    Ok(())
+                })();
+            }
+            COMMANDS
+                .lock()
+                .unwrap()
+                .retain(|_, command| command.plugin != name);
+        });
+    }
+}
+
+/// Whether a plugin registered a console command
+pub fn has_command(name: &str) -> bool {
+    COMMANDS.lock().unwrap().contains_key(name)
+}
+
+/// Gets the console commands registered by the plugins (name, description)
+pub fn commands() -> Vec<(String, String)> {
+    COMMANDS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, command)| (name.clone(), command.help.clone()))
+        .collect()
+}
+
+/**
+ * Sends a console command to the plugin that registered it
+ * @return An error if the plugin has stopped or is busy
+ */
+pub fn run_command(name: &str, args: &str) -> Result<()> {
+    let commands = COMMANDS.lock().unwrap();
+    let command = commands
+        .get(name)
+        .with_context(|| format!("Unknown command: {name}"))?;
+    command
+        .tx
+        .try_send((name.to_string(), args.to_string()))
+        .with_context(|| format!("The plugin is not responding: {}", command.plugin))
+}
+
+/// Runs a plugin, answering its requests until it exits or `shutdown` is cancelled
+async fn run(
+    plugin: PluginConfig,
+    control_tx: Sender<ControlRequest>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut child = hooks::shell(&plugin.command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start the plugin: {}", plugin.command))?;
+    let mut stdin = child.stdin.take().context("The plugin has no stdin")?;
+    let mut stdout =
+        BufReader::new(child.stdout.take().context("The plugin has no stdout")?).lines();
+    let mut stderr =
+        BufReader::new(child.stderr.take().context("The plugin has no stderr")?).lines();
+    tracing::info!(plugin = plugin.name, "Started a plugin");
+
+    let (command_tx, mut command_rx) = channel::<(String, String)>(8);
+    let mut events: Option<broadcast::Receiver<Event>> = None;
+    loop {
+        let message = tokio::select! {
+            line = stdout.next_line() => {
+                let Some(line) = line.context("Failed to read from the plugin")? else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = handle_request(&plugin.name, &line, &command_tx, &control_tx, &mut events).await;
+                serde_json::to_value(&response)?
+            }
+            Ok(Some(line)) = stderr.next_line() => {
+                tracing::warn!(plugin = plugin.name, "{line}");
+                continue;
+            }
+            event = control::next_event(&mut events) => {
+                json!({ "jsonrpc": "2.0", "method": "event", "params": event })
+            }
+            Some((name, args)) = command_rx.recv() => {
+                json!({ "jsonrpc": "2.0", "method": "command", "params": { "name": name, "args": args } })
+            }
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+        stdin
+            .write_all(format!("{message}\n").as_bytes())
+            .await
+            .context("Failed to write to the plugin")?;
+    }
+
+    let status = child.wait().await?;
+    tracing::info!(plugin = plugin.name, %status, "A plugin exited");
+    if !status.success() {
+        anyhow::bail!("The plugin exited with {status}");
+    }
+    Ok(())
+}
+
+/// Answers the plugin methods, forwarding the others to the control API
+async fn handle_request(
+    plugin: &str,
+    line: &str,
+    command_tx: &Sender<(String, String)>,
+    control_tx: &Sender<ControlRequest>,
+    events: &mut Option<broadcast::Receiver<Event>>,
+) -> RpcResponse {
+    let request: HashMap<String, Value> = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return RpcResponse::error(Value::Null, -32700, format!("Parse error: {err}")),
+    };
+    let id = request.get("id").cloned().unwrap_or_default();
+    let params = request.get("params").cloned().unwrap_or_default();
+    match request.get("method").and_then(Value::as_str) {
+        Some("register_command") => {
+            let params: RegisterParams = match serde_json::from_value(params) {
+                Ok(params) => params,
+                Err(err) => {
+                    return RpcResponse::error(id, -32602, format!("Invalid params: {err}"))
+                }
+            };
+            if params.name.is_empty() || params.name.contains(char::is_whitespace) {
+                return RpcResponse::error(id, -32602, "Invalid command name");
+            }
+            let mut commands = COMMANDS.lock().unwrap();
+            if commands
+                .get(&params.name)
+                .is_some_and(|command| command.plugin != plugin)
+            {
+                return RpcResponse::error(
+                    id,
+                    -32000,
+                    "The command is registered by another plugin",
+                );
+            }
+            tracing::info!(
+                plugin,
+                command = params.name,
+                "A plugin registered a console command"
+            );
+            commands.insert(
+                params.name,
+                PluginCommand {
+                    plugin: plugin.to_string(),
+                    help: params.help,
+                    tx: command_tx.clone(),
+                },
+            );
+            RpcResponse::result(id, Value::Bool(true))
+        }
+        Some("print") => {
+            let params: PrintParams = match serde_json::from_value(params) {
+                Ok(params) => params,
+                Err(err) => {
+                    return RpcResponse::error(id, -32602, format!("Invalid params: {err}"))
+                }
+            };
+            let _: Result<()> = (|| {
+                console::println!("□ [{plugin}] {}", params.text);
+                Ok(())
+            })();
+            RpcResponse::result(id, Value::Bool(true))
+        }
+        _ => control::handle_request(line, control_tx, events).await,
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::{bail, Context as _, Result};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    sync::broadcast::error::RecvError,
+    time::{interval, timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::events::{self, Event};
+
+/// Interval between attempts to reach the Discord client when it isn't running
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+/// Time to wait for the Discord client to answer
+const IPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Opcode of the handshake frame
+const OP_HANDSHAKE: u32 = 0;
+/// Opcode of a command frame
+const OP_FRAME: u32 = 1;
+
+/// Connection to the local Discord client
+trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> IpcStream for T {}
+
+/// What the presence shows
+#[derive(Default)]
+struct Session {
+    /// Number of guests in the session
+    guests: usize,
+    /// Last invite link
+    invite_url: Option<String>,
+    /// When the first guest joined (seconds since the epoch)
+    started_at: Option<i64>,
+}
+
+impl Session {
+    /**
+     * Builds the Rich Presence activity
+     * @return None when nothing is hosted
+     */
+    fn activity(&self, max_guests: Option<usize>) -> Option<Value> {
+        if self.guests == 0 && self.invite_url.is_none() {
+            return None;
+        }
+        let guests = match max_guests {
+            Some(max) => format!("{}/{max} guests", self.guests),
+            None if self.guests == 1 => "1 guest".to_string(),
+            None => format!("{} guests", self.guests),
+        };
+        let mut activity = json!({
+            "details": "Hosting Remote Play Together",
+            "state": match self.invite_url {
+                Some(_) => format!("{guests}, click to join"),
+                None => guests,
+            },
+        });
+        if let Some(started_at) = self.started_at {
+            activity["timestamps"] = json!({ "start": started_at });
+        }
+        if let Some(url) = &self.invite_url {
+            activity["buttons"] = json!([{ "label": "Join", "url": url }]);
+        }
+        Some(activity)
+    }
+}
+
+/// Shows the hosted session in the Discord profile (Rich Presence) until `shutdown` is cancelled
+pub async fn run(client_id: String, max_guests: Option<usize>, shutdown: CancellationToken) {
+    let mut events = events::subscribe();
+    let mut session = Session::default();
+    let mut ipc: Option<Box<dyn IpcStream>> = None;
+    let mut reconnect = interval(RECONNECT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Ok(Event::InviteCreated { url, .. }) => session.invite_url = Some(url),
+                    Ok(Event::GuestJoined { guests, .. }) => {
+                        session.guests = guests;
+                        session.started_at.get_or_insert_with(|| chrono::Utc::now().timestamp());
+                    }
+                    Ok(Event::GuestLeft { guests, .. }) => {
+                        session.guests = guests;
+                        if guests == 0 {
+                            session.started_at = None;
+                        }
+                    }
+                    Ok(Event::SteamLost) => session = Session::default(),
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+            _ = reconnect.tick(), if ipc.is_none() => (),
+            _ = shutdown.cancelled() => {
+                // Don't leave the session shown after exiting
+                if let Some(stream) = &mut ipc {
+                    let _ = set_activity(stream, None).await;
+                }
+                return;
+            }
+        }
+
+        // Connect to the Discord client (it may have been started since the last attempt)
+        if ipc.is_none() {
+            match connect(&client_id).await {
+                Ok(stream) => {
+                    tracing::info!("Connected to the Discord client for Rich Presence");
+                    ipc = Some(stream);
+                }
+                Err(err) => {
+                    tracing::debug!("Discord client not available: {err:#}");
+                    continue;
+                }
+            }
+        }
+        if let Some(stream) = &mut ipc {
+            if let Err(err) = set_activity(stream, session.activity(max_guests)).await {
+                tracing::debug!("Lost the connection to the Discord client: {err:#}");
+                ipc = None;
+            }
+        }
+    }
+}
+
+/// Connects to the Discord client and identifies as the application
+async fn connect(client_id: &str) -> Result<Box<dyn IpcStream>> {
+    let mut stream = open().await?;
+    let handshake = json!({ "v": 1, "client_id": client_id });
+    timeout(IPC_TIMEOUT, async {
+        write_frame(&mut stream, OP_HANDSHAKE, &handshake).await?;
+        // The client answers with a READY event
+        let ready = read_frame(&mut stream).await?;
+        if ready["evt"] != "READY" {
+            bail!(
+                "Discord refused the connection: {}",
+                ready["data"]["message"]
+            );
+        }
+        Ok(())
+    })
+    .await
+    .context("The Discord client did not answer")??;
+    Ok(stream)
+}
+
+/// Sets the activity shown in the profile (None: clears it)
+async fn set_activity(stream: &mut Box<dyn IpcStream>, activity: Option<Value>) -> Result<()> {
+    let command = json!({
+        "cmd": "SET_ACTIVITY",
+        "args": { "pid": std::process::id(), "activity": activity },
+        "nonce": Uuid::new_v4().to_string(),
+    });
+    timeout(IPC_TIMEOUT, async {
+        write_frame(stream, OP_FRAME, &command).await?;
+        let response = read_frame(stream).await?;
+        if response["evt"] == "ERROR" {
+            // Rejected activities (e.g. an invite URL Discord doesn't accept) don't close the connection
+            tracing::warn!(
+                "Discord rejected the Rich Presence: {}",
+                response["data"]["message"]
+            );
+        }
+        Ok(())
+    })
+    .await
+    .context("The Discord client did not answer")?
+}
+
+/// Writes a frame (opcode and length in little endian, then the JSON payload)
+async fn write_frame(stream: &mut Box<dyn IpcStream>, opcode: u32, payload: &Value) -> Result<()> {
+    let payload = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&opcode.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+    stream.write_all(&frame).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads a frame and parses its JSON payload
+async fn read_frame(stream: &mut Box<dyn IpcStream>) -> Result<Value> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).await?;
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > 64 * 1024 {
+        bail!("Discord IPC frame too large ({len} bytes)");
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    serde_json::from_slice(&payload).context("Invalid Discord IPC frame")
+}
+
+/// Opens the IPC socket of the Discord client (`discord-ipc-0` to `discord-ipc-9`)
+#[cfg(unix)]
+async fn open() -> Result<Box<dyn IpcStream>> {
+    let dirs: Vec<std::path::PathBuf> = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(Into::into)
+        .chain([std::path::PathBuf::from("/tmp")])
+        .collect();
+    // Also look where the Flatpak and Snap packages of Discord put the socket
+    let subdirs = ["", "app/com.discordapp.Discord", "snap.discord"];
+    for dir in &dirs {
+        for subdir in subdirs {
+            for i in 0..10 {
+                let path = dir.join(subdir).join(format!("discord-ipc-{i}"));
+                if let Ok(stream) = tokio::net::UnixStream::connect(&path).await {
+                    return Ok(Box::new(stream));
+                }
+            }
+        }
+    }
+    bail!("The Discord client is not running")
+}
+
+/// Opens the IPC pipe of the Discord client (`discord-ipc-0` to `discord-ipc-9`)
+#[cfg(windows)]
+async fn open() -> Result<Box<dyn IpcStream>> {
+    for i in 0..10 {
+        let path = format!(r"\\.\pipe\discord-ipc-{i}");
+        if let Ok(pipe) = tokio::net::windows::named_pipe::ClientOptions::new().open(&path) {
+            return Ok(Box::new(pipe));
+        }
+    }
+    bail!("The Discord client is not running")
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context as _, Result};
+use std::fs;
+
+use remoteplay_inviter::{
+    config::{self, EndpointConfig},
+    console,
+    i18n::t,
+};
+
+use crate::{
+    cli::{Cli, ProfileAction},
+    new_config,
+};
+
+/// Run a `profile` subcommand
+pub fn profile(cli: &Cli, action: &ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List => list(),
+        ProfileAction::Create { name } => create(cli, name),
+        ProfileAction::Delete { name } => delete(name),
+    }
+}
+
+/// Display the profiles
+fn list() -> Result<()> {
+    let profiles = config::list_profiles()?;
+    console::println!(
+        "□ {}",
+        t!("profiles-dir", path = config::profiles_dir()?.display())
+    );
+    if profiles.is_empty() {
+        console::println!("  {}", t!("profiles-none"));
+    }
+    for name in profiles {
+        let dir = config::profile_dir(&name)?;
+        let endpoint = fs::read_to_string(dir.join(config::PROFILE_ENDPOINT_FILE_NAME))
+            .ok()
+            .and_then(|content| toml::from_str::<EndpointConfig>(&content).ok())
+            .and_then(|endpoint| endpoint.url)
+            .unwrap_or_else(|| "(default endpoint)".to_string());
+        let active = if config::profile() == Some(name.as_str()) {
+            "★"
+        } else {
+            " "
+        };
+        console::println!("{active} {name:<16}: {endpoint}");
+    }
+    Ok(())
+}
+
+/// Create a profile with a new UUID
+fn create(cli: &Cli, name: &str) -> Result<()> {
+    let dir = config::profile_dir(name)?;
+    if dir.exists() {
+        anyhow::bail!("The profile already exists: {name}");
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("Unable to create directory: {:?}", &dir))?;
+
+    let config_path = dir.join(config::PROFILE_CONFIG_FILE_NAME);
+    let config_content = toml::to_string(&new_config()).context("Unable to serialize config")?;
+    fs::write(&config_path, config_content)
+        .with_context(|| format!("Unable to write config file: {:?}", &config_path))?;
+
+    // Remember the endpoint so that it doesn't have to be given on every run
+    if let Some(url) = &cli.endpoint {
+        let endpoint_path = dir.join(config::PROFILE_ENDPOINT_FILE_NAME);
+        let endpoint = EndpointConfig {
+            url: Some(url.clone()),
+            urls: vec![],
+            proxy: None,
+            bearer_auth: false,
+            network: Default::default(),
+            tls: Default::default(),
+        };
+        let endpoint_content =
+            toml::to_string(&endpoint).context("Unable to serialize endpoint config")?;
+        fs::write(&endpoint_path, endpoint_content).with_context(|| {
+            format!("Unable to write endpoint config file: {:?}", &endpoint_path)
+        })?;
+    }
+
+    console::println!(
+        "✓ {}",
+        t!("profile-created", name = name, path = dir.display())
+    );
+    console::println!("  {}", t!("profile-run-hint", name = name));
+    Ok(())
+}
+
+/// Delete a profile
+fn delete(name: &str) -> Result<()> {
+    let dir = config::profile_dir(name)?;
+    if !dir.exists() {
+        anyhow::bail!("The profile does not exist: {name}");
+    }
+    fs::remove_dir_all(&dir).with_context(|| format!("Unable to remove directory: {:?}", &dir))?;
+    console::println!("✓ {}", t!("profile-deleted", name = name));
+    Ok(())
+}
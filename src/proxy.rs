@@ -0,0 +1,169 @@
+use anyhow::{bail, Context as _, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::{env, fmt, str::FromStr};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::TcpStream,
+};
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::tungstenite::http::Uri;
+
+/// Environment variables to read the proxy from (in order of priority)
+const PROXY_ENVS: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+
+/// Proxy protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// HTTP proxy using the CONNECT method
+    Http,
+    /// SOCKS5 proxy
+    Socks5,
+}
+
+/// Proxy server to tunnel the connection through
+#[derive(Clone)]
+pub struct Proxy {
+    /// Proxy protocol
+    pub kind: ProxyKind,
+    /// Proxy host
+    pub host: String,
+    /// Proxy port
+    pub port: u16,
+    /// Username and password
+    pub auth: Option<(String, String)>,
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Do not display the credentials
+        let scheme = match self.kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5",
+        };
+        write!(f, "{scheme}://{}:{}", self.host, self.port)
+    }
+}
+
+impl FromStr for Proxy {
+    type Err = anyhow::Error;
+
+    /// Parses `http://[user:pass@]host[:port]` or `socks5://[user:pass@]host[:port]`
+    fn from_str(s: &str) -> Result<Self> {
+        // Treat URLs without a scheme as HTTP proxies
+        let s = if s.contains("://") {
+            s.to_string()
+        } else {
+            format!("http://{s}")
+        };
+        let uri: Uri = s.parse().context("Failed to parse proxy URL")?;
+
+        let (kind, default_port) = match uri.scheme_str() {
+            Some("http") => (ProxyKind::Http, 80),
+            Some("socks5" | "socks5h") => (ProxyKind::Socks5, 1080),
+            Some(scheme) => bail!("Unsupported proxy scheme: {scheme}"),
+            None => bail!("Missing proxy scheme"),
+        };
+        let authority = uri.authority().context("Missing proxy host")?;
+
+        // Credentials in `user:pass@host`
+        let auth = authority.as_str().rsplit_once('@').map(|(userinfo, _)| {
+            match userinfo.split_once(':') {
+                Some((user, pass)) => (user.to_string(), pass.to_string()),
+                None => (userinfo.to_string(), String::new()),
+            }
+        });
+
+        Ok(Proxy {
+            kind,
+            host: authority.host().to_string(),
+            port: authority.port_u16().unwrap_or(default_port),
+            auth,
+        })
+    }
+}
+
+impl Proxy {
+    /// Reads the proxy from the `HTTPS_PROXY` / `ALL_PROXY` environment variables
+    pub fn from_env() -> Result<Option<Proxy>> {
+        for name in PROXY_ENVS {
+            if let Ok(value) = env::var(name) {
+                if value.is_empty() {
+                    continue;
+                }
+                let proxy = value
+                    .parse()
+                    .with_context(|| format!("Invalid proxy in {name}"))?;
+                return Ok(Some(proxy));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Opens a tunnel to the target through the proxy
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        match self.kind {
+            ProxyKind::Http => self.connect_http(host, port).await,
+            ProxyKind::Socks5 => self.connect_socks5(host, port).await,
+        }
+    }
+
+    /// Opens a tunnel with the HTTP CONNECT method
+    async fn connect_http(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("Failed to connect to the proxy: {self}"))?;
+
+        // Send the CONNECT request
+        let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+        if let Some((user, pass)) = &self.auth {
+            let credentials = BASE64.encode(format!("{user}:{pass}"));
+            request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .context("Failed to send the CONNECT request to the proxy")?;
+
+        // Read the response headers
+        let mut reader = BufReader::new(&mut stream);
+        let mut status_line = String::new();
+        reader
+            .read_line(&mut status_line)
+            .await
+            .context("Failed to read the CONNECT response from the proxy")?;
+        loop {
+            let mut line = String::new();
+            let read = reader
+                .read_line(&mut line)
+                .await
+                .context("Failed to read the CONNECT response from the proxy")?;
+            if read == 0 || line == "\r\n" || line == "\n" {
+                break;
+            }
+        }
+
+        // Check the status code (e.g. `HTTP/1.1 200 Connection established`)
+        let status = status_line.split_whitespace().nth(1);
+        if status != Some("200") {
+            bail!("Proxy refused the connection: {}", status_line.trim());
+        }
+
+        Ok(stream)
+    }
+
+    /// Opens a tunnel with the SOCKS5 protocol
+    async fn connect_socks5(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let proxy = (self.host.as_str(), self.port);
+        let target = (host, port);
+        let stream = match &self.auth {
+            Some((user, pass)) => {
+                Socks5Stream::connect_with_password(proxy, target, user, pass).await
+            }
+            None => Socks5Stream::connect(proxy, target).await,
+        }
+        .with_context(|| format!("Failed to connect through the proxy: {self}"))?;
+
+        Ok(stream.into_inner())
+    }
+}
@@ -1,20 +1,155 @@
+use rand::Rng as _;
+
+use std::time::Duration;
+
+/// Reconnection policy
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Factor the delay is multiplied by after each attempt
+    pub multiplier: f64,
+    /// Maximum seconds to wait between attempts
+    pub max_backoff: u64,
+    /// Maximum number of consecutive attempts (None: retry forever)
+    pub max_retries: Option<u32>,
+    /// Random variation applied to the delay (0.0 - 1.0)
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            multiplier: 2.0,
+            max_backoff: 120,
+            max_retries: None,
+            jitter: 0.2,
+        }
+    }
+}
+
 /// Retry seconds
-pub struct RetrySec(u64);
+#[derive(Clone)]
+pub struct RetrySec {
+    sec: f64,
+    attempts: u32,
+    policy: RetryPolicy,
+}
 
 impl RetrySec {
     /// Creates a new RetrySec with an initial value of 1 second
     pub fn new() -> Self {
-        Self(1)
+        Self::with_policy(RetryPolicy::default())
     }
 
-    /// Doubles the retry seconds, capping at 60 seconds
-    pub fn next(&mut self) -> u64 {
-        self.0 = self.0.min(60) * 2;
-        self.0
+    /// Creates a new RetrySec following the given policy
+    pub fn with_policy(policy: RetryPolicy) -> Self {
+        Self {
+            sec: 1.0,
+            attempts: 0,
+            policy,
+        }
+    }
+
+    /**
+     * Multiplies the retry seconds, capping at the maximum backoff, and applies the jitter
+     * @return The delay before the next attempt, or None if the maximum number of attempts has been reached
+     */
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Duration> {
+        if self
+            .policy
+            .max_retries
+            .is_some_and(|max_retries| self.attempts >= max_retries)
+        {
+            return None;
+        }
+        self.attempts += 1;
+        let max_backoff = self.policy.max_backoff.max(1) as f64;
+        self.sec = (self.sec * self.policy.multiplier.max(1.0)).min(max_backoff);
+
+        // Spread out the reconnections so that clients don't reconnect in lockstep
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        Some(Duration::from_secs_f64((self.sec * factor).max(1.0)))
+    }
+
+    /// Number of attempts made since the last reset
+    pub fn attempts(&self) -> u32 {
+        self.attempts
     }
 
     /// Resets the retry seconds to the initial value of 1 second
     pub fn reset(&mut self) {
-        self.0 = 1;
+        self.sec = 1.0;
+        self.attempts = 0;
+    }
+}
+
+impl Default for RetrySec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Policy without jitter, so that the delays are exact
+    fn policy(max_backoff: u64, max_retries: Option<u32>) -> RetryPolicy {
+        RetryPolicy {
+            multiplier: 2.0,
+            max_backoff,
+            max_retries,
+            jitter: 0.0,
+        }
+    }
+
+    fn secs(retry: &mut RetrySec) -> Option<u64> {
+        retry.next().map(|delay| delay.as_secs())
+    }
+
+    #[test]
+    fn doubles_up_to_the_cap() {
+        let mut retry = RetrySec::with_policy(policy(10, None));
+        let delays: Vec<_> = (0..6).map(|_| secs(&mut retry)).collect();
+        assert_eq!(delays, [2, 4, 8, 10, 10, 10].map(Some));
+    }
+
+    #[test]
+    fn stops_after_max_retries() {
+        let mut retry = RetrySec::with_policy(policy(120, Some(3)));
+        assert_eq!(secs(&mut retry), Some(2));
+        assert_eq!(secs(&mut retry), Some(4));
+        assert_eq!(secs(&mut retry), Some(8));
+        assert_eq!(secs(&mut retry), None);
+        assert_eq!(retry.attempts(), 3);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut retry = RetrySec::with_policy(policy(120, Some(2)));
+        retry.next();
+        retry.next();
+        assert_eq!(secs(&mut retry), None);
+
+        retry.reset();
+        assert_eq!(retry.attempts(), 0);
+        assert_eq!(secs(&mut retry), Some(2));
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let mut retry = RetrySec::with_policy(RetryPolicy {
+            jitter: 0.5,
+            ..policy(8, None)
+        });
+        for expected in [2.0, 4.0, 8.0, 8.0] {
+            let delay = retry.next().unwrap().as_secs_f64();
+            assert!(delay >= (expected * 0.5_f64).max(1.0) && delay <= expected * 1.5);
+        }
     }
 }
@@ -0,0 +1,129 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::{DateTime, Datelike as _, Days, Local, NaiveDateTime, NaiveTime, Weekday};
+use std::{fmt, str::FromStr};
+use tokio::{
+    sync::mpsc::{channel, Receiver},
+    time::{sleep, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{console, i18n::t};
+
+/// Longest sleep before checking the clock again (it may jump after a suspend or a clock change)
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Recurring time at which an invite is created (`fri 20:00`: every Friday, `20:00`: every day)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    /// Day of the week (None: every day)
+    pub weekday: Option<Weekday>,
+    /// Local time of the day
+    pub time: NaiveTime,
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let usage = || format!("Invalid schedule: {s:?} (expected e.g. `fri 20:00` or `20:00`)");
+        let mut parts = s.split_whitespace();
+        let (weekday, time) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(time), None, None) => (None, time),
+            (Some(weekday), Some(time), None) => {
+                let weekday = weekday.parse::<Weekday>().ok().with_context(usage)?;
+                (Some(weekday), time)
+            }
+            _ => bail!(usage()),
+        };
+        let time = NaiveTime::parse_from_str(time, "%H:%M").with_context(usage)?;
+        Ok(Schedule { weekday, time })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.weekday {
+            Some(weekday) => write!(f, "every {weekday} at {}", self.time.format("%H:%M")),
+            None => write!(f, "every day at {}", self.time.format("%H:%M")),
+        }
+    }
+}
+
+impl Schedule {
+    /// Gets the first time on the schedule after `now`
+    pub fn next_after(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let today = now.date_naive();
+        let days = match self.weekday {
+            Some(weekday) => {
+                (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7
+            }
+            None => 0,
+        };
+        let step = if self.weekday.is_some() { 7 } else { 1 };
+        let mut date = today + Days::new(days.into());
+        loop {
+            let at = local_time(date.and_time(self.time));
+            if at > now {
+                return at;
+            }
+            date = date + Days::new(step);
+        }
+    }
+}
+
+/// Converts a local date and time, moving it past the gap when the clocks go forward
+fn local_time(naive: NaiveDateTime) -> DateTime<Local> {
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .or_else(|| {
+            (naive + chrono::Duration::hours(1))
+                .and_local_timezone(Local)
+                .earliest()
+        })
+        .unwrap_or_else(Local::now)
+}
+
+/**
+ * Starts a task waiting for the times on the schedule until `shutdown` is cancelled
+ * @return A receiver getting the game to invite to (None: the running game) each time one is reached
+ */
+pub fn spawn(
+    schedule: Schedule,
+    game: Option<u32>,
+    shutdown: CancellationToken,
+) -> Receiver<Option<u32>> {
+    let (tx, rx) = channel(1);
+    tokio::spawn(async move {
+        loop {
+            let next = schedule.next_after(Local::now());
+            let _: Result<()> = (|| {
+                console::println!(
+                    "★ {}",
+                    t!(
+                        "schedule-next",
+                        time = next.format("%a %Y-%m-%d %H:%M"),
+                        schedule = schedule
+                    )
+                );
+                Ok(())
+            })();
+
+            // Sleep in steps so that the time is kept when the machine was suspended
+            loop {
+                let remaining = (next - Local::now()).to_std().unwrap_or_default();
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::select! {
+                    _ = sleep(remaining.min(CLOCK_CHECK_INTERVAL)) => (),
+                    _ = shutdown.cancelled() => return,
+                }
+            }
+            if tx.send(game).await.is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
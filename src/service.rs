@@ -0,0 +1,286 @@
+use anyhow::{Context as _, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use remoteplay_inviter::{config, console, i18n::t, platform};
+
+use crate::cli::{Cli, ServiceAction};
+
+/// File name of the systemd user unit
+const UNIT_FILE_NAME: &str = "remoteplay-inviter.service";
+
+/// Run a `service` subcommand
+pub fn service(cli: &Cli, action: &ServiceAction) -> Result<()> {
+    match action {
+        ServiceAction::Install => windows::install(cli),
+        ServiceAction::Uninstall => windows::uninstall(),
+        ServiceAction::Run => windows::run(),
+        ServiceAction::GenerateUnit { path } => generate_unit(cli, path.as_deref()),
+    }
+}
+
+/// Arguments running the client unattended with the current settings
+pub fn background_args(cli: &Cli) -> Result<Vec<String>> {
+    // It is not started in the directory of the executable, so pass the config file explicitly
+    let mut args = match &cli.profile {
+        Some(profile) => vec!["--profile".to_string(), profile.clone()],
+        None => {
+            let config_path = std::path::absolute(config::config_path()?)
+                .context("Unable to resolve the config file path")?;
+            vec![
+                "--config".to_string(),
+                config_path.to_string_lossy().into_owned(),
+            ]
+        }
+    };
+    if let Some(endpoint) = &cli.endpoint {
+        args.extend(["--endpoint".to_string(), endpoint.clone()]);
+    }
+    if cli.prefer_ipv4 {
+        args.push("--prefer-ipv4".to_string());
+    } else if cli.prefer_ipv6 {
+        args.push("--prefer-ipv6".to_string());
+    }
+    // Steam may start after the client
+    args.extend(["--wait-for-steam", "--no-interactive"].map(String::from));
+    Ok(args)
+}
+
+/// Get the directory of the systemd user units (`$XDG_CONFIG_HOME/systemd/user`)
+fn user_unit_dir() -> Result<PathBuf> {
+    Ok(platform::xdg_config_home()?.join("systemd").join("user"))
+}
+
+/// Quote an argument for `ExecStart=` (`%` starts a specifier and `$` a variable)
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+/// Write a systemd user unit running the current executable
+fn generate_unit(cli: &Cli, path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => user_unit_dir()?.join(UNIT_FILE_NAME),
+    };
+
+    let exe_path = config::get_exe_path()?;
+    let args: Vec<String> = std::iter::once(exe_path.to_string_lossy().into_owned())
+        .chain(background_args(cli)?)
+        .chain(["--systemd".to_string()])
+        .map(|arg| quote(&arg))
+        .collect();
+
+    let unit = indoc::formatdoc! {"
+        [Unit]
+        Description=Remote Play Inviter
+
+        [Service]
+        Type=notify
+        NotifyAccess=main
+        ExecStart={exec_start}
+        # Ready only once connected to the server, which may take a while
+        TimeoutStartSec=infinity
+        WatchdogSec=60
+        Restart=on-failure
+        RestartSec=10
+
+        [Install]
+        WantedBy=default.target
+    ", exec_start = args.join(" ")};
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create directory: {:?}", dir))?;
+    }
+    fs::write(&path, unit).with_context(|| format!("Unable to write unit file: {:?}", &path))?;
+
+    console::println!("✓ {}", t!("service-unit-written", path = path.display()));
+    console::println!(
+        "  {}",
+        t!("service-unit-enable-hint", unit = UNIT_FILE_NAME)
+    );
+    Ok(())
+}
+
+/// Windows service
+#[cfg(windows)]
+mod windows {
+    use anyhow::{Context as _, Result};
+    use clap::Parser as _;
+    use std::{ffi::OsString, sync::OnceLock, time::Duration};
+    use tokio::runtime::Handle;
+    use tokio_util::sync::CancellationToken;
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+            ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    use remoteplay_inviter::{config, console, i18n::t, logging};
+
+    use crate::cli::Cli;
+
+    /// Name of the service
+    const SERVICE_NAME: &str = "RemotePlayInviter";
+
+    /// Runtime of the process, shared with the thread the service manager starts
+    static RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+    /// Install the client as a service starting with the machine
+    ///
+    /// The service runs as LocalSystem by default; change the account in `services.msc`
+    /// to the user running Steam so that the client can reach the Steam client.
+    pub fn install(cli: &Cli) -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )
+        .context("Unable to connect to the service manager (run as administrator)")?;
+
+        let launch_arguments: Vec<OsString> = super::background_args(cli)?
+            .into_iter()
+            .chain(["service".to_string(), "run".to_string()])
+            .map(OsString::from)
+            .collect();
+
+        let service_info = ServiceInfo {
+            name: SERVICE_NAME.into(),
+            display_name: "Remote Play Inviter".into(),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: config::get_exe_path()?,
+            launch_arguments,
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager
+            .create_service(&service_info, ServiceAccess::CHANGE_CONFIG)
+            .context("Unable to create the service")?;
+        service
+            .set_description("Creates Steam Remote Play Together invite links on request")
+            .context("Unable to set the service description")?;
+
+        console::println!("✓ {}", t!("service-installed", name = SERVICE_NAME));
+        Ok(())
+    }
+
+    /// Stop and remove the service
+    pub fn uninstall() -> Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .context("Unable to connect to the service manager (run as administrator)")?;
+        let service = manager
+            .open_service(
+                SERVICE_NAME,
+                ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+            )
+            .context("Unable to open the service (is it installed?)")?;
+
+        // Mark for deletion first so that it is removed as soon as it stops
+        service.delete().context("Unable to delete the service")?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop().context("Unable to stop the service")?;
+        }
+
+        console::println!("✓ {}", t!("service-uninstalled", name = SERVICE_NAME));
+        Ok(())
+    }
+
+    /// Run as the service (blocks until the service stops)
+    pub fn run() -> Result<()> {
+        let _ = RUNTIME.set(Handle::current());
+        tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))
+            .context("Unable to start the service (this command is run by the service manager)")
+    }
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Entry point called by the service manager on its own thread
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(err) = run_service() {
+            tracing::error!("The service failed: {err:#}");
+        }
+    }
+
+    /// Build a service status
+    fn status(state: ServiceState, exit_code: u32) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: match state {
+                ServiceState::Running => {
+                    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+                }
+                _ => ServiceControlAccept::empty(),
+            },
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }
+    }
+
+    /// Run the client until the service manager stops the service
+    fn run_service() -> Result<()> {
+        let runtime = RUNTIME.get().context("The runtime is not available")?;
+
+        // Stop gracefully when the service is stopped or the machine shuts down
+        let shutdown = CancellationToken::new();
+        let status_handle = service_control_handler::register(SERVICE_NAME, {
+            let shutdown = shutdown.clone();
+            move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    shutdown.cancel();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            }
+        })
+        .context("Unable to register the service control handler")?;
+        status_handle.set_service_status(status(ServiceState::Running, 0))?;
+
+        // The arguments were registered on installation
+        let cli = Cli::parse();
+        let result = runtime.block_on(crate::run(&cli, shutdown));
+        logging::shutdown();
+
+        let exit_code = if result.is_ok() { 0 } else { 1 };
+        status_handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+        result
+    }
+}
+
+/// Windows service (not available on this platform)
+#[cfg(not(windows))]
+mod windows {
+    use anyhow::Result;
+
+    use crate::cli::Cli;
+
+    pub fn install(_cli: &Cli) -> Result<()> {
+        anyhow::bail!("Windows services are only available on Windows")
+    }
+
+    pub fn uninstall() -> Result<()> {
+        anyhow::bail!("Windows services are only available on Windows")
+    }
+
+    pub fn run() -> Result<()> {
+        anyhow::bail!("Windows services are only available on Windows")
+    }
+}
@@ -0,0 +1,415 @@
+use anyhow::{anyhow, Context as _, Result};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use steam_stuff::{GameID, GameUID, SteamStuff};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{interval, sleep_until, Duration, Instant},
+};
+
+/// Default interval between calls of SteamStuff_RunCallbacks
+pub const CALLBACK_INTERVAL: Duration = Duration::from_millis(200);
+/// Interval between checks of the connection to the Steam client
+const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest interval between two attempts to reconnect to the Steam client
+const MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Callback for a new invite link (invitee, guest ID, connect URL)
+pub type InvitedCallback = Arc<dyn Fn(u64, u64, &str) + Send + Sync>;
+/// Callback for a guest joining or leaving the session (invitee, guest ID)
+pub type SessionCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Invites and guests a test worker was asked to cancel (invitee, guest ID)
+#[cfg(test)]
+pub(crate) type Cancelled = Arc<Mutex<Vec<(u64, u64)>>>;
+
+/// Callbacks from the Steam client (registered again when it restarts)
+#[derive(Clone)]
+pub struct Callbacks {
+    /// An invite link was created
+    pub on_invited: InvitedCallback,
+    /// A guest joined the session
+    pub on_started: SessionCallback,
+    /// A guest left the session
+    pub on_stopped: SessionCallback,
+}
+
+use crate::library;
+
+/**
+ * Connects to the Steam client, also reaching the Flatpak and Snap installations on Linux
+ * @return The connection, or an error with guidance for the detected installation
+ */
+pub fn connect() -> Result<SteamStuff> {
+    let err = match SteamStuff::new() {
+        Ok(steam) => return Ok(steam),
+        Err(err) => err,
+    };
+    let Some(package) = library::sandboxed_steam() else {
+        return Err(err);
+    };
+    tracing::info!(?package, "Connecting to the sandboxed Steam client");
+    // The sandbox is the home directory of the Steam client, where its `~/.steam` is
+    let steam_home = package.home().context(package.guidance())?;
+    SteamStuff::with_home(&steam_home).context(package.guidance())
+}
+
+/// How often the Steam callbacks are called
+#[derive(Clone, Copy, Debug)]
+pub struct Polling {
+    /// Interval while a session is active (invites waiting for a guest or guests connected)
+    pub active: Duration,
+    /// Interval while idle (the same as `active` to not adapt it)
+    pub idle: Duration,
+}
+
+impl Default for Polling {
+    fn default() -> Self {
+        Self {
+            active: CALLBACK_INTERVAL,
+            idle: CALLBACK_INTERVAL,
+        }
+    }
+}
+
+/// Invites and guests followed by the worker to adapt the polling
+#[derive(Default)]
+struct Activity {
+    /// Invites requested but not created yet
+    requested_invites: usize,
+    /// Guest IDs of the invites and guests of the session
+    guests: HashSet<u64>,
+}
+
+impl Activity {
+    /// Whether something is expected from the Steam client soon
+    fn is_active(&self) -> bool {
+        self.requested_invites > 0 || !self.guests.is_empty()
+    }
+}
+
+/// Requests to the Steam worker
+enum Request {
+    /// Get the running game and whether it supports Remote Play Together
+    RunningGame(oneshot::Sender<Result<(GameUID, bool)>>),
+    /// Keep the apps that support Remote Play Together
+    RemotePlayApps {
+        app_ids: Vec<u32>,
+        reply: oneshot::Sender<Result<Vec<u32>>>,
+    },
+    /// Create an invite link (delivered to the `on_invited` callback)
+    SendInvite {
+        invitee: u64,
+        game_uid: GameUID,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Revoke an invite or remove a guest
+    CancelInvite {
+        invitee: u64,
+        guest_id: u64,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Register the callbacks (None: unregister them)
+    SetCallbacks(Option<Callbacks>),
+    /// Unregister the callbacks and disconnect from the Steam client
+    Stop(oneshot::Sender<()>),
+}
+
+/// Handle to the task that owns the Steam client (requests are processed in order)
+#[derive(Clone)]
+pub struct Steam {
+    tx: mpsc::Sender<Request>,
+    connected: watch::Receiver<bool>,
+}
+
+impl Steam {
+    /// Starts the task that owns the Steam client, calls its callbacks and reconnects when it restarts
+    pub fn spawn(steam: SteamStuff) -> Self {
+        Self::spawn_with_polling(steam, Polling::default())
+    }
+
+    /// Starts the task that owns the Steam client, calling its callbacks at the given intervals
+    pub fn spawn_with_polling(steam: SteamStuff, polling: Polling) -> Self {
+        let (tx, rx) = mpsc::channel(32);
+        let (connected_tx, connected) = watch::channel(true);
+        tokio::spawn(run(steam, rx, connected_tx, polling));
+        Self { tx, connected }
+    }
+
+    /// Sends a request to the worker (ignored once it has stopped)
+    async fn request(&self, request: Request) {
+        if self.tx.send(request).await.is_err() {
+            tracing::debug!("The Steam worker has stopped");
+        }
+    }
+
+    /**
+     * Gets the running game
+     * @return The game and whether it supports Remote Play Together
+     */
+    pub async fn running_game(&self) -> Result<(GameUID, bool)> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(Request::RunningGame(reply_tx)).await;
+        reply_rx.await.context("The Steam worker has stopped")?
+    }
+
+    /**
+     * Checks which apps support Remote Play Together
+     * @return The app IDs that do, in the same order
+     */
+    pub async fn remote_play_apps(&self, app_ids: Vec<u32>) -> Result<Vec<u32>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.request(Request::RemotePlayApps { app_ids, reply })
+            .await;
+        reply_rx.await.context("The Steam worker has stopped")?
+    }
+
+    /**
+     * Creates an invite link for a game (delivered to the `on_invited` callback)
+     * @return An error if the Steam client is not connected
+     */
+    pub async fn send_invite(&self, invitee: u64, game_uid: GameUID) -> Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.request(Request::SendInvite {
+            invitee,
+            game_uid,
+            reply,
+        })
+        .await;
+        reply_rx.await.context("The Steam worker has stopped")?
+    }
+
+    /// Revokes an invite link or removes a guest from the session (logged if the Steam client is not connected)
+    pub async fn cancel_invite(&self, invitee: u64, guest_id: u64) {
+        let (reply, reply_rx) = oneshot::channel();
+        self.request(Request::CancelInvite {
+            invitee,
+            guest_id,
+            reply,
+        })
+        .await;
+        if let Ok(Err(err)) = reply_rx.await {
+            tracing::warn!(guest_id, "Failed to remove the guest: {err:#}");
+        }
+    }
+
+    /// Registers the callbacks (None: unregisters them)
+    pub async fn set_callbacks(&self, callbacks: Option<Callbacks>) {
+        self.request(Request::SetCallbacks(callbacks)).await;
+    }
+
+    /// Whether the Steam client is connected (changes when it stops or restarts)
+    pub fn connection(&self) -> watch::Receiver<bool> {
+        self.connected.clone()
+    }
+
+    /// Unregisters the callbacks and disconnects from the Steam client
+    pub async fn stop(&self) {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(Request::Stop(reply_tx)).await;
+        let _ = reply_rx.await;
+    }
+
+    /**
+     * Starts a worker without a Steam client, for tests
+     * @return The handle, and the invites and guests it was asked to cancel (invitee, guest ID)
+     */
+    #[cfg(test)]
+    pub(crate) fn recording() -> (Self, Cancelled) {
+        let (tx, mut rx) = mpsc::channel(32);
+        let (connected_tx, connected) = watch::channel(true);
+        let cancelled = Arc::new(Mutex::new(Vec::new()));
+        let recorded = cancelled.clone();
+        tokio::spawn(async move {
+            // Stay connected while the worker runs
+            let _connected_tx = connected_tx;
+            while let Some(request) = rx.recv().await {
+                match request {
+                    Request::RunningGame(reply) => {
+                        let _ = reply.send(Ok((0, false)));
+                    }
+                    Request::RemotePlayApps { reply, .. } => {
+                        let _ = reply.send(Ok(vec![]));
+                    }
+                    Request::SendInvite { reply, .. } => {
+                        let _ = reply.send(Ok(()));
+                    }
+                    Request::CancelInvite {
+                        invitee,
+                        guest_id,
+                        reply,
+                    } => {
+                        recorded.lock().unwrap().push((invitee, guest_id));
+                        let _ = reply.send(Ok(()));
+                    }
+                    Request::SetCallbacks(_) => (),
+                    Request::Stop(reply) => {
+                        let _ = reply.send(());
+                        return;
+                    }
+                }
+            }
+        });
+        (Self { tx, connected }, cancelled)
+    }
+}
+
+/// Registers the callbacks with the Steam client (following the session activity)
+fn register(steam: &SteamStuff, callbacks: &Option<Callbacks>, activity: &Arc<Mutex<Activity>>) {
+    let Some(callbacks) = callbacks else {
+        steam.clear_callbacks();
+        return;
+    };
+    let on_invited = callbacks.on_invited.clone();
+    let invited_activity = activity.clone();
+    steam.set_on_remote_invited(move |invitee, guest_id, connect_url| {
+        let mut activity = invited_activity.lock().unwrap();
+        activity.requested_invites = activity.requested_invites.saturating_sub(1);
+        activity.guests.insert(guest_id);
+        drop(activity);
+        on_invited(invitee, guest_id, connect_url)
+    });
+    let on_started = callbacks.on_started.clone();
+    let started_activity = activity.clone();
+    steam.set_on_remote_started(move |invitee, guest_id| {
+        started_activity.lock().unwrap().guests.insert(guest_id);
+        on_started(invitee, guest_id)
+    });
+    let on_stopped = callbacks.on_stopped.clone();
+    let stopped_activity = activity.clone();
+    steam.set_on_remote_stopped(move |invitee, guest_id| {
+        stopped_activity.lock().unwrap().guests.remove(&guest_id);
+        on_stopped(invitee, guest_id)
+    });
+}
+
+/// Processes the requests, calls the callbacks periodically and follows the connection
+async fn run(
+    mut steam: SteamStuff,
+    mut rx: mpsc::Receiver<Request>,
+    connected_tx: watch::Sender<bool>,
+    polling: Polling,
+) {
+    let mut callbacks = None;
+    let activity = Arc::new(Mutex::new(Activity::default()));
+    let mut callback_interval = interval(polling.idle);
+    let mut health_interval = interval(HEALTH_INTERVAL);
+    let mut connected = true;
+    // Next attempt to reconnect, backing off while the Steam client stays unavailable
+    let mut reconnect_interval = HEALTH_INTERVAL;
+    let mut reconnect_at = Instant::now();
+
+    loop {
+        // Poll faster while a session is active
+        let period = if activity.lock().unwrap().is_active() {
+            polling.active
+        } else {
+            polling.idle
+        };
+        if period != callback_interval.period() {
+            callback_interval = interval(period);
+        }
+
+        tokio::select! {
+            request = rx.recv() => {
+                let Some(request) = request else {
+                    break;
+                };
+                // The interfaces of the Steam client are released while it is disconnected
+                match request {
+                    Request::RunningGame(reply) => {
+                        let _ = reply.send(ensure_connected(connected).map(|()| {
+                            let game_id = steam.get_running_game_id();
+                            let valid = game_id.is_valid_app();
+                            let game_uid: GameUID = game_id.into();
+                            (game_uid, valid && steam.can_remote_play_together(game_uid))
+                        }));
+                    }
+                    Request::RemotePlayApps { app_ids, reply } => {
+                        let _ = reply.send(ensure_connected(connected).map(|()| {
+                            app_ids
+                                .into_iter()
+                                .filter(|&app_id| {
+                                    steam.can_remote_play_together(GameID::new(app_id, 0, 0).into())
+                                })
+                                .collect()
+                        }));
+                    }
+                    Request::SendInvite { invitee, game_uid, reply } => {
+                        let _ = reply.send(ensure_connected(connected).map(|()| {
+                            activity.lock().unwrap().requested_invites += 1;
+                            steam.send_invite(invitee, game_uid);
+                        }));
+                    }
+                    Request::CancelInvite { invitee, guest_id, reply } => {
+                        let _ = reply.send(ensure_connected(connected).map(|()| {
+                            activity.lock().unwrap().guests.remove(&guest_id);
+                            steam.cancel_invite(invitee, guest_id);
+                        }));
+                    }
+                    Request::SetCallbacks(new_callbacks) => {
+                        // Registered when the Steam client is connected again
+                        callbacks = new_callbacks;
+                        if connected {
+                            register(&steam, &callbacks, &activity);
+                        }
+                    }
+                    Request::Stop(reply) => {
+                        steam.clear_callbacks();
+                        drop(steam);
+                        let _ = reply.send(());
+                        return;
+                    }
+                }
+            }
+            _ = callback_interval.tick(), if connected => {
+                steam.run_callbacks();
+            }
+            _ = health_interval.tick(), if connected => {
+                if !steam.is_connected() {
+                    // The Steam client has stopped (or the user has logged off)
+                    connected = false;
+                    *activity.lock().unwrap() = Activity::default();
+                    reconnect_interval = HEALTH_INTERVAL;
+                    reconnect_at = Instant::now() + reconnect_interval;
+                    connected_tx.send_replace(false);
+                }
+            }
+            _ = sleep_until(reconnect_at), if !connected => {
+                // In the sandbox the Steam client was found in, if any
+                if steam.reconnect().is_ok() && steam.is_connected() {
+                    // The Steam client has restarted
+                    connected = true;
+                    register(&steam, &callbacks, &activity);
+                    connected_tx.send_replace(true);
+                } else {
+                    reconnect_interval = (reconnect_interval * 2).min(MAX_RECONNECT_INTERVAL);
+                    reconnect_at = Instant::now() + reconnect_interval;
+                    tracing::debug!(?reconnect_interval, "The Steam client is still unavailable");
+                }
+            }
+        }
+    }
+
+    steam.clear_callbacks();
+}
+
+/**
+ * Checks that the Steam client can be called
+ * @return An error while it is disconnected
+ */
+fn ensure_connected(connected: bool) -> Result<()> {
+    if connected {
+        Ok(())
+    } else {
+        Err(anyhow!("The Steam client is not connected"))
+    }
+}
+
+/// Gets the app ID of a game (None: not an app, e.g. a shortcut or no game)
+pub fn app_id(game_uid: GameUID) -> Option<u32> {
+    let game_id = GameID::from(game_uid);
+    game_id.is_valid_app().then_some(game_id.app_id)
+}
@@ -0,0 +1,185 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+use tokio::time::{timeout, Duration};
+
+use crate::{
+    config,
+    http::{self, Network},
+};
+
+/// Steam store API giving the details of an app
+const APP_DETAILS_URL: &str = "https://store.steampowered.com/api/appdetails";
+
+/// ID of the "Remote Play Together" store category
+const CATEGORY_REMOTE_PLAY_TOGETHER: u64 = 44;
+
+/// Time to wait for the Steam store to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Game metadata cache file name
+const CACHE_FILE_NAME: &str = "games.json";
+
+/// Age after which the cached details are fetched again (the store page may have changed)
+const CACHE_MAX_AGE: chrono::Duration = chrono::Duration::days(7);
+
+/// How the Steam store is reached (set once the network options are read)
+static NETWORK: OnceLock<Network> = OnceLock::new();
+
+/// Details of an app on the Steam store
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppDetails {
+    pub name: String,
+    /// URL of the capsule image
+    pub capsule_url: Option<String>,
+    /// Whether the store page advertises Remote Play Together
+    pub remote_play_together: bool,
+}
+
+/**
+ * Fetches the details of an app from the Steam store
+ * @return None when the app has no store page (e.g. removed or tools)
+ */
+pub async fn app_details(app_id: u32) -> Result<Option<AppDetails>> {
+    let url = format!("{APP_DETAILS_URL}?appids={app_id}&filters=basic,categories");
+    let network = NETWORK.get().cloned().unwrap_or_default();
+    let response = timeout(REQUEST_TIMEOUT, http::get(&network, &url))
+        .await
+        .context("The Steam store did not answer in time")??;
+    if !response.is_success() {
+        bail!("The Steam store answered with status {}", response.status);
+    }
+    let details: Value =
+        serde_json::from_slice(&response.body).context("Invalid Steam store response")?;
+
+    // {"<app_id>": {"success": true, "data": {...}}}
+    let details = &details[app_id.to_string()];
+    if details["success"] != true {
+        return Ok(None);
+    }
+    let data = &details["data"];
+    let remote_play_together = data["categories"].as_array().is_some_and(|categories| {
+        categories
+            .iter()
+            .any(|category| category["id"] == CATEGORY_REMOTE_PLAY_TOGETHER)
+    });
+    Ok(Some(AppDetails {
+        name: data["name"].as_str().unwrap_or_default().to_string(),
+        capsule_url: data["capsule_image"].as_str().map(String::from),
+        remote_play_together,
+    }))
+}
+
+/// Sets how the Steam store is reached (the proxy, DNS resolver and certificates of the network)
+pub fn set_network(network: Network) {
+    let _ = NETWORK.set(network);
+}
+
+/// Details of an app in the cache
+#[derive(Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    details: AppDetails,
+    /// When the details were fetched
+    fetched_at: DateTime<Utc>,
+}
+
+/// Get the game metadata cache file path
+pub fn cache_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join(CACHE_FILE_NAME))
+}
+
+/// The cached details (app ID -> details), read from the cache file on first use
+fn cache() -> &'static Mutex<BTreeMap<u32, CacheEntry>> {
+    static CACHE: OnceLock<Mutex<BTreeMap<u32, CacheEntry>>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let entries = cache_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Mutex::new(entries)
+    })
+}
+
+/// Write the cached details to the cache file
+fn save_cache(entries: &BTreeMap<u32, CacheEntry>) -> Result<()> {
+    let path = cache_path()?;
+    let content =
+        serde_json::to_string_pretty(entries).context("Unable to serialize game cache")?;
+    fs::write(&path, content)
+        .with_context(|| format!("Unable to write game cache file: {:?}", &path))
+}
+
+/**
+ * Gets the details of an app, from the cache or else the Steam store (the fetched details are cached)
+ * @return None when they cannot be known (the store is unreachable or the app has no store page)
+ */
+pub async fn game_details(app_id: u32) -> Option<AppDetails> {
+    let cached = cache().lock().unwrap().get(&app_id).cloned();
+    if let Some(entry) = &cached {
+        if Utc::now() - entry.fetched_at < CACHE_MAX_AGE {
+            return Some(entry.details.clone());
+        }
+    }
+
+    let details = match app_details(app_id).await {
+        Ok(details) => details?,
+        Err(err) => {
+            tracing::debug!(
+                app_id,
+                "Failed to get the app details from the Steam store: {err:#}"
+            );
+            // Better outdated details than none
+            return cached.map(|entry| entry.details);
+        }
+    };
+    let mut entries = cache().lock().unwrap();
+    entries.insert(
+        app_id,
+        CacheEntry {
+            details: details.clone(),
+            fetched_at: Utc::now(),
+        },
+    );
+    if let Err(err) = save_cache(&entries) {
+        tracing::warn!("{err:#}");
+    }
+    Some(details)
+}
+
+/// Fetches the details of an app in the background unless they are cached
+pub fn prefetch(app_id: u32) {
+    if !cache().lock().unwrap().contains_key(&app_id) {
+        tokio::spawn(game_details(app_id));
+    }
+}
+
+/// Gets the cached name of an app (doesn't fetch it)
+pub fn game_name(app_id: u32) -> Option<String> {
+    let entries = cache().lock().unwrap();
+    Some(entries.get(&app_id)?.details.name.clone()).filter(|name| !name.is_empty())
+}
+
+/// Formats an app ID with its cached name for display (e.g. `480 (Spacewar)`)
+pub fn game_label(app_id: u32) -> String {
+    match game_name(app_id) {
+        Some(name) => format!("{app_id} ({name})"),
+        None => app_id.to_string(),
+    }
+}
+
+/**
+ * Checks on the Steam store whether an app supports Remote Play Together
+ * @return None when it cannot be known (the store is unreachable or the app has no store page)
+ */
+pub async fn supports_remote_play(app_id: u32) -> Option<bool> {
+    Some(game_details(app_id).await?.remote_play_together)
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context as _, Result};
+use std::{env, os::unix::net::UnixDatagram, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{self, Event};
+
+/// Sends a state change to the service manager (`sd_notify`)
+/// Does nothing if the process was not started by systemd with `Type=notify`.
+pub fn notify(state: &str) -> Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound().context("Unable to create the notify socket")?;
+
+    // Abstract socket names start with '@'
+    let bytes = path.as_encoded_bytes();
+    if let Some(name) = bytes.strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::{linux::net::SocketAddrExt as _, unix::net::SocketAddr};
+            let addr =
+                SocketAddr::from_abstract_name(name).context("Invalid notify socket address")?;
+            socket
+                .send_to_addr(state.as_bytes(), &addr)
+                .context("Unable to notify systemd")?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("Abstract notify sockets are not supported: {:?}", name);
+    }
+    socket
+        .send_to(state.as_bytes(), &path)
+        .context("Unable to notify systemd")?;
+    Ok(())
+}
+
+/**
+ * Gets the watchdog interval requested by systemd (`WatchdogSec=`)
+ * @return Half of the watchdog timeout, or None if the watchdog is disabled
+ */
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // The watchdog is meant for this process only
+    if let Some(pid) = env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+    {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Reports readiness after the first connection to the server and keeps the watchdog fed
+/// until `shutdown` is cancelled
+pub async fn run(shutdown: CancellationToken) {
+    let mut events = events::subscribe();
+    let mut watchdog = watchdog_interval().map(tokio::time::interval);
+    let mut ready = false;
+
+    let result = loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break Ok(()),
+            },
+            _ = async {
+                match &mut watchdog {
+                    Some(watchdog) => watchdog.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                if let Err(err) = notify("WATCHDOG=1") {
+                    break Err(err);
+                }
+                continue;
+            }
+            _ = shutdown.cancelled() => break notify("STOPPING=1"),
+        };
+
+        let state = match event {
+            Event::Connected { .. } if !ready => {
+                ready = true;
+                "READY=1\nSTATUS=Connected to the server"
+            }
+            Event::Connected { .. } => "STATUS=Connected to the server",
+            Event::Reconnecting { .. } => "STATUS=Reconnecting to the server",
+            Event::SteamLost => "STATUS=Waiting for the Steam client",
+            _ => continue,
+        };
+        if let Err(err) = notify(state) {
+            break Err(err);
+        }
+    };
+
+    if let Err(err) = result {
+        tracing::warn!("{err:#}");
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{interval, Duration, MissedTickBehavior},
+};
+use tokio_tungstenite::tungstenite::http::Uri;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config,
+    events::{self, Event},
+    http::{self, Network},
+    VERSION,
+};
+
+/// Time covered by a report
+const REPORT_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Interval between the checks whether a report is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Path of the report on the server (relative to the endpoint)
+const REPORT_PATH: &str = "/telemetry";
+
+/// Coarse usage counters (nothing identifies the user, the guests or the games)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Counters {
+    /// Play sessions hosted (a first guest joined)
+    pub sessions_hosted: u64,
+    /// Invite links created
+    pub invites_created: u64,
+    /// Guests who joined
+    pub guests_joined: u64,
+    /// Reconnections after the connection to the server was lost
+    pub reconnects: u64,
+}
+
+/// Counters not reported yet, kept across runs
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    /// Start of the period counted
+    since: DateTime<Utc>,
+    #[serde(default)]
+    counters: Counters,
+}
+
+impl Default for Pending {
+    fn default() -> Self {
+        Self {
+            since: Utc::now(),
+            counters: Counters::default(),
+        }
+    }
+}
+
+/// Report posted to the server (exactly what `telemetry status` previews)
+#[derive(Serialize)]
+pub struct Report {
+    /// Client version
+    pub version: &'static str,
+    /// Operating system (e.g. `windows`)
+    pub os: &'static str,
+    /// CPU architecture (e.g. `x86_64`)
+    pub arch: &'static str,
+    /// Length of the period counted, in hours
+    pub period_hours: u64,
+    #[serde(flatten)]
+    pub counters: Counters,
+}
+
+impl From<&Pending> for Report {
+    fn from(pending: &Pending) -> Self {
+        Report {
+            version: VERSION,
+            os: env::consts::OS,
+            arch: env::consts::ARCH,
+            period_hours: (Utc::now() - pending.since).num_hours().max(0) as u64,
+            counters: pending.counters.clone(),
+        }
+    }
+}
+
+/// Get the file keeping the counters not reported yet
+fn pending_path() -> Result<PathBuf> {
+    Ok(config::config_path()?.with_extension("telemetry.json"))
+}
+
+/// Reads the counters not reported yet (a new period if there are none)
+fn load() -> Pending {
+    pending_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Saves the counters not reported yet
+fn save(pending: &Pending) -> Result<()> {
+    let path = pending_path()?;
+    let content = serde_json::to_string(pending)?;
+    fs::write(&path, content).with_context(|| format!("Unable to write file: {:?}", &path))
+}
+
+/// Removes the counters not reported yet (when telemetry is disabled)
+pub fn clear() -> Result<()> {
+    let path = pending_path()?;
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("Unable to remove file: {:?}", &path))?;
+    }
+    Ok(())
+}
+
+/// Gets the report that would be posted now
+pub fn preview() -> Report {
+    Report::from(&load())
+}
+
+/// Builds the URL the reports are posted to from the endpoint URL (`wss://host` -> `https://host/telemetry`)
+pub fn report_url(endpoint_url: &str) -> Result<String> {
+    let uri: Uri = endpoint_url.parse().context("Failed to parse URL")?;
+    let scheme = match uri.scheme_str() {
+        Some("wss" | "https") => "https",
+        Some("ws" | "http") => "http",
+        _ => bail!("Unsupported endpoint URL: {endpoint_url}"),
+    };
+    let authority = uri.authority().context("Missing host in URL")?;
+    Ok(format!("{scheme}://{authority}{REPORT_PATH}"))
+}
+
+/// Posts a report, resetting the counters once the server accepted it
+async fn send(network: &Network, url: &str, pending: &mut Pending) -> Result<()> {
+    let body = serde_json::to_string(&Report::from(&*pending))?;
+    let response = http::post_json(network, url, &body).await?;
+    if !response.is_success() {
+        bail!("The server answered with status {}", response.status);
+    }
+    *pending = Pending::default();
+    save(pending)
+}
+
+/// Counts the usage from the application events and posts a report every day until `shutdown` is cancelled
+pub async fn run(endpoint_url: String, network: Network, shutdown: CancellationToken) {
+    let url = match report_url(&endpoint_url) {
+        Ok(url) => url,
+        Err(err) => {
+            tracing::warn!("Telemetry disabled: {err:#}");
+            return;
+        }
+    };
+    let mut events = events::subscribe();
+    let mut pending = load();
+    let mut check = interval(CHECK_INTERVAL);
+    check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let counters = &mut pending.counters;
+                match event {
+                    Ok(Event::InviteCreated { .. }) => counters.invites_created += 1,
+                    Ok(Event::GuestJoined { guests, .. }) => {
+                        counters.guests_joined += 1;
+                        if guests == 1 {
+                            counters.sessions_hosted += 1;
+                        }
+                    }
+                    Ok(Event::Reconnecting { .. }) => counters.reconnects += 1,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+                if let Err(err) = save(&pending) {
+                    tracing::debug!("Failed to save the telemetry counters: {err:#}");
+                }
+            }
+            _ = check.tick() => {
+                let due = (Utc::now() - pending.since)
+                    .to_std()
+                    .is_ok_and(|elapsed| elapsed >= REPORT_PERIOD);
+                if due {
+                    match send(&network, &url, &mut pending).await {
+                        Ok(()) => tracing::info!("Posted the usage report"),
+                        Err(err) => tracing::debug!("Failed to post the usage report: {err:#}"),
+                    }
+                }
+            }
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{Context as _, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::digest;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    client::WebPkiServerVerifier,
+    crypto::{ring as provider, CryptoProvider},
+    pki_types::{pem::PemObject as _, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::{
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use crate::config::TlsConfig;
+
+/**
+ * Builds the TLS configuration for the connection to the server
+ * @return None if the defaults are used (the built-in root certificates without pinning)
+ */
+pub fn client_config(tls: &TlsConfig, pins: &[String]) -> Result<Option<Arc<ClientConfig>>> {
+    if tls.ca_file.is_none()
+        && tls.builtin_roots
+        && pins.is_empty()
+        && tls.client_cert.is_none()
+        && tls.client_key.is_none()
+    {
+        return Ok(None);
+    }
+
+    let mut roots = RootCertStore::empty();
+    if tls.builtin_roots {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    if let Some(path) = &tls.ca_file {
+        for cert in read_certificates(path)? {
+            roots
+                .add(cert)
+                .with_context(|| format!("Invalid CA certificate in {:?}", path))?;
+        }
+    }
+    if roots.is_empty() {
+        anyhow::bail!("No root certificates to verify the server with (set tls.ca_file or enable tls.builtin_roots)");
+    }
+
+    let provider = Arc::new(provider::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Unable to configure TLS")?;
+    let builder = if pins.is_empty() {
+        builder.with_root_certificates(roots)
+    } else {
+        let verifier = PinnedVerifier::new(roots, pins, provider)?;
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+    };
+
+    // Client certificate for servers requiring mutual TLS
+    let config = match (&tls.client_cert, &tls.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = read_certificates(cert_path)?;
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .with_context(|| format!("Unable to read the client key: {:?}", key_path))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("The client certificate does not match its key")?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => anyhow::bail!("tls.client_cert and tls.client_key must be set together"),
+    };
+    Ok(Some(Arc::new(config)))
+}
+
+/// Builds a TLS configuration trusting the built-in root certificates (shared by the callers)
+pub fn builtin_config() -> Result<Arc<ClientConfig>> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(config.clone());
+    }
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(provider::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Unable to configure TLS")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(CONFIG.get_or_init(|| Arc::new(config)).clone())
+}
+
+/// Reads the certificates of a PEM file
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .with_context(|| format!("Unable to read certificate file: {:?}", path))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Unable to parse certificate file: {:?}", path))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {:?}", path);
+    }
+    Ok(certs)
+}
+
+/// Verifies the certificate chain as usual, then requires the public key of one of its
+/// certificates to match a pin (SHA-256 of the SubjectPublicKeyInfo)
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<Vec<u8>>,
+}
+
+impl PinnedVerifier {
+    fn new(roots: RootCertStore, pins: &[String], provider: Arc<CryptoProvider>) -> Result<Self> {
+        let pins = pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<Vec<_>>>()?;
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .context("Unable to configure TLS")?;
+        Ok(Self { inner, pins })
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| subject_public_key_info(cert))
+            .any(|spki| {
+                let hash = digest::digest(&digest::SHA256, spki);
+                self.pins.iter().any(|pin| pin == hash.as_ref())
+            });
+        if !pinned {
+            return Err(rustls::Error::General(
+                "The server certificate does not match the pinned public keys".into(),
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Parses a pin (`sha256/<base64>`, as printed by `openssl ... | openssl dgst -sha256 -binary | base64`)
+fn parse_pin(pin: &str) -> Result<Vec<u8>> {
+    let encoded = pin.strip_prefix("sha256/").unwrap_or(pin);
+    let hash = BASE64
+        .decode(encoded.trim())
+        .with_context(|| format!("Invalid pin (not base64): {pin}"))?;
+    if hash.len() != digest::SHA256_OUTPUT_LEN {
+        anyhow::bail!("Invalid pin (not a SHA-256 hash): {pin}");
+    }
+    Ok(hash)
+}
+
+/// A DER element
+struct Der<'a> {
+    /// Content (without the tag and the length)
+    content: &'a [u8],
+    /// The whole element
+    whole: &'a [u8],
+    /// Data after the element
+    rest: &'a [u8],
+}
+
+/// Reads a DER element
+fn read_der(data: &[u8]) -> Option<Der<'_>> {
+    let (_tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let header_len = data.len() - rest.len();
+    Some(Der {
+        content: &rest[..len],
+        whole: &data[..header_len + len],
+        rest: &rest[len..],
+    })
+}
+
+/// Extracts the SubjectPublicKeyInfo of an X.509 certificate
+fn subject_public_key_info<'a>(cert: &'a CertificateDer<'_>) -> Option<&'a [u8]> {
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let certificate = read_der(cert.as_ref())?.content;
+    let mut tbs = read_der(certificate)?.content;
+    // Skip the optional version ([0])
+    if tbs.first() == Some(&0xa0) {
+        tbs = read_der(tbs)?.rest;
+    }
+    // Skip serialNumber, signature, issuer, validity and subject
+    for _ in 0..5 {
+        tbs = read_der(tbs)?.rest;
+    }
+    Some(read_der(tbs)?.whole)
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context as _, Result};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use std::{thread, time::Duration};
+use tokio::sync::{broadcast::error::TryRecvError, mpsc::Sender, oneshot};
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem},
+    Icon, TrayIcon, TrayIconBuilder,
+};
+
+use crate::{
+    control::{ControlMethod, ControlRequest},
+    events::{self, Event},
+};
+
+/// Interval between two polls of the tray loop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of the generated tray icon in pixels
+const ICON_SIZE: u32 = 32;
+
+/// Connection state shown in the tray
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Connecting,
+    Connected,
+    Reconnecting,
+    SteamLost,
+}
+
+impl Status {
+    fn tooltip(self) -> &'static str {
+        match self {
+            Self::Connecting => "Remote Play Inviter: connecting",
+            Self::Connected => "Remote Play Inviter: connected",
+            Self::Reconnecting => "Remote Play Inviter: reconnecting",
+            Self::SteamLost => "Remote Play Inviter: Steam is not running",
+        }
+    }
+
+    /// Colour of the icon (RGB)
+    fn color(self) -> [u8; 3] {
+        match self {
+            Self::Connected => [0xa4, 0xd0, 0x07],
+            Self::Connecting | Self::Reconnecting => [0xe0, 0xa0, 0x20],
+            Self::SteamLost => [0xe2, 0x4a, 0x4a],
+        }
+    }
+
+    /** Generates a filled circle of the status colour
+     * @return Icon for the tray
+     */
+    fn icon(self) -> Result<Icon> {
+        let [r, g, b] = self.color();
+        let center = ICON_SIZE as f32 / 2.0;
+        let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+        for y in 0..ICON_SIZE {
+            for x in 0..ICON_SIZE {
+                let dx = x as f32 + 0.5 - center;
+                let dy = y as f32 + 0.5 - center;
+                let alpha = if dx * dx + dy * dy <= center * center {
+                    0xff
+                } else {
+                    0
+                };
+                rgba.extend_from_slice(&[r, g, b, alpha]);
+            }
+        }
+        Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).context("Failed to create the tray icon")
+    }
+}
+
+/// Menu of the tray icon
+struct TrayMenu {
+    copy_link: MenuItem,
+    new_invite: MenuItem,
+    quit: MenuItem,
+}
+
+/// Starts the tray icon on its own thread
+///
+/// Menu actions are sent to the client loop as control requests.
+#[cfg(any(target_os = "linux", windows))]
+pub fn spawn(control_tx: Sender<ControlRequest>) {
+    // Subscribe now so that the events emitted while the tray starts are not missed
+    let events = events::subscribe();
+    let result = thread::Builder::new().name("tray".into()).spawn(move || {
+        if let Err(err) = run(control_tx, events) {
+            tracing::warn!("System tray is not available: {err:#}");
+        }
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to start the system tray thread: {err}");
+    }
+}
+
+/// Starts the tray icon on its own thread
+///
+/// The tray must run on the main thread on this platform, which belongs to the async runtime.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn spawn(_control_tx: Sender<ControlRequest>) {
+    tracing::warn!("The system tray is not supported on this platform");
+}
+
+/// Creates the tray icon and runs its loop until the process exits
+#[cfg(any(target_os = "linux", windows))]
+fn run(
+    control_tx: Sender<ControlRequest>,
+    mut events: tokio::sync::broadcast::Receiver<Event>,
+) -> Result<()> {
+    // The tray is driven by GTK on Linux, which must be initialized on this thread
+    #[cfg(target_os = "linux")]
+    gtk::init().context("Failed to initialize GTK")?;
+
+    let menu = TrayMenu {
+        copy_link: MenuItem::new("Copy invite link", false, None),
+        new_invite: MenuItem::new("New invite", true, None),
+        quit: MenuItem::new("Quit", true, None),
+    };
+    let tray_menu = Menu::new();
+    tray_menu
+        .append_items(&[&menu.copy_link, &menu.new_invite, &menu.quit])
+        .context("Failed to create the tray menu")?;
+
+    let mut status = Status::Connecting;
+    let tray = TrayIconBuilder::new()
+        .with_menu(Box::new(tray_menu))
+        .with_tooltip(status.tooltip())
+        .with_icon(status.icon()?)
+        .build()
+        .context("Failed to create the tray icon")?;
+
+    // Most recent invite link
+    let mut invite_url: Option<String> = None;
+
+    loop {
+        pump_messages();
+
+        // Menu actions
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            if event.id == *menu.copy_link.id() {
+                if let Some(url) = &invite_url {
+                    copy_to_clipboard(url);
+                }
+            } else if event.id == *menu.new_invite.id() {
+                request(&control_tx, ControlMethod::CreateInvite);
+            } else if event.id == *menu.quit.id() {
+                request(&control_tx, ControlMethod::Shutdown);
+            }
+        }
+
+        // Application events
+        let previous = status;
+        loop {
+            match events.try_recv() {
+                Ok(Event::Connected { .. }) => status = Status::Connected,
+                Ok(Event::Reconnecting { .. }) => status = Status::Reconnecting,
+                Ok(Event::SteamLost) => status = Status::SteamLost,
+                Ok(Event::SteamRestored) => status = Status::Connected,
+                Ok(Event::InviteCreated { url, .. }) => {
+                    invite_url = Some(url);
+                    menu.copy_link.set_enabled(true);
+                }
+                Ok(_) | Err(TryRecvError::Lagged(_)) => (),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => return Ok(()),
+            }
+        }
+        if status != previous {
+            update(&tray, status);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Reflects the status on the tray icon
+fn update(tray: &TrayIcon, status: Status) {
+    if let Err(err) = tray.set_tooltip(Some(status.tooltip())) {
+        tracing::debug!("Failed to update the tray tooltip: {err}");
+    }
+    match status.icon() {
+        Ok(icon) => {
+            if let Err(err) = tray.set_icon(Some(icon)) {
+                tracing::debug!("Failed to update the tray icon: {err}");
+            }
+        }
+        Err(err) => tracing::debug!("{err:#}"),
+    }
+}
+
+/// Sends a control request without blocking the tray loop
+fn request(control_tx: &Sender<ControlRequest>, method: ControlMethod) {
+    let (reply, rx) = oneshot::channel();
+    if control_tx
+        .blocking_send(ControlRequest { method, reply })
+        .is_err()
+    {
+        return;
+    }
+    // The handler prints the outcome on the console; only log failures here
+    thread::spawn(move || {
+        if let Ok(Err(message)) = rx.blocking_recv() {
+            tracing::warn!("Tray request failed: {message}");
+        }
+    });
+}
+
+/// Copies text to the clipboard
+fn copy_to_clipboard(text: &str) {
+    if let Err(err) =
+        ClipboardProvider::new().and_then(|mut ctx: ClipboardContext| ctx.set_contents(text.into()))
+    {
+        tracing::warn!("Failed to copy to clipboard: {err}");
+    }
+}
+
+/// Dispatches the pending messages of the platform's UI toolkit
+#[cfg(target_os = "linux")]
+fn pump_messages() {
+    while gtk::events_pending() {
+        gtk::main_iteration_do(false);
+    }
+}
+
+/// Dispatches the pending messages of the platform's UI toolkit
+#[cfg(windows)]
+fn pump_messages() {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE,
+    };
+
+    unsafe {
+        let mut msg: MSG = std::mem::zeroed();
+        while PeekMessageW(&mut msg, 0, 0, 0, PM_REMOVE) != 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+}
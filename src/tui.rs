@@ -0,0 +1,476 @@
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Local};
+use crossterm::{
+    cursor,
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    style::{Print, Stylize as _},
+    terminal, QueueableCommand,
+};
+use std::{
+    collections::VecDeque,
+    io::{stdout, Write},
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tokio::sync::{
+    broadcast::{error::TryRecvError, Receiver as EventReceiver},
+    mpsc::{channel, Receiver, Sender},
+};
+
+use crate::{
+    console::{self, ConsoleCmd},
+    events::{self, Event},
+    i18n::t,
+};
+
+/// Interval between two polls of the keyboard and the events
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of console lines kept for the log pane
+const LOG_LINES_KEPT: usize = 1000;
+
+/// Number of log lines printed again on the console after the interface is closed
+const LOG_LINES_REPRINTED: usize = 5;
+
+/// Most invite links shown at once in the invite pane
+const INVITES_SHOWN: usize = 5;
+
+/// Height of the connection and guest panes (with their borders)
+const TOP_PANES_HEIGHT: u16 = 6;
+
+/// Smallest terminal the panes fit in (columns, rows)
+const MIN_SIZE: (u16, u16) = (40, 16);
+
+/// Asks the interface thread to restore the terminal and exit
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Interface thread, returning the last log lines
+static THREAD: Mutex<Option<JoinHandle<Vec<String>>>> = Mutex::new(None);
+
+/// State of an invite link
+#[derive(Clone, Copy, PartialEq)]
+enum InviteState {
+    /// Not used yet
+    Unused,
+    /// A guest joined with it
+    Joined,
+    /// Revoked or expired
+    Revoked,
+}
+
+/// Invite link shown in the invite pane
+struct Invite {
+    guest_id: u64,
+    url: String,
+    game_name: Option<String>,
+    state: InviteState,
+}
+
+/// State displayed in the panes
+struct TuiState {
+    /// Connection to the server (None: connecting for the first time)
+    connection: Option<String>,
+    /// Whether the Steam client is running
+    steam: bool,
+    /// Connected guests (guest ID, name, joined at)
+    guests: Vec<(u64, String, DateTime<Local>)>,
+    /// Invite links created while running, oldest first
+    invites: Vec<Invite>,
+    /// Index of the selected invite link
+    selected: usize,
+    /// Console lines, oldest first
+    log: VecDeque<String>,
+    /// Lines the log pane is scrolled back by
+    scroll: usize,
+}
+
+impl TuiState {
+    fn new() -> Self {
+        Self {
+            connection: None,
+            steam: true,
+            guests: Vec::new(),
+            invites: Vec::new(),
+            selected: 0,
+            log: VecDeque::new(),
+            scroll: 0,
+        }
+    }
+
+    /// Applies an event to the state
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Connected { .. } => self.connection = Some(format!("✓ {}", t!("tui-connected"))),
+            Event::Reconnecting { delay_ms } | Event::Maintenance { delay_ms, .. } => {
+                let at = Local::now() + chrono::Duration::milliseconds(delay_ms as i64);
+                let time = at.format("%H:%M:%S");
+                self.connection = Some(format!("↪ {}", t!("tui-reconnecting-at", time = time)));
+            }
+            Event::InviteCreated {
+                guest_id,
+                url,
+                game_name,
+                ..
+            } => {
+                self.invites.push(Invite {
+                    guest_id,
+                    url,
+                    game_name,
+                    state: InviteState::Unused,
+                });
+                self.selected = self.invites.len() - 1;
+            }
+            Event::InviteRevoked { guest_id } => {
+                self.set_invite_state(guest_id, InviteState::Revoked)
+            }
+            Event::GuestJoined { guest_id, name, .. } => {
+                self.set_invite_state(guest_id, InviteState::Joined);
+                self.guests.retain(|(id, _, _)| *id != guest_id);
+                self.guests.push((guest_id, name, Local::now()));
+            }
+            Event::GuestLeft { guest_id, .. } => self.guests.retain(|(id, _, _)| *id != guest_id),
+            Event::SteamLost => {
+                self.steam = false;
+                self.guests.clear();
+            }
+            Event::SteamRestored => self.steam = true,
+            Event::Log { text, .. } => {
+                let time = Local::now().format("%H:%M:%S");
+                for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                    if self.log.len() >= LOG_LINES_KEPT {
+                        self.log.pop_front();
+                    }
+                    self.log.push_back(format!("[{time}] {line}"));
+                    // Keep the lines in view while scrolled back
+                    if self.scroll > 0 {
+                        self.scroll += 1;
+                    }
+                }
+            }
+            Event::SessionEnding { .. }
+            | Event::Announcement { .. }
+            | Event::PairingCode { .. }
+            | Event::Paired { .. } => (),
+        }
+    }
+
+    /// Updates the state of an invite link
+    fn set_invite_state(&mut self, guest_id: u64, state: InviteState) {
+        if let Some(invite) = self.invites.iter_mut().find(|i| i.guest_id == guest_id) {
+            invite.state = state;
+        }
+    }
+
+    /**
+     * Handles a key press
+     * @return The command to run for the key, if any
+     */
+    fn on_key(&mut self, key: KeyEvent, log_height: usize) -> Option<ConsoleCmd> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(ConsoleCmd::Quit)
+            }
+            KeyCode::Char('q') => Some(ConsoleCmd::Quit),
+            KeyCode::Char('i') => Some(ConsoleCmd::Invite),
+            KeyCode::Char('r') => Some(ConsoleCmd::Revoke(
+                self.invites
+                    .get(self.selected)
+                    .map(|invite| invite.guest_id),
+            )),
+            KeyCode::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.invites.len().saturating_sub(1));
+                None
+            }
+            KeyCode::PageUp => {
+                let max = self.log.len().saturating_sub(log_height);
+                self.scroll = (self.scroll + log_height).min(max);
+                None
+            }
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(log_height);
+                None
+            }
+            KeyCode::End => {
+                self.scroll = 0;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets the lines of the connection pane
+    fn connection_lines(&self) -> Vec<String> {
+        let connection = match &self.connection {
+            Some(connection) => connection.clone(),
+            None => format!("↪ {}", t!("tui-connecting")),
+        };
+        let steam = if self.steam {
+            format!("✓ {}", t!("tui-steam-running"))
+        } else {
+            format!("☓ {}", t!("tui-steam-lost"))
+        };
+        let unused = self
+            .invites
+            .iter()
+            .filter(|invite| invite.state == InviteState::Unused)
+            .count();
+        vec![
+            connection,
+            steam,
+            format!("□ {}", t!("tui-guest-count", guests = self.guests.len())),
+            format!("□ {}", t!("tui-unused-invites", invites = unused)),
+        ]
+    }
+
+    /// Gets the lines of the guest pane
+    fn guest_lines(&self) -> Vec<String> {
+        if self.guests.is_empty() {
+            return vec![t!("tui-no-guests")];
+        }
+        self.guests
+            .iter()
+            .map(|(_, name, joined_at)| {
+                let since = t!("tui-guest-since", time = joined_at.format("%H:%M"));
+                format!("★ {name}  ({since})")
+            })
+            .collect()
+    }
+
+    /// Gets the lines of the invite pane (at most `rows`, around the selected link)
+    fn invite_lines(&self, rows: usize) -> Vec<(String, bool)> {
+        if self.invites.is_empty() {
+            return vec![(t!("tui-no-invites"), false)];
+        }
+        let first = (self.selected + 1).saturating_sub(rows);
+        self.invites
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(index, invite)| {
+                let state = match invite.state {
+                    InviteState::Unused => format!("-> {}", t!("tui-invite-unused")),
+                    InviteState::Joined => format!("✓ {}", t!("tui-invite-joined")),
+                    InviteState::Revoked => format!("☓ {}", t!("tui-invite-revoked")),
+                };
+                let game = invite.game_name.as_deref().unwrap_or("-");
+                let line = format!("{state}  #{}  {game}  {}", invite.guest_id, invite.url);
+                (line, index == self.selected)
+            })
+            .collect()
+    }
+
+    /// Gets the lines of the log pane that are in view
+    fn log_lines(&self, rows: usize) -> Vec<String> {
+        let end = self.log.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(rows);
+        self.log.range(start..end).cloned().collect()
+    }
+}
+
+/// Queues a pane: a box with a title and its lines (cut to fit), `highlighted` lines are shown in reverse video
+fn queue_pane(
+    out: &mut impl Write,
+    (x, y, width, height): (u16, u16, u16, u16),
+    title: &str,
+    lines: &[(String, bool)],
+) -> Result<()> {
+    let inner = width.saturating_sub(2) as usize;
+    let title = console::cut_to_width(title, inner.saturating_sub(2));
+    let rule = "─".repeat(inner.saturating_sub(console::text_width(&title) + 2));
+    out.queue(cursor::MoveTo(x, y))?
+        .queue(Print(console::plain(&format!("┌ {title} {rule}┐"))))?;
+    for row in 0..height.saturating_sub(2) {
+        let (line, highlighted) = lines
+            .get(row as usize)
+            .map(|(line, highlighted)| (line.as_str(), *highlighted))
+            .unwrap_or_default();
+        let line = console::plain(line);
+        let line = console::cut_to_width(&line, inner);
+        let padding = " ".repeat(inner - console::text_width(&line));
+        let line = if highlighted {
+            format!("{line}{padding}").reverse().to_string()
+        } else {
+            format!("{}{padding}", console::styled(&line))
+        };
+        let border = console::plain("│");
+        out.queue(cursor::MoveTo(x, y + row + 1))?
+            .queue(Print(&border))?
+            .queue(Print(line))?
+            .queue(Print(&border))?;
+    }
+    out.queue(cursor::MoveTo(x, y + height.saturating_sub(1)))?
+        .queue(Print(console::plain(&format!("└{}┘", "─".repeat(inner)))))?;
+    Ok(())
+}
+
+/**
+ * Draws the panes to fit the terminal
+ * @return The height of the log pane (for scrolling by pages)
+ */
+fn draw(state: &TuiState) -> Result<usize> {
+    let mut out = stdout().lock();
+    let (width, height) = terminal::size().context("Failed to get the terminal size")?;
+    out.queue(terminal::Clear(terminal::ClearType::All))?;
+    if width < MIN_SIZE.0 || height < MIN_SIZE.1 {
+        out.queue(cursor::MoveTo(0, 0))?
+            .queue(Print(t!("tui-too-small")))?;
+        out.flush()?;
+        return Ok(0);
+    }
+
+    let plain = |lines: Vec<String>| -> Vec<(String, bool)> {
+        lines.into_iter().map(|line| (line, false)).collect()
+    };
+    let left = width / 2;
+    queue_pane(
+        &mut out,
+        (0, 0, left, TOP_PANES_HEIGHT),
+        &t!("tui-connection"),
+        &plain(state.connection_lines()),
+    )?;
+    queue_pane(
+        &mut out,
+        (left, 0, width - left, TOP_PANES_HEIGHT),
+        &t!("tui-guests", guests = state.guests.len()),
+        &plain(state.guest_lines()),
+    )?;
+
+    let invite_rows = state.invites.len().clamp(1, INVITES_SHOWN);
+    let invite_height = invite_rows as u16 + 2;
+    queue_pane(
+        &mut out,
+        (0, TOP_PANES_HEIGHT, width, invite_height),
+        &t!("tui-invites"),
+        &state.invite_lines(invite_rows),
+    )?;
+
+    // The log takes the rest of the screen, above the key help
+    let log_top = TOP_PANES_HEIGHT + invite_height;
+    let log_height = height - log_top - 1;
+    let log_rows = log_height.saturating_sub(2) as usize;
+    let title = match state.scroll {
+        0 => t!("tui-log"),
+        lines => t!("tui-log-scrolled", lines = lines),
+    };
+    queue_pane(
+        &mut out,
+        (0, log_top, width, log_height),
+        &title,
+        &plain(state.log_lines(log_rows)),
+    )?;
+
+    let help = console::cut_to_width(&t!("tui-help"), width as usize - 1).into_owned();
+    out.queue(cursor::MoveTo(0, height - 1))?
+        .queue(Print(help.dim()))?;
+    out.flush()?;
+    Ok(log_rows)
+}
+
+/// Restores the terminal left by the interface
+fn restore_terminal() {
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(stdout(), terminal::LeaveAlternateScreen, cursor::Show);
+}
+
+/// Draws the panes and reads the keys until `stop` is called
+fn run(
+    state: &mut TuiState,
+    mut events: EventReceiver<Event>,
+    tx: Sender<ConsoleCmd>,
+) -> Result<()> {
+    let mut dirty = true;
+    let mut log_height = 0;
+    while !STOP.load(Ordering::Relaxed) {
+        loop {
+            match events.try_recv() {
+                Ok(event) => {
+                    state.apply(event);
+                    dirty = true;
+                }
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        if dirty {
+            log_height = draw(state)?;
+            dirty = false;
+        }
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        match event::read()? {
+            TermEvent::Key(key) if key.kind == KeyEventKind::Press => {
+                if let Some(cmd) = state.on_key(key, log_height) {
+                    if tx.blocking_send(cmd).is_err() {
+                        break;
+                    }
+                }
+                dirty = true;
+            }
+            TermEvent::Resize(..) => dirty = true,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Replaces the console with a full-screen interface: connection, guests, invite links and log panes,
+ * with keys to create and revoke invite links
+ * @return The commands entered with the keys, as `console::spawn_command_reader` would read them
+ */
+pub fn spawn() -> Result<Receiver<ConsoleCmd>> {
+    let (tx, rx) = channel::<ConsoleCmd>(8);
+    // Subscribed before the console stops printing, so that no line is missed
+    let events = events::subscribe();
+    terminal::enable_raw_mode().context("Failed to enable the raw mode of the terminal")?;
+    if let Err(err) = execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide) {
+        restore_terminal();
+        return Err(err).context("Failed to open the full-screen interface");
+    }
+    console::set_tui(true);
+
+    // Give the terminal back before the panic message is printed
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    STOP.store(false, Ordering::Relaxed);
+    let handle = thread::spawn(move || {
+        let mut state = TuiState::new();
+        if let Err(err) = run(&mut state, events, tx) {
+            state.log.push_back(format!("☓ {err:#}"));
+        }
+        restore_terminal();
+        let reprinted = state.log.len().saturating_sub(LOG_LINES_REPRINTED);
+        state.log.into_iter().skip(reprinted).collect()
+    });
+    *THREAD.lock().unwrap_or_else(|err| err.into_inner()) = Some(handle);
+    Ok(rx)
+}
+
+/// Closes the full-screen interface, printing its last log lines on the console again (to read why it exited)
+pub fn stop() {
+    let Some(handle) = THREAD.lock().unwrap_or_else(|err| err.into_inner()).take() else {
+        return;
+    };
+    STOP.store(true, Ordering::Relaxed);
+    let lines = handle.join().unwrap_or_default();
+    console::set_tui(false);
+    for line in lines {
+        std::println!("{}", console::styled(&line));
+    }
+}
@@ -0,0 +1,284 @@
+use anyhow::{bail, Context as _, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use dotenvy_macro::dotenv;
+use ring::{
+    digest::{digest, SHA256},
+    signature::{UnparsedPublicKey, ED25519},
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    http::{self, Network},
+    VERSION,
+};
+
+/// Ed25519 public keys the releases are signed with (comma-separated base64, empty: no self-update)
+/// Several keys can be embedded so that the signing key can be rotated.
+const PUBLIC_KEYS: &str = dotenv!("UPDATE_PUBLIC_KEY");
+
+/// Suffix of the URL of the signature of a release binary
+/// (base64 Ed25519 signature of the version without `v` followed by the SHA-256 of the file)
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// Extension of the backup of the binary replaced by the last update
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Extension of a binary replaced while it was running, removed at the next start
+const OLD_EXTENSION: &str = "old";
+
+/// A newer release of the client, offered by the server when the running one is outdated
+#[derive(Clone, Debug)]
+pub struct Release {
+    /// Version of the release
+    pub version: String,
+    /// URL of the binary for this platform
+    pub url: String,
+    /// Download page to open when it cannot be installed automatically
+    pub page: String,
+}
+
+/// Gets the key of this platform in the release assets sent by the server (e.g. `windows-x86_64`)
+pub fn platform() -> String {
+    format!("{}-{}", env::consts::OS, env::consts::ARCH)
+}
+
+/// Get the path of the running binary (the AppImage rather than its mounted content)
+fn current_binary() -> Result<PathBuf> {
+    if let Some(appimage) = env::var_os("APPIMAGE").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(appimage));
+    }
+    env::current_exe().context("Unable to find the running binary")
+}
+
+/// Get a path next to the binary (e.g. `remoteplay-inviter.exe.old`)
+fn sibling(binary: &Path, extension: &str) -> PathBuf {
+    let mut name = binary.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{extension}"));
+    binary.with_file_name(name)
+}
+
+/**
+ * Parses a version (`1.2.3`, `v1.2.3-beta`) into its numeric parts, ignoring the pre-release and build suffixes
+ * @return None if it is not a version
+ */
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Whether `version` is newer than the running client
+pub fn is_newer(version: &str) -> bool {
+    match (parse_version(version), parse_version(VERSION)) {
+        (Some(version), Some(current)) => version > current,
+        _ => false,
+    }
+}
+
+/// Builds the message signed for a release: the version (without `v`) followed by the SHA-256 of the artifact
+fn signed_message(version: &str, artifact: &[u8]) -> Vec<u8> {
+    let version = version.trim().trim_start_matches('v');
+    [version.as_bytes(), digest(&SHA256, artifact).as_ref()].concat()
+}
+
+/// Checks the signature of an artifact released as `version` against the keys embedded in the binary
+pub fn verify(version: &str, artifact: &[u8], signature: &[u8]) -> Result<()> {
+    let keys: Vec<Vec<u8>> = PUBLIC_KEYS
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| BASE64.decode(key).context("Invalid release signing key"))
+        .collect::<Result<_>>()?;
+    verify_with(&keys, version, artifact, signature)
+}
+
+/// Checks the signature of an artifact released as `version` against the given keys
+fn verify_with(keys: &[Vec<u8>], version: &str, artifact: &[u8], signature: &[u8]) -> Result<()> {
+    if keys.is_empty() {
+        bail!("This build cannot update itself (it has no release signing key)");
+    }
+    let signature = BASE64
+        .decode(String::from_utf8_lossy(signature).trim())
+        .context("Invalid release signature")?;
+    let message = signed_message(version, artifact);
+    if !keys.iter().any(|key| {
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(&message, &signature)
+            .is_ok()
+    }) {
+        bail!("The signature of the downloaded release is not valid");
+    }
+    Ok(())
+}
+
+/**
+ * Downloads a release, checks its signature and replaces the running binary with it
+ * (the running binary is kept as a backup to roll back to)
+ * @return The path of the new binary
+ */
+pub async fn install(release: &Release, network: &Network) -> Result<PathBuf> {
+    // A compromised or misconfigured server must not bring back a vulnerable version
+    // (the version is signed with the binary, so an old release can't be passed off as a new one)
+    if !is_newer(&release.version) {
+        bail!(
+            "Refusing to replace version {VERSION} with {}, which is not newer",
+            release.version
+        );
+    }
+
+    let binary = http::download(network, &release.url).await?;
+    let signature = http::download(network, &format!("{}{SIGNATURE_SUFFIX}", release.url)).await?;
+    verify(&release.version, &binary, &signature)?;
+
+    let path = current_binary()?;
+    replace(&path, &binary)?;
+    Ok(path)
+}
+
+/// Writes the new binary next to the running one and swaps them, keeping the running one as the backup
+fn replace(path: &Path, content: &[u8]) -> Result<()> {
+    let new = sibling(path, "new");
+    let backup = sibling(path, BACKUP_EXTENSION);
+    fs::write(&new, content).with_context(|| format!("Unable to write file: {:?}", &new))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Unable to make {:?} executable", &new))?;
+    }
+
+    // A running binary cannot be overwritten on Windows, but it can be renamed
+    let _ = fs::remove_file(&backup);
+    fs::rename(path, &backup).with_context(|| format!("Unable to move {:?}", path))?;
+    if let Err(err) = fs::rename(&new, path) {
+        let _ = fs::rename(&backup, path);
+        return Err(err).with_context(|| format!("Unable to replace {:?}", path));
+    }
+    Ok(())
+}
+
+/**
+ * Puts back the binary replaced by the last update
+ * @return The path of the restored binary
+ */
+pub fn rollback() -> Result<PathBuf> {
+    let path = current_binary()?;
+    let backup = sibling(&path, BACKUP_EXTENSION);
+    if !backup.exists() {
+        bail!(
+            "No previous version to roll back to (no backup at {:?})",
+            &backup
+        );
+    }
+    // The binary may be running, it is removed at the next start
+    let old = sibling(&path, OLD_EXTENSION);
+    let _ = fs::remove_file(&old);
+    fs::rename(&path, &old).with_context(|| format!("Unable to move {:?}", &path))?;
+    if let Err(err) = fs::rename(&backup, &path) {
+        let _ = fs::rename(&old, &path);
+        return Err(err).with_context(|| format!("Unable to restore {:?}", &backup));
+    }
+    Ok(path)
+}
+
+/// Removes the binary replaced by the last rollback (it was still running then)
+pub fn cleanup() {
+    if let Ok(path) = current_binary() {
+        let _ = fs::remove_file(sibling(&path, OLD_EXTENSION));
+    }
+}
+
+/**
+ * Starts the new binary with the same arguments in place of this process
+ * @return The error if it could not be started (it doesn't return otherwise)
+ */
+pub fn restart(binary: &Path) -> anyhow::Error {
+    let mut command = Command::new(binary);
+    command.args(env::args_os().skip(1));
+
+    #[cfg(unix)]
+    let err = {
+        use std::os::unix::process::CommandExt as _;
+        command.exec()
+    };
+    #[cfg(not(unix))]
+    let err = match command.spawn() {
+        Ok(_) => std::process::exit(0),
+        Err(err) => err,
+    };
+    anyhow::Error::new(err).context(format!("Unable to start {:?}", binary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair as _},
+    };
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn sign(key_pair: &Ed25519KeyPair, version: &str, artifact: &[u8]) -> Vec<u8> {
+        let signature = key_pair.sign(&signed_message(version, artifact));
+        BASE64.encode(signature.as_ref()).into_bytes()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(parse_version("1.2.3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version("v1.2.3-beta+build"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version("1.x"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("999.0.0"));
+        assert!(!is_newer(VERSION));
+        assert!(!is_newer("0.0.0"));
+        assert!(!is_newer("latest"));
+        // Numerically, not alphabetically
+        assert!(parse_version("1.10.0") > parse_version("1.9.0"));
+    }
+
+    #[test]
+    fn verifies_signed_releases() {
+        let key_pair = key_pair();
+        let keys = [key_pair.public_key().as_ref().to_vec()];
+        let signature = sign(&key_pair, "1.2.3", b"binary");
+        assert!(verify_with(&keys, "1.2.3", b"binary", &signature).is_ok());
+        assert!(verify_with(&keys, "v1.2.3", b"binary", &signature).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let key_pair = key_pair();
+        let keys = [key_pair.public_key().as_ref().to_vec()];
+        let signature = sign(&key_pair, "1.0.0", b"binary");
+        assert!(verify_with(&keys, "2.0.0", b"binary", &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_artifacts() {
+        let key_pair = key_pair();
+        let keys = [key_pair.public_key().as_ref().to_vec()];
+        let signature = sign(&key_pair, "1.2.3", b"binary");
+        assert!(verify_with(&keys, "1.2.3", b"tampered", &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_other_keys() {
+        let keys = [key_pair().public_key().as_ref().to_vec()];
+        let signature = sign(&key_pair(), "1.2.3", b"binary");
+        assert!(verify_with(&keys, "1.2.3", b"binary", &signature).is_err());
+        assert!(verify_with(&[], "1.2.3", b"binary", &signature).is_err());
+    }
+}
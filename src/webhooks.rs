@@ -0,0 +1,161 @@
+use serde_json::Value;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{sleep, timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{WebhookConfig, WebhookFormat},
+    events::{self, Event},
+    http::{self, Network},
+    retry::{RetryPolicy, RetrySec},
+};
+
+/// Events posted when a webhook doesn't list its own
+const DEFAULT_EVENTS: [&str; 6] = [
+    "invite_created",
+    "guest_joined",
+    "guest_left",
+    "reconnecting",
+    "maintenance",
+    "steam_lost",
+];
+
+/// Time to wait for the webhook server to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts the session events to the webhooks until `shutdown` is cancelled
+pub async fn run(webhooks: Vec<WebhookConfig>, network: Network, shutdown: CancellationToken) {
+    let mut events = events::subscribe();
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = shutdown.cancelled() => return,
+        };
+
+        let Ok(data) = serde_json::to_value(&event) else {
+            continue;
+        };
+        let name = data["event"].as_str().unwrap_or_default();
+        for webhook in &webhooks {
+            let subscribed = if webhook.events.is_empty() {
+                DEFAULT_EVENTS.contains(&name)
+            } else {
+                webhook.events.iter().any(|event| event == name)
+            };
+            if !subscribed {
+                continue;
+            }
+            let body = match webhook.format {
+                WebhookFormat::Json => json_payload(data.clone()),
+                WebhookFormat::Discord => match discord_payload(&event) {
+                    Some(body) => body,
+                    None => continue,
+                },
+            };
+            // Deliver in the background so that a slow server doesn't hold up the following events
+            let webhook = webhook.clone();
+            let network = network.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = deliver(&network, &webhook, body.to_string()) => (),
+                    _ = shutdown.cancelled() => (),
+                }
+            });
+        }
+    }
+}
+
+/// Builds the JSON payload (the event with the time it happened)
+fn json_payload(mut data: Value) -> Value {
+    if let Value::Object(fields) = &mut data {
+        fields.insert(
+            "time".to_string(),
+            Value::String(chrono::Local::now().to_rfc3339()),
+        );
+    }
+    data
+}
+
+/**
+ * Builds the Discord webhook payload
+ * @return None for the events without a message
+ */
+fn discord_payload(event: &Event) -> Option<Value> {
+    let content = match event {
+        Event::Connected { reconnected: true } => "✓ Reconnected to the server".to_string(),
+        Event::Connected { reconnected: false } => "✓ Connected to the server".to_string(),
+        Event::Reconnecting { delay_ms } => format!(
+            "↪ Connection to the server lost, reconnecting in {:.1} seconds",
+            *delay_ms as f64 / 1000.0
+        ),
+        Event::InviteCreated { url, .. } => format!("-> Invite link created: {url}"),
+        Event::GuestJoined { name, guests, .. } => {
+            format!("✓ {name} joined ({guests} connected)")
+        }
+        Event::GuestLeft { name, guests, .. } => format!("↪ {name} left ({guests} connected)"),
+        Event::SteamLost => "☓ Lost connection to the Steam client".to_string(),
+        Event::SteamRestored => "✓ Reconnected to the Steam client".to_string(),
+        Event::SessionEnding { minutes_left } => {
+            format!("★ The session ends in {minutes_left} minutes (time limit)")
+        }
+        Event::Maintenance { delay_ms, .. } => format!(
+            "□ The server is down for maintenance, reconnecting in {} minutes",
+            delay_ms.div_ceil(60_000)
+        ),
+        Event::Paired { .. } => "✓ Linked to Discord".to_string(),
+        Event::InviteRevoked { .. }
+        | Event::Announcement { .. }
+        | Event::PairingCode { .. }
+        | Event::Log { .. } => return None,
+    };
+    Some(serde_json::json!({ "username": "Remote Play Inviter", "content": content }))
+}
+
+/// Posts a payload, retrying with backoff while the server is unreachable or failing
+async fn deliver(network: &Network, webhook: &WebhookConfig, body: String) {
+    let mut retry_sec = RetrySec::with_policy(RetryPolicy {
+        max_backoff: 60,
+        max_retries: Some(webhook.max_retries),
+        ..RetryPolicy::default()
+    });
+    loop {
+        let err = match timeout(
+            REQUEST_TIMEOUT,
+            http::post_json(network, &webhook.url, &body),
+        )
+        .await
+        {
+            Ok(Ok(response)) if response.is_success() => return,
+            // Don't retry requests the server rejected (except for rate limiting)
+            Ok(Ok(http::Response { status, .. }))
+                if (400..500).contains(&status) && status != 408 && status != 429 =>
+            {
+                tracing::warn!(url = webhook.url, status, "The webhook rejected the event");
+                return;
+            }
+            Ok(Ok(response)) => {
+                anyhow::anyhow!("The webhook answered with status {}", response.status)
+            }
+            Ok(Err(err)) => err,
+            Err(_) => anyhow::anyhow!("The webhook did not answer in time"),
+        };
+        let Some(delay) = retry_sec.next() else {
+            tracing::warn!(url = webhook.url, "Gave up posting an event: {err:#}");
+            return;
+        };
+        tracing::debug!(
+            url = webhook.url,
+            ?delay,
+            "Failed to post an event: {err:#}"
+        );
+        sleep(delay).await;
+    }
+}
@@ -1,13 +1,23 @@
-use crate::{console, ConnectionErrorMessage, ConnectionErrorType, VERSION};
+use crate::{
+    console,
+    i18n::t,
+    models::{ConnectionErrorMessage, ConnectionErrorType},
+    platform,
+    update::{self, Release},
+    VERSION,
+};
 use anyhow::{anyhow, Context as _, Result};
 use tokio_tungstenite::tungstenite::Error as WsError;
 
-/// Handle WebSocket errors
-pub fn handle_ws_error(err: WsError) -> Result<()> {
+/**
+ * Handle WebSocket errors
+ * @return The release to install when the client is outdated and `self_update` is set
+ */
+pub fn handle_ws_error(err: WsError, self_update: bool) -> Result<Option<Release>> {
     match err {
         // In case of Bad Request
         WsError::Http(res) if res.status() == 400 => {
-            let result: Result<()> = (|| {
+            let result: Result<Option<Release>> = (|| {
                 // Get the response body
                 let header = res
                     .headers()
@@ -23,17 +33,34 @@ pub fn handle_ws_error(err: WsError) -> Result<()> {
                 // If parsing is successful
                 match error {
                     // If the version is outdated
-                    ConnectionErrorType::Outdated { required, download } => {
+                    ConnectionErrorType::Outdated {
+                        required,
+                        download,
+                        assets,
+                    } => {
                         // Display the content
                         console::printdoc! {"
 
-                            ↑ Update required: {VERSION} to {required}
-                              Download: {download}
+                            ↑ {update}
+                              {download_line}
                             
-                            "};
+                            ", update = t!("update-required", current = VERSION, required = required),
+                        download_line = t!("update-download", url = download)};
+
+                        // Install the release for this platform if there is one
+                        if let Some(url) = assets.get(&update::platform()) {
+                            if self_update {
+                                return Ok(Some(Release {
+                                    version: required,
+                                    url: url.clone(),
+                                    page: download,
+                                }));
+                            }
+                            console::println!("  {}", t!("update-self-update-hint"));
+                        }
 
                         // Open the browser
-                        let _ = webbrowser::open(&download);
+                        let _ = platform::open_url(&download);
                     }
                     // For other errors
                     _ => {
@@ -49,21 +76,23 @@ pub fn handle_ws_error(err: WsError) -> Result<()> {
                             console::printdoc! {
                                 "
 
-                                    ☓ Connection error:
+                                    ☓ {title}
                                     {message}
 
-                                    "
+                                    ",
+                                title = t!("connection-error")
                             }
                         }
                     }
                 }
 
-                Ok(())
+                Ok(None)
             })();
 
-            if let Err(err) = result {
+            match result {
+                Ok(release) => return Ok(release),
                 // If parsing fails
-                console::eprintln!("☓ {err}");
+                Err(err) => console::eprintln!("☓ {err}"),
             }
         }
         // For other HTTP errors
@@ -72,5 +101,5 @@ pub fn handle_ws_error(err: WsError) -> Result<()> {
         _ => Err(err).context("Failed to connect to the server")?,
     }
 
-    Ok(())
+    Ok(None)
 }
@@ -12,7 +12,12 @@ extern "C" {
 
 bool SteamStuff_Init()
 {
-	return GClientContext()->Init();
+	return GClientContext()->Init(nullptr);
+}
+
+bool SteamStuff_InitWithHome(const char* steamHome)
+{
+	return GClientContext()->Init(steamHome);
 }
 
 void SteamStuff_Shutdown()
@@ -25,6 +30,11 @@ void SteamStuff_RunCallbacks()
 	GClientContext()->RunCallbacks();
 }
 
+bool SteamStuff_IsConnected()
+{
+	return GClientContext()->IsConnected();
+}
+
 uint64_t SteamStuff_GetRunningGameID()
 {
 	return GClientContext()->GetRunningGameID().ToUint64();
@@ -32,7 +42,13 @@ uint64_t SteamStuff_GetRunningGameID()
 
 bool SteamStuff_CanRemotePlayTogether(uint64_t gameID)
 {
-	return GClientContext()->AppManager()->BCanRemotePlayTogether(CGameID(uint64(gameID)).AppID());
+	IClientAppManager* appManager = GClientContext()->AppManager();
+	if (!appManager)
+	{
+		return false;
+	}
+
+	return appManager->BCanRemotePlayTogether(CGameID(uint64(gameID)).AppID());
 }
 
 
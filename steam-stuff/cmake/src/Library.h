@@ -8,8 +8,10 @@ extern "C" {
 #include "Types.h"
 
 bool SteamStuff_Init();
+bool SteamStuff_InitWithHome(const char* steamHome);
 void SteamStuff_Shutdown();
 void SteamStuff_RunCallbacks();
+bool SteamStuff_IsConnected();
 uint64_t SteamStuff_GetRunningGameID();
 bool SteamStuff_CanRemotePlayTogether(uint64_t gameID);
 
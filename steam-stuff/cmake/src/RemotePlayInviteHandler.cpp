@@ -13,12 +13,19 @@ RemotePlayInviteHandler::RemotePlayInviteHandler() :
 
 uint64 RemotePlayInviteHandler::SendInvite(CSteamID invitee, CGameID gameID)
 {
+	IClientRemoteClientManager* remoteManager = GClientContext()->RemoteClientManager();
+	if (!remoteManager)
+	{
+		// not connected to the Steam client
+		return 0;
+	}
+
 	RemotePlayPlayer_t rppInvitee = { invitee, m_remoteGuestID++, 0, 0, 0 };
 
 	if (gameID.IsSteamApp() && gameID.AppID() != m_nonsteamAppID)
 	{
 		// Start Remote Play session
-		GClientContext()->RemoteClientManager()->BCreateRemotePlayInviteAndSession(rppInvitee, gameID.AppID());
+		remoteManager->BCreateRemotePlayInviteAndSession(rppInvitee, gameID.AppID());
 	}
 	else
 	{
@@ -31,10 +38,11 @@ uint64 RemotePlayInviteHandler::SendInvite(CSteamID invitee, CGameID gameID)
 
 void RemotePlayInviteHandler::CancelInvite(CSteamID invitee, uint64 guestID)
 {
-	if (GClientContext()->RemoteClientManager()->BIsStreamingSessionActive())
+	IClientRemoteClientManager* remoteManager = GClientContext()->RemoteClientManager();
+	if (remoteManager && remoteManager->BIsStreamingSessionActive())
 	{
 		RemotePlayPlayer_t rppInvitee = { invitee, guestID, 0, 0, 0 };
-		GClientContext()->RemoteClientManager()->CancelRemotePlayInviteAndSession(rppInvitee);
+		remoteManager->CancelRemotePlayInviteAndSession(rppInvitee);
 	}
 }
 
@@ -1,5 +1,9 @@
 #include "SteamStuff.h"
 
+#ifdef __linux__
+#include <dlfcn.h>
+#endif
+
 // steam client context
 
 ClientContext::ClientContext() :
@@ -10,8 +14,10 @@ ClientContext::ClientContext() :
 	m_pSteamClient(nullptr),
 	m_pClientEngine(nullptr),
 	m_pClientRemoteManager(nullptr),
+	m_pClientAppManager(nullptr),
 	m_ShuttingDown(false),
-	m_Initialized(false)
+	m_Initialized(false),
+	m_hSteamClientModule(nullptr)
 {
 }
 
@@ -19,13 +25,32 @@ ClientContext::~ClientContext()
 {
 }
 
-bool ClientContext::Init()
+bool ClientContext::Init(const char* steamHome)
 {
 	if (m_Initialized)
 	{
 		return true;
 	}
 
+	m_SteamHome = steamHome ? steamHome : "";
+	m_ShuttingDown = false;
+	if (!Connect())
+	{
+		// Release what was acquired before the failure
+		Shutdown();
+		return false;
+	}
+
+	return m_Initialized = true;
+}
+
+bool ClientContext::Connect()
+{
+	if (!LoadSteamClient())
+	{
+		return false;
+	}
+
 	if (!OpenAPI_LoadLibrary())
 	{
 		return false;
@@ -74,12 +99,7 @@ bool ClientContext::Init()
 	}
 
 	m_pClientAppManager = m_pClientEngine->GetIClientAppManager(m_hUser, m_hPipe);
-	if (!m_pClientAppManager)
-	{
-		return false;
-	}
-
-	return m_Initialized = true;
+	return m_pClientAppManager != nullptr;
 }
 
 void ClientContext::Shutdown()
@@ -91,7 +111,7 @@ void ClientContext::Shutdown()
 
 	m_ShuttingDown = true;
 
-	if (m_hPipe)
+	if (m_hPipe && m_pSteamClient)
 	{
 		if (m_hUser)
 		{
@@ -102,6 +122,34 @@ void ClientContext::Shutdown()
 			m_pSteamClient->BShutdownIfAllPipesClosed();
 		}
 	}
+
+	// Allow initializing again (e.g. after the Steam client restarts)
+	// The interfaces belong to the released pipe, so they must not be called anymore
+	m_hPipe = 0;
+	m_hUser = 0;
+	m_pSteamUser = nullptr;
+	m_pSteamFriends = nullptr;
+	m_pClientRemoteManager = nullptr;
+	m_pClientAppManager = nullptr;
+	m_Initialized = false;
+}
+
+bool ClientContext::LoadSteamClient()
+{
+#ifdef __linux__
+	if (m_SteamHome.empty() || m_hSteamClientModule)
+	{
+		return true;
+	}
+
+	// Load the library of the given installation up front, so that the loader finds it in memory
+	// instead of looking for it under the HOME directory
+	std::string path = m_SteamHome + "/.steam/sdk64/steamclient.so";
+	m_hSteamClientModule = dlopen(path.c_str(), RTLD_NOW | RTLD_GLOBAL);
+	return m_hSteamClientModule != nullptr;
+#else
+	return true;
+#endif
 }
 
 ISteamUser019* ClientContext::SteamUser()
@@ -116,12 +164,22 @@ ISteamFriends015* ClientContext::SteamFriends()
 
 void ClientContext::RunCallbacks()
 {
-	if (!m_ShuttingDown)
+	if (m_Initialized && !m_ShuttingDown)
 	{
 		Steam_RunCallbacks(m_hPipe, false);
 	}
 }
 
+bool ClientContext::IsConnected()
+{
+	if (!m_Initialized || m_ShuttingDown)
+	{
+		return false;
+	}
+
+	return m_pSteamUser->BLoggedOn();
+}
+
 IClientRemoteClientManager* ClientContext::RemoteClientManager()
 {
 	return m_pClientRemoteManager;
@@ -134,8 +192,13 @@ IClientAppManager* ClientContext::AppManager()
 
 CGameID ClientContext::GetRunningGameID()
 {
+	if (!m_pSteamFriends || !m_pSteamUser)
+	{
+		return CGameID();
+	}
+
 	FriendGameInfo_t gameInfo;
-	if (GClientContext()->SteamFriends()->GetFriendGamePlayed(GClientContext()->SteamUser()->GetSteamID(), &gameInfo))
+	if (m_pSteamFriends->GetFriendGamePlayed(m_pSteamUser->GetSteamID(), &gameInfo))
 	{
 		return gameInfo.m_gameID;
 	}
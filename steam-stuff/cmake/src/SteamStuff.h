@@ -2,6 +2,7 @@
 #define STEAMSTUFF_H
 
 #include <Steamworks.h>
+#include <string>
 
 class ClientContext
 {
@@ -26,9 +27,10 @@ public:
 
 	/**
 		@brief Initialize the Steam client.
+		@param steamHome The home directory of the Steam installation, or nullptr for the user's home directory.
 		@return True if the Steam client was initialized successfully.
 	*/
-	bool Init();
+	bool Init(const char* steamHome);
 	/**
 		@brief Shutdown the Steam client.
 	*/
@@ -37,6 +39,11 @@ public:
 		@brief Run the Steam client callbacks.
 	*/
 	void RunCallbacks();
+	/**
+		@brief Check whether the connection to the Steam client is alive.
+		@return True if the Steam client is running and the user is logged on.
+	*/
+	bool IsConnected();
 
 	/**
 		@brief Get the game ID of the running game.
@@ -45,6 +52,17 @@ public:
 	CGameID GetRunningGameID();
 
 private:
+	/**
+		@brief Connect to the Steam client and get its interfaces.
+		@return True if all the interfaces were acquired.
+	*/
+	bool Connect();
+	/**
+		@brief Load the client library of the Steam installation in m_SteamHome.
+		@return True if the library is loaded.
+	*/
+	bool LoadSteamClient();
+
 	HSteamPipe m_hPipe;
 	HSteamUser m_hUser;
 
@@ -58,6 +76,9 @@ private:
 
 	bool m_ShuttingDown;
 	bool m_Initialized;
+
+	std::string m_SteamHome;
+	void* m_hSteamClientModule;
 };
 
 ClientContext* GClientContext();
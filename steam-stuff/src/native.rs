@@ -10,9 +10,10 @@ pub type OnRemoteStarted = ::std::option::Option<unsafe extern "C" fn(invitee: u
 pub type OnRemoteStopped = ::std::option::Option<unsafe extern "C" fn(invitee: u64, guestID: u64)>;
 
 extern "C" {
-    pub fn SteamStuff_Init() -> bool;
+    pub fn SteamStuff_InitWithHome(steamHome: *const ::std::os::raw::c_char) -> bool;
     pub fn SteamStuff_Shutdown();
     pub fn SteamStuff_RunCallbacks();
+    pub fn SteamStuff_IsConnected() -> bool;
     pub fn SteamStuff_GetRunningGameID() -> u64;
     pub fn SteamStuff_CanRemotePlayTogether(gameID: u64) -> bool;
     pub fn SteamStuff_SendInvite(invitee: u64, gameID: u64) -> u64;
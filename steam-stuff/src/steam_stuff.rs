@@ -1,7 +1,9 @@
 use crate::{native, GameID};
 use anyhow::Result;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
 use std::sync::{Arc, Mutex};
 
 static ON_REMOTE_INVITED: Mutex<Option<Arc<dyn Fn(u64, u64, &str) + Send + Sync>>> =
@@ -10,22 +12,51 @@ static ON_REMOTE_STARTED: Mutex<Option<Arc<dyn Fn(u64, u64) + Send + Sync>>> = M
 static ON_REMOTE_STOPPED: Mutex<Option<Arc<dyn Fn(u64, u64) + Send + Sync>>> = Mutex::new(None);
 
 pub struct SteamStuff {
-    _private: (),
+    /// Home directory of the Steam installation (None: the user's home directory)
+    steam_home: Option<CString>,
 }
 
 impl SteamStuff {
     pub fn new() -> Result<Self> {
-        if unsafe { native::SteamStuff_Init() } {
-            Ok(SteamStuff { _private: () })
+        Self::init(None)
+    }
+
+    /// Connects to the Steam client installed in another home directory (e.g. a Flatpak or Snap sandbox)
+    pub fn with_home(steam_home: &Path) -> Result<Self> {
+        let steam_home = CString::new(steam_home.to_string_lossy().into_owned())?;
+        Self::init(Some(steam_home))
+    }
+
+    fn init(steam_home: Option<CString>) -> Result<Self> {
+        let steam_home_ptr = steam_home.as_ref().map_or(ptr::null(), |home| home.as_ptr());
+        if unsafe { native::SteamStuff_InitWithHome(steam_home_ptr) } {
+            Ok(SteamStuff { steam_home })
         } else {
             Err(anyhow::anyhow!("Failed to initialize SteamStuff"))
         }
     }
 
+    fn steam_home_ptr(&self) -> *const c_char {
+        self.steam_home.as_ref().map_or(ptr::null(), |home| home.as_ptr())
+    }
+
     pub fn run_callbacks(&self) {
         unsafe { native::SteamStuff_RunCallbacks() }
     }
 
+    pub fn is_connected(&self) -> bool {
+        unsafe { native::SteamStuff_IsConnected() }
+    }
+
+    pub fn reconnect(&mut self) -> Result<()> {
+        unsafe { native::SteamStuff_Shutdown() }
+        if unsafe { native::SteamStuff_InitWithHome(self.steam_home_ptr()) } {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Failed to reinitialize SteamStuff"))
+        }
+    }
+
     pub fn get_running_game_id(&self) -> GameID {
         unsafe { GameID::from(native::SteamStuff_GetRunningGameID()) }
     }
@@ -97,6 +128,18 @@ impl SteamStuff {
 
         unsafe { native::SteamStuff_SetOnRemoteStopped(Some(trampoline)) }
     }
+
+    pub fn clear_callbacks(&self) {
+        *ON_REMOTE_INVITED.lock().unwrap() = None;
+        *ON_REMOTE_STARTED.lock().unwrap() = None;
+        *ON_REMOTE_STOPPED.lock().unwrap() = None;
+
+        unsafe {
+            native::SteamStuff_SetOnRemoteInvited(None);
+            native::SteamStuff_SetOnRemoteStarted(None);
+            native::SteamStuff_SetOnRemoteStopped(None);
+        }
+    }
 }
 
 impl Drop for SteamStuff {